@@ -0,0 +1,69 @@
+//! Throughput of color schemes calculation, run with `cargo bench --bench schemes`. Number of
+//! substances (1M by default) can be passed, like `cargo bench --bench schemes -- 100000`.
+use moleco::{calculate_scheme, hues_to_srgb};
+use palette::{FromColor, Hsv, Srgb};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+fn report(what: &str, count: usize, elapsed: Duration) {
+    println!(
+        "{:<28} {:>9} in {:>8.3} s, {:>12.0} per second",
+        what,
+        count,
+        elapsed.as_secs_f64(),
+        count as f64 / elapsed.as_secs_f64()
+    );
+}
+
+fn main() {
+    let count = std::env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse::<usize>().ok())
+        .unwrap_or(1_000_000);
+    // Distinct substances, so each of them is hashed into its own hues
+    let substances: Vec<String> = (0..count)
+        .map(|index| {
+            format!(
+                "InChI=1S/C{}H{}O/c{}",
+                index % 97 + 1,
+                index % 89 + 2,
+                index
+            )
+        })
+        .collect();
+
+    let start = Instant::now();
+    let schemes: Vec<_> = substances
+        .iter()
+        .map(|substance| calculate_scheme(substance.clone()))
+        .collect();
+    report("schemes", count, start.elapsed());
+
+    let hues: Vec<u32> = schemes
+        .iter()
+        .flat_map(|scheme| {
+            [
+                scheme.primary.hue,
+                scheme.first_accent.hue,
+                scheme.second_accent.hue,
+                scheme.complementary.hue,
+            ]
+        })
+        .collect();
+
+    let start = Instant::now();
+    let colors = hues_to_srgb(black_box(&hues));
+    report("colors, in batch", colors.len(), start.elapsed());
+
+    let start = Instant::now();
+    let converted: Vec<Srgb<u8>> = black_box(&hues)
+        .iter()
+        .map(|hue| Srgb::from_color(Hsv::new(*hue as f32, 0.7, 0.9)).into_format())
+        .collect();
+    report(
+        "colors, converted one by one",
+        converted.len(),
+        start.elapsed(),
+    );
+    assert!(colors == converted);
+}
@@ -0,0 +1,110 @@
+#[cfg(feature = "render")]
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Subdirectory with answers of resolver.
+pub const RESOLVER_DIR: &str = "resolver";
+/// Subdirectory with rendered images.
+pub const IMAGES_DIR: &str = "images";
+
+/// Cache directory of the user - `$XDG_CACHE_HOME/moleco`, `~/.cache/moleco`, or local app
+/// data on Windows.
+pub fn default_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .or_else(|| env::var_os("LOCALAPPDATA").map(PathBuf::from))?;
+    Some(base.join("moleco"))
+}
+
+/// Remove all cached files in directory (and its subdirectories), returns how many there were.
+/// Missing directory is already clear.
+pub fn clear(directory: &Path) -> Result<usize, String> {
+    if !directory.exists() {
+        return Ok(0);
+    }
+    let removed = count_files(directory)?;
+    fs::remove_dir_all(directory).map_err(|e| format!("Can't remove {:?} - {}", directory, e))?;
+    Ok(removed)
+}
+
+fn count_files(directory: &Path) -> Result<usize, String> {
+    let entries =
+        fs::read_dir(directory).map_err(|e| format!("Can't read {:?} - {}", directory, e))?;
+    let mut count = 0;
+    for entry in entries {
+        let path = entry
+            .map_err(|e| format!("Can't read {:?} - {}", directory, e))?
+            .path();
+        count += match path.is_dir() {
+            true => count_files(&path)?,
+            false => 1,
+        };
+    }
+    Ok(count)
+}
+
+/// Write file under temporary name first, so other runs never read half-written one.
+pub fn write_atomically(path: &Path, contents: &[u8]) -> Result<(), String> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)
+            .map_err(|e| format!("Can't create {:?} - {}", directory, e))?;
+    }
+    let temporary = path.with_extension(format!("tmp-{}", std::process::id()));
+    fs::write(&temporary, contents)
+        .and_then(|_| fs::rename(&temporary, path))
+        .map_err(|e| {
+            let _ = fs::remove_file(&temporary);
+            format!("Can't write {:?} - {}", path, e)
+        })
+}
+
+/// Rendered images, keyed by substance and everything else that changes their pixels. Version
+/// of moleco is part of the key too, so images of older versions aren't reused.
+#[cfg(feature = "render")]
+#[derive(Debug, Clone)]
+pub struct ImageCache {
+    directory: PathBuf,
+}
+
+#[cfg(feature = "render")]
+impl ImageCache {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+
+    /// Cache in images subdirectory of default cache directory.
+    pub fn in_default_dir() -> Option<Self> {
+        default_dir().map(|directory| Self::new(directory.join(IMAGES_DIR)))
+    }
+
+    /// Key of image of the payload, rendered with options described by `options`.
+    pub fn key(payload: &str, options: &str) -> String {
+        let hash = Sha256::digest(
+            format!("{}\n{}\n{}", env!("CARGO_PKG_VERSION"), payload, options).as_bytes(),
+        );
+        data_encoding::HEXLOWER.encode(&hash)
+    }
+
+    pub fn path(&self, key: &str) -> PathBuf {
+        self.directory.join(format!("{}.png", key))
+    }
+
+    /// Cached image, None when there is none (or it can't be read).
+    pub fn get(&self, key: &str) -> Option<image::RgbaImage> {
+        let bytes = fs::read(self.path(key)).ok()?;
+        image::load_from_memory_with_format(&bytes, image::ImageFormat::Png)
+            .ok()
+            .map(|image| image.to_rgba8())
+    }
+
+    pub fn put(&self, key: &str, buffer: &image::RgbaImage) -> Result<(), String> {
+        let mut bytes = vec![];
+        crate::export::encode_png(buffer, &mut bytes, None, None)?;
+        write_atomically(&self.path(key), &bytes)
+    }
+}
@@ -0,0 +1,219 @@
+use sha2::{Digest, Sha512};
+
+/// Hues (in degrees) of color scheme of the substance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hues {
+    pub primary: u32,
+    pub first_accent: u32,
+    pub second_accent: u32,
+    pub complementary: u32,
+}
+
+/// Substance as it is hashed - InChI prefix is dropped, so InChI and molecule of MInChI give
+/// the same hash.
+pub fn hashed_part(substance: &str) -> &str {
+    substance.strip_prefix("InChI=").unwrap_or(substance)
+}
+
+/// SHA-512 hash of the substance, that hues (and short identifier) come from.
+pub fn substance_hash(substance: &str) -> [u8; 64] {
+    Sha512::digest(hashed_part(substance).as_bytes()).into()
+}
+
+/// Remainder of the hash, read as big-endian number, divided by `divisor`.
+pub fn hash_modulo(hash: &[u8], divisor: u32) -> u32 {
+    let divisor = divisor as u64;
+    hash.iter()
+        .fold(0, |rest, byte| (rest * 256 + *byte as u64) % divisor) as u32
+}
+
+/// How hues of the scheme relate to each other. Hues may depend on the hash only, so schemes
+/// stay deterministic. Strategies are shared between threads, so they have to be `Send` and `Sync`.
+pub trait HueStrategy: Send + Sync {
+    /// Hues for hash of the substance. Going clockwise from primary hue, first accent should be
+    /// before complementary hue and second accent after it - composition mode nudges accents
+    /// only within these sides of the wheel.
+    fn hues(&self, hash: &[u8]) -> Hues;
+}
+
+/// Primary hue, complementary one about half of the wheel away, and accents anywhere between
+/// them on both sides. Default strategy.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Classic;
+
+/// Spacing of hues of `Classic` strategy - complementary hue is `complementary_offset` plus less
+/// than `complementary_spread` degrees from primary one, and accents stay more than twice the
+/// `margin` before the next hue clockwise. Defaults give the same schemes as `Classic`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchemeParams {
+    complementary_offset: u32,
+    complementary_spread: u32,
+    margin: u32,
+}
+
+impl Default for SchemeParams {
+    fn default() -> Self {
+        Self {
+            complementary_offset: 165,
+            complementary_spread: 30,
+            margin: 5,
+        }
+    }
+}
+
+impl SchemeParams {
+    /// Params leaving room for accents on both sides of complementary hue, for any hash.
+    pub fn new(
+        complementary_offset: u32,
+        complementary_spread: u32,
+        margin: u32,
+    ) -> Result<Self, &'static str> {
+        let margins = margin.saturating_mul(2);
+        if complementary_spread == 0 {
+            return Err("Spread of complementary hue must be at least 1 degree");
+        }
+        if complementary_offset <= margins {
+            return Err("Complementary offset must be more than twice the margin");
+        }
+        if complementary_offset
+            .saturating_add(complementary_spread)
+            .saturating_add(margins)
+            > 360
+        {
+            return Err(
+                "Complementary offset and spread with twice the margin must fit in 360 degrees",
+            );
+        }
+        Ok(Self {
+            complementary_offset,
+            complementary_spread,
+            margin,
+        })
+    }
+
+    pub fn complementary_offset(&self) -> u32 {
+        self.complementary_offset
+    }
+
+    pub fn complementary_spread(&self) -> u32 {
+        self.complementary_spread
+    }
+
+    pub fn margin(&self) -> u32 {
+        self.margin
+    }
+}
+
+/// Accents about a third of the wheel away from primary hue, on both sides, and complementary
+/// hue opposite to it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Triadic;
+
+/// Two pairs of opposite hues - primary and complementary one, and accents turned 30 to 90
+/// degrees from them.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Tetradic;
+
+/// Accents close to primary hue (15 to 45 degrees away, on both sides), and complementary hue
+/// about half of the wheel away.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Analogous;
+
+impl HueStrategy for SchemeParams {
+    fn hues(&self, hash: &[u8]) -> Hues {
+        let margin = self.margin;
+        let primary = hash_modulo(hash, 360);
+        let complementary =
+            primary + self.complementary_offset + hash_modulo(hash, self.complementary_spread);
+        let first_accent =
+            primary + hash_modulo(hash, (complementary - margin) - (primary + margin));
+        let second_accent =
+            complementary + hash_modulo(hash, (primary + 360 - margin) - (complementary + margin));
+        normalized(primary, first_accent, second_accent, complementary)
+    }
+}
+
+impl HueStrategy for Classic {
+    fn hues(&self, hash: &[u8]) -> Hues {
+        SchemeParams::default().hues(hash)
+    }
+}
+
+impl HueStrategy for Triadic {
+    fn hues(&self, hash: &[u8]) -> Hues {
+        let primary = hash_modulo(hash, 360);
+        normalized(
+            primary,
+            primary + 110 + hash_modulo(hash, 21),
+            primary + 229 + hash_modulo(hash, 23),
+            primary + 175 + hash_modulo(hash, 11),
+        )
+    }
+}
+
+impl HueStrategy for Tetradic {
+    fn hues(&self, hash: &[u8]) -> Hues {
+        let primary = hash_modulo(hash, 360);
+        let turn = 30 + hash_modulo(hash, 61);
+        normalized(primary, primary + turn, primary + 180 + turn, primary + 180)
+    }
+}
+
+impl HueStrategy for Analogous {
+    fn hues(&self, hash: &[u8]) -> Hues {
+        let primary = hash_modulo(hash, 360);
+        normalized(
+            primary,
+            primary + 15 + hash_modulo(hash, 31),
+            primary + 345 - hash_modulo(hash, 29),
+            primary + 165 + hash_modulo(hash, 30),
+        )
+    }
+}
+
+/// Hues can go over 360 while they are calculated.
+fn normalized(primary: u32, first_accent: u32, second_accent: u32, complementary: u32) -> Hues {
+    Hues {
+        primary: primary % 360,
+        first_accent: first_accent % 360,
+        second_accent: second_accent % 360,
+        complementary: complementary % 360,
+    }
+}
+
+/// Hues of the scheme by default (`Classic`) strategy.
+pub fn hues_from_hash(hash: &[u8]) -> Hues {
+    Classic.hues(hash)
+}
+
+/// Saturation and value (brightness) of colors of the scheme, in percent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Shade {
+    pub saturation: u32,
+    pub value: u32,
+}
+
+/// The same for all schemes of version 1.
+impl Default for Shade {
+    fn default() -> Self {
+        Self {
+            saturation: 70,
+            value: 90,
+        }
+    }
+}
+
+/// Shade of schemes of version 2 - saturation from 55 to 85 percent and value from 75 to 95
+/// percent, so colors stay vivid and light enough for black text. They come from halves of the
+/// hash, independently of hues.
+pub fn shade_from_hash(hash: &[u8]) -> Shade {
+    let (first, second) = hash.split_at(hash.len() / 2);
+    Shade {
+        saturation: 55 + hash_modulo(first, 31),
+        value: 75 + hash_modulo(second, 21),
+    }
+}
+
+pub fn calculate_hues(substance: &str) -> Hues {
+    hues_from_hash(&substance_hash(substance))
+}
@@ -0,0 +1,313 @@
+use crate::layouts::Scene;
+use crate::short_id;
+use crate::svg::escape;
+use crate::timing::{measure, Stage};
+use base64::Engine;
+use image::codecs::webp::WebPEncoder;
+use image::RgbaImage;
+use little_exif::exif_tag::{ExifTag, ExifTagGroup};
+use little_exif::filetype::FileExtension;
+use little_exif::metadata::Metadata;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+const METERS_PER_INCH: f64 = 0.0254;
+
+/// Size presets for common label formats. Sizes are chosen so swatch of mixture (with bar)
+/// fits printable area of the label at preset resolution, except the icon.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    /// Avery 5160 address labels (1" x 2 5/8"), 300 DPI.
+    #[value(name = "avery-5160")]
+    Avery5160,
+    /// Icon for screens, single substance is 511 pixels square (the largest swatch fitting in
+    /// 512, as its sizes are always odd). Mixtures are wider and taller, by their bar.
+    #[value(name = "icon")]
+    Icon,
+    /// Direct thermal printers with 2" wide labels, 203 DPI.
+    #[value(name = "thermal-2in")]
+    Thermal2In,
+}
+
+impl Preset {
+    pub fn base_size(&self) -> u32 {
+        match self {
+            Preset::Avery5160 => 100,
+            Preset::Icon => 250,
+            Preset::Thermal2In => 150,
+        }
+    }
+
+    /// Border size in percent points of base size.
+    pub fn border_size(&self) -> u32 {
+        match self {
+            Preset::Avery5160 => 2,
+            Preset::Icon => 1,
+            Preset::Thermal2In => 2,
+        }
+    }
+
+    pub fn dpi(&self) -> u32 {
+        match self {
+            Preset::Avery5160 => 300,
+            Preset::Icon => 72,
+            Preset::Thermal2In => 203,
+        }
+    }
+}
+
+/// Format of saved images.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FileFormat {
+    #[default]
+    Png,
+    /// Lossless WebP. It has neither metadata nor resolution.
+    Webp,
+}
+
+impl FileFormat {
+    /// Format of file with given path, by its extension.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = Path::new(path).extension()?.to_str()?;
+        match extension.to_ascii_lowercase().as_str() {
+            "png" => Some(FileFormat::Png),
+            "webp" => Some(FileFormat::Webp),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            FileFormat::Png => "png",
+            FileFormat::Webp => "webp",
+        }
+    }
+}
+
+/// EXIF tag holding description of the image (the substance).
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PayloadTag {
+    /// Shown as caption by most image viewers.
+    #[default]
+    ImageDescription,
+    /// For pipelines reserving `ImageDescription` for human captions.
+    UserComment,
+    /// Comment shown by Windows Explorer.
+    XpComment,
+}
+
+/// Text stored in EXIF metadata of the image, with the tag holding it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Description<'a> {
+    pub text: &'a str,
+    pub tag: PayloadTag,
+}
+
+impl<'a> From<&'a str> for Description<'a> {
+    fn from(text: &'a str) -> Self {
+        Self {
+            text,
+            tag: PayloadTag::default(),
+        }
+    }
+}
+
+impl Description<'_> {
+    /// Tags of the description, with version of moleco in `Software` tag.
+    fn exif_tags(&self) -> [ExifTag; 2] {
+        let payload = match self.tag {
+            PayloadTag::ImageDescription => ExifTag::ImageDescription(self.text.to_string()),
+            // First 8 bytes are character code, `little_exif` writes little endian
+            PayloadTag::UserComment => ExifTag::UserComment(match self.text.is_ascii() {
+                true => [b"ASCII\0\0\0", self.text.as_bytes()].concat(),
+                false => [b"UNICODE\0".to_vec(), utf16_le(self.text)].concat(),
+            }),
+            // Windows XP tags are always UTF-16 little endian, null terminated
+            PayloadTag::XpComment => ExifTag::UnknownINT8U(
+                [utf16_le(self.text), vec![0, 0]].concat(),
+                0x9c9c,
+                ExifTagGroup::IFD0,
+            ),
+        };
+        [
+            payload,
+            ExifTag::Software(format!("moleco {}", env!("CARGO_PKG_VERSION"))),
+        ]
+    }
+}
+
+fn utf16_le(text: &str) -> Vec<u8> {
+    text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+/// Encode picture as PNG. Description (substance) is stored in EXIF, and resolution in `pHYs`
+/// chunk, so print software can scale picture to intended physical size. Without description,
+/// no EXIF is written at all.
+pub fn encode_png<W: Write>(
+    buffer: &RgbaImage,
+    writer: W,
+    description: Option<Description>,
+    dpi: Option<u32>,
+) -> Result<(), String> {
+    // Writer is dropped (and flushed) within measured time
+    measure(Stage::Encoding, || {
+        png_writer(writer, buffer.width(), buffer.height(), description, dpi)
+            .and_then(|mut writer| writer.write_image_data(buffer.as_raw()))
+            .map_err(|e| format!("Can't encode PNG - {}", e))
+    })
+}
+
+/// Encode picture as lossless WebP.
+pub fn encode_webp<W: Write>(buffer: &RgbaImage, writer: W) -> Result<(), String> {
+    measure(Stage::Encoding, || {
+        WebPEncoder::new_lossless(writer)
+            .encode(
+                buffer.as_raw(),
+                buffer.width(),
+                buffer.height(),
+                image::ColorType::Rgba8,
+            )
+            .map_err(|e| format!("Can't encode WebP - {}", e))
+    })
+}
+
+/// Encode picture in given format. Description and resolution are kept only by formats which
+/// can store them.
+pub fn encode_image<W: Write>(
+    buffer: &RgbaImage,
+    writer: W,
+    format: FileFormat,
+    description: Option<Description>,
+    dpi: Option<u32>,
+) -> Result<(), String> {
+    match format {
+        FileFormat::Png => encode_png(buffer, writer, description, dpi),
+        FileFormat::Webp => encode_webp(buffer, writer),
+    }
+}
+
+/// PNG writer with header (and metadata) of the image already written.
+fn png_writer<W: Write>(
+    writer: W,
+    width: u32,
+    height: u32,
+    description: Option<Description>,
+    dpi: Option<u32>,
+) -> Result<png::Writer<W>, png::EncodingError> {
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    if let Some(dpi) = dpi {
+        let pixels_per_meter = (dpi as f64 / METERS_PER_INCH).round() as u32;
+        encoder.set_pixel_dims(Some(png::PixelDimensions {
+            xppu: pixels_per_meter,
+            yppu: pixels_per_meter,
+            unit: png::Unit::Meter,
+        }));
+    }
+
+    // EXIF is written the way `little_exif` does, but directly - it can't edit files with
+    // `pHYs` chunk.
+    let exif = description.map(|description| {
+        let mut metadata = Metadata::new();
+        for tag in description.exif_tags() {
+            metadata.set_tag(tag);
+        }
+        metadata.as_u8_vec(FileExtension::PNG {
+            as_zTXt_chunk: true,
+        })
+    });
+
+    let mut writer = encoder.write_header()?;
+    if let Some(exif) = exif {
+        let (chunk_type, data) = exif.split_first_chunk::<4>().ok_or_else(|| {
+            png::EncodingError::IoError(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "EXIF chunk has no type",
+            ))
+        })?;
+        writer.write_chunk(png::chunk::ChunkType(*chunk_type), data)?;
+    }
+    Ok(writer)
+}
+
+/// Encode scene as PNG band by band, rasterizing each band just before it's encoded - only one
+/// band of pixels is in memory at a time, so even poster-size pictures can be saved.
+pub fn encode_png_in_bands<W: Write>(
+    scene: &Scene,
+    writer: W,
+    description: Option<Description>,
+    dpi: Option<u32>,
+    band_height: u32,
+) -> Result<(), String> {
+    let error = |e: png::EncodingError| format!("Can't encode PNG - {}", e);
+    let mut writer = measure(Stage::Encoding, || {
+        png_writer(writer, scene.width, scene.height, description, dpi)
+    })
+    .map_err(error)?;
+    let mut stream = writer.stream_writer().map_err(error)?;
+    let mut bands = scene.bands(band_height);
+    while let Some(band) = measure(Stage::Rasterization, || bands.next()) {
+        measure(Stage::Encoding, || stream.write_all(band.as_raw()))
+            .map_err(|e| format!("Can't encode PNG - {}", e))?;
+    }
+    measure(Stage::Encoding, || stream.finish()).map_err(error)?;
+    measure(Stage::Encoding, || writer.finish()).map_err(error)
+}
+
+pub fn save_png(
+    buffer: &RgbaImage,
+    path: &Path,
+    description: Option<Description>,
+    dpi: Option<u32>,
+) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("Can't create {:?} - {}", path, e))?;
+    encode_png(buffer, BufWriter::new(file), description, dpi)
+}
+
+pub fn save_png_in_bands(
+    scene: &Scene,
+    path: &Path,
+    description: Option<Description>,
+    dpi: Option<u32>,
+    band_height: u32,
+) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("Can't create {:?} - {}", path, e))?;
+    encode_png_in_bands(scene, BufWriter::new(file), description, dpi, band_height)
+}
+
+/// Encode picture as `data:` URI, so it can be inlined in HTML (`<img src="...">`) or emails.
+pub fn data_uri(
+    buffer: &RgbaImage,
+    description: Option<Description>,
+    dpi: Option<u32>,
+) -> Result<String, String> {
+    let mut bytes = vec![];
+    encode_png(buffer, &mut bytes, description, dpi)?;
+    Ok(format!(
+        "data:image/png;base64,{}",
+        base64::engine::general_purpose::STANDARD.encode(bytes)
+    ))
+}
+
+/// Text alternative of picture of the substance, with its short identifier.
+pub fn alt_text(substance: &str) -> String {
+    format!("Color code {} of {}", short_id(substance), substance)
+}
+
+/// HTML `<img>` with picture inlined as `data:` URI and alt text, ready to paste into wikis or
+/// electronic lab notebooks. It is valid JSX as well.
+pub fn html_img(
+    buffer: &RgbaImage,
+    description: Description,
+    dpi: Option<u32>,
+) -> Result<String, String> {
+    Ok(format!(
+        "<img src=\"{}\" width=\"{}\" height=\"{}\" alt=\"{}\"/>",
+        data_uri(buffer, Some(description), dpi)?,
+        buffer.width(),
+        buffer.height(),
+        escape(&alt_text(description.text))
+    ))
+}
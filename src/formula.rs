@@ -0,0 +1,201 @@
+use std::collections::BTreeMap;
+
+/// Symbols of all elements, in order of atomic numbers.
+pub const ELEMENTS: [&str; 118] = [
+    "H", "He", "Li", "Be", "B", "C", "N", "O", "F", "Ne", "Na", "Mg", "Al", "Si", "P", "S", "Cl",
+    "Ar", "K", "Ca", "Sc", "Ti", "V", "Cr", "Mn", "Fe", "Co", "Ni", "Cu", "Zn", "Ga", "Ge", "As",
+    "Se", "Br", "Kr", "Rb", "Sr", "Y", "Zr", "Nb", "Mo", "Tc", "Ru", "Rh", "Pd", "Ag", "Cd", "In",
+    "Sn", "Sb", "Te", "I", "Xe", "Cs", "Ba", "La", "Ce", "Pr", "Nd", "Pm", "Sm", "Eu", "Gd", "Tb",
+    "Dy", "Ho", "Er", "Tm", "Yb", "Lu", "Hf", "Ta", "W", "Re", "Os", "Ir", "Pt", "Au", "Hg", "Tl",
+    "Pb", "Bi", "Po", "At", "Rn", "Fr", "Ra", "Ac", "Th", "Pa", "U", "Np", "Pu", "Am", "Cm", "Bk",
+    "Cf", "Es", "Fm", "Md", "No", "Lr", "Rf", "Db", "Sg", "Bh", "Hs", "Mt", "Ds", "Rg", "Cn", "Nh",
+    "Fl", "Mc", "Lv", "Ts", "Og",
+];
+
+/// Standard atomic weights (in g/mol) of elements, in the same order as `ELEMENTS`.
+/// For elements without stable isotopes mass number of the longest-lived isotope is used.
+pub const ATOMIC_MASSES: [f64; 118] = [
+    1.008, 4.0026, 6.94, 9.0122, 10.81, 12.011, 14.007, 15.999, 18.998, 20.180, 22.990, 24.305,
+    26.982, 28.085, 30.974, 32.06, 35.45, 39.95, 39.098, 40.078, 44.956, 47.867, 50.942, 51.996,
+    54.938, 55.845, 58.933, 58.693, 63.546, 65.38, 69.723, 72.630, 74.922, 78.971, 79.904, 83.798,
+    85.468, 87.62, 88.906, 91.224, 92.906, 95.95, 97.0, 101.07, 102.91, 106.42, 107.87, 112.41,
+    114.82, 118.71, 121.76, 127.60, 126.90, 131.29, 132.91, 137.33, 138.91, 140.12, 140.91, 144.24,
+    145.0, 150.36, 151.96, 157.25, 158.93, 162.50, 164.93, 167.26, 168.93, 173.05, 174.97, 178.49,
+    180.95, 183.84, 186.21, 190.23, 192.22, 195.08, 196.97, 200.59, 204.38, 207.2, 208.98, 209.0,
+    210.0, 222.0, 223.0, 226.0, 227.0, 232.04, 231.04, 238.03, 237.0, 244.0, 243.0, 247.0, 247.0,
+    251.0, 252.0, 257.0, 258.0, 259.0, 266.0, 267.0, 268.0, 269.0, 270.0, 269.0, 278.0, 281.0,
+    282.0, 285.0, 286.0, 289.0, 290.0, 293.0, 294.0, 294.0,
+];
+
+/// Single dot-separated part of formula, like `2H2O` in `Ca.H3O4P.2H2O`.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct FormulaPart {
+    pub multiplier: usize,
+    pub elements: BTreeMap<String, usize>,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Default)]
+pub struct Formula {
+    pub parts: Vec<FormulaPart>,
+}
+
+impl Formula {
+    /// Total number of atoms of each element, multipliers included.
+    /// Fails when the total does not fit in `usize`.
+    pub fn element_counts(&self) -> Result<BTreeMap<String, usize>, String> {
+        let mut result = BTreeMap::new();
+        for part in &self.parts {
+            for (element, count) in &part.elements {
+                let total = result.entry(element.clone()).or_insert(0usize);
+                *total = count
+                    .checked_mul(part.multiplier)
+                    .and_then(|atoms| total.checked_add(atoms))
+                    .ok_or_else(|| format!("Invalid formula, number too big - {:?}", element))?;
+            }
+        }
+        Ok(result)
+    }
+
+    pub fn count(&self, element: &str) -> Result<usize, String> {
+        Ok(self.element_counts()?.get(element).copied().unwrap_or(0))
+    }
+
+    /// Sum of atomic masses, in g/mol.
+    pub fn molecular_weight(&self) -> Result<f64, String> {
+        Ok(self
+            .element_counts()?
+            .iter()
+            .map(|(element, count)| atomic_mass(element).unwrap_or(0f64) * *count as f64)
+            .sum())
+    }
+}
+
+pub fn atomic_mass(element: &str) -> Option<f64> {
+    ELEMENTS
+        .iter()
+        .position(|symbol| *symbol == element)
+        .map(|index| ATOMIC_MASSES[index])
+}
+
+/// Calculate molecular weight (in g/mol) of InChI, including protons added or removed
+/// in the protonation (`/p`) layer. Weights are of natural abundance of isotopes, so isotopically
+/// labelled substances (with `/i` layer) are rejected, as well as MInChI - weights of mixtures
+/// are those of their components.
+pub fn molecular_weight(inchi: &str) -> Result<f64, String> {
+    if inchi.starts_with("MInChI=") {
+        return Err(format!(
+            "Mixture has no molecular weight, weigh its components instead - {:?}",
+            inchi
+        ));
+    }
+    if inchi.split('/').any(|layer| layer.starts_with('i')) {
+        return Err(format!(
+            "Isotopic layer is not supported for molecular weight - {:?}",
+            inchi
+        ));
+    }
+    let formula = parse_inchi_formula(inchi)?;
+    let protons = match inchi.split('/').find(|layer| layer.starts_with('p')) {
+        Some(layer) => layer[1..]
+            .parse::<i64>()
+            .map_err(|_| format!("Invalid protonation layer - {:?}", layer))?,
+        None => 0,
+    };
+    Ok(formula.molecular_weight()? + protons as f64 * ATOMIC_MASSES[0])
+}
+
+/// Extract formula layer from InChI - the first layer after version, so `C8H10N4O2` for caffeine.
+/// Molecules taken from MInChI (without `InChI=` prefix and version) are accepted as well.
+/// Some substances (like proton - `InChI=1S/p+1`) have no formula, then empty layer is returned.
+pub fn formula_layer(inchi: &str) -> Result<&str, String> {
+    let layers = match inchi.strip_prefix("InChI=") {
+        Some(rest) => match rest.split_once('/') {
+            Some((_, layers)) => layers,
+            None => return Err(format!("InChI has no layers - {:?}", inchi)),
+        },
+        None => inchi,
+    };
+    let layer = layers.split('/').next().unwrap_or("");
+    match layer.chars().next() {
+        Some(c) if c.is_ascii_lowercase() => Ok(""),
+        _ => Ok(layer),
+    }
+}
+
+/// Parse formula of InChI (see `formula_layer`).
+pub fn parse_inchi_formula(inchi: &str) -> Result<Formula, String> {
+    parse_formula(formula_layer(inchi)?)
+}
+
+/// Parse formula in InChI notation, like `C12H26O4S.Na` or `Ca.H3O4P.2H2O`.
+pub fn parse_formula(formula: &str) -> Result<Formula, String> {
+    let mut result = Formula::default();
+    if formula.is_empty() {
+        return Ok(result);
+    }
+    for part in formula.split('.') {
+        result.parts.push(parse_part(part)?);
+    }
+    Ok(result)
+}
+
+fn parse_part(part: &str) -> Result<FormulaPart, String> {
+    let mut chars = part.chars().peekable();
+    let multiplier = match take_number(&mut chars, part)? {
+        Some(0) => return Err(format!("Invalid formula, zero multiplier - {:?}", part)),
+        Some(multiplier) => multiplier,
+        None => 1,
+    };
+
+    let mut elements = BTreeMap::new();
+    while let Some(c) = chars.next() {
+        if !c.is_ascii_uppercase() {
+            return Err(format!(
+                "Invalid formula, unexpected character {:?} - {:?}",
+                c, part
+            ));
+        }
+        let mut symbol = c.to_string();
+        while let Some(c) = chars.peek().filter(|c| c.is_ascii_lowercase()) {
+            symbol.push(*c);
+            chars.next();
+        }
+        if !ELEMENTS.contains(&symbol.as_str()) {
+            return Err(format!(
+                "Invalid formula, unknown element {:?} - {:?}",
+                symbol, part
+            ));
+        }
+        let count = take_number(&mut chars, part)?.unwrap_or(1);
+        let total = elements.entry(symbol).or_insert(0usize);
+        *total = total
+            .checked_add(count)
+            .ok_or_else(|| format!("Invalid formula, number too big - {:?}", part))?;
+    }
+
+    if elements.is_empty() {
+        return Err(format!("Invalid formula, empty part - {:?}", part));
+    }
+    Ok(FormulaPart {
+        multiplier,
+        elements,
+    })
+}
+
+fn take_number(
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+    part: &str,
+) -> Result<Option<usize>, String> {
+    let mut digits = String::new();
+    while let Some(c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+        digits.push(*c);
+        chars.next();
+    }
+    if digits.is_empty() {
+        return Ok(None);
+    }
+    match digits.parse::<usize>() {
+        Ok(number) => Ok(Some(number)),
+        Err(_) => Err(format!("Invalid formula, number too big - {:?}", part)),
+    }
+}
@@ -1,9 +1,15 @@
-use crate::tokenize::{Capacity, Concentration, Content, Ingredient, Mixture};
-use crate::Scheme;
+use crate::text::{blend, TextRenderer};
+use crate::timing::{measure, Stage};
+use crate::tokenize::{
+    check_ranges, is_known_index, Capacity, Concentration, Content, Ingredient, MagnitudeError,
+    Mixture, Warning, WarningKind,
+};
+use crate::{contrast_ratio, Scheme};
 use image::{ImageBuffer, Rgba};
 use log::debug;
 use palette::{FromColor, Hsv, Srgba};
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 
 macro_rules! vertical_square {
     ($shapes: expr, $size: expr, $point: expr, $color: expr) => {
@@ -13,18 +19,23 @@ macro_rules! vertical_square {
             size: $size,
             orientation: Orientation::Vertical,
             color: $color.into(),
+            blend: Blend::Over,
         }));
     };
 }
 
 macro_rules! horizontal_square {
     ($shapes: expr, $size: expr, $point: expr, $color: expr) => {
+        horizontal_square!($shapes, $size, $point, $color, Blend::Over)
+    };
+    ($shapes: expr, $size: expr, $point: expr, $color: expr, $blend: expr) => {
         $shapes.push(Shape::Square(Square {
             x: $point.x,
             y: $point.y,
             size: $size,
             orientation: Orientation::Horizontal,
             color: $color.into(),
+            blend: $blend,
         }));
     };
 }
@@ -38,6 +49,7 @@ macro_rules! line {
             y2: $end.y,
             border_size: $size,
             color: $color.into(),
+            blend: Blend::Over,
         }));
     };
     ($shapes: expr, $start_x: expr, $start_y: expr, $end_x: expr, $end_y: expr, $size: expr, $color: expr) => {
@@ -48,6 +60,7 @@ macro_rules! line {
             y2: $end_y,
             border_size: $size,
             color: $color.into(),
+            blend: Blend::Over,
         }));
     };
 }
@@ -58,25 +71,729 @@ macro_rules! line_color {
     };
 }
 
+macro_rules! light_line_color {
+    () => {
+        Srgba::from_color(Hsv::new(0.0, 0.0, 0.95)).into_format()
+    };
+}
+
 macro_rules! unknown_color {
     () => {
         Srgba::from_color(Hsv::new(0.0, 0.0, 0.8)).into_format()
     };
 }
 
+/// Fill pattern of shapes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Pattern {
+    #[default]
+    Solid,
+    /// Diagonal stripes, gaps are left transparent.
+    Hatched,
+    /// Grid of square dots, gaps are left transparent.
+    Dotted,
+}
+
+/// How shape is combined with pixels already drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Blend {
+    /// Shape is drawn over the picture, translucent colors let what is below show through.
+    #[default]
+    Over,
+    /// Pixels of the shape are reset to the background of the scene, cutting out everything
+    /// drawn before. Color of the shape doesn't matter, but it should be the background color,
+    /// so renderers without blend modes draw it the same way (on opaque background).
+    Clear,
+}
+
+/// How "open" end of the mixture bar (which indicates unestimated capacity) is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OpenEnd {
+    /// Notch cut in the end of the bar.
+    #[default]
+    ZigZag,
+    /// End of the bar fades out to transparency.
+    Fade,
+    /// End of the bar is closed with dotted line.
+    Dotted,
+    /// End of the bar is shaped as arrowhead.
+    Arrow,
+}
+
+/// Shape of the swatch of single substance.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Geometry {
+    /// Four diamonds forming kite, with cutouts.
+    #[default]
+    Kite,
+    /// Circle split into four quadrants, for round badges.
+    Circle,
+}
+
+/// Which cutouts are made in swatches.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Cutouts {
+    /// Central cutout, and in kite also cutouts where its diamonds meet.
+    #[default]
+    All,
+    /// Only the central cutout.
+    Center,
+    /// Solid swatch without holes, for small sizes (like favicons) where cutouts are just noise.
+    None,
+}
+
+/// Where orientation mark is drawn in swatches, so rotated ones can be recognized.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MarkPosition {
+    #[default]
+    Bottom,
+    Top,
+    Left,
+    Right,
+    /// No mark, for pictures rotated or cropped where they are embedded.
+    None,
+}
+
+/// Shape of orientation mark.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MarkShape {
+    /// Short line across the swatch.
+    #[default]
+    Line,
+    /// Dot in the middle of where the line would be.
+    Dot,
+}
+
+/// Direction of stereochemistry marker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stereo {
+    /// Triangle pointing up.
+    Normal,
+    /// Triangle pointing down, for inverted absolute configuration (`/m1`).
+    Inverted,
+}
+
+/// Charge badge, based on charge and protonation layers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Charge {
+    /// Plus sign.
+    Positive,
+    /// Minus sign.
+    Negative,
+    /// Plus-minus sign, for charged components with total charge zero (like salts).
+    Neutralized,
+}
+
+/// Features of the substance that can be flagged with markers in corners of its swatch,
+/// detected from InChI layers. Colors differ anyway, but markers are easier to notice.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Markers {
+    pub stereo: Option<Stereo>,
+    pub charge: Option<Charge>,
+    /// Substance is isotopically labelled.
+    pub isotopes: bool,
+}
+
+/// Visual options of the picture. None of them changes colors of the substance itself, so they can
+/// be adjusted to match house style of labels.
+#[derive(Debug, Clone)]
+pub struct Style {
+    /// Color of mixture bar segment representing unknown substances.
+    pub unknown_color: Srgba<u8>,
+    /// Fill pattern of unknown segment.
+    pub unknown_pattern: Pattern,
+    /// Minimal size of unknown segment. This value is relative (same as widths of other segments
+    /// before logarithm is applied, where the smallest known segment is at least 10), and it is
+    /// also used as size of unknown segment added to indicate unestimated capacity.
+    pub unknown_min_width: f32,
+    /// Style of the end of mixture bar, when capacity is unestimated.
+    pub open_end: OpenEnd,
+    /// Draw triangle in top left corner of swatches of substances with stereochemistry.
+    pub stereo_marker: bool,
+    /// Draw charge badge in top right corner of swatches of charged substances (and salts).
+    pub charge_marker: bool,
+    /// Draw dots in bottom left corner of swatches of isotopically labelled substances.
+    pub isotope_marker: bool,
+    /// Shape of swatches.
+    pub geometry: Geometry,
+    /// Color of all borders and lines.
+    pub border_color: Srgba<u8>,
+    /// Color of the background, visible in cutouts and corners of cells as well.
+    pub background: Srgba<u8>,
+    /// Size of kite cutouts relative to the default one, `0` removes cutouts. Up to `2`.
+    pub cutout_scale: f32,
+    pub cutouts: Cutouts,
+    /// Color filling cutouts. By default they are transparent, showing the background.
+    pub cutout_fill: Option<Srgba<u8>>,
+    pub orientation_mark: MarkPosition,
+    pub mark_shape: MarkShape,
+    /// Radius of rounded corners of the whole picture in pixels, they are transparent. `0`
+    /// keeps corners square.
+    pub corner_radius: u32,
+    /// Draw rectangular frame (of border color and size) around the picture, with given gap in
+    /// pixels between it and edges of the picture, and between it and swatches. By default
+    /// there is no frame.
+    pub frame_inset: Option<u32>,
+    /// Gap between cells of substances, in pixels. By default cells share their borders.
+    pub spacing: u32,
+    /// Draw components without width in the bar (like additions beyond 100%) as thin hatched
+    /// markers after other components. By default they are left out.
+    pub zero_width_markers: bool,
+    /// Color of lines between segments of mixture bar, border color by default.
+    pub divider_color: Option<Srgba<u8>>,
+    /// Width of lines between segments of mixture bar in pixels (odd, even ones are rounded
+    /// down), border size by default. `0` removes them, so segments touch each other.
+    pub divider_width: Option<u32>,
+    /// Minimal contrast ratio (WCAG, 1 to 21) of swatch borders with its colors. Swatches whose
+    /// colors are too close to border color get the most contrasting of border color, near-black
+    /// and near-white borders instead. By default border color is always used.
+    pub min_border_contrast: Option<f64>,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            unknown_color: unknown_color!(),
+            unknown_pattern: Pattern::Solid,
+            // Chosen by fair dice roll.
+            unknown_min_width: 4f32,
+            open_end: OpenEnd::ZigZag,
+            stereo_marker: false,
+            charge_marker: false,
+            isotope_marker: false,
+            geometry: Geometry::Kite,
+            border_color: line_color!(),
+            background: Srgba::new(0, 0, 0, 0),
+            cutout_scale: 1f32,
+            cutouts: Cutouts::All,
+            cutout_fill: None,
+            orientation_mark: MarkPosition::Bottom,
+            mark_shape: MarkShape::Line,
+            corner_radius: 0,
+            frame_inset: None,
+            spacing: 0,
+            zero_width_markers: false,
+            divider_color: None,
+            divider_width: None,
+            min_border_contrast: None,
+        }
+    }
+}
+
+/// Z-order of built-in layers. Values are spaced, so custom layers (like watermarks or
+/// highlights) can be put between them.
+pub mod z {
+    pub const BAR: i32 = 0;
+    pub const BAR_LINES: i32 = 100;
+    pub const COLORS: i32 = 200;
+    pub const LINES: i32 = 300;
+    pub const CUTOUTS: i32 = 400;
+    /// Orientation marks and borders of cutouts.
+    pub const DETAILS: i32 = 500;
+    pub const MARKERS: i32 = 600;
+    pub const FRAME: i32 = 700;
+}
+
+/// Named layer of shapes. Layers are drawn from the lowest `z`, layers with the same `z` in
+/// order they were added - later ones cover earlier ones.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "LayerFormat")]
+pub struct Layer {
+    pub name: String,
+    pub z: i32,
+    pub shapes: Vec<Shape>,
+}
+
+impl Layer {
+    pub fn new(name: &str, z: i32) -> Layer {
+        Layer {
+            name: name.to_string(),
+            z,
+            shapes: Vec::new(),
+        }
+    }
+
+    pub fn with_shapes(self, shapes: Vec<Shape>) -> Layer {
+        Layer { shapes, ..self }
+    }
+}
+
+/// Scenes saved by older versions have layers as plain lists of shapes.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LayerFormat {
+    Named {
+        #[serde(default)]
+        name: String,
+        #[serde(default)]
+        z: i32,
+        shapes: Vec<Shape>,
+    },
+    Shapes(Vec<Shape>),
+}
+
+impl From<LayerFormat> for Layer {
+    fn from(format: LayerFormat) -> Layer {
+        match format {
+            LayerFormat::Named { name, z, shapes } => Layer { name, z, shapes },
+            LayerFormat::Shapes(shapes) => Layer::new("", 0).with_shapes(shapes),
+        }
+    }
+}
+
+/// Position and sizes of the cell of single substance, passed to layouts.
+/// Smaller sizes are derived from base size in a way that keeps drawing pixel perfect.
+#[derive(Debug, Clone)]
+pub struct CellGeometry {
+    /// Horizontal position of the cell in the picture.
+    pub offset: u32,
+    pub base_size: u32,
+    pub border_size: u32,
+    pub half_border: u32,
+    pub half_size: u32,
+    pub quarter_size: u32,
+    pub eight_size: u32,
+    pub border_color: Srgba<u8>,
+    /// Color of the background, for shapes cutting out parts of already drawn ones (with
+    /// `Blend::Clear`).
+    pub eraser: Srgba<u8>,
+    /// Size of kite cutouts (always odd), `0` if cutouts are disabled.
+    pub cutout_size: u32,
+    pub cutouts: Cutouts,
+    pub cutout_fill: Option<Srgba<u8>>,
+    pub orientation_mark: MarkPosition,
+    pub mark_shape: MarkShape,
+}
+
+impl CellGeometry {
+    /// Cells are squares, neighbouring cells share the border.
+    pub fn cell_size(&self) -> u32 {
+        self.base_size * 2 + self.border_size * 3
+    }
+
+    /// Color and blend mode of cutouts - filled ones are painted over, others are cleared to
+    /// the background.
+    pub fn cutout_paint(&self) -> (Srgba<u8>, Blend) {
+        match self.cutout_fill {
+            Some(color) => (color, Blend::Over),
+            None => (self.eraser, Blend::Clear),
+        }
+    }
+
+    /// Shapes of orientation mark. Layouts give it as line in the bottom part of the swatch, and
+    /// it is rotated around swatch center to configured position.
+    pub fn orientation_mark(&self, center: &Point, start: &Point, end: &Point) -> Vec<Shape> {
+        let rotate = |point: &Point| {
+            let dx = point.x as i64 - center.x as i64;
+            let dy = point.y as i64 - center.y as i64;
+            let (dx, dy) = match self.orientation_mark {
+                MarkPosition::Bottom | MarkPosition::None => (dx, dy),
+                MarkPosition::Top => (-dx, -dy),
+                MarkPosition::Left => (-dy, dx),
+                MarkPosition::Right => (dy, -dx),
+            };
+            Point {
+                x: (center.x as i64 + dx) as u32,
+                y: (center.y as i64 + dy) as u32,
+            }
+        };
+        let (start, end) = (rotate(start), rotate(end));
+        let size = self.border_size;
+        let mut shapes = Vec::new();
+        match (self.orientation_mark, self.mark_shape) {
+            (MarkPosition::None, _) => {}
+            (_, MarkShape::Line) => {
+                line!(shapes, start, end, size, self.border_color);
+            }
+            (_, MarkShape::Dot) => {
+                let middle = Point {
+                    x: (start.x + end.x) / 2,
+                    y: (start.y + end.y) / 2,
+                };
+                line!(shapes, middle, middle, size * 3, self.border_color);
+            }
+        }
+        shapes
+    }
+}
+
+/// Design of the swatch of single substance. Implement it and pass to `Picture::with_layout`
+/// to draw your own design. Shapes should fit in the cell (square, see `CellGeometry::cell_size`)
+/// and leave its corners empty, as markers are drawn there. Layers should use `z` of built-in
+/// ones (see `z` module), so they are ordered the same way as in other swatches. Layout is shared
+/// with threads rendering the picture, so it has to be `Send` and `Sync`.
+pub trait Layout: Send + Sync {
+    fn shapes(&self, scheme: &Scheme, geometry: &CellGeometry) -> Vec<Layer>;
+}
+
+/// Default layout, four diamonds forming kite.
+pub struct KiteLayout;
+
+/// Circle split into four quadrants. It occupies the same space as kite, and colors are in the
+/// same places - primary at the top, accents on the sides and complementary at the bottom.
+pub struct CircleLayout;
+
+impl Geometry {
+    /// Built-in layout of given geometry.
+    pub fn layout(&self) -> Box<dyn Layout> {
+        match self {
+            Geometry::Kite => Box::new(KiteLayout),
+            Geometry::Circle => Box::new(CircleLayout),
+        }
+    }
+}
+
+/// The largest scene (in pixels) that is rendered, 16384 x 16384 - 1 GiB of RGBA pixels. Scenes
+/// loaded from files may come from anywhere, so their buffers are never allocated unbounded.
+pub const MAX_SCENE_PIXELS: u64 = 1 << 28;
+
+/// Picture described as layers of shapes, before rasterization. It can be serialized, so external
+/// renderers (like JS canvas) can draw it natively.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Scene {
+    pub width: u32,
+    pub height: u32,
+    pub background: Srgba<u8>,
+    pub layers: Vec<Layer>,
+    /// Radius of rounded corners in pixels, pixels outside of them are transparent.
+    #[serde(default)]
+    pub corner_radius: u32,
+}
+
+impl Scene {
+    /// Layers in order they are drawn.
+    pub fn ordered_layers(&self) -> Vec<&Layer> {
+        let mut layers: Vec<&Layer> = self.layers.iter().collect();
+        layers.sort_by_key(|layer| layer.z);
+        layers
+    }
+
+    /// Check if scene (for example loaded from file) can be rendered - sizes must be positive (and
+    /// within `MAX_SCENE_PIXELS`) and shapes anchored inside the picture.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.width == 0 || self.height == 0 {
+            return Err("Scene must not be empty".to_string());
+        }
+        if self.width as u64 * self.height as u64 > MAX_SCENE_PIXELS {
+            return Err(format!(
+                "Scene of {}x{} pixels is too large, at most {} pixels are rendered",
+                self.width, self.height, MAX_SCENE_PIXELS
+            ));
+        }
+        if self.corner_radius > self.width.min(self.height) / 2 {
+            return Err(format!(
+                "Corner radius {} doesn't fit the scene",
+                self.corner_radius
+            ));
+        }
+        let max_size = self
+            .width
+            .max(self.height)
+            .checked_mul(2)
+            .ok_or("Scene is too large")?;
+        let inside = |x: u32, y: u32| x <= self.width && y <= self.height;
+        let fits = |size: u32| size > 0 && size <= max_size;
+        let shapes = self.layers.iter().flat_map(|layer| &layer.shapes);
+        for (index, shape) in shapes.enumerate() {
+            let valid = match shape {
+                Shape::Square(square) => {
+                    let half_size = square.size.saturating_sub(1) / 2;
+                    inside(square.x, square.y)
+                        && fits(square.size)
+                        && square.x >= half_size
+                        && square.y >= half_size
+                }
+                Shape::Rectangle(rectangle) => {
+                    inside(rectangle.x, rectangle.y)
+                        && rectangle.width <= max_size
+                        && rectangle.height <= max_size
+                }
+                Shape::Line(line) => {
+                    inside(line.x1, line.y1) && inside(line.x2, line.y2) && fits(line.border_size)
+                }
+                Shape::Circle(circle) => {
+                    inside(circle.x, circle.y)
+                        && circle.radius <= max_size
+                        && circle.ring.is_none_or(fits)
+                }
+                Shape::Text(text) => inside(text.x, text.y) && fits(text.size),
+            };
+            if !valid {
+                return Err(format!(
+                    "Invalid shape #{} in scene - {:?}",
+                    index + 1,
+                    shape
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Scale whole scene. Pictures are pixel perfect only in size they were generated in, so
+    /// it's better to generate them again if substance is known.
+    pub fn scaled(&self, factor: f32) -> Scene {
+        let scale = |value: u32| (value as f32 * factor).round() as u32;
+        let scale_size = |value: u32| scale(value).max(1);
+        let layers = self
+            .layers
+            .iter()
+            .map(|layer| Layer {
+                shapes: layer
+                    .shapes
+                    .iter()
+                    .map(|shape| match shape {
+                        Shape::Square(square) => Shape::Square(Square {
+                            x: scale(square.x),
+                            y: scale(square.y),
+                            size: scale_size(square.size),
+                            ..square.clone()
+                        }),
+                        Shape::Rectangle(rectangle) => Shape::Rectangle(Rectangle {
+                            x: scale(rectangle.x),
+                            y: scale(rectangle.y),
+                            width: scale(rectangle.width),
+                            height: scale(rectangle.height),
+                            ..rectangle.clone()
+                        }),
+                        Shape::Line(line) => Shape::Line(Line {
+                            x1: scale(line.x1),
+                            y1: scale(line.y1),
+                            x2: scale(line.x2),
+                            y2: scale(line.y2),
+                            border_size: scale_size(line.border_size),
+                            ..line.clone()
+                        }),
+                        Shape::Circle(circle) => Shape::Circle(Circle {
+                            x: scale(circle.x),
+                            y: scale(circle.y),
+                            radius: scale(circle.radius),
+                            ring: circle.ring.map(scale_size),
+                            ..circle.clone()
+                        }),
+                        Shape::Text(text) => Shape::Text(Text {
+                            x: scale(text.x),
+                            y: scale(text.y),
+                            size: scale_size(text.size),
+                            max_width: text.max_width.map(scale),
+                            ..text.clone()
+                        }),
+                    })
+                    .collect(),
+                ..layer.clone()
+            })
+            .collect();
+        Scene {
+            width: scale_size(self.width),
+            height: scale_size(self.height),
+            background: self.background,
+            layers,
+            corner_radius: scale(self.corner_radius)
+                .min(scale_size(self.width.min(self.height)) / 2),
+        }
+    }
+
+    pub fn render(&self) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        self.render_tile(&mut TextRenderer::new(), self.whole())
+    }
+
+    fn whole(&self) -> Tile {
+        Tile {
+            x: 0,
+            y: 0,
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    /// Rasterize tiles on up to given number of threads, and composite them into the picture.
+    /// Each tile gets every shape reaching into it, so the result is the same as of `render`.
+    /// Parts of the picture not covered by tiles are left empty.
+    pub fn render_tiles(&self, tiles: &[Tile], threads: usize) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let mut buffer =
+            ImageBuffer::from_pixel(self.width, self.height, Rgba(self.background.into()));
+        let per_thread = tiles.len().div_ceil(threads.max(1)).max(1);
+        std::thread::scope(|scope| {
+            let workers: Vec<_> = tiles
+                .chunks(per_thread)
+                .map(|tiles| {
+                    scope.spawn(move || {
+                        let mut text_renderer = TextRenderer::new();
+                        tiles
+                            .iter()
+                            .map(|tile| (*tile, self.render_tile(&mut text_renderer, *tile)))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            for worker in workers {
+                for (tile, image) in worker.join().expect("Rasterization of tiles failed") {
+                    image::imageops::replace(&mut buffer, &image, tile.x as i64, tile.y as i64);
+                }
+            }
+        });
+        buffer
+    }
+
+    /// Rasterize picture in horizontal bands of given height, from top to bottom. Bands are
+    /// rendered only when asked for, so huge pictures never have to fit in memory at once.
+    pub fn bands(
+        &self,
+        band_height: u32,
+    ) -> impl Iterator<Item = ImageBuffer<Rgba<u8>, Vec<u8>>> + '_ {
+        let mut text_renderer = TextRenderer::new();
+        (0..self.height)
+            .step_by(band_height.max(1) as usize)
+            .map(move |top| {
+                let band = Tile {
+                    y: top,
+                    height: band_height.max(1),
+                    ..self.whole()
+                };
+                self.render_tile(&mut text_renderer, band)
+            })
+    }
+
+    /// Rasterize single tile of the picture (cut to the picture, when it reaches beyond it).
+    fn render_tile(
+        &self,
+        text_renderer: &mut TextRenderer,
+        tile: Tile,
+    ) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let width = tile.width.min(self.width.saturating_sub(tile.x));
+        let height = tile.height.min(self.height.saturating_sub(tile.y));
+        let mut buffer = ImageBuffer::from_pixel(width, height, Rgba(self.background.into()));
+        let mut canvas = Canvas {
+            buffer: &mut buffer,
+            left: tile.x,
+            top: tile.y,
+            width: self.width,
+            height: self.height,
+        };
+        for layer in self.ordered_layers() {
+            for shape in &layer.shapes {
+                match shape {
+                    Shape::Square(square) => square.draw(&mut canvas, self.background),
+                    Shape::Line(line) => line.draw(&mut canvas, self.background),
+                    Shape::Rectangle(rectangle) => rectangle.draw(&mut canvas, self.background),
+                    Shape::Circle(circle) => circle.draw(&mut canvas, self.background),
+                    Shape::Text(text) => text.draw(&mut canvas, text_renderer),
+                }
+            }
+        }
+        self.round_corners(&mut canvas);
+        buffer
+    }
+
+    /// Pixels are kept when their centers are inside of the rounded rectangle.
+    fn round_corners(&self, canvas: &mut Canvas) {
+        let radius = self.corner_radius.min(self.width.min(self.height) / 2);
+        if radius == 0 {
+            return;
+        }
+        let limit = radius as f32 * radius as f32;
+        for y in 0..radius {
+            for x in 0..radius {
+                let dx = radius as f32 - x as f32 - 0.5;
+                let dy = radius as f32 - y as f32 - 0.5;
+                if dx * dx + dy * dy <= limit {
+                    continue;
+                }
+                let (right, bottom) = (self.width - 1 - x, self.height - 1 - y);
+                for (x, y) in [(x, y), (right, y), (x, bottom), (right, bottom)] {
+                    if let Some(pixel) = canvas.pixel_mut(x, y) {
+                        *pixel = Rgba([0, 0, 0, 0]);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Part of the mixture bar representing single component.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BarSegment {
+    /// Index of the component in the mixture (starting from 1, as in MInChI), none for unknown
+    /// substances.
+    pub component: Option<usize>,
+    /// Share of the component in the mixture. Bar is scaled logarithmically, so it may differ
+    /// a lot from share of the width.
+    pub fraction: f32,
+    /// First column of the segment, in pixels.
+    pub x: u32,
+    /// Width of the segment, in pixels.
+    pub width: u32,
+    /// Component has no width of its own (like addition beyond capacity), so it is drawn as thin
+    /// hatched marker. See `Style::zero_width_markers`.
+    pub marker: bool,
+}
+
+/// Kind of the part of the picture, see `Region`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RegionKind {
+    Swatch,
+    Segment,
+}
+
+/// Rectangular part of the picture showing single component - its swatch or segment of the
+/// mixture bar. Lets interactive pictures find component under the pointer.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Region {
+    pub kind: RegionKind,
+    /// Index of the component (starting from 1, as in MInChI, so always 1 for InChI), none for
+    /// unknown substances in the bar.
+    pub component: Option<usize>,
+    /// Share of the component in the mixture, none when it is not a mixture.
+    pub fraction: Option<f32>,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
 struct WidthsResult {
     widths: Vec<(String, f32)>,
     unestimated_capacity: bool,
 }
 
+/// Substance or mixture ready to be rendered. Picture holds no interior mutability and it is
+/// `Send` and `Sync`, so prepared pictures can be shared between threads (in `Arc`, for example)
+/// and rendered or described as scene from any of them.
 pub struct Picture {
     base_size: u32,
     border_size: u32,
     schemes: Vec<Scheme>,
     // Indexing and concentration information combined into tree
     mixture_info: Option<Mixture>,
+    style: Style,
+    // Same order as schemes, may be empty if nothing is known
+    markers: Vec<Markers>,
+    // Custom layout, if not set - layout of style geometry is used
+    layout: Option<Box<dyn Layout>>,
+    // Ready scene, when picture was restored from it
+    scene: Option<Scene>,
+    // Threads rasterizing cells and the bar, all available cores if not set
+    threads: Option<usize>,
+    // Non-fatal problems found in the payload
+    warnings: Vec<Warning>,
 }
 
+// Prepared pictures (and whatever they are made of) are shared between threads rendering them
+const _: fn() = || {
+    fn shared_between_threads<T: Send + Sync>() {}
+    shared_between_threads::<Picture>();
+    shared_between_threads::<Scene>();
+    shared_between_threads::<Style>();
+    shared_between_threads::<Mixture>();
+    shared_between_threads::<Box<dyn Layout>>();
+};
+
 impl Picture {
     pub fn new(
         base_size: u32,
@@ -89,15 +806,140 @@ impl Picture {
             border_size,
             schemes,
             mixture_info,
+            style: Style::default(),
+            markers: vec![],
+            layout: None,
+            scene: None,
+            threads: None,
+            warnings: vec![],
+        }
+    }
+
+    /// Restore picture from scene (see `to_scene`). Such picture is always rendered as described
+    /// in the scene, style and layout are ignored.
+    pub fn from_scene(scene: Scene) -> Result<Self, String> {
+        scene.validate()?;
+        let mut picture = Self::new(0, 0, vec![], None);
+        picture.scene = Some(scene);
+        Ok(picture)
+    }
+
+    pub fn with_style(mut self, style: Style) -> Self {
+        self.style = style;
+        self
+    }
+
+    pub fn with_markers(mut self, markers: Vec<Markers>) -> Self {
+        self.markers = markers;
+        self
+    }
+
+    /// Color schemes of substances, in order of the input.
+    pub fn schemes(&self) -> &[Scheme] {
+        &self.schemes
+    }
+
+    pub fn with_warnings(mut self, warnings: Vec<Warning>) -> Self {
+        self.warnings = warnings;
+        self
+    }
+
+    /// Non-fatal problems found in the payload (like index pointing to no component) - picture
+    /// is rendered anyway, but it may be misleading.
+    pub fn warnings(&self) -> Vec<Warning> {
+        self.warnings.clone()
+    }
+
+    /// Simplifications made to draw valid payload (like ranges drawn as single value), for
+    /// information only - unlike warnings, they don't mean anything is wrong with it.
+    pub fn notes(&self) -> Vec<Warning> {
+        let Some(mixture) = &self.mixture_info else {
+            return vec![];
+        };
+        let mut notes = check_ranges(mixture);
+        // Errors are reported when picture is rendered
+        if let Ok((_, _, true)) = self.calculate_mixture_bar(mixture, self.width()) {
+            notes.push(Warning {
+                kind: WarningKind::UnestimatedCapacity,
+                message: "Unestimated capacity, the rest is drawn as unknown substance".to_string(),
+            });
         }
+        notes
     }
 
-    pub fn generate(&mut self) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, String> {
-        let border_color: Srgba<u8> = line_color!();
-        let eraser = Srgba::new(0, 0, 0, 0);
+    /// Use custom layout of swatches, instead of the one selected by style geometry.
+    pub fn with_layout(mut self, layout: Box<dyn Layout>) -> Self {
+        self.layout = Some(layout);
+        self
+    }
+
+    /// Rasterize cells and the bar on at most given number of threads, `1` rasterizes whole
+    /// picture at once. By default all available cores are used.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads.max(1));
+        self
+    }
+
+    /// Rasterize the picture. It only reads the picture, so one prepared picture can be rendered
+    /// by many threads at once.
+    pub fn generate(&self) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, String> {
+        let scene = measure(Stage::ShapeGeneration, || self.to_scene())?;
+        let threads = self.threads.unwrap_or_else(|| {
+            std::thread::available_parallelism().map_or(1, |threads| threads.get())
+        });
+        let tiles = self.tiles(&scene);
+        Ok(measure(Stage::Rasterization, || {
+            match threads > 1 && tiles.len() > 1 {
+                true => scene.render_tiles(&tiles, threads),
+                false => scene.render(),
+            }
+        }))
+    }
+
+    /// Tiles rasterized in parallel - column of each cell and the mixture bar below them. They
+    /// cover the whole scene, so frame and gaps between cells are included too.
+    fn tiles(&self, scene: &Scene) -> Vec<Tile> {
+        // Restored scene may be anything, it is rasterized at once
+        if self.scene.is_some() {
+            return vec![scene.whole()];
+        }
         let cell_size = self.base_size * 2 + self.border_size * 3;
-        let width = cell_size * self.schemes.len() as u32
-            - (self.schemes.len() as u32 - 1) * self.border_size;
+        let cell_step = cell_size - self.border_size + self.style.spacing;
+        let margin = self.margin();
+        let cells_height = (margin + cell_size).min(scene.height);
+        let mut edges: Vec<u32> = (1..self.schemes.len() as u32)
+            .map(|index| margin + index * cell_step)
+            .collect();
+        edges.insert(0, 0);
+        edges.push(scene.width);
+        let mut tiles: Vec<Tile> = edges
+            .windows(2)
+            .map(|edges| Tile {
+                x: edges[0],
+                y: 0,
+                width: edges[1] - edges[0],
+                height: cells_height,
+            })
+            .collect();
+        if cells_height < scene.height {
+            tiles.push(Tile {
+                y: cells_height,
+                height: scene.height - cells_height,
+                ..scene.whole()
+            });
+        }
+        tiles
+    }
+
+    /// Describe picture as shapes, without rasterizing it.
+    pub fn to_scene(&self) -> Result<Scene, String> {
+        if let Some(scene) = &self.scene {
+            return Ok(scene.clone());
+        }
+        let eraser = self.style.background;
+        let cell_size = self.base_size * 2 + self.border_size * 3;
+        let cell_step = cell_size - self.border_size + self.style.spacing;
+        let width = self.width();
 
         // Subtraction is required as generation is pixel perfect and
         // operates on odd sizes - subtraction assures we don't miss pixels in edge cases.
@@ -105,6 +947,13 @@ impl Picture {
         let half_size = (self.base_size - 1) / 2;
         let quarter_size = (half_size - 1) / 2;
         let eight_size = (quarter_size - 1) / 2;
+        let cutout_size = match (
+            self.style.cutouts,
+            self.style.cutout_scale.clamp(0f32, 2f32),
+        ) {
+            (Cutouts::None, _) | (_, 0f32) => 0,
+            (_, scale) => (eight_size as f32 * scale).round() as u32 * 2 + 1,
+        };
 
         // Height is calculated based on the presence of mixture information.
         let height = match &self.mixture_info.is_some() {
@@ -116,240 +965,366 @@ impl Picture {
         // layers are empty and ordering is just a sequence of indices.
         // Altering ordering is neat trick to improve readability of the generated image. See
         // readme for details.
-        let mut layers: Vec<Vec<Shape>> = Vec::new();
+        let mut layers: Vec<Layer> = Vec::new();
         let ordering;
 
         // Lots of details (like ordering) depending on the presence of mixture information,
         // that's why all work is started from drawing the mixture bar first.
         match &self.mixture_info {
             Some(mixture) => {
-                let widths = calculate_widths(&mixture.ingredients)?;
-                let unestimated_capacity = widths.unestimated_capacity;
-                debug!("Mixture basic widths: {:?}", widths.widths);
-
-                let ordered_widths = calculate_ordered_widths(&self.schemes, widths);
-                debug!("Mixture ordered widths: {:?}", ordered_widths);
-
-                ordering = self._calculate_ordered_indices(Some(&ordered_widths));
-                debug!("Mixture ordering: {:?}", ordering);
+                let (segments, bar_ordering, unestimated_capacity) =
+                    self.calculate_mixture_bar(mixture, width - half_border - 1)?;
+                ordering = bar_ordering;
 
-                let mut bar_layers: Vec<Shape> = Vec::new();
-                let mut line_layers: Vec<Shape> = Vec::new();
+                let mut bar_layer = Layer::new("bar", z::BAR);
+                let mut line_layer = Layer::new("bar-lines", z::BAR_LINES);
 
                 // Offset and width gets '-1' because from this time we are working on the actual pixels,
                 // which are indexed from 0.
                 self.draw_mixture_bar(
-                    ordered_widths,
+                    segments,
                     cell_size + quarter_size - 1,
                     0,
                     width - half_border - 1,
                     quarter_size,
                     unestimated_capacity,
-                    &mut bar_layers,
-                    &mut line_layers,
+                    &mut bar_layer.shapes,
+                    &mut line_layer.shapes,
                 );
 
-                layers.push(bar_layers);
-                layers.push(line_layers);
+                layers.push(bar_layer);
+                layers.push(line_layer);
             }
             None => {
                 ordering = self._calculate_ordered_indices(None);
             }
         };
 
-        let mut buffer = ImageBuffer::new(width, height);
+        let default_layout;
+        let layout: &dyn Layout = match &self.layout {
+            Some(layout) => layout.as_ref(),
+            None => {
+                default_layout = self.style.geometry.layout();
+                default_layout.as_ref()
+            }
+        };
 
         let mut offset = 0;
         for index in ordering {
-            self.draw_single_swatch(
-                &mut layers,
-                index,
+            let geometry = CellGeometry {
                 offset,
-                &border_color,
-                &eraser,
-                &half_border,
-                &half_size,
-                &quarter_size,
-                &eight_size,
-            );
+                base_size: self.base_size,
+                border_size: self.border_size,
+                half_border,
+                half_size,
+                quarter_size,
+                eight_size,
+                border_color: self.border_color(&self.schemes[index]),
+                eraser,
+                cutout_size,
+                cutouts: self.style.cutouts,
+                cutout_fill: self.style.cutout_fill,
+                orientation_mark: self.style.orientation_mark,
+                mark_shape: self.style.mark_shape,
+            };
+            layers.extend(layout.shapes(&self.schemes[index], &geometry));
+            if let Some(markers) = self.markers.get(index) {
+                self.draw_markers(&mut layers, markers, &geometry);
+            }
 
-            offset += cell_size - self.border_size;
+            offset += cell_step;
         }
 
-        for layer in &layers {
-            for shape in layer {
-                match shape {
-                    Shape::Square(square) => square.draw(&mut buffer),
-                    Shape::Line(line) => line.draw(&mut buffer),
-                    Shape::Rectangle(rectangle) => rectangle.draw(&mut buffer),
-                }
+        let mut scene = Scene {
+            width,
+            height,
+            background: eraser,
+            layers,
+            corner_radius: self.style.corner_radius,
+        };
+        if let Some(inset) = self.style.frame_inset {
+            self.draw_frame(&mut scene, inset);
+        }
+        Ok(scene)
+    }
+
+    /// Frame is put in margin around the picture, so everything else is moved.
+    fn draw_frame(&self, scene: &mut Scene, inset: u32) {
+        let margin = self.margin();
+        for layer in scene.layers.iter_mut() {
+            for shape in layer.shapes.iter_mut() {
+                *shape = shape.translated(margin, margin);
             }
         }
-        Ok(buffer)
+        scene.width += margin * 2;
+        scene.height += margin * 2;
+
+        let first = inset + (self.border_size - 1) / 2;
+        let (right, bottom) = (scene.width - 1 - first, scene.height - 1 - first);
+        let color = self.style.border_color;
+        let size = self.border_size;
+        let mut frame = Layer::new("frame", z::FRAME);
+        line!(frame.shapes, first, first, right, first, size, color);
+        line!(frame.shapes, right, first, right, bottom, size, color);
+        line!(frame.shapes, right, bottom, first, bottom, size, color);
+        line!(frame.shapes, first, bottom, first, first, size, color);
+        scene.layers.push(frame);
     }
 
-    fn draw_single_swatch(
-        &self,
-        layers: &mut Vec<Vec<Shape>>,
-        index: usize,
-        offset: u32,
-        border_color: &Srgba<u8>,
-        eraser: &Srgba<u8>,
-        half_border: &u32,
-        half_size: &u32,
-        quarter_size: &u32,
-        eight_size: &u32,
-    ) {
-        // Now lets calculate key points in drawing
-        //
-        //             A
-        //           /   \
-        //         /       \
-        //       B     C     D
-        //     /   \       /   \
-        //   /       \   /       \
-        // E     F     G     H     I
-        //   \       /   \       /
-        //     \   /       \   /
-        //       J     K     L
-        //         \       /
-        //           X   Y
-        //             M
+    /// Space around swatches taken by the frame.
+    fn margin(&self) -> u32 {
+        match self.style.frame_inset {
+            Some(inset) => inset * 2 + self.border_size,
+            None => 0,
+        }
+    }
 
-        let a = Point {
-            x: offset + self.base_size + self.border_size + half_border,
-            y: *half_border,
-        };
-        let b = Point {
-            x: offset + half_size + self.border_size,
-            y: half_size + self.border_size,
-        };
-        let c = Point {
-            x: offset + self.base_size + self.border_size + half_border,
-            y: half_size + self.border_size,
-        };
-        let d = Point {
-            x: offset + self.base_size + half_size + self.border_size * 2,
-            y: half_size + self.border_size,
-        };
-        let e = Point {
-            x: offset + half_border,
-            y: self.base_size + self.border_size + half_border,
-        };
-        let f = Point {
-            x: offset + half_size + self.border_size,
-            y: self.base_size + self.border_size + half_border,
-        };
-        let g = Point {
-            x: offset + self.base_size + self.border_size + half_border,
-            y: self.base_size + self.border_size + half_border,
-        };
-        let h = Point {
-            x: offset + self.base_size + half_size + self.border_size * 2,
-            y: self.base_size + self.border_size + half_border,
-        };
-        let i = Point {
-            x: offset + self.base_size * 2 + self.border_size * 2 + half_border,
-            y: self.base_size + self.border_size + half_border,
-        };
-        let j = Point {
-            x: offset + half_size + self.border_size,
-            y: self.base_size + half_size + self.border_size * 2,
-        };
-        let k = Point {
-            x: offset + self.base_size + self.border_size + half_border,
-            y: self.base_size + half_size + self.border_size * 2,
-        };
-        let l = Point {
-            x: offset + self.base_size + half_size + self.border_size * 2,
-            y: self.base_size + half_size + self.border_size * 2,
-        };
-        let m = Point {
-            x: offset + self.base_size + self.border_size + half_border,
-            y: self.base_size * 2 + self.border_size * 2 + half_border,
+    /// Segments of the mixture bar, in order they are drawn (left to right), so interactive
+    /// pictures can match exactly what was rendered. Empty when picture is not a mixture.
+    pub fn bar_segments(&self) -> Result<Vec<BarSegment>, String> {
+        match &self.mixture_info {
+            Some(mixture) => {
+                let half_border = (self.border_size - 1) / 2;
+                let (segments, _, _) =
+                    self.calculate_mixture_bar(mixture, self.width() - half_border - 1)?;
+                let margin = self.margin();
+                Ok(segments
+                    .into_iter()
+                    .map(|segment| BarSegment {
+                        x: segment.x + margin,
+                        ..segment
+                    })
+                    .collect())
+            }
+            None => Ok(vec![]),
+        }
+    }
+
+    /// CSS `linear-gradient` with hard stops matching segments of the mixture bar, so web pages
+    /// can draw the bar natively. Patterns (like hatched markers) become solid colors. None
+    /// for pictures without mixture.
+    pub fn bar_gradient(&self) -> Result<Option<String>, String> {
+        let segments = self.bar_segments()?;
+        let (Some(first), Some(last)) = (segments.first(), segments.last()) else {
+            return Ok(None);
         };
-        let x = Point {
-            x: j.x + (m.x - j.x) * 2 / 3,
-            y: j.y + (m.y - j.y) * 2 / 3,
+        let start = first.x as f32;
+        let length = (last.x + last.width) as f32 - start;
+        let percent = |x: u32| (x as f32 - start) / length * 100.0;
+        let stops: Vec<String> = segments
+            .iter()
+            .map(|segment| {
+                let color = match segment.component {
+                    Some(value) => self.schemes[value - 1].primary.srgb.into(),
+                    None => self.style.unknown_color,
+                };
+                format!(
+                    "{} {:.2}% {:.2}%",
+                    css_color(color),
+                    percent(segment.x),
+                    percent(segment.x + segment.width)
+                )
+            })
+            .collect();
+        Ok(Some(format!(
+            "linear-gradient(to right, {})",
+            stops.join(", ")
+        )))
+    }
+
+    /// Indices of schemes (see `schemes`) in order their swatches are drawn, left to right.
+    /// Components of mixtures are reordered to match the bar, so legends should follow it.
+    pub fn swatch_order(&self) -> Result<Vec<usize>, String> {
+        match &self.mixture_info {
+            Some(mixture) => {
+                let half_border = (self.border_size - 1) / 2;
+                let (_, ordering, _) =
+                    self.calculate_mixture_bar(mixture, self.width() - half_border - 1)?;
+                Ok(ordering)
+            }
+            None => Ok(self._calculate_ordered_indices(None)),
+        }
+    }
+
+    /// Color of borders of the swatch, see `Style::min_border_contrast`.
+    pub fn border_color(&self, scheme: &Scheme) -> Srgba<u8> {
+        let border_color = self.style.border_color;
+        let Some(min_contrast) = self.style.min_border_contrast else {
+            return border_color;
         };
-        let y = Point {
-            x: m.x + (l.x - m.x) / 3,
-            y: x.y,
+        let fills = [
+            &scheme.primary,
+            &scheme.first_accent,
+            &scheme.second_accent,
+            &scheme.complementary,
+        ];
+        let lowest_contrast = |color: &Srgba<u8>| {
+            fills
+                .iter()
+                .map(|fill| contrast_ratio(fill.srgb, color.color))
+                .fold(f64::MAX, f64::min)
         };
+        if lowest_contrast(&border_color) >= min_contrast {
+            return border_color;
+        }
+        [border_color, line_color!(), light_line_color!()]
+            .into_iter()
+            .max_by(|a, b| lowest_contrast(a).total_cmp(&lowest_contrast(b)))
+            .unwrap()
+    }
 
-        let scheme = &self.schemes[index];
-        let mut base_colors: Vec<Shape> = Vec::new();
-        // Primary color
-        vertical_square!(base_colors, self.base_size, c, scheme.primary.srgb);
-        // First accent
-        vertical_square!(base_colors, self.base_size, f, scheme.first_accent.srgb);
-        // Second accent
-        vertical_square!(base_colors, self.base_size, h, scheme.second_accent.srgb);
-        // Complementary color
-        vertical_square!(base_colors, self.base_size, k, scheme.complementary.srgb);
-        layers.push(base_colors);
+    /// Swatches (in order they are drawn) followed by segments of the mixture bar. Empty for
+    /// pictures restored from scene.
+    pub fn regions(&self) -> Result<Vec<Region>, String> {
+        if self.schemes.is_empty() {
+            return Ok(vec![]);
+        }
+        let cell_size = self.base_size * 2 + self.border_size * 3;
+        let cell_step = cell_size - self.border_size + self.style.spacing;
+        let quarter_size = ((self.base_size - 1) / 2 - 1) / 2;
+        let margin = self.margin();
+        let segments = self.bar_segments()?;
+        let fraction = |component: usize| {
+            self.mixture_info.as_ref().map(|_| {
+                segments
+                    .iter()
+                    .find(|segment| segment.component == Some(component))
+                    .map_or(0f32, |segment| segment.fraction)
+            })
+        };
 
-        let mut base_lines: Vec<Shape> = Vec::new();
-        // Cross - left top to right bottom
-        line!(base_lines, b, l, self.border_size, *border_color);
-        // Cross - left bottom to right top
-        line!(base_lines, j, d, self.border_size, *border_color);
+        let mut regions: Vec<Region> = self
+            .swatch_order()?
+            .into_iter()
+            .enumerate()
+            .map(|(position, index)| Region {
+                kind: RegionKind::Swatch,
+                component: Some(index + 1),
+                fraction: fraction(index + 1),
+                x: position as u32 * cell_step + margin,
+                y: margin,
+                width: cell_size,
+                height: cell_size,
+            })
+            .collect();
+        regions.extend(segments.iter().map(|segment| Region {
+            kind: RegionKind::Segment,
+            component: segment.component,
+            fraction: Some(segment.fraction),
+            x: segment.x,
+            y: cell_size + quarter_size - 1 + margin,
+            width: segment.width,
+            height: quarter_size,
+        }));
+        Ok(regions)
+    }
 
-        // Border - top left
-        line!(base_lines, e, a, self.border_size, *border_color);
-        // Border - top right
-        line!(base_lines, a, i, self.border_size, *border_color);
-        // Border - bottom right
-        line!(base_lines, i, m, self.border_size, *border_color);
-        // Border - bottom left
-        line!(base_lines, m, e, self.border_size, *border_color);
-        layers.push(base_lines);
+    fn width(&self) -> u32 {
+        let cell_size = self.base_size * 2 + self.border_size * 3;
+        let cell_step = cell_size - self.border_size + self.style.spacing;
+        cell_size + (self.schemes.len() as u32 - 1) * cell_step
+    }
 
-        let mut cutouts: Vec<Shape> = Vec::new();
-        // Left top cutout
-        horizontal_square!(cutouts, *quarter_size, b, *eraser);
-        // Right top cutout
-        horizontal_square!(cutouts, *quarter_size, d, *eraser);
-        // Left bottom cutout
-        horizontal_square!(cutouts, *quarter_size, j, *eraser);
-        // Right bottom cutout
-        horizontal_square!(cutouts, *quarter_size, l, *eraser);
-        // Central cutout
-        horizontal_square!(cutouts, *quarter_size, g, *eraser);
-        layers.push(cutouts);
+    /// Segments of the bar, order of swatches and whether capacity is unestimated.
+    fn calculate_mixture_bar(
+        &self,
+        mixture: &Mixture,
+        available_width: u32,
+    ) -> Result<(Vec<BarSegment>, Vec<usize>, bool), String> {
+        measure(Stage::WidthCalculation, || {
+            let widths = calculate_widths(&mixture.ingredients)?;
+            let unestimated_capacity = widths.unestimated_capacity;
+            debug!("Mixture basic widths: {:?}", widths.widths);
+
+            let ordered_widths = calculate_ordered_widths(&self.schemes, widths);
+            debug!("Mixture ordered widths: {:?}", ordered_widths);
+
+            let ordering = self._calculate_ordered_indices(Some(&ordered_widths));
+            debug!("Mixture ordering: {:?}", ordering);
+
+            let (segments, unestimated_capacity) =
+                self.segment_mixture_bar(ordered_widths, 0, available_width, unestimated_capacity);
+            Ok((segments, ordering, unestimated_capacity))
+        })
+    }
 
-        // Aliases, to fit declarative code in single lines
-        let es = eight_size;
+    /// Markers are drawn in empty corners of the cell, outside of the swatch itself.
+    fn draw_markers(&self, layers: &mut Vec<Layer>, markers: &Markers, geometry: &CellGeometry) {
+        let offset = geometry.offset;
+        let color = &geometry.border_color;
+        let marker_size = &geometry.quarter_size;
         let size = self.border_size;
-        let color = border_color;
-
-        let mut lines: Vec<Shape> = Vec::new();
-        // Left top cutout borders
-        line!(lines, b.x + es, b.y - es, b.x + es, b.y + es, size, *color);
-        line!(lines, b.x - es, b.y + es, b.x + es, b.y + es, size, *color);
-
-        // Right top cutout borders
-        line!(lines, d.x - es, d.y - es, d.x - es, d.y + es, size, *color);
-        line!(lines, d.x - es, d.y + es, d.x + es, d.y + es, size, *color);
-
-        // Left bottom cutout borders
-        line!(lines, j.x - es, j.y - es, j.x + es, j.y - es, size, *color);
-        line!(lines, j.x + es, j.y - es, j.x + es, j.y + es, size, *color);
-
-        // Right bottom cutout borders
-        line!(lines, l.x - es, l.y - es, l.x + es, l.y - es, size, *color);
-        line!(lines, l.x - es, l.y - es, l.x - es, l.y + es, size, *color);
+        let margin = self.border_size * 2;
+        let mut shapes: Vec<Shape> = Vec::new();
+
+        if let (true, Some(stereo)) = (self.style.stereo_marker, &markers.stereo) {
+            let left = offset + margin;
+            let right = left + marker_size;
+            let (apex_y, base_y) = match stereo {
+                Stereo::Normal => (margin, margin + marker_size),
+                Stereo::Inverted => (margin + marker_size, margin),
+            };
+            let apex_x = left + marker_size / 2;
+            line!(shapes, left, base_y, right, base_y, size, *color);
+            line!(shapes, left, base_y, apex_x, apex_y, size, *color);
+            line!(shapes, apex_x, apex_y, right, base_y, size, *color);
+        }
 
-        // Central cutout borders
-        line!(lines, g.x - es, g.y - es, g.x + es, g.y - es, size, *color);
-        line!(lines, g.x - es, g.y - es, g.x - es, g.y + es, size, *color);
-        line!(lines, g.x - es, g.y + es, g.x + es, g.y + es, size, *color);
-        line!(lines, g.x + es, g.y - es, g.x + es, g.y + es, size, *color);
+        if let (true, Some(charge)) = (self.style.charge_marker, &markers.charge) {
+            let right = offset + geometry.cell_size() - 1 - margin;
+            let left = right - marker_size;
+            let middle_x = left + marker_size / 2;
+            let middle_y = margin + marker_size / 2;
+            match charge {
+                Charge::Positive => {
+                    line!(shapes, left, middle_y, right, middle_y, size, *color);
+                    line!(
+                        shapes,
+                        middle_x,
+                        margin,
+                        middle_x,
+                        margin + marker_size,
+                        size,
+                        *color
+                    );
+                }
+                Charge::Negative => {
+                    line!(shapes, left, middle_y, right, middle_y, size, *color);
+                }
+                Charge::Neutralized => {
+                    // Plus in upper part, minus at the bottom
+                    let plus_y = margin + marker_size / 3;
+                    let minus_y = margin + marker_size;
+                    line!(shapes, left, plus_y, right, plus_y, size, *color);
+                    line!(
+                        shapes,
+                        middle_x,
+                        margin,
+                        middle_x,
+                        minus_y - size * 2,
+                        size,
+                        *color
+                    );
+                    line!(shapes, left, minus_y, right, minus_y, size, *color);
+                }
+            }
+        }
 
-        // Orientation mark
-        line!(lines, x, y, size, *color);
+        if self.style.isotope_marker && markers.isotopes {
+            // 3x3 grid of dots
+            let bottom = geometry.cell_size() - 1 - margin;
+            let left = offset + margin;
+            let step = marker_size / 2;
+            for row in 0..3 {
+                for column in 0..3 {
+                    let x = left + column * step;
+                    let y = bottom - row * step;
+                    line!(shapes, x, y, x, y, size + 1, *color);
+                }
+            }
+        }
 
-        layers.push(lines);
+        layers.push(Layer::new("markers", z::MARKERS).with_shapes(shapes));
     }
 
     /// Normalize indices order. During mixture bar calculation indices may be reordered to improve readability.
@@ -380,23 +1355,25 @@ impl Picture {
         }
     }
 
-    fn draw_mixture_bar(
+    /// Split the bar into segments. Widths are scaled logarithmically, so even small additions
+    /// stay visible, and pixels are apportioned with `apportion`.
+    /// Returns segments and whether capacity of the mixture is unestimated.
+    fn segment_mixture_bar(
         &self,
         widths: Vec<(String, f32)>,
-        y_offset: u32,
         start_x: u32,
-        end_x: u32,
-        base_bar_size: u32,
+        available_width: u32,
         mut unestimated_capacity: bool,
-        bar_layers: &mut Vec<Shape>,
-        line_layers: &mut Vec<Shape>,
-    ) {
-        let available_width = end_x - start_x;
+    ) -> (Vec<BarSegment>, bool) {
         let mut indices: Vec<String> = vec![];
         let mut sizes: Vec<f32> = vec![];
+        let mut markers: Vec<String> = vec![];
         let mut unknown_substance_present = false;
         for (index, width) in &widths {
             if *width == 0f32 {
+                if self.style.zero_width_markers && !index.is_empty() {
+                    markers.push(index.clone());
+                }
                 continue;
             }
             indices.push(index.clone());
@@ -410,6 +1387,10 @@ impl Picture {
         debug!("sizes: {:?}", sizes);
         debug!("unknown_substance_present: {}", unknown_substance_present);
 
+        // Share of each component, before any adjustments made for readability.
+        let total: f32 = sizes.iter().sum();
+        let mut fractions: Vec<f32> = sizes.iter().map(|s| s / total).collect();
+
         if sizes.len() > 0 {
             // Streching sizes so ln values will be bigger than 10
             while sizes
@@ -429,55 +1410,71 @@ impl Picture {
         // add a bit of space for it.
         if unestimated_capacity && !unknown_substance_present {
             indices.push("".to_string());
-            // Chosen by fair dice roll.
-            sizes.push(4f32);
+            sizes.push(self.style.unknown_min_width);
+            fractions.push(0f32);
+        }
+        for (index, size) in indices.iter().zip(sizes.iter_mut()) {
+            if index.is_empty() && *size < self.style.unknown_min_width {
+                *size = self.style.unknown_min_width;
+            }
         }
 
         let ln_sizes = sizes.iter().map(|s| s.ln()).collect::<Vec<f32>>();
 
         debug!("Mixture sizes after logarithm: {:?}", ln_sizes);
 
-        let ln_sum = ln_sizes.iter().sum::<f32>();
+        // Markers take their place first, the rest is shared by other segments
+        let marker_width = (self.border_size * 3).min(available_width / (markers.len() as u32 + 1));
+        let available_width = available_width - marker_width * markers.len() as u32;
 
-        let mut actual_sizes = ln_sizes
-            .iter()
-            .map(|s| ((s / ln_sum * available_width as f32) as u32))
-            .collect::<Vec<u32>>();
-
-        // Streching, to reclaim pixels lost on rounding
-        let mut sizes_sum_diff: isize =
-            available_width as isize - actual_sizes.iter().sum::<u32>() as isize;
-        while sizes_sum_diff > 0 {
-            for i in 0..sizes.len() {
-                if sizes_sum_diff == 0 {
-                    break;
-                }
-                actual_sizes[i] += 1;
-                sizes_sum_diff -= 1;
-            }
-        }
-        // Shrinking, to remove excess pixels
-        while sizes_sum_diff < 0 {
-            for i in 0..sizes.len() {
-                if sizes_sum_diff == 0 {
-                    break;
-                }
-                actual_sizes[i] -= 1;
-                sizes_sum_diff += 1;
-            }
-        }
+        let actual_sizes = apportion(&ln_sizes, available_width);
 
         debug!("Mixture actual sizes: {:?}", actual_sizes);
 
-        line!(
-            line_layers,
-            start_x,
-            y_offset,
-            end_x,
-            y_offset,
-            self.border_size,
-            line_color!()
+        let mut parts: Vec<(String, f32, u32, bool)> = indices
+            .into_iter()
+            .zip(fractions)
+            .zip(actual_sizes)
+            .map(|((index, fraction), width)| (index, fraction, width, false))
+            .collect();
+        // Unknown substances stay at the very end
+        let markers_at = parts
+            .iter()
+            .position(|(index, ..)| index.is_empty())
+            .unwrap_or(parts.len());
+        parts.splice(
+            markers_at..markers_at,
+            markers
+                .into_iter()
+                .map(|index| (index, 0f32, marker_width, true)),
         );
+
+        let mut segments = vec![];
+        let mut x = start_x;
+        for (index, fraction, width, marker) in parts {
+            segments.push(BarSegment {
+                component: index.parse::<usize>().ok(),
+                fraction,
+                x,
+                width,
+                marker,
+            });
+            x += width;
+        }
+        (segments, unestimated_capacity)
+    }
+
+    fn draw_mixture_bar(
+        &self,
+        segments: Vec<BarSegment>,
+        y_offset: u32,
+        start_x: u32,
+        end_x: u32,
+        base_bar_size: u32,
+        unestimated_capacity: bool,
+        bar_layers: &mut Vec<Shape>,
+        line_layers: &mut Vec<Shape>,
+    ) {
         line!(
             line_layers,
             start_x,
@@ -485,7 +1482,7 @@ impl Picture {
             end_x,
             y_offset,
             self.border_size,
-            line_color!()
+            self.style.border_color
         );
         line!(
             line_layers,
@@ -494,7 +1491,7 @@ impl Picture {
             start_x,
             y_offset + base_bar_size,
             self.border_size,
-            line_color!()
+            self.style.border_color
         );
         line!(
             line_layers,
@@ -503,73 +1500,430 @@ impl Picture {
             end_x,
             y_offset + base_bar_size,
             self.border_size,
-            line_color!()
+            self.style.border_color
         );
 
-        let mut start = start_x;
-        let mut end = start_x;
-        let mut step_index = 0;
-
-        for substance_index in indices {
-            end += actual_sizes[step_index];
-
-            let color = match substance_index.parse::<usize>() {
-                Ok(value) => self.schemes[value - 1].primary.srgb.into(),
-                Err(_) => unknown_color!(),
+        let divider_color = self.style.divider_color.unwrap_or(self.style.border_color);
+        let divider_width = self.style.divider_width.unwrap_or(self.border_size);
+        let last = segments.len().saturating_sub(1);
+        for (position, segment) in segments.into_iter().enumerate() {
+            let (color, pattern) = match (segment.component, segment.marker) {
+                (Some(value), true) => (
+                    self.schemes[value - 1].primary.srgb.into(),
+                    Pattern::Hatched,
+                ),
+                (Some(value), false) => {
+                    (self.schemes[value - 1].primary.srgb.into(), Pattern::Solid)
+                }
+                (None, _) => (self.style.unknown_color, self.style.unknown_pattern),
             };
             bar_layers.push(Shape::Rectangle(Rectangle {
-                x: start,
+                x: segment.x,
                 y: y_offset,
-                width: end - start,
+                width: segment.width,
                 height: base_bar_size,
                 color,
+                pattern,
+                blend: Blend::Over,
             }));
 
-            start = end;
-            step_index += 1;
+            // End of the last segment is the end of the bar, it is either closed with border
+            // or left open.
+            let (border_size, color) = match position == last {
+                true if unestimated_capacity => continue,
+                true => (self.border_size, self.style.border_color),
+                false if divider_width == 0 => continue,
+                false => (divider_width, divider_color),
+            };
+            let start = segment.x + segment.width;
             line_layers.push(Shape::Line(Line {
                 x1: start,
                 y1: y_offset,
                 x2: start,
                 y2: y_offset + base_bar_size,
-                border_size: self.border_size,
-                color: line_color!(),
+                border_size,
+                color,
+                blend: Blend::Over,
             }));
         }
 
         // Indicate "open" end of the bar.
         if unestimated_capacity {
-            // Remove last vertical line.
-            line_layers.pop();
-
             let half_height = (base_bar_size - 1) / 2;
+            let eraser = self.style.background;
+
+            match self.style.open_end {
+                OpenEnd::ZigZag => {
+                    bar_layers.push(Shape::Square(Square {
+                        x: end_x,
+                        y: y_offset + half_height,
+                        size: base_bar_size,
+                        orientation: Orientation::Vertical,
+                        color: eraser,
+                        blend: Blend::Clear,
+                    }));
+                    line!(
+                        line_layers,
+                        end_x,
+                        y_offset,
+                        end_x - half_height,
+                        y_offset + half_height,
+                        self.border_size,
+                        self.style.border_color
+                    );
+                    line!(
+                        line_layers,
+                        end_x - half_height,
+                        y_offset + half_height,
+                        end_x,
+                        y_offset + base_bar_size,
+                        self.border_size,
+                        self.style.border_color
+                    );
+                }
+                OpenEnd::Fade => {
+                    // End of the bar (with borders) is cleared first, then columns fading into
+                    // background are drawn, so whole end of the bar vanishes.
+                    let half_border = (self.border_size - 1) / 2;
+                    let fade_width = base_bar_size.min(end_x - start_x);
+                    let color = self.style.unknown_color;
+                    line_layers.push(Shape::Rectangle(Rectangle {
+                        x: end_x + 1 - fade_width,
+                        y: y_offset - half_border,
+                        width: fade_width,
+                        height: base_bar_size + self.border_size,
+                        color: eraser,
+                        pattern: Pattern::Solid,
+                        blend: Blend::Clear,
+                    }));
+                    for step in 0..fade_width {
+                        let alpha = color.alpha as u32 * step / fade_width;
+                        line_layers.push(Shape::Rectangle(Rectangle {
+                            x: end_x - step,
+                            y: y_offset - half_border,
+                            width: 1,
+                            height: base_bar_size + self.border_size,
+                            color: Srgba::new(color.red, color.green, color.blue, alpha as u8),
+                            pattern: Pattern::Solid,
+                            blend: Blend::Over,
+                        }));
+                    }
+                }
+                OpenEnd::Dotted => {
+                    let step = (self.border_size * 3).max(3);
+                    for y in (y_offset..=y_offset + base_bar_size).step_by(step as usize) {
+                        line!(
+                            line_layers,
+                            end_x,
+                            y,
+                            end_x,
+                            y,
+                            self.border_size,
+                            self.style.border_color
+                        );
+                    }
+                }
+                OpenEnd::Arrow => {
+                    // Erasers have to cover borders, so they go to line layer.
+                    for y in [y_offset, y_offset + base_bar_size] {
+                        line_layers.push(Shape::Square(Square {
+                            x: end_x,
+                            y,
+                            size: base_bar_size,
+                            orientation: Orientation::Vertical,
+                            color: eraser,
+                            blend: Blend::Clear,
+                        }));
+                    }
+                    line!(
+                        line_layers,
+                        end_x - half_height,
+                        y_offset,
+                        end_x,
+                        y_offset + half_height,
+                        self.border_size,
+                        self.style.border_color
+                    );
+                    line!(
+                        line_layers,
+                        end_x,
+                        y_offset + half_height,
+                        end_x - half_height,
+                        y_offset + base_bar_size,
+                        self.border_size,
+                        self.style.border_color
+                    );
+                }
+            }
+        }
+    }
+}
 
-            bar_layers.push(Shape::Square(Square {
-                x: end_x,
-                y: y_offset + half_height,
-                size: base_bar_size,
-                orientation: Orientation::Vertical,
-                color: Srgba::new(0, 0, 0, 0),
-            }));
+impl Layout for KiteLayout {
+    fn shapes(&self, scheme: &Scheme, geometry: &CellGeometry) -> Vec<Layer> {
+        let offset = geometry.offset;
+        let border_color = &geometry.border_color;
+        let (cutout_color, cutout_blend) = geometry.cutout_paint();
+        let half_border = &geometry.half_border;
+        let half_size = &geometry.half_size;
+        let mut layers: Vec<Layer> = Vec::new();
 
-            line_layers.push(Shape::Line(Line {
-                x1: end_x,
-                y1: y_offset,
-                x2: end_x - half_height,
-                y2: y_offset + half_height,
-                border_size: self.border_size,
-                color: line_color!(),
+        // Now lets calculate key points in drawing
+        //
+        //             A
+        //           /   \
+        //         /       \
+        //       B     C     D
+        //     /   \       /   \
+        //   /       \   /       \
+        // E     F     G     H     I
+        //   \       /   \       /
+        //     \   /       \   /
+        //       J     K     L
+        //         \       /
+        //           X   Y
+        //             M
+
+        let a = Point {
+            x: offset + geometry.base_size + geometry.border_size + half_border,
+            y: *half_border,
+        };
+        let b = Point {
+            x: offset + half_size + geometry.border_size,
+            y: half_size + geometry.border_size,
+        };
+        let c = Point {
+            x: offset + geometry.base_size + geometry.border_size + half_border,
+            y: half_size + geometry.border_size,
+        };
+        let d = Point {
+            x: offset + geometry.base_size + half_size + geometry.border_size * 2,
+            y: half_size + geometry.border_size,
+        };
+        let e = Point {
+            x: offset + half_border,
+            y: geometry.base_size + geometry.border_size + half_border,
+        };
+        let f = Point {
+            x: offset + half_size + geometry.border_size,
+            y: geometry.base_size + geometry.border_size + half_border,
+        };
+        let g = Point {
+            x: offset + geometry.base_size + geometry.border_size + half_border,
+            y: geometry.base_size + geometry.border_size + half_border,
+        };
+        let h = Point {
+            x: offset + geometry.base_size + half_size + geometry.border_size * 2,
+            y: geometry.base_size + geometry.border_size + half_border,
+        };
+        let i = Point {
+            x: offset + geometry.base_size * 2 + geometry.border_size * 2 + half_border,
+            y: geometry.base_size + geometry.border_size + half_border,
+        };
+        let j = Point {
+            x: offset + half_size + geometry.border_size,
+            y: geometry.base_size + half_size + geometry.border_size * 2,
+        };
+        let k = Point {
+            x: offset + geometry.base_size + geometry.border_size + half_border,
+            y: geometry.base_size + half_size + geometry.border_size * 2,
+        };
+        let l = Point {
+            x: offset + geometry.base_size + half_size + geometry.border_size * 2,
+            y: geometry.base_size + half_size + geometry.border_size * 2,
+        };
+        let m = Point {
+            x: offset + geometry.base_size + geometry.border_size + half_border,
+            y: geometry.base_size * 2 + geometry.border_size * 2 + half_border,
+        };
+        let x = Point {
+            x: j.x + (m.x - j.x) * 2 / 3,
+            y: j.y + (m.y - j.y) * 2 / 3,
+        };
+        let y = Point {
+            x: m.x + (l.x - m.x) / 3,
+            y: x.y,
+        };
+
+        let mut base_colors: Vec<Shape> = Vec::new();
+        // Primary color
+        vertical_square!(base_colors, geometry.base_size, c, scheme.primary.srgb);
+        // First accent
+        vertical_square!(base_colors, geometry.base_size, f, scheme.first_accent.srgb);
+        // Second accent
+        vertical_square!(
+            base_colors,
+            geometry.base_size,
+            h,
+            scheme.second_accent.srgb
+        );
+        // Complementary color
+        vertical_square!(
+            base_colors,
+            geometry.base_size,
+            k,
+            scheme.complementary.srgb
+        );
+        layers.push(Layer::new("colors", z::COLORS).with_shapes(base_colors));
+
+        let mut base_lines: Vec<Shape> = Vec::new();
+        // Cross - left top to right bottom
+        line!(base_lines, b, l, geometry.border_size, *border_color);
+        // Cross - left bottom to right top
+        line!(base_lines, j, d, geometry.border_size, *border_color);
+
+        // Border - top left
+        line!(base_lines, e, a, geometry.border_size, *border_color);
+        // Border - top right
+        line!(base_lines, a, i, geometry.border_size, *border_color);
+        // Border - bottom right
+        line!(base_lines, i, m, geometry.border_size, *border_color);
+        // Border - bottom left
+        line!(base_lines, m, e, geometry.border_size, *border_color);
+        layers.push(Layer::new("lines", z::LINES).with_shapes(base_lines));
+
+        // Aliases, to fit declarative code in single lines
+        let cs = geometry.cutout_size;
+        let es = (cs.max(1) - 1) / 2;
+        let size = geometry.border_size;
+        let color = border_color;
+
+        let mut lines: Vec<Shape> = Vec::new();
+        // Orientation mark
+        lines.extend(geometry.orientation_mark(&g, &x, &y));
+        if cs == 0 {
+            layers.push(Layer::new("details", z::DETAILS).with_shapes(lines));
+            return layers;
+        }
+
+        let mut cutouts: Vec<Shape> = Vec::new();
+        // Central cutout
+        horizontal_square!(cutouts, cs, g, cutout_color, cutout_blend);
+        if geometry.cutouts == Cutouts::All {
+            // Left top cutout
+            horizontal_square!(cutouts, cs, b, cutout_color, cutout_blend);
+            // Right top cutout
+            horizontal_square!(cutouts, cs, d, cutout_color, cutout_blend);
+            // Left bottom cutout
+            horizontal_square!(cutouts, cs, j, cutout_color, cutout_blend);
+            // Right bottom cutout
+            horizontal_square!(cutouts, cs, l, cutout_color, cutout_blend);
+        }
+        layers.push(Layer::new("cutouts", z::CUTOUTS).with_shapes(cutouts));
+
+        // Central cutout borders
+        line!(lines, g.x - es, g.y - es, g.x + es, g.y - es, size, *color);
+        line!(lines, g.x - es, g.y - es, g.x - es, g.y + es, size, *color);
+        line!(lines, g.x - es, g.y + es, g.x + es, g.y + es, size, *color);
+        line!(lines, g.x + es, g.y - es, g.x + es, g.y + es, size, *color);
+        if geometry.cutouts == Cutouts::Center {
+            layers.push(Layer::new("details", z::DETAILS).with_shapes(lines));
+            return layers;
+        }
+
+        // Left top cutout borders
+        line!(lines, b.x + es, b.y - es, b.x + es, b.y + es, size, *color);
+        line!(lines, b.x - es, b.y + es, b.x + es, b.y + es, size, *color);
+
+        // Right top cutout borders
+        line!(lines, d.x - es, d.y - es, d.x - es, d.y + es, size, *color);
+        line!(lines, d.x - es, d.y + es, d.x + es, d.y + es, size, *color);
+
+        // Left bottom cutout borders
+        line!(lines, j.x - es, j.y - es, j.x + es, j.y - es, size, *color);
+        line!(lines, j.x + es, j.y - es, j.x + es, j.y + es, size, *color);
+
+        // Right bottom cutout borders
+        line!(lines, l.x - es, l.y - es, l.x + es, l.y - es, size, *color);
+        line!(lines, l.x - es, l.y - es, l.x - es, l.y + es, size, *color);
+
+        layers.push(Layer::new("details", z::DETAILS).with_shapes(lines));
+
+        layers
+    }
+}
+
+impl Layout for CircleLayout {
+    fn shapes(&self, scheme: &Scheme, geometry: &CellGeometry) -> Vec<Layer> {
+        let mut layers: Vec<Layer> = Vec::new();
+        let half_border = geometry.half_border;
+        let eight_size = geometry.eight_size;
+        let center = Point {
+            x: geometry.offset + geometry.base_size + geometry.border_size + half_border,
+            y: geometry.base_size + geometry.border_size + half_border,
+        };
+        let radius = geometry.base_size + geometry.border_size;
+
+        let mut base_colors: Vec<Shape> = Vec::new();
+        for (sector, color) in [
+            (Sector::Top, scheme.primary.srgb),
+            (Sector::Left, scheme.first_accent.srgb),
+            (Sector::Right, scheme.second_accent.srgb),
+            (Sector::Bottom, scheme.complementary.srgb),
+        ] {
+            base_colors.push(Shape::Circle(Circle {
+                x: center.x,
+                y: center.y,
+                radius,
+                ring: None,
+                sector: Some(sector),
+                color: color.into(),
+                blend: Blend::Over,
             }));
+        }
+        layers.push(Layer::new("colors", z::COLORS).with_shapes(base_colors));
 
-            line_layers.push(Shape::Line(Line {
-                x1: end_x - half_height,
-                y1: y_offset + half_height,
-                x2: end_x,
-                y2: y_offset + base_bar_size,
-                border_size: self.border_size,
-                color: line_color!(),
+        // Diagonal split lines end on the circle, so they go from center at 45 degrees
+        let diagonal = radius * 707 / 1000;
+        let size = geometry.border_size;
+        let color = geometry.border_color;
+        let mut lines: Vec<Shape> = Vec::new();
+        let (left, right) = (center.x - diagonal, center.x + diagonal);
+        let (top, bottom) = (center.y - diagonal, center.y + diagonal);
+        line!(lines, left, top, right, bottom, size, color);
+        line!(lines, left, bottom, right, top, size, color);
+
+        // Central cutout, with its border
+        let mut rings = vec![radius];
+        if geometry.cutouts != Cutouts::None {
+            let cutout_radius = eight_size * 2;
+            let (cutout_color, cutout_blend) = geometry.cutout_paint();
+            lines.push(Shape::Circle(Circle {
+                x: center.x,
+                y: center.y,
+                radius: cutout_radius,
+                ring: None,
+                sector: None,
+                color: cutout_color,
+                blend: cutout_blend,
+            }));
+            rings.insert(0, cutout_radius);
+        }
+        for radius in rings {
+            lines.push(Shape::Circle(Circle {
+                x: center.x,
+                y: center.y,
+                radius,
+                ring: Some(size),
+                sector: None,
+                color,
+                blend: Blend::Over,
             }));
         }
+
+        // Orientation mark, like in kite it is off-center in the bottom part
+        let mark_y = center.y + radius * 2 / 3;
+        let start = Point {
+            x: center.x,
+            y: mark_y,
+        };
+        let end = Point {
+            x: center.x + radius / 3,
+            y: mark_y,
+        };
+        lines.extend(geometry.orientation_mark(&center, &start, &end));
+        layers.push(Layer::new("lines", z::LINES).with_shapes(lines));
+        layers
     }
 }
 
@@ -580,20 +1934,12 @@ fn calculate_ordered_widths(
 ) -> Vec<(String, f32)> {
     let mut sums: HashMap<String, f32> = HashMap::new();
 
-    // Sum up widths for each index, while checking if the index is valid
+    // Sum up widths for each index, while checking if the index is valid. Unknown indices are
+    // reported as warnings of the picture.
     for (index, width) in calculated_widths.widths {
-        let actual_index = match index.parse::<usize>() {
-            Ok(value) => match schemes.get(value - 1) {
-                Some(_) => index,
-                None => {
-                    debug!("Unknown index: '{}'", index);
-                    "".to_string()
-                }
-            },
-            Err(_) => {
-                debug!("Unknown index: '{}'", index);
-                "".to_string()
-            }
+        let actual_index = match is_known_index(&index, schemes.len()) {
+            true => index,
+            false => "".to_string(),
         };
 
         let sum = sums.entry(actual_index).or_insert(0f32);
@@ -681,6 +2027,9 @@ fn calculate_widths(components: &Vec<Ingredient>) -> Result<WidthsResult, String
                     unknown += 1;
                 }
             },
+            Ingredient::Invalid(_) => {
+                unknown += 1;
+            }
         }
     }
 
@@ -699,7 +2048,7 @@ fn calculate_widths(components: &Vec<Ingredient>) -> Result<WidthsResult, String
     }
 
     let mut result: Vec<(String, f32)> = vec![];
-    let final_sum: Option<f32>;
+    let final_sum: Option<usize>;
     let mut default_width = 0f32;
     let mut min_magnitude = &0isize;
     if seen_concentrations.len() == 1 {
@@ -718,17 +2067,21 @@ fn calculate_widths(components: &Vec<Ingredient>) -> Result<WidthsResult, String
         for component in components {
             match component {
                 Ingredient::Mixture(mixture) => match &mixture.content {
-                    Some(content) => values.push(content.value_at_magnitude(min_magnitude)),
+                    Some(content) => values.push(content.value_at_magnitude(min_magnitude)?),
                     None => {}
                 },
                 Ingredient::Substance(substance) => match &substance.content {
-                    Some(content) => values.push(content.value_at_magnitude(min_magnitude)),
+                    Some(content) => values.push(content.value_at_magnitude(min_magnitude)?),
                     None => {}
                 },
+                Ingredient::Invalid(_) => {}
             }
         }
-        let sum = values.iter().sum::<usize>();
-        let capacity = match Content::calculate_capacity(concentration, min_magnitude) {
+        let sum = values
+            .iter()
+            .try_fold(0usize, |sum, value| sum.checked_add(*value))
+            .ok_or(MagnitudeError::Overflow)?;
+        let capacity = match Content::calculate_capacity(concentration, min_magnitude)? {
             Capacity::Absolute(capacity) => capacity,
             Capacity::Relative => sum,
             Capacity::Unestimated => {
@@ -771,7 +2124,7 @@ fn calculate_widths(components: &Vec<Ingredient>) -> Result<WidthsResult, String
             }
         }
 
-        final_sum = Some(sum as f32);
+        final_sum = Some(sum);
     } else {
         final_sum = None;
         unestimated_capacity = true;
@@ -790,13 +2143,12 @@ fn calculate_widths(components: &Vec<Ingredient>) -> Result<WidthsResult, String
         match component {
             Ingredient::Mixture(mixture) => {
                 let size = match final_sum {
-                    Some(value) => {
-                        let partial_size = match &mixture.content {
-                            Some(content) => content.value_at_magnitude(min_magnitude) as f32,
-                            None => default_width,
-                        };
-                        partial_size / value
-                    }
+                    Some(value) => match &mixture.content {
+                        Some(content) => content
+                            .as_fraction(value, min_magnitude)
+                            .unwrap_or_default() as f32,
+                        None => default_width / value as f32,
+                    },
                     None => 0f32,
                 };
                 let calculated = calculate_widths(&mixture.ingredients)?;
@@ -815,16 +2167,24 @@ fn calculate_widths(components: &Vec<Ingredient>) -> Result<WidthsResult, String
                 match final_sum {
                     Some(value) => {
                         let size = match &substance.content {
-                            Some(content) => content.value_at_magnitude(min_magnitude) as f32,
-                            None => default_width,
+                            Some(content) => content
+                                .as_fraction(value, min_magnitude)
+                                .unwrap_or_default()
+                                as f32,
+                            None => default_width / value as f32,
                         };
-                        result.push((index, size / value as f32));
+                        result.push((index, size));
                     }
                     None => {
                         result.push((index, 0f32));
                     }
                 }
             }
+            // Whatever was there, it is not known what it is.
+            Ingredient::Invalid(_) => match final_sum {
+                Some(value) => result.push(("".to_string(), default_width / value as f32)),
+                None => result.push(("".to_string(), 0f32)),
+            },
         }
     }
     Ok(WidthsResult {
@@ -834,13 +2194,14 @@ fn calculate_widths(components: &Vec<Ingredient>) -> Result<WidthsResult, String
 }
 
 #[derive(Debug)]
-struct Point {
-    x: u32,
-    y: u32,
+pub struct Point {
+    pub x: u32,
+    pub y: u32,
 }
 
-#[derive(Debug)]
-enum Orientation {
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Orientation {
     /// □
     Horizontal,
     /// ◇
@@ -848,13 +2209,15 @@ enum Orientation {
 }
 
 // The square is drawn in very specific way, check draw method to understand how it works.
-#[derive(Debug)]
-struct Square {
-    x: u32,
-    y: u32,
-    size: u32,
-    orientation: Orientation,
-    color: Srgba<u8>,
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Square {
+    pub x: u32,
+    pub y: u32,
+    pub size: u32,
+    pub orientation: Orientation,
+    pub color: Srgba<u8>,
+    #[serde(default)]
+    pub blend: Blend,
 }
 
 impl Square {
@@ -889,46 +2252,46 @@ impl Square {
     // of the square that would fit the diamond (the size is actually size of its diagonals).
     //
     // Be warned.
-    fn draw(&self, buffer: &mut ImageBuffer<Rgba<u8>, Vec<u8>>) {
-        let max_width = buffer.width();
-        let max_height = buffer.height();
-
+    fn draw(&self, canvas: &mut Canvas, background: Srgba<u8>) {
         let half_size = (self.size - 1) / 2;
-        let start_x = self.x - half_size;
-        let end_x = self.x + self.size / 2;
-        let start_y = self.y - half_size;
-        let end_y = self.y + self.size / 2;
+        let (start_x, end_x) =
+            canvas.clip_columns(self.x.saturating_sub(half_size), self.x + self.size / 2);
+        let (start_y, end_y) =
+            canvas.clip_rows(self.y.saturating_sub(half_size), self.y + self.size / 2);
         for x in start_x..=end_x {
             for y in start_y..=end_y {
                 if self.pixel_belongs(x, y) {
-                    if x < max_width && y < max_height {
-                        buffer.put_pixel(
-                            x,
-                            y,
-                            Rgba([
-                                self.color.red,
-                                self.color.green,
-                                self.color.blue,
-                                self.color.alpha,
-                            ]),
-                        );
-                    }
+                    canvas.paint(x, y, self.color, self.blend, background);
                 }
             }
         }
     }
 }
 
-#[derive(Debug)]
-struct Rectangle {
-    x: u32,
-    y: u32,
-    width: u32,
-    height: u32,
-    color: Srgba<u8>,
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rectangle {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub color: Srgba<u8>,
+    pub pattern: Pattern,
+    #[serde(default)]
+    pub blend: Blend,
 }
 
 impl Rectangle {
+    fn pixel_in_pattern(&self, x: u32, y: u32) -> bool {
+        // Pattern scales with height, so it looks the same at any size
+        let period = (self.height / 4).max(2);
+        let (x, y) = (x - self.x, y - self.y);
+        match self.pattern {
+            Pattern::Solid => true,
+            Pattern::Hatched => (x + y) % period < period / 2,
+            Pattern::Dotted => x % period < period / 2 && y % period < period / 2,
+        }
+    }
+
     fn pixel_belongs(&self, x: u32, y: u32, max_width: u32, max_height: u32) -> bool {
         x >= self.x
             && x < self.x + self.width
@@ -938,54 +2301,144 @@ impl Rectangle {
             && y < max_height
     }
 
-    fn draw(&self, buffer: &mut ImageBuffer<Rgba<u8>, Vec<u8>>) {
-        let max_width = buffer.width();
-        let max_height = buffer.height();
-
-        for x in self.x..=self.x + self.width {
-            for y in self.y..=self.y + self.height {
-                if self.pixel_belongs(x, y, max_width, max_height) {
-                    buffer.put_pixel(
-                        x,
-                        y,
-                        Rgba([
-                            self.color.red,
-                            self.color.green,
-                            self.color.blue,
-                            self.color.alpha,
-                        ]),
-                    );
+    fn draw(&self, canvas: &mut Canvas, background: Srgba<u8>) {
+        let (max_width, max_height) = (canvas.width, canvas.height);
+        let (start_x, end_x) = canvas.clip_columns(self.x, self.x + self.width);
+        let (start_y, end_y) = canvas.clip_rows(self.y, self.y + self.height);
+        for x in start_x..=end_x {
+            for y in start_y..=end_y {
+                if self.pixel_belongs(x, y, max_width, max_height) && self.pixel_in_pattern(x, y) {
+                    canvas.paint(x, y, self.color, self.blend, background);
                 }
             }
         }
     }
 }
 
+/// Rectangular part of the picture, rasterized on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Tile of the picture being rasterized. Shapes are drawn in coordinates of the whole picture,
+/// pixels outside of the tile are skipped.
+struct Canvas<'a> {
+    buffer: &'a mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    /// Top left corner of the tile.
+    left: u32,
+    top: u32,
+    /// Size of the whole picture.
+    width: u32,
+    height: u32,
+}
+
+impl Canvas<'_> {
+    fn pixel_mut(&mut self, x: u32, y: u32) -> Option<&mut Rgba<u8>> {
+        let column = x.checked_sub(self.left)?;
+        let row = y.checked_sub(self.top)?;
+        match column < self.buffer.width() && row < self.buffer.height() {
+            true => Some(self.buffer.get_pixel_mut(column, row)),
+            false => None,
+        }
+    }
+
+    /// Rows from `start` to `end` (inclusive) which are in the tile - empty range, when there are
+    /// none, so shapes don't go through rows they can't paint.
+    fn clip_rows(&self, start: u32, end: u32) -> (u32, u32) {
+        clip(start, end, self.top, self.buffer.height())
+    }
+
+    /// Columns from `start` to `end` (inclusive) which are in the tile.
+    fn clip_columns(&self, start: u32, end: u32) -> (u32, u32) {
+        clip(start, end, self.left, self.buffer.width())
+    }
+
+    /// Paint single pixel of the shape, according to its blend mode.
+    fn paint(&mut self, x: u32, y: u32, color: Srgba<u8>, mode: Blend, background: Srgba<u8>) {
+        if let Some(pixel) = self.pixel_mut(x, y) {
+            *pixel = match mode {
+                Blend::Over => blend(*pixel, color, color.alpha),
+                Blend::Clear => Rgba(background.into()),
+            };
+        }
+    }
+}
+
+fn clip(start: u32, end: u32, first: u32, length: u32) -> (u32, u32) {
+    match length {
+        0 => (1, 0),
+        length => (start.max(first), end.min(first + length - 1)),
+    }
+}
+
+/// Split `total` pixels proportionally to weights, with largest remainder method - each part
+/// gets whole pixels of its exact share, and pixels left are given to parts with the largest
+/// fractions (earlier ones on ties). Parts never differ from exact share by a pixel or more, and
+/// sum up to `total`. Negative weights count as zero, and without any weight pixels are split
+/// evenly.
+pub fn apportion(weights: &[f32], total: u32) -> Vec<u32> {
+    if weights.is_empty() {
+        return vec![];
+    }
+    let weights: Vec<f64> = weights.iter().map(|w| (*w as f64).max(0f64)).collect();
+    let sum: f64 = weights.iter().sum();
+    let quotas: Vec<f64> = match sum > 0f64 {
+        true => weights.iter().map(|w| w / sum * total as f64).collect(),
+        false => vec![total as f64 / weights.len() as f64; weights.len()],
+    };
+    let mut parts: Vec<u32> = quotas.iter().map(|q| q.floor() as u32).collect();
+    // Floating point errors must not give more than total
+    while parts.iter().sum::<u32>() > total {
+        let largest = (0..parts.len()).max_by_key(|i| parts[*i]).unwrap();
+        parts[largest] -= 1;
+    }
+
+    let mut order: Vec<usize> = (0..parts.len()).collect();
+    order.sort_by(|a, b| {
+        let remainder = |i: usize| quotas[i] - parts[i] as f64;
+        remainder(*b).total_cmp(&remainder(*a)).then(a.cmp(b))
+    });
+    let left = total - parts.iter().sum::<u32>();
+    for i in order.into_iter().cycle().take(left as usize) {
+        parts[i] += 1;
+    }
+    parts
+}
+
 fn abs(a: u32, b: u32) -> u32 {
     (a as i32 - b as i32).abs() as u32
 }
 
-struct Line {
-    x1: u32,
-    y1: u32,
-    x2: u32,
-    y2: u32,
-    border_size: u32,
-    color: Srgba<u8>,
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Line {
+    pub x1: u32,
+    pub y1: u32,
+    pub x2: u32,
+    pub y2: u32,
+    pub border_size: u32,
+    pub color: Srgba<u8>,
+    #[serde(default)]
+    pub blend: Blend,
 }
 
 impl Line {
-    fn draw(&self, buffer: &mut ImageBuffer<Rgba<u8>, Vec<u8>>) {
+    fn draw(&self, canvas: &mut Canvas, background: Srgba<u8>) {
         // Decide which point is the starting point
         let (x1, x2, y1, y2) = match (self.x1, self.x2, self.y1, self.y2) {
-            (x1, x2, y1, y2) if x1 > x2 => (x2 as i32, x1 as i32, y2 as i32, y1 as i32),
+            (x1, x2, y1, y2) if x1 > x2 || (x1 == x2 && y1 > y2) => {
+                (x2 as i32, x1 as i32, y2 as i32, y1 as i32)
+            }
             (x1, x2, y1, y2) => (x1 as i32, x2 as i32, y1 as i32, y2 as i32),
         };
 
         let half_border = ((self.border_size - 1) / 2) as i32;
 
-        let max_width = buffer.width() as i32;
-        let max_height = buffer.height() as i32;
+        let max_width = canvas.width as i32;
+        let max_height = canvas.height as i32;
 
         let mut pixels = Vec::new();
         if x1 == x2 {
@@ -999,19 +2452,34 @@ impl Line {
             }
         }
 
+        // Brush positions overlap, so each pixel is painted once - otherwise translucent lines
+        // would get darker where the brush passed more times.
+        let mut painted = HashSet::new();
+        let (first_row, last_row) = canvas.clip_rows(0, canvas.height.saturating_sub(1));
+        let (first_column, last_column) = canvas.clip_columns(0, canvas.width.saturating_sub(1));
         for (x, y) in pixels {
+            // Brush out of the tile rasterized now
+            if y + half_border < first_row as i32
+                || y - half_border > last_row as i32
+                || x + half_border < first_column as i32
+                || x - half_border > last_column as i32
+            {
+                continue;
+            }
             for i in -half_border..half_border + 1 {
                 for j in -half_border..half_border + 1 {
-                    if x + i < max_width && y + j < max_height && x + i >= 0 && y + j >= 0 {
-                        buffer.put_pixel(
+                    if x + i < max_width
+                        && y + j < max_height
+                        && x + i >= 0
+                        && y + j >= 0
+                        && painted.insert((x + i, y + j))
+                    {
+                        canvas.paint(
                             (x + i) as u32,
                             (y + j) as u32,
-                            Rgba([
-                                self.color.red,
-                                self.color.green,
-                                self.color.blue,
-                                self.color.alpha,
-                            ]),
+                            self.color,
+                            self.blend,
+                            background,
                         );
                     }
                 }
@@ -1020,8 +2488,168 @@ impl Line {
     }
 }
 
-enum Shape {
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Sector {
+    Top,
+    Left,
+    Right,
+    Bottom,
+}
+
+/// Circle anchored in its center, optionally only ring (of given width) or quarter of it - sectors
+/// are split diagonally, so they match positions of kite squares.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Circle {
+    pub x: u32,
+    pub y: u32,
+    pub radius: u32,
+    pub ring: Option<u32>,
+    pub sector: Option<Sector>,
+    pub color: Srgba<u8>,
+    #[serde(default)]
+    pub blend: Blend,
+}
+
+impl Circle {
+    fn pixel_belongs(&self, x: u32, y: u32) -> bool {
+        let dx = x as i64 - self.x as i64;
+        let dy = y as i64 - self.y as i64;
+        let distance = dx * dx + dy * dy;
+        let radius = self.radius as i64;
+        let in_circle = match self.ring {
+            Some(width) => {
+                let half_width = (width as i64 - 1) / 2;
+                let inner = (radius - half_width).max(0);
+                let outer = radius + half_width;
+                distance >= inner * inner && distance <= outer * outer + outer
+            }
+            None => distance <= radius * radius + radius,
+        };
+        let in_sector = match self.sector {
+            None => true,
+            Some(Sector::Top) => dx.abs() <= -dy,
+            Some(Sector::Bottom) => dx.abs() <= dy,
+            Some(Sector::Left) => dy.abs() <= -dx,
+            Some(Sector::Right) => dy.abs() <= dx,
+        };
+        in_circle && in_sector
+    }
+
+    fn draw(&self, canvas: &mut Canvas, background: Srgba<u8>) {
+        let reach = self.radius + self.ring.unwrap_or(0);
+        let (start_x, end_x) = canvas.clip_columns(self.x.saturating_sub(reach), self.x + reach);
+        let (start_y, end_y) = canvas.clip_rows(self.y.saturating_sub(reach), self.y + reach);
+        for x in start_x..=end_x {
+            for y in start_y..=end_y {
+                if self.pixel_belongs(x, y) {
+                    canvas.paint(x, y, self.color, self.blend, background);
+                }
+            }
+        }
+    }
+}
+
+/// Horizontal alignment of text, relative to its anchor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+/// Single line of text, drawn with bundled font. It is anchored in the top of the line, and
+/// horizontally according to alignment. Size is the font size in pixels.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Text {
+    pub x: u32,
+    pub y: u32,
+    pub size: u32,
+    pub content: String,
+    #[serde(default)]
+    pub align: Align,
+    pub color: Srgba<u8>,
+    /// Longer text is truncated in the middle, so it doesn't overflow (or get clipped).
+    #[serde(default)]
+    pub max_width: Option<u32>,
+    /// Shrink font (down to half of the size) before truncating text.
+    #[serde(default)]
+    pub shrink: bool,
+}
+
+impl Text {
+    fn draw(&self, canvas: &mut Canvas, renderer: &mut TextRenderer) {
+        let (content, size) = match self.max_width {
+            Some(max_width) => renderer.fit(&self.content, self.size, max_width, self.shrink),
+            None => (self.content.clone(), self.size),
+        };
+        let (width, _) = renderer.measure(&content, size);
+        let x = match self.align {
+            Align::Left => self.x as i32,
+            Align::Center => self.x as i32 - width as i32 / 2,
+            Align::Right => self.x as i32 - width as i32,
+        };
+        // Text renderer clips text to the tile on its own
+        let (x, y) = (x - canvas.left as i32, self.y as i32 - canvas.top as i32);
+        renderer.draw(canvas.buffer, &content, x, y, size, self.color);
+    }
+}
+
+/// Shapes that layouts are built of.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Shape {
     Square(Square),
     Rectangle(Rectangle),
     Line(Line),
+    Circle(Circle),
+    Text(Text),
+}
+
+impl Shape {
+    /// The same shape, moved right and down by given number of pixels.
+    pub fn translated(&self, dx: u32, dy: u32) -> Shape {
+        match self {
+            Shape::Square(square) => Shape::Square(Square {
+                x: square.x + dx,
+                y: square.y + dy,
+                ..square.clone()
+            }),
+            Shape::Rectangle(rectangle) => Shape::Rectangle(Rectangle {
+                x: rectangle.x + dx,
+                y: rectangle.y + dy,
+                ..rectangle.clone()
+            }),
+            Shape::Line(line) => Shape::Line(Line {
+                x1: line.x1 + dx,
+                y1: line.y1 + dy,
+                x2: line.x2 + dx,
+                y2: line.y2 + dy,
+                ..line.clone()
+            }),
+            Shape::Circle(circle) => Shape::Circle(Circle {
+                x: circle.x + dx,
+                y: circle.y + dy,
+                ..circle.clone()
+            }),
+            Shape::Text(text) => Shape::Text(Text {
+                x: text.x + dx,
+                y: text.y + dy,
+                ..text.clone()
+            }),
+        }
+    }
+}
+
+/// Color as CSS hex code, with alpha only when it isn't opaque.
+fn css_color(color: Srgba<u8>) -> String {
+    match color.alpha {
+        255 => format!("#{:02x}{:02x}{:02x}", color.red, color.green, color.blue),
+        alpha => format!(
+            "#{:02x}{:02x}{:02x}{:02x}",
+            color.red, color.green, color.blue, alpha
+        ),
+    }
 }
@@ -1,57 +1,492 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+#[cfg(feature = "std")]
+pub mod cache;
+/// Hues of the substance, usable without std (like in label printers or WASM).
+pub mod core;
+#[cfg(feature = "render")]
+pub mod export;
+#[cfg(feature = "std")]
+pub mod formula;
+#[cfg(feature = "render")]
 pub mod layouts;
+#[cfg(feature = "render")]
+pub mod pdf;
+#[cfg(feature = "remote")]
+pub mod remote;
+#[cfg(feature = "std")]
+pub mod resolve;
+#[cfg(feature = "render")]
+pub mod svg;
+#[cfg(feature = "render")]
+pub mod terminal;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "render")]
+pub mod text;
+#[cfg(feature = "render")]
+pub mod theme;
+#[cfg(feature = "std")]
+pub mod timing;
+#[cfg(feature = "std")]
 pub mod tokenize;
-use crate::layouts::Picture;
-use crate::tokenize::generate_mixture_tree;
+#[cfg(feature = "render")]
+pub mod writers;
+#[cfg(feature = "std")]
+use crate::core::{
+    hashed_part, shade_from_hash, substance_hash, Analogous, Classic, HueStrategy, Shade, Tetradic,
+    Triadic,
+};
+#[cfg(feature = "std")]
+use crate::formula::parse_inchi_formula;
+#[cfg(feature = "render")]
+use crate::layouts::{Charge, Markers, Picture, Stereo};
+#[cfg(feature = "render")]
+use crate::timing::{measure, Stage};
+#[cfg(feature = "std")]
+use crate::tokenize::{
+    check_contents, check_indices, generate_mixture_tree_lenient, is_known_index, ParseError,
+    Warning,
+};
+#[cfg(feature = "render")]
+use crate::tokenize::{generate_mixture_tree, Ingredient, Substance};
+#[cfg(feature = "std")]
 use log::{debug, info};
+#[cfg(feature = "std")]
 use num_bigint::BigUint;
-use num_traits::Zero;
+#[cfg(feature = "std")]
 use palette::{FromColor, Hsv, Srgb};
-use sha2::{Digest, Sha512};
+#[cfg(feature = "std")]
+use std::fmt;
 
+/// Color of the scheme. New fields may be added, so colors come from schemes rather than being
+/// written out.
+#[cfg(feature = "std")]
+#[non_exhaustive]
 pub struct Color {
     pub hue: u32,
+    pub shade: Shade,
     pub srgb: Srgb<u8>,
 }
 
+/// Colors of the substance. New fields may be added, so schemes are made with `Scheme::new` (or
+/// calculated) rather than written out.
+#[cfg(feature = "std")]
+#[non_exhaustive]
 pub struct Scheme {
     pub primary: Color,
     pub first_accent: Color,
     pub second_accent: Color,
     pub complementary: Color,
+    /// Short identifier of the substance (see `short_id`), None when scheme is made of hues only.
+    pub short_id: Option<String>,
 }
 
-fn to_color(hue: u32) -> Srgb<u8> {
-    Srgb::from_color(Hsv::new(hue as f32, 0.7, 0.9)).into_format()
+// Schemes are calculated once and shared between threads
+#[cfg(feature = "std")]
+const _: fn() = || {
+    fn shared_between_threads<T: Send + Sync>() {}
+    shared_between_threads::<Scheme>();
+    shared_between_threads::<Color>();
+    shared_between_threads::<&dyn HueStrategy>();
+};
+
+/// Hex code of sRGB color, like `#e6a345`.
+#[cfg(feature = "std")]
+pub(crate) fn srgb_hex(srgb: Srgb<u8>) -> String {
+    format!("#{:02x}{:02x}{:02x}", srgb.red, srgb.green, srgb.blue)
+}
+
+#[cfg(feature = "std")]
+impl Color {
+    /// Color as hex code, like `#e6a345`.
+    pub fn to_hex(&self) -> String {
+        srgb_hex(self.srgb)
+    }
+
+    /// Red, green and blue channels, from 0 to 255.
+    pub fn rgb_tuple(&self) -> (u8, u8, u8) {
+        (self.srgb.red, self.srgb.green, self.srgb.blue)
+    }
+
+    /// Color in HSV, which it was converted from.
+    pub fn hsv(&self) -> Hsv {
+        hsv(self.hue, self.shade)
+    }
+}
+
+/// Hex code, like `#e6a345`.
+#[cfg(feature = "std")]
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(&self.to_hex())
+    }
+}
+
+/// Relative luminance of the color, as defined by WCAG 2.
+#[cfg(feature = "std")]
+fn relative_luminance(color: Srgb<u8>) -> f64 {
+    let linear = |channel: u8| {
+        let value = channel as f64 / 255f64;
+        match value <= 0.04045 {
+            true => value / 12.92,
+            false => ((value + 0.055) / 1.055).powf(2.4),
+        }
+    };
+    0.2126 * linear(color.red) + 0.7152 * linear(color.green) + 0.0722 * linear(color.blue)
+}
+
+/// Contrast ratio of two colors as defined by WCAG 2 - from 1 (the same luminance) to 21 (black
+/// and white). Text needs at least 4.5 (level AA), large text and graphics at least 3.
+#[cfg(feature = "std")]
+pub fn contrast_ratio(first: Srgb<u8>, second: Srgb<u8>) -> f64 {
+    let (first, second) = (relative_luminance(first), relative_luminance(second));
+    (first.max(second) + 0.05) / (first.min(second) + 0.05)
+}
+
+#[cfg(feature = "std")]
+fn to_color(hue: u32, shade: Shade) -> Srgb<u8> {
+    if shade != Shade::default() {
+        return convert_hue(hue, shade);
+    }
+    match hue_colors().get(hue as usize) {
+        Some(color) => *color,
+        None => convert_hue(hue, shade),
+    }
+}
+
+#[cfg(feature = "std")]
+fn hsv(hue: u32, shade: Shade) -> Hsv {
+    Hsv::new(
+        hue as f32,
+        shade.saturation as f32 / 100.0,
+        shade.value as f32 / 100.0,
+    )
+}
+
+#[cfg(feature = "std")]
+fn convert_hue(hue: u32, shade: Shade) -> Srgb<u8> {
+    Srgb::from_color(hsv(hue, shade)).into_format()
+}
+
+/// Colors of all whole hues in default shade, converted once - schemes of many substances share
+/// them, instead of going through HSV conversion for each color.
+#[cfg(feature = "std")]
+fn hue_colors() -> &'static [Srgb<u8>; 360] {
+    static COLORS: std::sync::OnceLock<[Srgb<u8>; 360]> = std::sync::OnceLock::new();
+    COLORS.get_or_init(|| std::array::from_fn(|hue| convert_hue(hue as u32, Shade::default())))
+}
+
+/// Colors of given hues (in degrees), the same as colors of schemes with them.
+#[cfg(feature = "std")]
+pub fn hues_to_srgb(hues: &[u32]) -> Vec<Srgb<u8>> {
+    hues.iter()
+        .map(|hue| to_color(*hue, Shade::default()))
+        .collect()
 }
 
+#[cfg(feature = "std")]
 impl Scheme {
+    /// Scheme of given hues, in default shade.
     pub fn new(primary: u32, first_accent: u32, second_accent: u32, complementary: u32) -> Self {
+        let color = |hue| Color {
+            hue,
+            shade: Shade::default(),
+            srgb: to_color(hue, Shade::default()),
+        };
+        Self {
+            primary: color(primary),
+            first_accent: color(first_accent),
+            second_accent: color(second_accent),
+            complementary: color(complementary),
+            short_id: None,
+        }
+    }
+
+    /// The same hues in another shade.
+    pub fn with_shade(mut self, shade: Shade) -> Self {
+        for color in [
+            &mut self.primary,
+            &mut self.first_accent,
+            &mut self.second_accent,
+            &mut self.complementary,
+        ] {
+            color.shade = shade;
+            color.srgb = to_color(color.hue, shade);
+        }
+        self
+    }
+
+    pub fn with_short_id(mut self, short_id: Option<String>) -> Self {
+        self.short_id = short_id;
+        self
+    }
+
+    /// Primary, first accent, second accent and complementary color.
+    pub fn colors(&self) -> [&Color; 4] {
+        Role::ALL.map(|role| self.get(role))
+    }
+
+    pub fn get(&self, role: Role) -> &Color {
+        match role {
+            Role::Primary => &self.primary,
+            Role::FirstAccent => &self.first_accent,
+            Role::SecondAccent => &self.second_accent,
+            Role::Complementary => &self.complementary,
+        }
+    }
+
+    /// Colors with their roles, in order of `Role::ALL`.
+    pub fn iter(&self) -> impl Iterator<Item = (Role, &Color)> {
+        Role::ALL.into_iter().zip(self.colors())
+    }
+
+    /// Ladder of lighter and darker variants of the colors, for theming of user interfaces -
+    /// levels spread evenly from 100 (almost white) to 900 (almost black), where 500 is the
+    /// scheme itself. 9 steps give levels 100, 200 and so on, single step gives level 500 only.
+    pub fn variants(&self, steps: usize) -> Vec<Variant> {
+        (0..steps)
+            .map(|step| {
+                let level = match steps {
+                    1 => 500,
+                    _ => 100 + (800 * step / (steps - 1)) as u32,
+                };
+                Variant {
+                    level,
+                    colors: self.colors().map(|color| vary(color.srgb, level)),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Colors of the scheme lightened or darkened to the same level (see `Scheme::variants`).
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Variant {
+    pub level: u32,
+    /// Colors in order of `Role::ALL`.
+    pub colors: [Srgb<u8>; 4],
+}
+
+#[cfg(feature = "std")]
+impl Variant {
+    pub fn get(&self, role: Role) -> Srgb<u8> {
+        self.colors[role as usize]
+    }
+}
+
+/// Share of white in level 100.
+#[cfg(feature = "std")]
+const LIGHTEST_TINT: f32 = 0.9;
+/// Share of black in level 900.
+#[cfg(feature = "std")]
+const DARKEST_SHADE: f32 = 0.8;
+
+/// Mix color with white (below level 500) or black (above it).
+#[cfg(feature = "std")]
+fn vary(color: Srgb<u8>, level: u32) -> Srgb<u8> {
+    let color: Srgb = color.into_format();
+    let shift = (level as f32 - 500.0) / 400.0;
+    let (target, amount) = match shift < 0.0 {
+        true => (1.0, -shift * LIGHTEST_TINT),
+        false => (0.0, shift * DARKEST_SHADE),
+    };
+    let mix = |channel: f32| channel + (target - channel) * amount;
+    Srgb::new(mix(color.red), mix(color.green), mix(color.blue)).into_format()
+}
+
+#[cfg(feature = "std")]
+impl std::ops::Index<Role> for Scheme {
+    type Output = Color;
+
+    fn index(&self, role: Role) -> &Color {
+        self.get(role)
+    }
+}
+
+/// Role of color in the scheme.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Role {
+    Primary,
+    FirstAccent,
+    SecondAccent,
+    Complementary,
+}
+
+#[cfg(feature = "std")]
+impl Role {
+    pub const ALL: [Role; 4] = [
+        Role::Primary,
+        Role::FirstAccent,
+        Role::SecondAccent,
+        Role::Complementary,
+    ];
+
+    /// Name used in outputs, like `first_accent`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Role::Primary => "primary",
+            Role::FirstAccent => "first_accent",
+            Role::SecondAccent => "second_accent",
+            Role::Complementary => "complementary",
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+/// Hex codes of colors (see `colors`), separated by spaces.
+#[cfg(feature = "std")]
+impl fmt::Display for Scheme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [first, rest @ ..] = self.colors();
+        write!(f, "{}", first)?;
+        for color in rest {
+            write!(f, " {}", color)?;
+        }
+        Ok(())
+    }
+}
+
+/// How hues of the scheme are chosen.
+#[cfg(feature = "std")]
+#[derive(clap::ValueEnum, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum SchemeMode {
+    /// Hues depend on hash of the substance only.
+    #[default]
+    Hash,
+    /// Accent hues are nudged towards color family of elements found in the substance
+    /// (like green for halogens), which gives weak hint about composition.
+    Composition,
+}
+
+/// Version of schemes - new versions change colors, so they are never default.
+#[cfg(feature = "std")]
+#[derive(clap::ValueEnum, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum SchemeVersion {
+    /// Saturation and value are the same for all substances.
+    #[default]
+    #[value(name = "1")]
+    V1,
+    /// Saturation and value come from hash too, so substances with close hues are easier to
+    /// tell apart.
+    #[value(name = "2")]
+    V2,
+}
+
+/// Built-in strategies of relationship of hues in the scheme (see `core::HueStrategy`).
+#[cfg(feature = "std")]
+#[derive(clap::ValueEnum, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum Harmony {
+    /// Complementary hue about half of the wheel away from primary one, accents anywhere
+    /// between them.
+    #[default]
+    Classic,
+    /// Accents about a third of the wheel away from primary hue.
+    Triadic,
+    /// Accents 30 to 90 degrees from primary and complementary hue.
+    Tetradic,
+    /// Accents close to primary hue.
+    Analogous,
+}
+
+#[cfg(feature = "std")]
+impl Harmony {
+    pub fn strategy(&self) -> &'static dyn HueStrategy {
+        match self {
+            Harmony::Classic => &Classic,
+            Harmony::Triadic => &Triadic,
+            Harmony::Tetradic => &Tetradic,
+            Harmony::Analogous => &Analogous,
+        }
+    }
+}
+
+/// How schemes are calculated. Default options give schemes of `calculate_scheme`. New options
+/// may be added, so they are set with `with_*` methods rather than written out.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy)]
+#[non_exhaustive]
+pub struct SchemeOptions<'a> {
+    pub mode: SchemeMode,
+    /// Relationship of hues in the scheme, like `Harmony::Triadic.strategy()`.
+    pub strategy: &'a dyn HueStrategy,
+    pub version: SchemeVersion,
+}
+
+#[cfg(feature = "std")]
+impl Default for SchemeOptions<'_> {
+    fn default() -> Self {
         Self {
-            primary: Color {
-                hue: primary,
-                srgb: to_color(primary),
-            },
-            first_accent: Color {
-                hue: first_accent,
-                srgb: to_color(first_accent),
-            },
-            second_accent: Color {
-                hue: second_accent,
-                srgb: to_color(second_accent),
-            },
-            complementary: Color {
-                hue: complementary,
-                srgb: to_color(complementary),
-            },
+            mode: SchemeMode::default(),
+            strategy: &Classic,
+            version: SchemeVersion::default(),
         }
     }
 }
 
+#[cfg(feature = "std")]
+impl<'a> SchemeOptions<'a> {
+    pub fn with_mode(self, mode: SchemeMode) -> Self {
+        Self { mode, ..self }
+    }
+
+    pub fn with_strategy(self, strategy: &'a dyn HueStrategy) -> Self {
+        Self { strategy, ..self }
+    }
+
+    pub fn with_version(self, version: SchemeVersion) -> Self {
+        Self { version, ..self }
+    }
+}
+
+/// Families of elements and hues that accents are nudged towards, earlier wins ties.
+#[cfg(feature = "std")]
+const ELEMENT_FAMILY_HUES: [(&[&str], u32); 4] = [
+    // Halogens - green, like chlorine gas
+    (&["F", "Cl", "Br", "I", "At"], 120),
+    // Chalcogens (other than oxygen) - yellow, like sulfur
+    (&["S", "Se", "Te"], 55),
+    // Phosphorus and arsenic - orange
+    (&["P", "As"], 30),
+    // Nitrogen - blue
+    (&["N"], 230),
+];
+
+/// Maximum shift (in degrees) of accent hue in composition mode.
+#[cfg(feature = "std")]
+const MAX_COMPOSITION_NUDGE: u32 = 60;
+
+#[cfg(feature = "render")]
 pub fn generate_moleco(
     payload: String,
     base_size: u32,
     border_size_percent_points: u32,
     strict_version_check: bool,
+) -> Result<Picture, String> {
+    generate_moleco_with(
+        payload,
+        base_size,
+        border_size_percent_points,
+        strict_version_check,
+        SchemeOptions::default(),
+    )
+}
+
+/// Picture with schemes calculated with given options (see `calculate_scheme_with`).
+#[cfg(feature = "render")]
+pub fn generate_moleco_with(
+    payload: String,
+    base_size: u32,
+    border_size_percent_points: u32,
+    strict_version_check: bool,
+    options: SchemeOptions,
 ) -> Result<Picture, String> {
     if payload.starts_with("InChI=") {
         if !payload.starts_with("InChI=1S/") && strict_version_check {
@@ -64,6 +499,7 @@ pub fn generate_moleco(
             payload,
             base_size,
             border_size_percent_points,
+            options,
         )?)
     } else if payload.starts_with("MInChI=") {
         if !payload.starts_with("MInChI=0.00.1S/") && strict_version_check {
@@ -76,6 +512,7 @@ pub fn generate_moleco(
             payload,
             base_size,
             border_size_percent_points,
+            options,
         )?)
     } else if payload.starts_with("InChIKey=") || payload.starts_with("MInChIKey=") {
         Err("Keys are not supported. Check readme for more info.".to_string())
@@ -84,42 +521,96 @@ pub fn generate_moleco(
     }
 }
 
-pub fn calculate_scheme(substance: String) -> Scheme {
-    let substance = match substance {
-        s if s.starts_with("InChI=") => s[6..].to_string(),
-        s => s,
+/// Length of short identifier, in bytes of the hash. 80 bits make collisions practically
+/// impossible and encode to 16 characters without padding.
+#[cfg(feature = "std")]
+const SHORT_ID_BYTES: usize = 10;
+
+#[cfg(feature = "std")]
+fn encode_short_id(hash: &[u8]) -> String {
+    data_encoding::BASE32_NOPAD
+        .encode(&hash[..SHORT_ID_BYTES])
+        .to_lowercase()
+}
+
+/// Clean up substance copied from other programs, so it hashes the same as typed one. Byte order
+/// mark (put at the start of files by Windows tools), carriage returns (of CRLF lines) and
+/// surrounding whitespace are dropped. With `url_decode` percent-encoded characters (like
+/// `InChI%3D1S%2FH2O%2Fh1H2` from web forms) are decoded too - but not `+`, which is part of
+/// InChI charges.
+#[cfg(feature = "std")]
+pub fn normalize_input(input: &str, url_decode: bool) -> String {
+    let input = input.trim_start_matches('\u{feff}');
+    let input = match url_decode {
+        true => percent_decode(input),
+        false => input.to_string(),
     };
-    info!("Substance: {}", substance);
+    input.replace('\r', "").trim().to_string()
+}
 
-    let mut hasher = Sha512::new();
-    hasher.update(substance);
-    let result = hasher.finalize();
-    debug!(" -> Raw hash: {:?}", result);
-    let mut sum: BigUint = Zero::zero();
-    for i in result.iter() {
-        sum <<= 8;
-        let step = i.clone() as u64;
-        sum += step;
+/// Decode `%XX` sequences, invalid ones are kept as they are.
+#[cfg(feature = "std")]
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = bytes
+            .get(index + 1..index + 3)
+            // Radix parsing alone would take sign, like `%+1`
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[index], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
     }
-    info!(" -> Substance hash: {}", sum);
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// Compact identifier of the substance, like `73vquxf2pgyl4etw` (water) - lowercase base32 of
+/// the first 10 bytes of the same SHA-512 hash that hues come from. Safe to use in filenames.
+#[cfg(feature = "std")]
+pub fn short_id(substance: &str) -> String {
+    encode_short_id(&substance_hash(substance))
+}
 
-    let primary_hue = modulo(&sum, 360);
-    let complementary_hue = primary_hue + 165 + modulo(&sum, 30);
-    let first_accent_hue = primary_hue + modulo(&sum, (complementary_hue - 5) - (primary_hue + 5));
-    let second_accent_hue =
-        complementary_hue + modulo(&sum, (primary_hue + 355) - (complementary_hue + 5));
+#[cfg(feature = "std")]
+pub fn calculate_scheme(substance: String) -> Scheme {
+    hashed_scheme(substance, &Classic, SchemeVersion::V1)
+}
 
-    // Normalization of hues, as they can go over 360
-    let complementary_hue = complementary_hue % 360;
-    let first_accent_hue = first_accent_hue % 360;
-    let second_accent_hue = second_accent_hue % 360;
+#[cfg(feature = "std")]
+fn hashed_scheme(
+    substance: String,
+    strategy: &dyn HueStrategy,
+    scheme_version: SchemeVersion,
+) -> Scheme {
+    info!("Substance: {}", hashed_part(&substance));
 
+    let result = substance_hash(&substance);
+    debug!(" -> Raw hash: {:?}", result);
+    info!(" -> Substance hash: {}", BigUint::from_bytes_be(&result));
+
+    let hues = strategy.hues(&result);
     let scheme = Scheme::new(
-        primary_hue,
-        first_accent_hue,
-        second_accent_hue,
-        complementary_hue,
-    );
+        hues.primary,
+        hues.first_accent,
+        hues.second_accent,
+        hues.complementary,
+    )
+    .with_short_id(Some(encode_short_id(&result)));
+    let scheme = match scheme_version {
+        SchemeVersion::V1 => scheme,
+        SchemeVersion::V2 => scheme.with_shade(shade_from_hash(&result)),
+    };
     info!(
         " -> Hues, primary: {}, complementary: {}, first accent: {}, second accent: {}",
         scheme.primary.hue,
@@ -130,6 +621,130 @@ pub fn calculate_scheme(substance: String) -> Scheme {
     scheme
 }
 
+/// Calculate scheme with given options - hues related by their strategy, optionally nudged by
+/// composition of the substance. Substances without parsable formula get the same scheme in
+/// every mode.
+#[cfg(feature = "std")]
+pub fn calculate_scheme_with(substance: String, options: SchemeOptions) -> Scheme {
+    let formula = match options.mode {
+        SchemeMode::Hash => None,
+        SchemeMode::Composition => parse_inchi_formula(&substance).ok(),
+    };
+    let scheme = hashed_scheme(substance, options.strategy, options.version);
+    let Some(formula) = formula else {
+        return scheme;
+    };
+
+    let Ok(counts) = formula.element_counts() else {
+        return scheme;
+    };
+    let heavy_atoms: usize = counts
+        .iter()
+        .filter(|(element, _)| element.as_str() != "H")
+        .map(|(_, count)| count)
+        .sum();
+    let mut dominant: Option<(usize, u32)> = None;
+    for (elements, hue) in ELEMENT_FAMILY_HUES {
+        let atoms: usize = elements.iter().filter_map(|e| counts.get(*e)).sum();
+        if atoms > 0 && dominant.is_none_or(|(most, _)| atoms > most) {
+            dominant = Some((atoms, hue));
+        }
+    }
+    let Some((atoms, target)) = dominant else {
+        return scheme;
+    };
+
+    // Even single heteroatom in the molecule should be noticeable, quarter of atoms is enough
+    // for full shift.
+    let nudge = (MAX_COMPOSITION_NUDGE * 4 * atoms as u32)
+        .div_ceil(heavy_atoms.max(1) as u32)
+        .min(MAX_COMPOSITION_NUDGE);
+    debug!(" -> Composition nudge: {} towards hue {}", nudge, target);
+
+    // Accents must stay on their sides of the wheel (see HueStrategy), so hues are unwrapped
+    // to keep order primary < first accent < complementary < second accent.
+    let primary = scheme.primary.hue;
+    let complementary = unwrap_hue(scheme.complementary.hue, primary);
+    let first_accent = unwrap_hue(scheme.first_accent.hue, primary);
+    let second_accent = unwrap_hue(scheme.second_accent.hue, complementary);
+    Scheme::new(
+        primary,
+        nudge_hue(
+            first_accent,
+            target,
+            nudge,
+            (primary + 5, complementary - 5),
+        ),
+        nudge_hue(
+            second_accent,
+            target,
+            nudge,
+            (complementary + 5, primary + 355),
+        ),
+        scheme.complementary.hue,
+    )
+    .with_shade(scheme.primary.shade)
+    .with_short_id(scheme.short_id)
+}
+
+/// Represent hue as value not lower than given base.
+#[cfg(feature = "std")]
+fn unwrap_hue(hue: u32, base: u32) -> u32 {
+    hue + base.saturating_sub(hue).div_ceil(360) * 360
+}
+
+/// Move (unwrapped) hue towards target the shorter way around the wheel, but not further than
+/// target and not outside of given bounds.
+#[cfg(feature = "std")]
+fn nudge_hue(hue: u32, target: u32, amount: u32, bounds: (u32, u32)) -> u32 {
+    let forward = (target + 360 - hue % 360) % 360;
+    let nudged = if forward <= 180 {
+        (hue + forward.min(amount)).min(bounds.1.max(hue))
+    } else {
+        hue.saturating_sub((360 - forward).min(amount))
+            .max(bounds.0.min(hue))
+    };
+    nudged % 360
+}
+
+/// Group hues which are close to each other - each hue in a group is at most `threshold`
+/// degrees from its neighbour, so group can span more than threshold (like gradient of reds
+/// and oranges). Returns indices of hues, both groups and their members ordered by hue.
+#[cfg(feature = "std")]
+pub fn cluster_hues(hues: &[u32], threshold: u32) -> Vec<Vec<usize>> {
+    let mut order: Vec<usize> = (0..hues.len()).collect();
+    order.sort_by_key(|index| (hues[*index] % 360, *index));
+    // Distance to the next hue clockwise
+    let gap = |from: usize, to: usize| (hues[to] % 360 + 360 - hues[from] % 360) % 360;
+
+    // Wheel is cut at a gap bigger than threshold, if there is any - so red hues on both sides
+    // of 0 end up in the same group.
+    let cut = (0..order.len())
+        .find(|position| gap(order[*position], order[(position + 1) % order.len()]) > threshold);
+    let Some(cut) = cut else {
+        return match order.is_empty() {
+            true => vec![],
+            false => vec![order],
+        };
+    };
+    order.rotate_left(cut + 1);
+
+    let mut clusters: Vec<Vec<usize>> = vec![];
+    let mut previous: Option<usize> = None;
+    for index in order {
+        match previous {
+            Some(previous) if gap(previous, index) <= threshold => {
+                clusters.last_mut().unwrap().push(index)
+            }
+            _ => clusters.push(vec![index]),
+        }
+        previous = Some(index);
+    }
+    clusters.sort_by_key(|cluster| hues[cluster[0]] % 360);
+    clusters
+}
+
+#[cfg(feature = "std")]
 pub fn modulo(divident: &BigUint, divisor: u32) -> u32 {
     let rest = divident % BigUint::from(divisor);
     let mut result: u32 = 0;
@@ -140,54 +755,454 @@ pub fn modulo(divident: &BigUint, divisor: u32) -> u32 {
     result
 }
 
+#[cfg(feature = "render")]
 pub fn generate_for_inchi(
     substance: String,
     base_size: u32,
     border_size_percent_points: u32,
+    options: SchemeOptions,
 ) -> Result<Picture, String> {
     let (actual_size, actual_border_size) = check_sizes(base_size, border_size_percent_points)?;
-    let scheme = calculate_scheme(substance);
+    let markers = detect_markers(&substance);
+    let scheme = measure(Stage::Hashing, || calculate_scheme_with(substance, options));
 
-    Ok(Picture::new(
-        actual_size,
-        actual_border_size,
-        vec![scheme],
-        None,
-    ))
+    Ok(
+        Picture::new(actual_size, actual_border_size, vec![scheme], None)
+            .with_markers(vec![markers]),
+    )
 }
 
+#[cfg(feature = "render")]
 pub fn generate_for_minchi(
     substance: String,
     base_size: u32,
     border_size_percent_points: u32,
+    options: SchemeOptions,
 ) -> Result<Picture, String> {
     let (actual_size, actual_border_size) = check_sizes(base_size, border_size_percent_points)?;
+    let (molecules, mixture) = measure(Stage::Parsing, || {
+        let (molecules, indexing, concentration) = split_minchi(&substance)?;
+        let mixture =
+            generate_mixture_tree(&indexing, &concentration).map_err(|e| e.within(&substance))?;
+        Ok::<_, String>((molecules, mixture))
+    })?;
+    let mut warnings = check_contents(&mixture);
+    warnings.extend(check_indices(&mixture, molecules.len()));
+    let mixture_info = Some(mixture);
+
+    let markers = molecules.iter().map(|m| detect_markers(m)).collect();
+    let schemes = measure(Stage::Hashing, || {
+        molecules
+            .into_iter()
+            .map(|molecule| calculate_scheme_with(molecule, options))
+            .collect()
+    });
+
+    Ok(
+        Picture::new(actual_size, actual_border_size, schemes, mixture_info)
+            .with_markers(markers)
+            .with_warnings(warnings),
+    )
+}
+
+/// Component of the mixture, see `composition`.
+#[cfg(feature = "render")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Component {
+    /// Index of the component, starting from 1 as in MInChI.
+    pub index: usize,
+    /// Standard InChI of the component.
+    pub inchi: String,
+    /// Contents of the component from concentration layer in canonical form (like `10mr-1`) -
+    /// decimals become integers of lower magnitude, so `33.3vp0` is given as `333vp-1`. One for
+    /// each place it is listed at in indexing layer, empty when nothing is known.
+    pub contents: Vec<String>,
+    /// Share of the component in the mixture, the same one mixture bar is drawn from. Zero when
+    /// it can't be estimated.
+    pub fraction: f32,
+}
+
+/// Components of MInChI in order, with their contents and shares in the mixture - numbers the
+/// mixture bar is made of, without drawing anything.
+#[cfg(feature = "render")]
+pub fn composition(substance: &str) -> Result<Vec<Component>, String> {
+    let (molecules, indexing, concentration) = split_minchi(substance)?;
+    let mixture =
+        generate_mixture_tree(&indexing, &concentration).map_err(|e| e.within(substance))?;
+    let mut contents = vec![vec![]; molecules.len()];
+    collect_contents(&mixture.ingredients, &mut contents);
+    let picture = generate_for_minchi(substance.to_string(), 64, 3, SchemeOptions::default())?;
+    let segments = picture.bar_segments()?;
+    Ok(molecules
+        .into_iter()
+        .zip(contents)
+        .enumerate()
+        .map(|(position, (molecule, contents))| Component {
+            index: position + 1,
+            inchi: format!("InChI=1S/{}", molecule),
+            contents,
+            fraction: segments
+                .iter()
+                .filter(|segment| segment.component == Some(position + 1))
+                .map(|segment| segment.fraction)
+                .sum(),
+        })
+        .collect())
+}
+
+#[cfg(feature = "render")]
+fn collect_contents(ingredients: &[Ingredient], contents: &mut [Vec<String>]) {
+    for ingredient in ingredients {
+        match ingredient {
+            Ingredient::Mixture(mixture) => collect_contents(&mixture.ingredients, contents),
+            Ingredient::Substance(Substance {
+                index: Some(index),
+                content,
+            }) if is_known_index(index, contents.len()) => {
+                let index = index.parse::<usize>().unwrap();
+                contents[index - 1].extend(content.iter().map(|content| content.to_string()));
+            }
+            Ingredient::Substance(_) | Ingredient::Invalid(_) => {}
+        }
+    }
+}
+
+/// Detect features of the substance flagged by markers, based on its InChI layers.
+/// Works both for InChI and molecules of MInChI (which lack prefix and version).
+#[cfg(feature = "render")]
+pub fn detect_markers(substance: &str) -> Markers {
+    // First chunk is either version or formula, none of them is interesting here.
+    let layers: Vec<&str> = substance.split('/').skip(1).collect();
+    let has_layer = |prefix: char| layers.iter().any(|layer| layer.starts_with(prefix));
+
+    let stereo = if layers.contains(&"m1") {
+        Some(Stereo::Inverted)
+    } else if has_layer('b') || has_layer('t') || has_layer('m') || has_layer('s') {
+        Some(Stereo::Normal)
+    } else {
+        None
+    };
+
+    let charge = detect_charge(&layers);
+
+    Markers {
+        stereo,
+        charge,
+        isotopes: has_layer('i'),
+    }
+}
+
+/// Charge is sum of charges of components (`/q`, like `q;2*+1`) and added or removed
+/// protons (`/p`). Charged components which sum up to zero are considered neutralized.
+#[cfg(feature = "render")]
+fn detect_charge(layers: &[&str]) -> Option<Charge> {
+    let mut total: i64 = 0;
+    let mut charged = false;
+    for layer in layers {
+        if let Some(charges) = layer.strip_prefix('q') {
+            for charge in charges.split(';').filter(|c| !c.is_empty()) {
+                let (count, charge) = match charge.split_once('*') {
+                    Some((count, charge)) => (count.parse::<i64>().ok()?, charge),
+                    None => (1, charge),
+                };
+                let charge = charge.parse::<i64>().ok()?;
+                charged |= charge != 0;
+                total += count * charge;
+            }
+        } else if let Some(protons) = layer.strip_prefix('p') {
+            let protons = protons.parse::<i64>().ok()?;
+            charged |= protons != 0;
+            total += protons;
+        }
+    }
+    match (charged, total) {
+        (false, _) => None,
+        (true, 0) => Some(Charge::Neutralized),
+        (true, t) if t > 0 => Some(Charge::Positive),
+        (true, _) => Some(Charge::Negative),
+    }
+}
+
+/// Split MInChI into its molecules, indexing and concentration layers.
+///
+/// Real world MInChIs often lack quantitative data, so both indexing and concentration layers
+/// may be empty (`/g`) or absent. Missing indexing lists all molecules in order, and missing
+/// concentration leaves every component without content (thus with unknown width).
+#[cfg(feature = "std")]
+fn split_minchi(substance: &str) -> Result<(Vec<String>, String, String), String> {
+    let MinchiLayers {
+        molecules,
+        indexing,
+        concentration,
+    } = minchi_layers(substance)?;
+    let indexing = match indexing {
+        Some(indexing) => indexing.to_string(),
+        None => {
+            let indices: Vec<String> = (1..=molecules.len()).map(|i| i.to_string()).collect();
+            format!("n{{{}}}", indices.join("&"))
+        }
+    };
+    let concentration = match concentration {
+        Some(concentration) => concentration.to_string(),
+        // Same structure as indexing, but with all values empty
+        None => indexing
+            .chars()
+            .skip(1)
+            .filter(|c| matches!(c, '{' | '}' | '&'))
+            .fold("g".to_string(), |mut layer, c| {
+                layer.push(c);
+                layer
+            }),
+    };
+    Ok((molecules, indexing, concentration))
+}
+
+/// Layers of MInChI as they are written, indexing and concentration are None when they are empty
+/// or absent.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MinchiLayers<'a> {
+    /// Molecules of components, without prefix and version.
+    pub molecules: Vec<String>,
+    pub indexing: Option<&'a str>,
+    pub concentration: Option<&'a str>,
+}
+
+#[cfg(feature = "std")]
+pub fn minchi_layers(substance: &str) -> Result<MinchiLayers<'_>, String> {
     let mut chunks: Vec<&str> = substance.split('/').collect();
-    if chunks.len() < 4 {
-        return Err("MInChI must have at least 4 parts separated by '/'.".to_string());
+    // Trailing slash, like in "/g/", leaves empty chunk behind
+    if chunks.last() == Some(&"") {
+        chunks.pop();
     }
     // Popping concentration, THEN indexing, order is flipped if you start from the end
-    let concentration = chunks.pop().unwrap();
-    let indexing = chunks.pop().unwrap();
-    let mixture_info = Some(generate_mixture_tree(indexing, concentration)?);
+    let concentration = match chunks.last() {
+        Some(chunk) if chunk.starts_with('g') => chunks.pop(),
+        _ => None,
+    };
+    let indexing = match chunks.last() {
+        Some(chunk) if chunk.starts_with('n') => chunks.pop(),
+        _ => None,
+    };
 
     // Drop version chunk
     chunks.remove(0);
+    if chunks.is_empty() {
+        return Err("MInChI must contain at least one component.".to_string());
+    }
 
-    let schemes = chunks
+    let molecules: Vec<String> = chunks
         .join("/")
         .split('&')
-        .map(|molecule| calculate_scheme(molecule.to_string()))
+        .map(|molecule| molecule.to_string())
         .collect();
+    Ok(MinchiLayers {
+        molecules,
+        indexing: indexing.filter(|indexing| *indexing != "n"),
+        concentration: concentration.filter(|concentration| *concentration != "g"),
+    })
+}
+
+/// Sort components of MInChI and renumber indexing layer to match, so the same mixture gets the
+/// same payload (and the same colors) however its components are listed. Parts of indexing
+/// (with their contents) are sorted by indices too, and empty layers are dropped. Other payloads
+/// are returned as they are.
+#[cfg(feature = "std")]
+pub fn canonicalize_minchi(payload: &str) -> Result<String, String> {
+    if !payload.starts_with("MInChI=") {
+        return Ok(payload.to_string());
+    }
+    let written = minchi_layers(payload)?;
+    let (mut molecules, indexing, concentration) = split_minchi(payload)?;
+    let mut order: Vec<usize> = (0..molecules.len()).collect();
+    order.sort_by(|a, b| molecules[*a].cmp(&molecules[*b]));
+    let mut new_indices = vec![0; molecules.len()];
+    for (position, index) in order.iter().enumerate() {
+        new_indices[*index] = position + 1;
+    }
+    molecules.sort();
+
+    let (indexing, concentration) = sort_parts(
+        parse_parts(&indexing[1..])?,
+        parse_parts(&concentration[1..])?,
+        &new_indices,
+    )?;
+    let version = payload.split('/').next().unwrap_or_default();
+    let mut canonical = format!("{}/{}", version, molecules.join("&"));
+    if written.indexing.is_some() || written.concentration.is_some() {
+        canonical.push_str(&format!("/n{}", write_parts(&indexing)));
+    }
+    if written.concentration.is_some() {
+        canonical.push_str(&format!("/g{}", write_parts(&concentration)));
+    }
+    Ok(canonical)
+}
+
+/// Part of indexing or concentration layer - single value, or parts in braces followed by value
+/// (content of the whole group, in concentration layer).
+#[cfg(feature = "std")]
+enum Part {
+    Value(String),
+    Group(Vec<Part>, String),
+}
+
+#[cfg(feature = "std")]
+fn parse_parts(layer: &str) -> Result<Vec<Part>, String> {
+    let chars: Vec<char> = layer.chars().collect();
+    let mut position = 0;
+    let parts = parse_part_list(&chars, &mut position)?;
+    match position == chars.len() {
+        true => Ok(parts),
+        false => Err(format!("Unbalanced braces in {:?}", layer)),
+    }
+}
+
+#[cfg(feature = "std")]
+fn parse_part_list(chars: &[char], position: &mut usize) -> Result<Vec<Part>, String> {
+    let mut parts = vec![parse_part(chars, position)?];
+    while chars.get(*position) == Some(&'&') {
+        *position += 1;
+        parts.push(parse_part(chars, position)?);
+    }
+    Ok(parts)
+}
 
-    Ok(Picture::new(
-        actual_size,
-        actual_border_size,
-        schemes,
-        mixture_info,
-    ))
+#[cfg(feature = "std")]
+fn parse_part(chars: &[char], position: &mut usize) -> Result<Part, String> {
+    let read_value = |position: &mut usize| {
+        let start = *position;
+        while chars
+            .get(*position)
+            .is_some_and(|c| !matches!(c, '{' | '}' | '&'))
+        {
+            *position += 1;
+        }
+        chars[start..*position].iter().collect::<String>()
+    };
+    if chars.get(*position) != Some(&'{') {
+        return Ok(Part::Value(read_value(position)));
+    }
+    *position += 1;
+    let parts = parse_part_list(chars, position)?;
+    if chars.get(*position) != Some(&'}') {
+        return Err("Unbalanced braces in MInChI layer".to_string());
+    }
+    *position += 1;
+    Ok(Part::Group(parts, read_value(position)))
+}
+
+#[cfg(feature = "std")]
+fn write_parts(parts: &[Part]) -> String {
+    parts
+        .iter()
+        .map(|part| match part {
+            Part::Value(value) => value.clone(),
+            Part::Group(parts, value) => format!("{{{}}}{}", write_parts(parts), value),
+        })
+        .collect::<Vec<String>>()
+        .join("&")
+}
+
+/// Renumber indexing parts with `new_indices` and sort them (together with their contents) by
+/// the lowest index they hold. Unknown indices are kept as they are, and go last.
+#[cfg(feature = "std")]
+fn sort_parts(
+    indexing: Vec<Part>,
+    concentration: Vec<Part>,
+    new_indices: &[usize],
+) -> Result<(Vec<Part>, Vec<Part>), String> {
+    if indexing.len() != concentration.len() {
+        return Err("Indexing and concentration layers differ in structure".to_string());
+    }
+    let mut pairs = vec![];
+    for (index, content) in indexing.into_iter().zip(concentration) {
+        let pair = match (index, content) {
+            (Part::Value(index), Part::Value(content)) => {
+                let index = match index.parse::<usize>() {
+                    Ok(old) if (1..=new_indices.len()).contains(&old) => {
+                        new_indices[old - 1].to_string()
+                    }
+                    _ => index,
+                };
+                (Part::Value(index), Part::Value(content))
+            }
+            (Part::Group(indices, index), Part::Group(contents, content)) => {
+                let (indices, contents) = sort_parts(indices, contents, new_indices)?;
+                (Part::Group(indices, index), Part::Group(contents, content))
+            }
+            _ => return Err("Indexing and concentration layers differ in structure".to_string()),
+        };
+        pairs.push(pair);
+    }
+    pairs.sort_by_key(|(index, _)| lowest_index(index, new_indices.len()));
+    Ok(pairs.into_iter().unzip())
+}
+
+#[cfg(feature = "std")]
+fn lowest_index(part: &Part, components: usize) -> (usize, String) {
+    match part {
+        Part::Value(index) if is_known_index(index, components) => {
+            (index.parse().unwrap(), index.clone())
+        }
+        Part::Value(index) => (usize::MAX, index.clone()),
+        Part::Group(parts, _) => parts
+            .iter()
+            .map(|part| lowest_index(part, components))
+            .min()
+            .unwrap_or((usize::MAX, String::new())),
+    }
+}
+
+/// Result of payload validation.
+#[cfg(feature = "std")]
+#[derive(Debug, Default)]
+pub struct Validation {
+    pub errors: Vec<ParseError>,
+    pub warnings: Vec<Warning>,
+}
+
+/// Check the payload without generating anything, reporting all problems found at once instead
+/// of failing on the first one.
+#[cfg(feature = "std")]
+pub fn validate(payload: &str, strict_version_check: bool) -> Validation {
+    let mut validation = Validation::default();
+    let error = |message: &str| ParseError::new(message.to_string(), payload, None);
+    if payload.starts_with("InChI=") {
+        if !payload.starts_with("InChI=1S/") && strict_version_check {
+            validation
+                .errors
+                .push(error("Only InChI version 1S is supported for now."));
+        }
+    } else if payload.starts_with("MInChI=") {
+        if !payload.starts_with("MInChI=0.00.1S/") && strict_version_check {
+            validation
+                .errors
+                .push(error("Only MInChI version 0.00.1S is supported for now."));
+        }
+        match split_minchi(payload) {
+            Ok((molecules, indexing, concentration)) => {
+                let (mixture, errors) = generate_mixture_tree_lenient(&indexing, &concentration);
+                validation
+                    .errors
+                    .extend(errors.into_iter().map(|e| e.within(payload)));
+                validation.warnings = check_contents(&mixture);
+                validation
+                    .warnings
+                    .extend(check_indices(&mixture, molecules.len()));
+            }
+            Err(message) => validation.errors.push(error(&message)),
+        }
+    } else if payload.starts_with("InChIKey=") || payload.starts_with("MInChIKey=") {
+        validation
+            .errors
+            .push(error("Keys are not supported. Check readme for more info."));
+    } else {
+        validation.errors.push(error("No InChI or MInChI provided"));
+    }
+    validation
 }
 
+#[cfg(feature = "render")]
 fn check_sizes(base_size: u32, border_size_percent_points: u32) -> Result<(u32, u32), String> {
     if base_size < 16 {
         return Err("Base size must be bigger than 16 pixels.".to_string());
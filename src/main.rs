@@ -1,25 +1,274 @@
+use arboard::{Clipboard, ImageData};
 use clap::{arg, command, Parser, Subcommand};
 use clap_verbosity_flag::{Verbosity, WarnLevel};
 use dialoguer::Confirm;
 use image::{ImageBuffer, Rgba};
-use little_exif::exif_tag::ExifTag;
-use little_exif::metadata::Metadata;
-use log::{debug, error, info};
-use moleco::{calculate_scheme, generate_moleco};
+use log::{debug, error, info, warn};
+use moleco::cache::{
+    clear as clear_cache, default_dir as default_cache_dir, ImageCache, IMAGES_DIR, RESOLVER_DIR,
+};
+use moleco::export::{
+    alt_text, data_uri, encode_image, encode_png_in_bands, html_img, Description, FileFormat,
+    PayloadTag, Preset,
+};
+use moleco::layouts::{Cutouts, Geometry, MarkPosition, MarkShape, Picture, Scene, Style};
+use moleco::pdf::{label_book, Label, PageSize};
+use moleco::resolve::{check_cas, Query, Resolver, DEFAULT_ENDPOINT};
+use moleco::svg::{picture_to_inline_svg, picture_to_svg, SpriteSheet};
+use moleco::terminal::{
+    block_art, braille, detect_graphics, half_blocks, sixel, truecolor_available, Graphics,
+};
+use moleco::theme::{parse_color, Theme};
+use moleco::timing::{self, Stage, Timings};
+use moleco::tokenize::{ParseError, Warning, WarningKind};
+use moleco::writers::{
+    json_schema, A11y, Calculation, CalculationOptions, Registry, WriterOptions, TABLE,
+};
+use moleco::{
+    calculate_scheme_with, canonicalize_minchi, cluster_hues, generate_moleco_with, minchi_layers,
+    normalize_input, short_id, validate, Color, Harmony, Scheme, SchemeMode, SchemeVersion,
+};
 use num::integer::gcd;
+use palette::Srgba;
 use pretty_env_logger;
-use prettytable::{row, Table};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, BufWriter, Cursor, IsTerminal, Write};
+use std::time::{Duration, Instant};
 use viuer::Config;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
 
-#[derive(clap::ValueEnum, Clone, Default, Debug)]
-pub enum Format {
+#[derive(clap::ValueEnum, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
     #[default]
-    Table,
+    Png,
+    /// JSON description of shapes, printed to standard output instead of saving image.
+    Scene,
+    /// JSON list of mixture bar segments (component, fraction, position and width in pixels),
+    /// printed to standard output instead of saving image.
+    Bar,
+    /// CSS `linear-gradient` matching mixture bar, printed to standard output instead of saving
+    /// image.
+    Gradient,
+    /// PNG image encoded as `data:` URI, printed to standard output instead of saving image.
+    Datauri,
+    /// HTML `<img>` (valid in JSX too) with PNG image as `data:` URI and alt text, printed to
+    /// standard output instead of saving image.
+    Html,
+    /// SVG image to inline in HTML, with accessible label. Printed to standard output instead
+    /// of saving image.
+    HtmlSvg,
+    /// JSON list of swatches in order they are drawn (left to right) with their colors, for
+    /// legends matching the picture. Printed to standard output instead of saving image.
+    Legend,
+    /// SVG image, with `data-` attributes (component, fraction and hue) over swatches and bar
+    /// segments for interactive web pages. Printed to standard output instead of saving image.
+    Svg,
+    /// PDF label book of all substances of input file, with captions and page numbers. Output
+    /// file is the PDF document.
+    Pdf,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum ErrorFormat {
+    #[default]
+    Text,
+    /// One JSON record per problem, on standard error.
     Json,
-    Yaml,
-    Csv,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClipboardContent {
+    Image,
+    /// Hex codes of primary, accent and complementary colors, one line per substance.
+    Palette,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum OnConflict {
+    /// Ask whether to overwrite. Without terminal (or in quiet mode) nothing is overwritten.
+    #[default]
+    Ask,
+    Overwrite,
+    /// Keep existing file and don't write anything.
+    Skip,
+    /// Write to the first free name with incrementing suffix, like `moleco-2.png`, without
+    /// asking - for unattended runs into shared folders.
+    Rename,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum SortBy {
+    /// Order of input file.
+    #[default]
+    Input,
+    /// Primary hue (of the first component, for mixtures), so similar colors end up together.
+    Hue,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Checksums {
+    /// `<file>.sha256` next to each saved file.
+    Sidecar,
+    /// Single `SHA256SUMS` file next to output, listing all saved files.
+    Sums,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum IdentifierKind {
+    /// Numbers are CIDs, well formed CAS registry numbers are CAS numbers, the rest are names.
+    #[default]
+    Auto,
+    Name,
+    Cas,
+    Cid,
+}
+
+impl IdentifierKind {
+    fn query(self, identifier: &str) -> Result<Query, String> {
+        let identifier = identifier.trim().to_string();
+        match self {
+            IdentifierKind::Auto => Ok(Query::detect(&identifier)),
+            IdentifierKind::Name => Ok(Query::Name(identifier)),
+            IdentifierKind::Cas => Ok(Query::Cas(identifier)),
+            IdentifierKind::Cid => identifier
+                .parse()
+                .map(Query::Cid)
+                .map_err(|_| format!("{:?} is not PubChem CID", identifier)),
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum PreviewMode {
+    /// Graphics of the terminal if there are any, half blocks otherwise.
+    #[default]
+    Auto,
+    /// Colored blocks, two characters each.
+    Blocks,
+    /// Half blocks - two pixels in each character.
+    HalfBlocks,
+    /// Braille dots - 2x4 pixels in each character, sharing one color.
+    Braille,
+}
+
+/// Placement and size of image printed to terminal.
+#[derive(clap::Args)]
+struct PreviewOptions {
+    #[arg(long, value_enum, default_value_t)]
+    /// How image is printed. Text modes work in any terminal, also over SSH.
+    preview_mode: PreviewMode,
+    #[arg(long, default_value_t = 10)]
+    /// Offset of printed image from the left edge of terminal, in columns.
+    preview_x: u16,
+    #[arg(long, default_value_t = 0, allow_negative_numbers = true)]
+    /// Offset of printed image from the cursor, in rows.
+    preview_y: i16,
+    #[arg(long)]
+    /// Width of printed image, in columns. By default image fits the terminal.
+    preview_width: Option<u32>,
+    #[arg(long)]
+    /// Height of printed image, in rows. By default image fits the terminal.
+    preview_height: Option<u32>,
+    #[arg(long, default_value = "false")]
+    /// Show terminal background through transparent parts of image, instead of checkerboard.
+    /// Works only in terminals without graphics protocol.
+    preview_transparent: bool,
+}
+
+/// Where substances given by name or CID are looked up.
+#[derive(clap::Args)]
+struct ResolverOptions {
+    #[arg(long, default_value = DEFAULT_ENDPOINT)]
+    /// PUG REST endpoint resolving names and CIDs to InChI, like mirror of PubChem.
+    resolver_endpoint: String,
+    #[arg(long)]
+    /// Directory caching resolved substances. By default user cache directory is used, like
+    /// `~/.cache/moleco`.
+    resolver_cache: Option<String>,
+    #[arg(long, default_value = "false", conflicts_with = "resolver_cache")]
+    /// Always ask resolver, without caching its answers.
+    no_resolver_cache: bool,
+}
+
+impl ResolverOptions {
+    fn resolver(&self) -> Resolver {
+        let resolver = Resolver::new().with_endpoint(&self.resolver_endpoint);
+        match (&self.resolver_cache, self.no_resolver_cache) {
+            (_, true) => resolver.with_cache(None),
+            (Some(path), false) => resolver.with_cache(Some(path.into())),
+            (None, false) => resolver,
+        }
+    }
+}
+
+/// Substance given by name or identifier, instead of InChI.
+#[derive(clap::Args)]
+struct LookupOptions {
+    #[arg(
+        long,
+        conflicts_with_all = ["substance", "batch_input", "payload_file", "cid", "cas"]
+    )]
+    /// Resolve substance by its name (like "acetone") to standard InChI, see
+    /// `--resolver-endpoint`. Requires `remote` feature, unless the answer is cached.
+    name: Option<String>,
+    #[arg(
+        long,
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with_all = ["substance", "batch_input", "payload_file", "cas"]
+    )]
+    /// Resolve substance by its PubChem compound identifier (CID) to standard InChI, like
+    /// `--name`.
+    cid: Option<u64>,
+    #[arg(long, conflicts_with_all = ["substance", "batch_input", "payload_file"])]
+    /// Resolve substance by its CAS registry number (like 67-64-1) to standard InChI, like
+    /// `--name`.
+    cas: Option<String>,
+    #[command(flatten)]
+    resolver: ResolverOptions,
+}
+
+impl LookupOptions {
+    fn query(&self) -> Option<Query> {
+        match (&self.name, self.cid, &self.cas) {
+            (Some(name), _, _) => Some(Query::Name(name.clone())),
+            (None, Some(cid), _) => Some(Query::Cid(cid)),
+            (None, None, Some(cas)) => Some(Query::Cas(cas.clone())),
+            (None, None, None) => None,
+        }
+    }
+}
+
+/// How hues of color scheme are chosen.
+#[derive(clap::Args, Debug, Clone, Copy, Default)]
+struct SchemeOptions {
+    #[arg(long, value_enum, default_value_t)]
+    /// How hues are chosen - "composition" nudges accents by elements found in substance.
+    scheme_mode: SchemeMode,
+    #[arg(long, value_enum, default_value_t)]
+    /// How hues of accents and complementary color relate to primary one.
+    harmony: Harmony,
+    #[arg(long, value_enum, default_value_t)]
+    /// Version of schemes - "2" derives saturation and value of colors from hash too, so
+    /// substances with close hues are easier to tell apart. Colors of version 1 never change.
+    scheme_version: SchemeVersion,
+}
+
+impl SchemeOptions {
+    fn options(&self) -> moleco::SchemeOptions<'static> {
+        moleco::SchemeOptions::default()
+            .with_mode(self.scheme_mode)
+            .with_strategy(self.harmony.strategy())
+            .with_version(self.scheme_version)
+    }
+
+    fn scheme(&self, substance: String) -> Scheme {
+        calculate_scheme_with(substance, self.options())
+    }
 }
 
 #[derive(Parser)]
@@ -30,13 +279,30 @@ struct Cli {
     command: Commands,
     #[command(flatten)]
     verbose: Verbosity<WarnLevel>,
+    #[arg(long, global = true, value_enum, default_value_t)]
+    /// How problems with substances, scenes and themes are reported.
+    error_format: ErrorFormat,
+    #[arg(long, global = true, value_enum, default_value_t)]
+    /// What to do when output file already exists.
+    on_conflict: OnConflict,
+    #[arg(long, global = true, default_value = "false")]
+    /// Create missing directories of output file.
+    create_dirs: bool,
+    #[arg(long, global = true, default_value = "false")]
+    /// Decode URL-encoded substances, like `InChI%3D1S%2FH2O%2Fh1H2` copied from web forms.
+    url_decode: bool,
+    #[arg(long, global = true, default_value = "false")]
+    /// Sort components of MInChIs (renumbering their indices), so the same mixture gets the same
+    /// colors however its components are listed.
+    canonicalize: bool,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Generate color scheme image for a given substance.
     Generate {
-        substance: String,
+        #[arg(required_unless_present_any = ["input_file", "input_url", "input_glob", "payload_file", "name", "cid", "cas"])]
+        substance: Option<String>,
         #[arg(default_value_t = 200, long)]
         base_size: u32,
         /// Print image to terminal.
@@ -46,24 +312,207 @@ enum Commands {
         /// Print image to terminal only, without saving.
         print_only: bool,
         #[arg(long, default_value = "moleco.png")]
-        /// Output filename. Only PNG format is supported.
+        /// Output filename, image (or PDF label book with `--format pdf`), `-` for standard
+        /// output (of single image, without `--print`). `{id}` is replaced with short identifier of the substance, and with input file
+        /// `{index}` with line number (or position, when sorted by hue). With input glob `{name}`
+        /// is replaced with name of payload file without extension, and `{path}` with its path
+        /// (relative to directory the pattern starts from) without extension. With input file,
+        /// `.zip` output puts all images and manifest into single archive.
         output_file: String,
+        #[arg(
+            long,
+            group = "batch_input",
+            conflicts_with_all = ["substance", "print", "print_only", "clipboard", "open"]
+        )]
+        /// Read substances from file, one per line, and generate image for each of them.
+        input_file: Option<String>,
+        #[arg(
+            long,
+            group = "batch_input",
+            conflicts_with_all = ["substance", "print", "print_only", "clipboard", "open"]
+        )]
+        /// Fetch substances from URL (like registry export), one per line, and generate image for
+        /// each of them. Requires `remote` feature.
+        input_url: Option<String>,
+        #[arg(
+            long,
+            group = "batch_input",
+            conflicts_with_all = ["substance", "print", "print_only", "clipboard", "open"]
+        )]
+        /// Read substances from files matching glob pattern (like `data/**/*.inchi`, with `**`
+        /// for any depth of directories), one per file, and generate image for each of them.
+        input_glob: Option<String>,
+        #[arg(long, conflicts_with_all = ["substance", "input_file", "input_url", "input_glob"])]
+        /// Read substance from file (`-` for standard input), for payloads too long to pass as
+        /// argument.
+        payload_file: Option<String>,
+        #[command(flatten)]
+        lookup: Box<LookupOptions>,
+        #[arg(long, requires = "batch_input")]
+        /// Write JSON manifest mapping each input line to its output file, hues, short identifier
+        /// and status (ok, skipped or error). `-` prints it to standard output.
+        manifest: Option<String>,
+        #[arg(long, value_enum, default_value_t, requires = "batch_input")]
+        /// Order of images generated from input file, and of manifest entries.
+        sort_by: SortBy,
+        #[arg(long, default_value = "false", requires = "batch_input")]
+        /// Treat substances differing only by whitespace as duplicates.
+        ignore_whitespace: bool,
+        #[arg(long, default_value = "false", requires = "batch_input")]
+        /// Print summary of the run (substances by status, time spent in each stage) to standard
+        /// error at the end.
+        stats: bool,
         #[arg(long, default_value = "false")]
-        /// When output file exists, overwrite it without asking.
-        overwrite: bool,
+        /// Print time spent in each stage of generation of every image to standard error.
+        profile: bool,
+        #[arg(long, default_value_t = 12, value_parser = clap::value_parser!(u32).range(1..))]
+        /// Number of swatches on each page of PDF label book.
+        per_page: u32,
+        #[arg(long, value_enum, default_value_t)]
+        /// Paper size of PDF label book.
+        page_size: PageSize,
+        #[arg(long, value_enum)]
+        /// Write SHA-256 checksums of saved files (in `sha256sum` format), so their transfer can
+        /// be verified.
+        checksums: Option<Checksums>,
         #[arg(long, default_value = "1")]
         /// Border size in percent points of base size.
         border_size: u32,
         #[arg(long, default_value = "false")]
+        /// Reuse images rendered earlier with the same substance and options, cached in
+        /// `~/.cache/moleco`.
+        image_cache: bool,
+        #[arg(
+            long,
+            value_parser = clap::value_parser!(u32).range(1..),
+            conflicts_with_all = ["batch_input", "print", "print_only", "clipboard", "image_cache"]
+        )]
+        /// Rasterize and encode PNG in horizontal bands of given number of rows, so poster-size
+        /// images never have to fit in memory at once.
+        band_height: Option<u32>,
+        #[arg(long, default_value = "false")]
+        /// Skip version check.
+        skip_version_check: bool,
+        #[arg(long, default_value = "false")]
+        /// Treat warnings (like indices pointing to no component of mixture) as errors.
+        strict: bool,
+        #[command(flatten)]
+        scheme: SchemeOptions,
+        #[arg(long, default_value = "false")]
+        /// Mark substances with stereochemistry with triangle in top left corner.
+        stereo_marker: bool,
+        #[arg(long, default_value = "false")]
+        /// Mark charged substances (and salts) with +, - or ± sign in top right corner.
+        charge_marker: bool,
+        #[arg(long, default_value = "false")]
+        /// Mark isotopically labelled substances with dots in bottom left corner.
+        isotope_marker: bool,
+        #[arg(long, default_value = "false")]
+        /// Show mixture components without width (like additions beyond 100%) as thin hatched
+        /// markers in the bar, instead of leaving them out.
+        zero_width_markers: bool,
+        #[arg(long, value_parser = parse_contrast)]
+        /// Minimal contrast ratio (WCAG, 1 to 21) of swatch borders with swatch colors - when
+        /// border color is too close to them, near-black or near-white borders are used instead.
+        min_border_contrast: Option<f64>,
+        #[arg(long, value_enum, default_value_t)]
+        /// Shape of the swatch.
+        style: Geometry,
+        #[arg(long, value_enum, default_value_t)]
+        /// Which cutouts are made in swatches.
+        cutouts: Cutouts,
+        #[arg(long, value_parser = parse_color)]
+        /// Fill cutouts with given color (#rrggbb or #rrggbbaa), instead of leaving them
+        /// transparent.
+        cutout_fill: Option<Srgba<u8>>,
+        #[arg(long, value_enum, default_value_t)]
+        /// Where orientation mark is drawn in swatches.
+        orientation_mark: MarkPosition,
+        #[arg(long, value_enum, default_value_t)]
+        /// Shape of orientation mark.
+        mark_shape: MarkShape,
+        #[arg(long, default_value_t = 0)]
+        /// Round corners of the picture with given radius in pixels, like avatars.
+        corner_radius: u32,
+        #[arg(long)]
+        /// Draw frame around the picture, with given gap in pixels on both of its sides.
+        frame_inset: Option<u32>,
+        #[arg(long)]
+        /// Theme file (TOML or JSON) with colors, cutouts, bar style and spacing.
+        theme: Option<String>,
+        #[arg(long, value_enum, conflicts_with_all = ["base_size", "border_size"])]
+        /// Use base size, border size and resolution of common label format.
+        preset: Option<Preset>,
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        /// Resolution (dots per inch) stored in the image, so it prints at intended physical size.
+        /// Overrides resolution of preset.
+        dpi: Option<u32>,
+        #[arg(long, default_value = "false")]
+        /// Don't store the substance in EXIF metadata of saved images, for images distributed
+        /// where its identity must not be disclosed.
+        no_metadata: bool,
+        #[arg(long, value_enum, default_value_t, conflicts_with = "no_metadata")]
+        /// EXIF tag holding the substance. Version of moleco is stored in `Software` tag.
+        payload_tag: PayloadTag,
+        #[arg(long, value_enum, default_value_t)]
+        format: ImageFormat,
+        #[arg(long, value_enum)]
+        /// Format of saved images, by extension of output file when not given.
+        image_format: Option<FileFormat>,
+        #[arg(long, value_enum)]
+        /// Copy image, or hex codes of its colors, to the clipboard.
+        clipboard: Option<ClipboardContent>,
+        #[arg(long, default_value = "false")]
+        /// Open saved image in default image viewer.
+        open: bool,
+        #[command(flatten)]
+        preview: PreviewOptions,
+    },
+    /// Validate given substance and report all problems found in it.
+    Validate {
+        substance: String,
+        #[arg(long, default_value = "false")]
         /// Skip version check.
         skip_version_check: bool,
+        #[arg(long, default_value = "false")]
+        /// Treat warnings (like contents exceeding 100%) as errors.
+        strict: bool,
+    },
+    /// Print MInChIs with components sorted and indices renumbered (see `--canonicalize`), each
+    /// followed by its layers changed on the way. Other substances are printed as they are.
+    Canonicalize {
+        /// Substances to canonicalize. Providing input file has precedence over this.
+        substances: Vec<String>,
+        #[arg(long)]
+        /// Read substances from file, one per line.
+        input_file: Option<String>,
+    },
+    /// Rasterize scene saved with `generate --format scene`.
+    Render {
+        scene_file: String,
+        #[arg(long)]
+        /// Width of the image, height is scaled proportionally. By default size of the scene is
+        /// kept - scaled pictures are not pixel perfect, so regenerate them if possible.
+        width: Option<u32>,
+        #[arg(long, default_value = "moleco.png")]
+        /// Output filename, `-` for standard output.
+        output_file: String,
+        #[arg(long, value_enum)]
+        /// Format of the image, by extension of output file when not given.
+        image_format: Option<FileFormat>,
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        /// Resolution (dots per inch) stored in the image.
+        dpi: Option<u32>,
+        #[arg(long, default_value = "false")]
+        /// Open saved image in default image viewer.
+        open: bool,
     },
     /// Calculate and print color scheme without generating image.
     Calculate {
         /// Substances to calculate. Providing input file has precedence over this.
         substances: Vec<String>,
-        #[arg(long, value_enum, default_value_t)]
-        format: Format,
+        #[arg(long, default_value = TABLE, value_parser = format_parser())]
+        format: String,
         #[arg(long)]
         /// Read substances from file, one per line.
         input_file: Option<String>,
@@ -71,21 +520,295 @@ enum Commands {
         /// Output file. (Doesn't work for table format, but you can redirect output.)
         output_file: Option<String>,
         #[arg(long, default_value = "false")]
-        /// Skip version check (calculate anyway) and totally skip lines that do not start with InChI.
+        /// Skip version check (calculate anyway) and totally skip lines that do not start with
+        /// InChI or MInChI.
         skip_errors: bool,
+        #[arg(long, default_value = "false")]
+        /// Add molecular weight (in g/mol, at natural abundance of isotopes) of each substance to
+        /// the output. Isotopically labelled substances are rejected.
+        with_mw: bool,
+        #[arg(long, default_value = "false")]
+        /// Add short identifier of each substance to the output.
+        with_id: bool,
+        #[arg(long, default_value = "false")]
+        /// Add WCAG contrast ratios of scheme colors with text and background colors, and flag
+        /// colors unsuitable for text overlays.
+        a11y: bool,
+        #[arg(long, default_value = "#000000", value_parser = parse_color)]
+        /// Color of text drawn over swatches, for `--a11y`.
+        a11y_foreground: Srgba<u8>,
+        #[arg(long, default_value = "#ffffff", value_parser = parse_color)]
+        /// Color of the page swatches are shown on, for `--a11y`.
+        a11y_background: Srgba<u8>,
+        #[arg(long, default_value_t = 4.5)]
+        /// Minimal contrast ratio with text color, for `--a11y` - 4.5 is WCAG level AA for
+        /// normal text, 7 is level AAA.
+        min_contrast: f64,
+        #[command(flatten)]
+        scheme: SchemeOptions,
+        #[arg(long, default_value = "false")]
+        /// Print JSON Schema of JSON output and exit.
+        print_schema: bool,
+    },
+    /// Find groups of substances with similar primary hues, whose labels are easy to confuse.
+    Cluster {
+        /// Substances to compare. Providing input file has precedence over this.
+        substances: Vec<String>,
+        #[arg(long)]
+        /// Read substances from file, one per line.
+        input_file: Option<String>,
+        #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(0..180))]
+        /// Maximal difference of primary hues (in degrees) of neighbours in a group.
+        threshold: u32,
+        #[command(flatten)]
+        scheme: SchemeOptions,
+    },
+    /// Resolve substances listed in CSV by names, CAS numbers or PubChem CIDs, generate image
+    /// for each of them and write the CSV enriched with results.
+    Pipeline {
+        /// CSV file with header row, one substance per row.
+        input_file: String,
+        #[arg(long)]
+        /// Column with identifiers of substances, the first one by default.
+        column: Option<String>,
+        #[arg(long, value_enum, default_value_t)]
+        /// Kind of identifiers in the column.
+        identifier: IdentifierKind,
+        #[arg(long, default_value = "moleco-{index}.png")]
+        /// Output filename of images. `{index}` is replaced with row number (header excluded) and
+        /// `{id}` with short identifier of the substance.
+        output_file: String,
+        #[arg(long, default_value = "-")]
+        /// Enriched CSV - columns of input file, followed by resolved InChI, short identifier,
+        /// hues, image and status of each row. `-` prints it to standard output.
+        report_file: String,
+        #[arg(default_value_t = 200, long)]
+        base_size: u32,
+        #[arg(long, default_value = "1")]
+        /// Border size in percent points of base size.
+        border_size: u32,
+        #[arg(long, value_enum, conflicts_with_all = ["base_size", "border_size"])]
+        /// Use base size, border size and resolution of common label format.
+        preset: Option<Preset>,
+        #[arg(long, default_value = "false")]
+        /// Reuse images rendered earlier with the same substance and options, cached in
+        /// `~/.cache/moleco`.
+        image_cache: bool,
+        #[arg(long, default_value = "false")]
+        /// Print summary of the run (substances by status, time spent in each stage) to standard
+        /// error at the end.
+        stats: bool,
+        #[arg(long, default_value = "false")]
+        /// Print time spent in each stage of generation of every image to standard error.
+        profile: bool,
+        #[command(flatten)]
+        resolver: ResolverOptions,
+    },
+    /// Manage cache of resolved substances and rendered images.
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheCommand {
+    /// Remove cached files.
+    Clear {
+        #[arg(long, value_enum)]
+        /// Remove only resolved substances or only images.
+        only: Option<CacheKind>,
     },
 }
 
-fn print_to_terminal(buffer: ImageBuffer<Rgba<u8>, Vec<u8>>) {
-    let height = buffer.height();
-    let img = image::DynamicImage::ImageRgba8(buffer);
-    let conf = Config {
-        // set offset in terminal
-        x: 10,
-        y: height as i16,
-        ..Default::default()
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheKind {
+    /// Substances resolved by name, CAS number or CID.
+    Resolver,
+    /// Images rendered with `--image-cache`.
+    Images,
+}
+
+fn parse_contrast(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(contrast) if (1f64..=21f64).contains(&contrast) => Ok(contrast),
+        _ => Err(format!(
+            "Contrast ratio must be between 1 and 21, got {}",
+            value
+        )),
+    }
+}
+
+/// Batch run finished, but some substances were skipped (with `--skip-errors`) or failed.
+/// Other exit codes follow `sysexits.h`, see readme.
+const EXIT_PARTIAL_SUCCESS: i32 = 3;
+
+/// Problem with the input - what failed (`code`), why, and where, if position is known.
+/// `span` is `(start, end)` character offsets in `input`, with exclusive end.
+#[derive(Serialize)]
+struct ErrorRecord {
+    level: &'static str,
+    code: &'static str,
+    message: String,
+    input: Option<String>,
+    span: Option<(usize, usize)>,
+    /// Message for the log, with input annotated if possible.
+    #[serde(skip)]
+    text: String,
+}
+
+impl ErrorRecord {
+    fn new(code: &'static str, message: String, input: Option<&str>) -> Self {
+        ErrorRecord {
+            level: "error",
+            code,
+            text: message.clone(),
+            message,
+            input: input.map(|input| input.to_string()),
+            span: None,
+        }
+    }
+
+    fn from_parse_error(error: &ParseError) -> Self {
+        ErrorRecord {
+            level: "error",
+            code: match error.span {
+                Some(_) => "invalid-notation",
+                None => "invalid-substance",
+            },
+            message: error.message.clone(),
+            input: Some(error.input.clone()),
+            span: error.span,
+            text: String::from(error.clone()),
+        }
+    }
+
+    fn from_warning(warning: &Warning, input: &str) -> Self {
+        ErrorRecord {
+            level: "warning",
+            code: match warning.kind {
+                WarningKind::ZeroValue => "zero-value",
+                WarningKind::ExceedsCapacity => "exceeds-capacity",
+                WarningKind::SumExceedsCapacity => "sum-exceeds-capacity",
+                WarningKind::UnknownIndex => "unknown-index",
+                WarningKind::CollapsedRange => "collapsed-range",
+                WarningKind::UnestimatedCapacity => "unestimated-capacity",
+            },
+            message: warning.message.clone(),
+            input: Some(input.to_string()),
+            span: None,
+            text: warning.to_string(),
+        }
+    }
+
+    /// Simplification made to draw the picture (see `Picture::notes`), nothing is wrong.
+    fn from_note(note: &Warning, input: &str) -> Self {
+        ErrorRecord {
+            level: "note",
+            ..Self::from_warning(note, input)
+        }
+    }
+}
+
+fn report(error_format: ErrorFormat, record: ErrorRecord) {
+    match error_format {
+        ErrorFormat::Text if record.level == "note" => info!("{}", record.text),
+        ErrorFormat::Text if record.level == "warning" => warn!("{}", record.text),
+        // Notes are printed in JSON only when asked for, like in text
+        ErrorFormat::Json if record.level == "note" && !log::log_enabled!(log::Level::Info) => {}
+        ErrorFormat::Text => error!("{}", record.text),
+        ErrorFormat::Json => eprintln!("{}", serde_json::to_string(&record).unwrap()),
+    }
+}
+
+fn copy_to_clipboard(
+    content: ClipboardContent,
+    buffer: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    picture: &Picture,
+) -> Result<(), String> {
+    let mut clipboard = Clipboard::new().map_err(|e| format!("Can't access clipboard - {}", e))?;
+    let set = clipboard.set();
+    // On Linux clipboard is emptied when its owner exits, so contents are served until something
+    // else is copied (clipboard managers take them over right away). Waiting is announced at warn
+    // level, so it doesn't look like a hang at default verbosity.
+    #[cfg(target_os = "linux")]
+    let set = {
+        use arboard::SetExtLinux;
+        warn!("Serving clipboard contents until something else is copied");
+        set.wait()
+    };
+    match content {
+        ClipboardContent::Image => set.image(ImageData {
+            width: buffer.width() as usize,
+            height: buffer.height() as usize,
+            bytes: Cow::Borrowed(buffer.as_raw()),
+        }),
+        ClipboardContent::Palette => set.text(
+            picture
+                .schemes()
+                .iter()
+                .map(|scheme| scheme.to_string())
+                .collect::<Vec<String>>()
+                .join("\n"),
+        ),
+    }
+    .map_err(|e| format!("Can't copy to clipboard - {}", e))
+}
+
+/// Print image with graphics protocol of the terminal, or as text if there is none (or text is
+/// requested).
+fn print_to_terminal(buffer: ImageBuffer<Rgba<u8>, Vec<u8>>, preview: &PreviewOptions) {
+    let graphics = match preview.preview_mode {
+        PreviewMode::Auto => detect_graphics(),
+        _ => Graphics::None,
     };
-    viuer::print(&img, &conf).expect("Image printing failed.");
+    debug!("Terminal graphics: {:?}", graphics);
+    match graphics {
+        Graphics::Kitty | Graphics::Iterm => {
+            let img = image::DynamicImage::ImageRgba8(buffer.clone());
+            let conf = Config {
+                x: preview.preview_x,
+                y: preview.preview_y,
+                absolute_offset: false,
+                width: preview.preview_width,
+                height: preview.preview_height,
+                transparent: preview.preview_transparent,
+                ..Default::default()
+            };
+            if let Err(e) = viuer::print(&img, &conf) {
+                warn!("Can't print image with {:?} graphics - {}", graphics, e);
+                print_text_art(&buffer, preview);
+            }
+        }
+        Graphics::Sixel => {
+            print!("{}", " ".repeat(preview.preview_x as usize));
+            println!("{}", sixel(&buffer));
+        }
+        Graphics::None => print_text_art(&buffer, preview),
+    }
+}
+
+/// Print image as colored characters, fitted in the terminal (or in preview size, if given).
+fn print_text_art(buffer: &ImageBuffer<Rgba<u8>, Vec<u8>>, preview: &PreviewOptions) {
+    let (terminal_width, terminal_height) = viuer::terminal_size();
+    let columns = preview
+        .preview_width
+        .unwrap_or(terminal_width.saturating_sub(preview.preview_x) as u32);
+    // Each row of characters is as high as two columns are wide
+    let rows = preview
+        .preview_height
+        .unwrap_or(terminal_height.saturating_sub(1) as u32);
+    let columns = columns.min(rows * 2 * buffer.width() / buffer.height().max(1));
+    let indent = " ".repeat(preview.preview_x as usize);
+    let truecolor = truecolor_available();
+    let art = match preview.preview_mode {
+        PreviewMode::Blocks => block_art(buffer, columns, truecolor),
+        PreviewMode::Braille => braille(buffer, columns, truecolor),
+        PreviewMode::Auto | PreviewMode::HalfBlocks => half_blocks(buffer, columns, truecolor),
+    };
+    for line in art.lines() {
+        println!("{}{}", indent, line);
+    }
 }
 
 fn main() {
@@ -94,6 +817,8 @@ fn main() {
     pretty_env_logger::formatted_builder()
         .filter_level(cli.verbose.log_level_filter())
         .init();
+    // Below default level, only problems are reported and nothing is asked
+    let quiet = cli.verbose.log_level_filter() < log::LevelFilter::Warn;
 
     match &cli.command {
         Commands::Generate {
@@ -102,28 +827,343 @@ fn main() {
             print,
             print_only,
             output_file,
-            overwrite,
+            input_file,
+            input_url,
+            input_glob,
+            payload_file,
+            lookup,
+            manifest,
+            sort_by,
+            ignore_whitespace,
+            stats,
+            profile,
+            per_page,
+            page_size,
+            checksums,
             border_size,
+            image_cache,
+            band_height,
             skip_version_check,
+            strict,
+            scheme,
+            stereo_marker,
+            charge_marker,
+            isotope_marker,
+            zero_width_markers,
+            min_border_contrast,
+            style,
+            cutouts,
+            cutout_fill,
+            orientation_mark,
+            mark_shape,
+            corner_radius,
+            frame_inset,
+            theme,
+            preset,
+            dpi,
+            no_metadata,
+            payload_tag,
+            format,
+            image_format,
+            clipboard,
+            open,
+            preview,
         } => {
-            let picture = generate_moleco(
-                substance.to_string(),
-                base_size.clone(),
-                border_size.clone(),
-                !skip_version_check,
-            );
-            match picture {
-                Ok(mut picture) => {
-                    if !*print_only && !output_file.ends_with(".png") {
-                        error!("Only PNG format is supported.");
+            let (base_size, border_size, dpi) = match preset {
+                Some(preset) => (
+                    preset.base_size(),
+                    preset.border_size(),
+                    dpi.or(Some(preset.dpi())),
+                ),
+                None => (*base_size, *border_size, *dpi),
+            };
+            let style = Style {
+                geometry: *style,
+                stereo_marker: *stereo_marker,
+                charge_marker: *charge_marker,
+                isotope_marker: *isotope_marker,
+                zero_width_markers: *zero_width_markers,
+                min_border_contrast: *min_border_contrast,
+                cutouts: *cutouts,
+                cutout_fill: *cutout_fill,
+                orientation_mark: *orientation_mark,
+                mark_shape: *mark_shape,
+                corner_radius: *corner_radius,
+                frame_inset: *frame_inset,
+                ..Default::default()
+            };
+            let style = match theme {
+                Some(path) => match Theme::load(std::path::Path::new(path))
+                    .and_then(|theme| theme.apply(style))
+                {
+                    Ok(style) => style,
+                    Err(e) => {
+                        report(
+                            cli.error_format,
+                            ErrorRecord::new("invalid-theme", e, Some(path)),
+                        );
+                        std::process::exit(exitcode::CONFIG);
+                    }
+                },
+                None => style,
+            };
+            let settings = ImageSettings {
+                base_size,
+                border_size,
+                strict_version_check: !skip_version_check,
+                strict: *strict,
+                scheme: *scheme,
+                style,
+                dpi,
+                metadata: !no_metadata,
+                payload_tag: *payload_tag,
+                file_format: match format {
+                    ImageFormat::Png if !print_only => file_format(output_file, *image_format),
+                    _ => image_format.unwrap_or_default(),
+                },
+            };
+            if *no_metadata && matches!(format, ImageFormat::Html | ImageFormat::HtmlSvg) {
+                error!("HTML snippets name the substance in alt text, they can't be anonymous.");
+                std::process::exit(exitcode::USAGE);
+            }
+            if output_file == "-" && *print {
+                error!("Image written to standard output can't be printed to terminal too.");
+                std::process::exit(exitcode::USAGE);
+            }
+            if input_glob.is_none() && uses_file_placeholders(output_file) {
+                error!("`{{name}}` and `{{path}}` of output filename work only with --input-glob.");
+                std::process::exit(exitcode::USAGE);
+            }
+            if let Some(input) = input_file
+                .as_ref()
+                .or(input_url.as_ref())
+                .or(input_glob.as_ref())
+            {
+                if !matches!(
+                    format,
+                    ImageFormat::Png | ImageFormat::Pdf | ImageFormat::Svg
+                ) {
+                    error!("Only PNG, PDF and SVG formats are supported for input file.");
+                    std::process::exit(exitcode::USAGE);
+                }
+                if output_file == "-" {
+                    error!("Images of input file can't be written to standard output.");
+                    std::process::exit(exitcode::USAGE);
+                }
+                if *format == ImageFormat::Pdf && !output_file.ends_with(".pdf") {
+                    error!("PDF label book must be saved to .pdf file.");
+                    std::process::exit(exitcode::USAGE);
+                }
+                if *format == ImageFormat::Svg && !output_file.ends_with(".svg") {
+                    error!("SVG sprite sheet must be saved to .svg file.");
+                    std::process::exit(exitcode::USAGE);
+                }
+                let batch = Batch {
+                    settings: &settings,
+                    output_file,
+                    sort_by: *sort_by,
+                    ignore_whitespace: *ignore_whitespace,
+                    book: match format {
+                        ImageFormat::Pdf => Some((*per_page as usize, *page_size)),
+                        _ => None,
+                    },
+                    sprite: *format == ImageFormat::Svg,
+                    checksums: *checksums,
+                    image_cache: image_cache.then(image_cache_or_exit),
+                    profile: *profile,
+                    url_decode: cli.url_decode,
+                    canonicalize: cli.canonicalize,
+                    on_conflict: cli.on_conflict,
+                    error_format: cli.error_format,
+                    quiet,
+                    create_dirs: cli.create_dirs,
+                };
+                let started = Instant::now();
+                timing::take();
+                let entries = match input_glob {
+                    Some(pattern) => batch.run(read_input_files(pattern)),
+                    None => {
+                        let reader: Box<dyn BufRead> = match input_file {
+                            Some(path) => Box::new(BufReader::new(open_file(path))),
+                            None => Box::new(Cursor::new(fetch_substances(input))),
+                        };
+                        batch.run(reader.lines().map(|line| match line {
+                            Ok(substance) => (substance, None),
+                            Err(e) => {
+                                error!("Error reading file \"{}\" - {}", input, e);
+                                std::process::exit(exitcode::IOERR);
+                            }
+                        }))
+                    }
+                };
+                if let Some(path) = manifest {
+                    write_manifest(&entries, path, cli.on_conflict, quiet, cli.create_dirs);
+                }
+                if *stats {
+                    let statuses: Vec<Status> = entries.iter().map(|entry| entry.status).collect();
+                    print_stats(&statuses, started.elapsed(), timing::take());
+                }
+                let incomplete = entries.iter().filter(|entry| entry.incomplete()).count();
+                if incomplete > 0 {
+                    warn!("{} substance(s) failed or weren't saved", incomplete);
+                    std::process::exit(EXIT_PARTIAL_SUCCESS);
+                }
+                return;
+            }
+            let substance = match (payload_file, lookup.query()) {
+                (Some(path), _) => read_payload(path),
+                (None, Some(query)) => {
+                    resolve_substance(&lookup.resolver.resolver(), &query, cli.error_format)
+                }
+                // Required without input file
+                (None, None) => substance.clone().unwrap(),
+            };
+            let substance = &normalize(&substance, cli.url_decode, cli.canonicalize);
+            timing::take();
+            match settings.picture(substance).and_then(|picture| {
+                settings.check_warnings(&picture, substance, cli.error_format)?;
+                Ok(picture)
+            }) {
+                Ok(picture) => {
+                    if *format == ImageFormat::Scene {
+                        match picture.to_scene() {
+                            Ok(scene) => {
+                                println!("{}", serde_json::to_string_pretty(&scene).unwrap())
+                            }
+                            Err(e) => {
+                                report(
+                                    cli.error_format,
+                                    ErrorRecord::new("invalid-substance", e, Some(substance)),
+                                );
+                                std::process::exit(exitcode::DATAERR);
+                            }
+                        }
+                        return;
+                    }
+                    if matches!(format, ImageFormat::Svg | ImageFormat::HtmlSvg) {
+                        let svg = match format {
+                            ImageFormat::HtmlSvg => {
+                                let id = short_id(substance);
+                                picture_to_inline_svg(&picture, &id, &alt_text(substance))
+                            }
+                            _ => picture_to_svg(&picture),
+                        };
+                        match svg {
+                            Ok(svg) => print!("{}", svg),
+                            Err(e) => {
+                                report(
+                                    cli.error_format,
+                                    ErrorRecord::new("invalid-substance", e, Some(substance)),
+                                );
+                                std::process::exit(exitcode::DATAERR);
+                            }
+                        }
+                        return;
+                    }
+                    if *format == ImageFormat::Legend {
+                        match legend(&picture) {
+                            Ok(legend) => {
+                                println!("{}", serde_json::to_string_pretty(&legend).unwrap())
+                            }
+                            Err(e) => {
+                                report(
+                                    cli.error_format,
+                                    ErrorRecord::new("invalid-substance", e, Some(substance)),
+                                );
+                                std::process::exit(exitcode::DATAERR);
+                            }
+                        }
+                        return;
+                    }
+                    if *format == ImageFormat::Pdf {
+                        error!("PDF format is supported only for input file.");
                         std::process::exit(exitcode::USAGE);
                     }
+                    if *format == ImageFormat::Bar {
+                        match picture.bar_segments() {
+                            Ok(segments) => {
+                                println!("{}", serde_json::to_string_pretty(&segments).unwrap())
+                            }
+                            Err(e) => {
+                                report(
+                                    cli.error_format,
+                                    ErrorRecord::new("invalid-substance", e, Some(substance)),
+                                );
+                                std::process::exit(exitcode::DATAERR);
+                            }
+                        }
+                        return;
+                    }
+                    if *format == ImageFormat::Gradient {
+                        match picture.bar_gradient() {
+                            Ok(Some(gradient)) => println!("{}", gradient),
+                            Ok(None) => {
+                                error!("Only mixtures (MInChI) have the bar to draw gradient of.");
+                                std::process::exit(exitcode::USAGE);
+                            }
+                            Err(e) => {
+                                report(
+                                    cli.error_format,
+                                    ErrorRecord::new("invalid-substance", e, Some(substance)),
+                                );
+                                std::process::exit(exitcode::DATAERR);
+                            }
+                        }
+                        return;
+                    }
+                    if let Some(band_height) = band_height {
+                        if *format != ImageFormat::Png || settings.file_format != FileFormat::Png {
+                            error!("Only PNG format can be rendered in bands.");
+                            std::process::exit(exitcode::USAGE);
+                        }
+                        let scene =
+                            match timing::measure(Stage::ShapeGeneration, || picture.to_scene()) {
+                                Ok(scene) => scene,
+                                Err(e) => {
+                                    report(
+                                        cli.error_format,
+                                        ErrorRecord::new("invalid-substance", e, Some(substance)),
+                                    );
+                                    std::process::exit(exitcode::DATAERR);
+                                }
+                            };
+                        info!("Image size: {}x{}", scene.width, scene.height);
+                        let saved = save_image(
+                            &output_path(output_file, None, &short_id(substance)),
+                            cli.on_conflict,
+                            quiet,
+                            cli.create_dirs,
+                            |writer| {
+                                encode_png_in_bands(
+                                    &scene,
+                                    writer,
+                                    settings.description(substance),
+                                    dpi,
+                                    *band_height,
+                                )
+                            },
+                        );
+                        if let Some(checksums) = checksums {
+                            write_checksums(saved.as_slice(), *checksums, cli.on_conflict, quiet);
+                        }
+                        if let (Some(path), true) = (saved, *open) {
+                            open_image(&path);
+                        }
+                        if *profile {
+                            print_profile(&short_id(substance), &timing::take());
+                        }
+                        return;
+                    }
 
-                    let buffer = match picture.generate() {
+                    let image_cache = image_cache.then(image_cache_or_exit);
+                    let buffer = match settings.render(&picture, substance, image_cache.as_ref()) {
                         Ok(buffer) => buffer,
                         Err(e) => {
-                            error!("{}", e);
-                            std::process::exit(exitcode::USAGE);
+                            report(
+                                cli.error_format,
+                                ErrorRecord::new("invalid-substance", e, Some(substance)),
+                            );
+                            std::process::exit(exitcode::DATAERR);
                         }
                     };
                     let width = buffer.width();
@@ -135,38 +1175,182 @@ fn main() {
                         width / divisor,
                         height / divisor
                     );
+                    if let Some(dpi) = dpi {
+                        info!(
+                            "Printed size: {:.2}x{:.2} in",
+                            width as f32 / dpi as f32,
+                            height as f32 / dpi as f32
+                        );
+                    }
+                    if matches!(format, ImageFormat::Datauri | ImageFormat::Html) {
+                        let text = match format {
+                            ImageFormat::Html => html_img(
+                                &buffer,
+                                Description {
+                                    text: substance,
+                                    tag: *payload_tag,
+                                },
+                                dpi,
+                            ),
+                            _ => data_uri(&buffer, settings.description(substance), dpi),
+                        };
+                        match text {
+                            Ok(text) => println!("{}", text),
+                            Err(e) => {
+                                error!("{}", e);
+                                std::process::exit(exitcode::SOFTWARE);
+                            }
+                        }
+                        if *profile {
+                            print_profile(&short_id(substance), &timing::take());
+                        }
+                        return;
+                    }
                     if *print || *print_only {
-                        print_to_terminal(buffer.clone());
+                        print_to_terminal(buffer.clone(), preview);
                     }
                     if !*print_only {
-                        if !output_file.ends_with(".png") {
-                            error!("Only PNG format is supported.");
-                            std::process::exit(exitcode::USAGE);
+                        let saved = save_image(
+                            &output_path(output_file, None, &short_id(substance)),
+                            cli.on_conflict,
+                            quiet,
+                            cli.create_dirs,
+                            |writer| settings.encode(&buffer, writer, substance),
+                        );
+                        if let Some(checksums) = checksums {
+                            write_checksums(saved.as_slice(), *checksums, cli.on_conflict, quiet);
                         }
-                        if file_exists(output_file) && !overwrite {
-                            if !Confirm::new()
-                                .with_prompt(format!(
-                                    "File \"{}\" already exists, overwrite?",
-                                    output_file
-                                ))
-                                .interact()
-                                .unwrap()
-                            {
-                                std::process::exit(exitcode::OK);
-                            }
+                        if let (Some(path), true) = (saved, *open) {
+                            open_image(&path);
+                        }
+                    }
+                    if let Some(content) = clipboard {
+                        if let Err(e) = copy_to_clipboard(*content, &buffer, &picture) {
+                            error!("{}", e);
+                            std::process::exit(exitcode::UNAVAILABLE);
                         }
-                        buffer.save(output_file).unwrap();
-                        let image_path = std::path::Path::new(output_file);
-                        let mut metadata = Metadata::new();
-                        metadata.set_tag(ExifTag::ImageDescription(substance.to_string()));
-                        metadata.write_to_file(&image_path).unwrap();
-                        info!("Image saved as {}", output_file);
+                    }
+                    if *profile {
+                        print_profile(&short_id(substance), &timing::take());
                     }
                 }
                 Err(e) => {
-                    error!("{}", e);
-                    std::process::exit(exitcode::USAGE);
+                    report(
+                        cli.error_format,
+                        ErrorRecord::new("invalid-substance", e, Some(substance)),
+                    );
+                    std::process::exit(exitcode::DATAERR);
+                }
+            }
+        }
+        Commands::Validate {
+            substance,
+            skip_version_check,
+            strict,
+        } => {
+            let substance = &normalize(substance, cli.url_decode, cli.canonicalize);
+            let validation = validate(substance, !skip_version_check);
+            if validation.errors.is_empty() && validation.warnings.is_empty() {
+                if !quiet {
+                    println!("No problems found.");
+                }
+                return;
+            }
+            for e in &validation.errors {
+                report(cli.error_format, ErrorRecord::from_parse_error(e));
+            }
+            for w in &validation.warnings {
+                report(cli.error_format, ErrorRecord::from_warning(w, substance));
+            }
+            if !validation.errors.is_empty() || *strict {
+                std::process::exit(exitcode::DATAERR);
+            }
+        }
+        Commands::Canonicalize {
+            substances,
+            input_file,
+        } => {
+            // Canonicalized here, with input kept for comparison
+            let substances = match input_file {
+                Some(path) => read_substances(path, cli.url_decode, false),
+                None => substances
+                    .iter()
+                    .map(|substance| normalize(substance, cli.url_decode, false))
+                    .collect(),
+            };
+            if substances.is_empty() {
+                error!("No substances provided");
+                std::process::exit(exitcode::USAGE);
+            }
+            let mut failed = 0;
+            for substance in substances {
+                let canonical = match canonicalize_minchi(&substance) {
+                    Ok(canonical) => canonical,
+                    Err(e) => {
+                        report(
+                            cli.error_format,
+                            ErrorRecord::new("invalid-substance", e, Some(&substance)),
+                        );
+                        failed += 1;
+                        continue;
+                    }
+                };
+                println!("{}", canonical);
+                if !quiet {
+                    for line in layers_diff(&substance, &canonical) {
+                        println!("{}", line);
+                    }
+                }
+            }
+            if failed > 0 {
+                warn!("{} substance(s) skipped", failed);
+                std::process::exit(EXIT_PARTIAL_SUCCESS);
+            }
+        }
+        Commands::Render {
+            scene_file,
+            width,
+            output_file,
+            image_format,
+            dpi,
+            open,
+        } => {
+            let image_format = file_format(output_file, *image_format);
+            let content = match fs::read_to_string(scene_file) {
+                Ok(content) => content,
+                Err(e) => {
+                    error!("Can't read scene {:?} - {}", scene_file, e);
+                    std::process::exit(exitcode::NOINPUT);
+                }
+            };
+            let scene = serde_json::from_str::<Scene>(&content)
+                .map_err(|e| format!("Invalid scene - {}", e));
+            let picture = scene.and_then(|scene| match width {
+                Some(width) => {
+                    Picture::from_scene(scene.scaled(*width as f32 / scene.width as f32))
+                }
+                None => Picture::from_scene(scene),
+            });
+            let buffer = match picture.and_then(|picture| picture.generate()) {
+                Ok(buffer) => buffer,
+                Err(e) => {
+                    report(
+                        cli.error_format,
+                        ErrorRecord::new("invalid-scene", e, Some(scene_file)),
+                    );
+                    std::process::exit(exitcode::DATAERR);
                 }
+            };
+            info!("Image size: {}x{}", buffer.width(), buffer.height());
+            let saved = save_image(
+                output_file,
+                cli.on_conflict,
+                quiet,
+                cli.create_dirs,
+                |writer| encode_image(&buffer, writer, image_format, None, *dpi),
+            );
+            if let (Some(path), true) = (saved, *open) {
+                open_image(&path);
             }
         }
         Commands::Calculate {
@@ -175,57 +1359,91 @@ fn main() {
             input_file,
             output_file,
             skip_errors,
+            with_mw,
+            with_id,
+            a11y,
+            a11y_foreground,
+            a11y_background,
+            min_contrast,
+            scheme,
+            print_schema,
         } => {
-            if let Some(path) = output_file {
-                match format {
-                    Format::Table => {
-                        error!("Output file is not supported for table format.");
-                        std::process::exit(exitcode::USAGE);
-                    }
-                    _ => {}
-                }
-
-                if file_exists(path) {
-                    if !Confirm::new()
-                        .with_prompt(format!("File \"{}\" already exists, overwrite?", path))
-                        .interact()
-                        .unwrap()
-                    {
-                        std::process::exit(exitcode::OK);
+            if *print_schema {
+                println!("{}", serde_json::to_string_pretty(&json_schema()).unwrap());
+                return;
+            }
+            let output_file = match output_file {
+                Some(path) => match resolve_conflict(path, cli.on_conflict, quiet) {
+                    Some(path) => {
+                        ensure_parent_dir(&path, cli.create_dirs);
+                        Some(path)
                     }
+                    None => return,
+                },
+                None => None,
+            };
+            let writer_options = WriterOptions {
+                output_file,
+                with_mw: *with_mw,
+                with_id: *with_id,
+                with_contrast: *a11y,
+            };
+            let options = CalculationOptions {
+                skip_errors: *skip_errors,
+                a11y: A11y {
+                    foreground: a11y_foreground.color,
+                    background: a11y_background.color,
+                    min_contrast: *min_contrast,
+                },
+                scheme: scheme.options(),
+            };
+            let registry = Registry::default();
+            let mut writer = match Calculation::new(&registry, format, &writer_options, options) {
+                Ok(writer) => writer,
+                Err(e) => {
+                    error!("{}", e);
+                    std::process::exit(exitcode::CANTCREAT);
                 }
-            }
-            let mut writer =
-                DataWriter::new(format.clone(), output_file.clone(), skip_errors.clone());
+            };
             match input_file {
                 Some(path) => {
                     if !file_exists(path) {
                         error!("File \"{}\" does not exist", path);
-                        std::process::exit(exitcode::USAGE);
+                        std::process::exit(exitcode::NOINPUT);
                     }
 
                     debug!("Reading from file {:?}", path);
 
                     if is_file_empty(path) {
                         error!("File \"{}\" is empty", path);
-                        std::process::exit(exitcode::USAGE);
+                        std::process::exit(exitcode::NOINPUT);
                     }
 
                     let file = std::fs::File::open(path);
                     if file.is_err() {
                         error!("Error reading file \"{}\"", path);
-                        std::process::exit(exitcode::USAGE);
+                        std::process::exit(exitcode::IOERR);
                     }
                     let file = file.unwrap();
                     let reader = BufReader::new(file);
 
                     debug!("Output generation started");
                     for line in reader.lines() {
-                        if let Ok(substance) = line {
-                            if let Err(message) = writer.write(substance) {
-                                error!("{}", message);
-                                std::process::exit(exitcode::USAGE);
+                        let substance = match line {
+                            Ok(substance) => substance,
+                            Err(e) => {
+                                error!("Error reading file \"{}\" - {}", path, e);
+                                std::process::exit(exitcode::IOERR);
                             }
+                        };
+                        if let Err(message) =
+                            writer.write(normalize(&substance, cli.url_decode, cli.canonicalize))
+                        {
+                            report(
+                                cli.error_format,
+                                ErrorRecord::new("invalid-substance", message, Some(&substance)),
+                            );
+                            std::process::exit(exitcode::DATAERR);
                         }
                     }
                 }
@@ -236,272 +1454,1478 @@ fn main() {
                     } else {
                         debug!("Output generation started");
                         for substance in substances {
-                            if let Err(message) = writer.write(substance.clone()) {
-                                error!("{}", message);
-                                std::process::exit(exitcode::USAGE);
+                            if let Err(message) =
+                                writer.write(normalize(substance, cli.url_decode, cli.canonicalize))
+                            {
+                                report(
+                                    cli.error_format,
+                                    ErrorRecord::new("invalid-substance", message, Some(substance)),
+                                );
+                                std::process::exit(exitcode::DATAERR);
                             }
                         }
                     }
                 }
             }
-            writer.flush();
+            if let Err(e) = writer.flush() {
+                error!("{}", e);
+                std::process::exit(exitcode::CANTCREAT);
+            }
+            if writer.skipped() > 0 {
+                warn!("{} substance(s) skipped or incomplete", writer.skipped());
+                std::process::exit(EXIT_PARTIAL_SUCCESS);
+            }
+        }
+        Commands::Cluster {
+            substances,
+            input_file,
+            threshold,
+            scheme,
+        } => {
+            let substances = match input_file {
+                Some(path) => read_substances(path, cli.url_decode, cli.canonicalize),
+                None => substances
+                    .iter()
+                    .map(|substance| normalize(substance, cli.url_decode, cli.canonicalize))
+                    .collect(),
+            };
+            if substances.is_empty() {
+                error!("No substances provided");
+                std::process::exit(exitcode::USAGE);
+            }
+            let mut failed = 0;
+            let mut compared = vec![];
+            let mut seen = HashSet::new();
+            for substance in substances {
+                // Duplicate would always look similar to itself
+                if !seen.insert(substance.clone()) {
+                    warn!("{} is listed more than once, skipped", substance);
+                    continue;
+                }
+                if !substance.starts_with("InChI=") {
+                    report(
+                        cli.error_format,
+                        ErrorRecord::new(
+                            "invalid-substance",
+                            "Only InChI is supported for clustering".to_string(),
+                            Some(&substance),
+                        ),
+                    );
+                    failed += 1;
+                    continue;
+                }
+                let hue = scheme.scheme(substance.clone()).primary.hue;
+                compared.push((substance, hue));
+            }
+            let hues: Vec<u32> = compared.iter().map(|(_, hue)| *hue).collect();
+            // Substances without similar ones are not interesting
+            let clusters: Vec<Vec<usize>> = cluster_hues(&hues, *threshold)
+                .into_iter()
+                .filter(|cluster| cluster.len() > 1)
+                .collect();
+            if clusters.is_empty() && !quiet {
+                println!("No similar substances found.");
+            }
+            for (number, cluster) in clusters.iter().enumerate() {
+                if number > 0 {
+                    println!();
+                }
+                println!(
+                    "Cluster {} (hues {}-{}):",
+                    number + 1,
+                    hues[cluster[0]],
+                    hues[*cluster.last().unwrap()]
+                );
+                for index in cluster {
+                    println!("{:>5}  {}", hues[*index], compared[*index].0);
+                }
+            }
+            if failed > 0 {
+                warn!("{} substance(s) skipped", failed);
+                std::process::exit(EXIT_PARTIAL_SUCCESS);
+            }
+        }
+        Commands::Pipeline {
+            input_file,
+            column,
+            identifier,
+            output_file,
+            report_file,
+            base_size,
+            border_size,
+            preset,
+            image_cache,
+            stats,
+            profile,
+            resolver,
+        } => {
+            let started = Instant::now();
+            timing::take();
+            let settings = ImageSettings {
+                base_size: preset.map_or(*base_size, |preset| preset.base_size()),
+                border_size: preset.map_or(*border_size, |preset| preset.border_size()),
+                // Resolvers give standard InChI
+                strict_version_check: true,
+                strict: false,
+                scheme: SchemeOptions::default(),
+                style: Style::default(),
+                dpi: preset.map(|preset| preset.dpi()),
+                metadata: true,
+                payload_tag: PayloadTag::default(),
+                file_format: file_format(output_file, None),
+            };
+            let batch = Batch {
+                settings: &settings,
+                output_file,
+                sort_by: SortBy::Input,
+                ignore_whitespace: false,
+                book: None,
+                sprite: false,
+                checksums: None,
+                image_cache: image_cache.then(image_cache_or_exit),
+                profile: *profile,
+                url_decode: false,
+                canonicalize: false,
+                on_conflict: cli.on_conflict,
+                error_format: cli.error_format,
+                quiet,
+                create_dirs: cli.create_dirs,
+            };
+            let pipeline = Pipeline {
+                batch,
+                resolver: resolver.resolver(),
+                identifier: *identifier,
+            };
+            let mut reader = match csv::Reader::from_path(input_file) {
+                Ok(reader) => reader,
+                Err(e) => {
+                    error!("Can't read {:?} - {}", input_file, e);
+                    std::process::exit(exitcode::NOINPUT);
+                }
+            };
+            let mut header = match reader.headers() {
+                Ok(header) => header.clone(),
+                Err(e) => {
+                    error!("Can't read header of {:?} - {}", input_file, e);
+                    std::process::exit(exitcode::DATAERR);
+                }
+            };
+            let position = match column {
+                Some(column) => header.iter().position(|name| name == column),
+                None => (!header.is_empty()).then_some(0),
+            };
+            let Some(position) = position else {
+                error!(
+                    "{:?} has no column {}",
+                    input_file,
+                    column.as_deref().unwrap_or_default()
+                );
+                std::process::exit(exitcode::DATAERR);
+            };
+            let report: Box<dyn Write> = match report_file.as_str() {
+                "-" => Box::new(std::io::stdout()),
+                path => match resolve_conflict(path, cli.on_conflict, quiet) {
+                    Some(path) => {
+                        ensure_parent_dir(&path, cli.create_dirs);
+                        Box::new(create_file(&path))
+                    }
+                    None => return,
+                },
+            };
+            let mut report = csv::Writer::from_writer(report);
+            header.extend(PIPELINE_COLUMNS);
+            report.write_record(&header).unwrap();
+            let mut statuses = vec![];
+            let mut incomplete = 0;
+            for (index, record) in reader.records().enumerate() {
+                let mut record = match record {
+                    Ok(record) => record,
+                    Err(e) => {
+                        error!("Error reading file {:?} - {}", input_file, e);
+                        std::process::exit(exitcode::DATAERR);
+                    }
+                };
+                let entry = pipeline.run(index + 1, record.get(position).unwrap_or_default());
+                statuses.push(entry.status);
+                incomplete += usize::from(entry.incomplete());
+                record.extend(pipeline_record(&entry));
+                report.write_record(&record).unwrap();
+            }
+            report.flush().unwrap();
+            if *stats {
+                print_stats(&statuses, started.elapsed(), timing::take());
+            }
+            if incomplete > 0 {
+                warn!("{} substance(s) failed or weren't saved", incomplete);
+                std::process::exit(EXIT_PARTIAL_SUCCESS);
+            }
+        }
+        Commands::Cache {
+            command: CacheCommand::Clear { only },
+        } => {
+            let Some(directory) = default_cache_dir() else {
+                error!("There is no cache directory, set HOME or XDG_CACHE_HOME");
+                std::process::exit(exitcode::CONFIG);
+            };
+            let kinds = match only {
+                Some(CacheKind::Resolver) => vec![RESOLVER_DIR],
+                Some(CacheKind::Images) => vec![IMAGES_DIR],
+                None => vec![RESOLVER_DIR, IMAGES_DIR],
+            };
+            let mut removed = 0;
+            for kind in kinds {
+                match clear_cache(&directory.join(kind)) {
+                    Ok(count) => removed += count,
+                    Err(e) => {
+                        error!("{}", e);
+                        std::process::exit(exitcode::IOERR);
+                    }
+                }
+            }
+            if !quiet {
+                println!("Removed {} cached file(s) from {:?}", removed, directory);
+            }
         }
     }
 }
 
-struct DataWriter {
-    skip_errors: bool,
-    actual_writer: Box<dyn Writer>,
+/// Summary of batch run - substances by status, and where the time went.
+fn print_stats(statuses: &[Status], elapsed: Duration, timings: Timings) {
+    let count = |status: Status| statuses.iter().filter(|s| **s == status).count();
+    eprintln!(
+        "Processed {} substance(s): {} ok, {} skipped, {} duplicate, {} failed",
+        statuses.len(),
+        count(Status::Ok),
+        count(Status::Skipped),
+        count(Status::Duplicate),
+        count(Status::Error)
+    );
+    eprintln!("Total time: {:.1} ms", elapsed.as_secs_f64() * 1000.0);
+    for stage in Stage::ALL {
+        eprintln!(
+            "  {:<17} {:>10.1} ms",
+            stage.name(),
+            timings.get(stage).as_secs_f64() * 1000.0
+        );
+    }
 }
-impl DataWriter {
-    fn new(format: Format, output_file: Option<String>, skip_errors: bool) -> Self {
-        DataWriter {
-            skip_errors,
-            actual_writer: match format {
-                Format::Table => {
-                    if output_file.is_some() {
-                        unreachable!();
-                    }
-                    Box::new(TableWriter::new())
-                }
-                Format::Json => Box::new(JsonWriter::new(output_file)),
-                Format::Yaml => Box::new(YamlWriter::new(output_file)),
-                Format::Csv => match output_file {
-                    Some(path) => Box::new(CsvFileWriter::new(path)),
-                    None => Box::new(CsvStdoutWriter::new()),
-                },
-            },
+
+/// Time spent in each stage of generation of single image.
+fn print_profile(image: &str, timings: &Timings) {
+    let stages: Vec<String> = Stage::ALL
+        .iter()
+        .map(|stage| {
+            format!(
+                "{} {:.2} ms",
+                stage.name(),
+                timings.get(*stage).as_secs_f64() * 1000.0
+            )
+        })
+        .collect();
+    eprintln!(
+        "Profile of {}: {}, total {:.2} ms",
+        image,
+        stages.join(", "),
+        timings.total().as_secs_f64() * 1000.0
+    );
+}
+
+/// Image cache in default cache directory, which has to exist when asked for.
+fn image_cache_or_exit() -> ImageCache {
+    match ImageCache::in_default_dir() {
+        Some(cache) => cache,
+        None => {
+            error!("There is no cache directory for images, set HOME or XDG_CACHE_HOME");
+            std::process::exit(exitcode::CONFIG);
         }
     }
-    fn write(&mut self, substance: String) -> Result<(), String> {
-        if !substance.starts_with("InChI=1S/") && !self.skip_errors {
-            return Err(format!(
-                "Only InChI version 1S is supported for now, you may pass flag to skip it. Error received: {}",
-                substance
-            ));
-        }
+}
 
-        if substance.starts_with("InChI=") {
-            self.actual_writer.write(substance);
-        } else {
-            if !self.skip_errors {
-                return Err(format!(
-                    "No InChI provided, only payload starting with 'InChI=' is supported for calculation. Error source: {}",
-                    substance
-                ));
+/// Swatch of the legend, component is numbered from 1 as in MInChI (always 1 for InChI).
+#[derive(Serialize)]
+struct LegendEntry {
+    component: usize,
+    primary: String,
+    first_accent: String,
+    second_accent: String,
+    complementary: String,
+}
+
+fn legend(picture: &Picture) -> Result<Vec<LegendEntry>, String> {
+    let schemes = picture.schemes();
+    Ok(picture
+        .swatch_order()?
+        .into_iter()
+        .map(|index| {
+            let [primary, first_accent, second_accent, complementary] =
+                schemes[index].colors().map(Color::to_hex);
+            LegendEntry {
+                component: index + 1,
+                primary,
+                first_accent,
+                second_accent,
+                complementary,
             }
+        })
+        .collect())
+}
+
+/// Everything needed to generate image of a substance, except the substance itself.
+#[derive(Debug)]
+struct ImageSettings {
+    base_size: u32,
+    border_size: u32,
+    strict_version_check: bool,
+    /// Fail pictures with warnings.
+    strict: bool,
+    scheme: SchemeOptions,
+    style: Style,
+    dpi: Option<u32>,
+    /// Store the substance in EXIF metadata of saved images.
+    metadata: bool,
+    payload_tag: PayloadTag,
+    file_format: FileFormat,
+}
+
+impl ImageSettings {
+    /// Description saved in metadata of image of the substance, if any.
+    fn description<'a>(&self, substance: &'a str) -> Option<Description<'a>> {
+        self.metadata.then_some(Description {
+            text: substance,
+            tag: self.payload_tag,
+        })
+    }
+
+    /// Encode image of the substance in format of saved images.
+    fn encode(
+        &self,
+        buffer: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+        writer: impl Write,
+        substance: &str,
+    ) -> Result<(), String> {
+        encode_image(
+            buffer,
+            writer,
+            self.file_format,
+            self.description(substance),
+            self.dpi,
+        )
+    }
+
+    fn picture(&self, substance: &str) -> Result<Picture, String> {
+        generate_moleco_with(
+            substance.to_string(),
+            self.base_size,
+            self.border_size,
+            self.strict_version_check,
+            self.scheme.options(),
+        )
+        .map(|picture| picture.with_style(self.style.clone()))
+    }
+
+    /// Report warnings of picture of the substance, or fail with them in strict mode.
+    fn check_warnings(
+        &self,
+        picture: &Picture,
+        substance: &str,
+        error_format: ErrorFormat,
+    ) -> Result<(), String> {
+        let warnings = picture.warnings();
+        if self.strict && !warnings.is_empty() {
+            let messages: Vec<String> = warnings.iter().map(Warning::to_string).collect();
+            return Err(format!("Strict mode - {}", messages.join("; ")));
+        }
+        for warning in &warnings {
+            report(error_format, ErrorRecord::from_warning(warning, substance));
+        }
+        for note in &picture.notes() {
+            report(error_format, ErrorRecord::from_note(note, substance));
         }
         Ok(())
     }
-    fn flush(&mut self) {
-        self.actual_writer.flush();
+
+    /// Render picture of the substance, or take the same one rendered earlier from the cache.
+    fn render(
+        &self,
+        picture: &Picture,
+        substance: &str,
+        cache: Option<&ImageCache>,
+    ) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, String> {
+        let Some(cache) = cache else {
+            return picture.generate();
+        };
+        let key = ImageCache::key(substance, &format!("{:?}", self));
+        if let Some(buffer) = cache.get(&key) {
+            debug!("Image taken from cache {:?}", cache.path(&key));
+            return Ok(buffer);
+        }
+        let buffer = picture.generate()?;
+        if let Err(e) = cache.put(&key, &buffer) {
+            warn!("Can't cache image - {}", e);
+        }
+        Ok(buffer)
     }
 }
 
-trait Writer {
-    fn write(&mut self, substance: String);
-    fn flush(&mut self);
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Ok,
+    /// Output file already existed and was kept (see `--on-conflict`).
+    Skipped,
+    Error,
+    /// The same substance is on earlier line, its output is shared.
+    Duplicate,
 }
 
-struct TableWriter {
-    table: Table,
+#[derive(Serialize, Clone)]
+struct Hues {
+    primary: u32,
+    first_accent: u32,
+    second_accent: u32,
+    complementary: u32,
 }
-impl TableWriter {
-    fn new() -> Self {
-        let mut table = Table::new();
-        table.add_row(row![
-            "Substance",
-            "Primary hue",
-            "First accent hue",
-            "Second accent hue",
-            "Complementary hue"
-        ]);
-        TableWriter { table }
+
+impl From<&Scheme> for Hues {
+    fn from(scheme: &Scheme) -> Self {
+        let [primary, first_accent, second_accent, complementary] =
+            scheme.colors().map(|color| color.hue);
+        Hues {
+            primary,
+            first_accent,
+            second_accent,
+            complementary,
+        }
     }
 }
 
-impl Writer for TableWriter {
-    fn write(&mut self, substance: String) {
-        let palette = calculate_scheme(substance.to_string());
-        self.table.add_row(row![
-            substance,
-            palette.primary.hue,
-            palette.first_accent.hue,
-            palette.second_accent.hue,
-            palette.complementary.hue
-        ]);
+/// What happened to single line of input file (or single payload file of input glob). Hues are
+/// listed per component (one for InChI).
+#[derive(Serialize, Clone)]
+struct ManifestEntry {
+    line: usize,
+    /// Payload file the substance was read from, with input glob.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+    input: String,
+    output: Option<String>,
+    hues: Vec<Hues>,
+    id: String,
+    status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+    /// Line of the first occurrence of duplicated substance.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duplicate_of: Option<usize>,
+}
+
+impl ManifestEntry {
+    /// Entry failed, or its image wasn't saved because overwriting wasn't confirmed (files kept
+    /// by `--on-conflict skip` are not a problem).
+    fn incomplete(&self) -> bool {
+        match self.status {
+            Status::Error => true,
+            Status::Skipped => self.message.is_some(),
+            Status::Ok | Status::Duplicate => false,
+        }
+    }
+}
+
+/// Generation of images for all substances of input file. Invalid substances are reported and
+/// skipped, so one bad line doesn't stop the whole batch.
+struct Batch<'a> {
+    settings: &'a ImageSettings,
+    /// Template of output filenames.
+    output_file: &'a str,
+    sort_by: SortBy,
+    ignore_whitespace: bool,
+    /// Swatches per page and paper size, when all swatches go into single PDF label book.
+    book: Option<(usize, PageSize)>,
+    /// All pictures go into single SVG sprite sheet, as symbols named by short identifiers.
+    sprite: bool,
+    checksums: Option<Checksums>,
+    image_cache: Option<ImageCache>,
+    /// Print time spent generating each image.
+    profile: bool,
+    url_decode: bool,
+    canonicalize: bool,
+    on_conflict: OnConflict,
+    error_format: ErrorFormat,
+    quiet: bool,
+    create_dirs: bool,
+}
+
+impl Batch<'_> {
+    /// Generate images for substances of input file (or URL), one per line, or of payload files
+    /// they were read from.
+    fn run(
+        &self,
+        substances: impl Iterator<Item = (String, Option<InputFile>)>,
+    ) -> Vec<ManifestEntry> {
+        // Pictures are cheap, only images are generated one by one
+        let mut pictures = vec![];
+        let mut first_lines = HashMap::new();
+        let mut duplicates = vec![];
+        for (index, (substance, file)) in substances.enumerate() {
+            let substance = normalize(&substance, self.url_decode, self.canonicalize);
+            if substance.is_empty() {
+                if let Some(file) = file {
+                    warn!("No substance in \"{}\", skipped", file.path);
+                }
+                continue;
+            }
+            let key = duplicate_key(&substance, self.ignore_whitespace);
+            if let Some((first, first_file)) = first_lines.get(&key) {
+                match (&file, first_file) {
+                    (Some(file), Some(first_file)) => warn!(
+                        "File \"{}\" duplicates \"{}\", skipped",
+                        file.path, first_file
+                    ),
+                    _ => warn!("Line {} duplicates line {}, skipped", index + 1, first),
+                }
+                duplicates.push((index + 1, substance, file, *first));
+                continue;
+            }
+            first_lines.insert(
+                key,
+                (index + 1, file.as_ref().map(|file| file.path.clone())),
+            );
+            let before = timing::peek();
+            let picture = self.settings.picture(&substance);
+            let spent = timing::peek().since(&before);
+            pictures.push((index + 1, substance, file, picture, spent));
+        }
+        if self.sort_by == SortBy::Hue {
+            // Invalid substances go last
+            pictures.sort_by_key(|(_, _, _, picture, _)| match picture {
+                Ok(picture) => picture.schemes()[0].primary.hue,
+                Err(_) => u32::MAX,
+            });
+        }
+        let mut output = match (self.book, self.output_file.ends_with(".zip")) {
+            (Some(_), _) => BatchOutput::Book(vec![]),
+            _ if self.sprite => BatchOutput::Sprite(SpriteSheet::new()),
+            (None, true) => BatchOutput::Archive(self.open_archive()),
+            (None, false) => BatchOutput::Files,
+        };
+        let mut entries: Vec<ManifestEntry> = pictures
+            .into_iter()
+            .enumerate()
+            .map(|(position, (line, substance, file, picture, mut spent))| {
+                let index = match self.sort_by {
+                    SortBy::Input => line,
+                    SortBy::Hue => position + 1,
+                };
+                let before = timing::peek();
+                let entry =
+                    self.generate(index, line, substance, file.as_ref(), picture, &mut output);
+                spent.add(&timing::peek().since(&before));
+                if self.profile && entry.status != Status::Error {
+                    let place = match file {
+                        Some(file) => file.path,
+                        None => format!("line {}", line),
+                    };
+                    print_profile(&format!("{} ({})", place, entry.id), &spent);
+                }
+                entry
+            })
+            .collect();
+        let document = match (self.book, &mut output) {
+            (Some((per_page, page_size)), BatchOutput::Book(labels)) => {
+                Some(self.save_book(labels, per_page, page_size))
+            }
+            (_, BatchOutput::Sprite(sheet)) => Some(self.save_sprite(std::mem::take(sheet))),
+            _ => None,
+        };
+        if let Some(output) = document {
+            for entry in entries
+                .iter_mut()
+                .filter(|entry| entry.status == Status::Ok)
+            {
+                entry.output = output.clone();
+                if output.is_none() {
+                    entry.status = Status::Skipped;
+                }
+            }
+        }
+
+        // Duplicates share results of the first occurrence, and follow it in hue order
+        let positions: HashMap<usize, usize> = entries
+            .iter()
+            .enumerate()
+            .map(|(position, entry)| (entry.line, position))
+            .collect();
+        for (line, substance, file, first) in duplicates {
+            let mut entry = entries[positions[&first]].clone();
+            entry.line = line;
+            entry.file = file.map(|file| file.path);
+            entry.input = substance;
+            entry.status = Status::Duplicate;
+            entry.duplicate_of = Some(first);
+            entries.push(entry);
+        }
+        match self.sort_by {
+            SortBy::Input => entries.sort_by_key(|entry| entry.line),
+            SortBy::Hue => {
+                entries.sort_by_key(|entry| positions[&entry.duplicate_of.unwrap_or(entry.line)])
+            }
+        }
+        let saved = match output {
+            BatchOutput::Archive(Some((path, archive))) => {
+                self.finish_archive(&path, archive, &entries);
+                vec![path]
+            }
+            BatchOutput::Archive(None) => vec![],
+            BatchOutput::Files | BatchOutput::Book(_) | BatchOutput::Sprite(_) => {
+                let mut paths: Vec<String> = entries
+                    .iter()
+                    .filter(|entry| entry.status == Status::Ok)
+                    .filter_map(|entry| entry.output.clone())
+                    .collect();
+                // All entries of label book (or sprite sheet) share the same document
+                paths.dedup();
+                paths
+            }
+        };
+        if let Some(checksums) = self.checksums {
+            write_checksums(&saved, checksums, self.on_conflict, self.quiet);
+        }
+        entries
     }
-    fn flush(&mut self) {
-        self.table.printstd();
+
+    /// Generate and save image, `index` (and payload file) is used in its filename. Images of
+    /// label book are only collected, book is saved when all of them are ready. Images of archive
+    /// are added to it right away. Pictures of sprite sheet are added to it without rendering.
+    fn generate(
+        &self,
+        index: usize,
+        line: usize,
+        substance: String,
+        file: Option<&InputFile>,
+        picture: Result<Picture, String>,
+        output: &mut BatchOutput,
+    ) -> ManifestEntry {
+        let id = short_id(&substance);
+        let mut entry = ManifestEntry {
+            line,
+            file: file.map(|file| file.path.clone()),
+            input: substance.clone(),
+            output: None,
+            hues: vec![],
+            id: id.clone(),
+            status: Status::Error,
+            message: None,
+            duplicate_of: None,
+        };
+        let generated = picture.and_then(|picture| {
+            entry.hues = picture.schemes().iter().map(Hues::from).collect();
+            self.settings
+                .check_warnings(&picture, &substance, self.error_format)?;
+            if let BatchOutput::Sprite(sheet) = output {
+                let added = timing::measure(Stage::Encoding, || sheet.add(&id, &picture));
+                return added.map(|_| None);
+            }
+            self.settings
+                .render(&picture, &substance, self.image_cache.as_ref())
+                .map(Some)
+        });
+        let buffer = match generated {
+            Ok(Some(buffer)) => buffer,
+            Ok(None) => {
+                entry.status = Status::Ok;
+                return entry;
+            }
+            Err(e) => {
+                report(
+                    self.error_format,
+                    ErrorRecord::new("invalid-substance", e.clone(), Some(&substance)),
+                );
+                entry.message = Some(e);
+                return entry;
+            }
+        };
+        entry.output = match output {
+            BatchOutput::Files => {
+                let path =
+                    fill_file_placeholders(output_path(self.output_file, Some(index), &id), file);
+                match entry_target(&path, self.on_conflict, self.quiet) {
+                    Ok(Some(path)) => {
+                        ensure_parent_dir(&path, self.create_dirs);
+                        let written = write_image(&path, |writer| {
+                            self.settings.encode(&buffer, writer, &substance)
+                        });
+                        if let Err(e) = written {
+                            report(
+                                self.error_format,
+                                ErrorRecord::new("unwritten-image", e.clone(), Some(&substance)),
+                            );
+                            entry.message = Some(e);
+                            return entry;
+                        }
+                        Some(path)
+                    }
+                    Ok(None) => None,
+                    Err(e) => {
+                        entry.status = Status::Skipped;
+                        entry.message = Some(e);
+                        return entry;
+                    }
+                }
+            }
+            BatchOutput::Book(labels) => {
+                labels.push(Label {
+                    image: buffer,
+                    caption: vec![format!("{}. {}", index, id), substance],
+                });
+                entry.status = Status::Ok;
+                return entry;
+            }
+            BatchOutput::Archive(Some((path, archive))) => {
+                let name = fill_file_placeholders(self.archive_entry(index, &id), file);
+                let written = archive
+                    .start_file(name.as_str(), stored())
+                    .map_err(|e| e.to_string())
+                    .and_then(|_| self.settings.encode(&buffer, archive, &substance));
+                if let Err(e) = written {
+                    error!("Can't write {:?} - {}", path, e);
+                    std::process::exit(exitcode::CANTCREAT);
+                }
+                debug!("Image added to archive as {}", name);
+                Some(name)
+            }
+            BatchOutput::Archive(None) => None,
+            BatchOutput::Sprite(_) => unreachable!(),
+        };
+        entry.status = match entry.output {
+            Some(_) => Status::Ok,
+            None => Status::Skipped,
+        };
+        entry
     }
 }
-struct JsonWriter {
-    path: Option<String>,
-    doc_root: serde_json::Map<String, serde_json::Value>,
+
+/// Columns added to input file by pipeline.
+const PIPELINE_COLUMNS: [&str; 9] = [
+    "inchi",
+    "id",
+    "primary",
+    "first_accent",
+    "second_accent",
+    "complementary",
+    "output",
+    "status",
+    "message",
+];
+
+/// Resolution of substances given by identifiers, followed by generation of their images.
+struct Pipeline<'a> {
+    batch: Batch<'a>,
+    resolver: Resolver,
+    identifier: IdentifierKind,
 }
 
-impl JsonWriter {
-    fn new(path: Option<String>) -> Self {
-        let doc_root = serde_json::Map::new();
-        JsonWriter { path, doc_root }
+impl Pipeline<'_> {
+    /// Resolve and generate single row, input of the entry is resolved InChI (empty, when
+    /// resolution failed).
+    fn run(&self, row: usize, identifier: &str) -> ManifestEntry {
+        let resolved = self
+            .identifier
+            .query(identifier)
+            .and_then(|query| self.resolver.resolve(&query));
+        match resolved {
+            Ok(inchi) => {
+                let before = timing::peek();
+                let picture = self.batch.settings.picture(&inchi);
+                let entry =
+                    self.batch
+                        .generate(row, row, inchi, None, picture, &mut BatchOutput::Files);
+                if self.batch.profile && entry.status != Status::Error {
+                    let spent = timing::peek().since(&before);
+                    print_profile(&format!("row {} ({})", row, entry.id), &spent);
+                }
+                entry
+            }
+            Err(e) => {
+                report(
+                    self.batch.error_format,
+                    ErrorRecord::new("unresolved-substance", e.clone(), Some(identifier)),
+                );
+                ManifestEntry {
+                    line: row,
+                    file: None,
+                    input: String::new(),
+                    output: None,
+                    hues: vec![],
+                    id: String::new(),
+                    status: Status::Error,
+                    message: Some(e),
+                    duplicate_of: None,
+                }
+            }
+        }
     }
 }
 
-impl Writer for JsonWriter {
-    fn write(&mut self, substance: String) {
-        let palette = calculate_scheme(substance.to_string());
-        let mut sub_json = serde_json::Map::new();
-        sub_json.insert("primary".to_string(), palette.primary.hue.into());
-        sub_json.insert("first_accent".to_string(), palette.first_accent.hue.into());
-        sub_json.insert(
-            "second_accent".to_string(),
-            palette.second_accent.hue.into(),
-        );
-        sub_json.insert(
-            "complementary".to_string(),
-            palette.complementary.hue.into(),
-        );
-        self.doc_root
-            .insert(substance.to_string(), serde_json::Value::Object(sub_json));
+/// Values of `PIPELINE_COLUMNS` for the entry.
+fn pipeline_record(entry: &ManifestEntry) -> Vec<String> {
+    let hues = match entry.hues.first() {
+        Some(hues) => [
+            hues.primary,
+            hues.first_accent,
+            hues.second_accent,
+            hues.complementary,
+        ]
+        .map(|hue| hue.to_string()),
+        None => Default::default(),
+    };
+    let status = serde_json::to_value(entry.status).unwrap();
+    let mut record = vec![entry.input.clone(), entry.id.clone()];
+    record.extend(hues);
+    record.extend([
+        entry.output.clone().unwrap_or_default(),
+        status.as_str().unwrap_or_default().to_string(),
+        entry.message.clone().unwrap_or_default(),
+    ]);
+    record
+}
+
+/// Where images of the batch go.
+enum BatchOutput {
+    /// Separate PNG files.
+    Files,
+    /// Labels of PDF label book, saved when all of them are ready.
+    Book(Vec<Label>),
+    /// SVG sprite sheet, saved when all pictures are in it.
+    Sprite(SpriteSheet),
+    /// ZIP archive and its path, None when existing archive is kept.
+    Archive(Option<(String, Box<ZipWriter<fs::File>>)>),
+}
+
+/// PNG images are compressed already, so they are only stored in archive.
+fn stored() -> SimpleFileOptions {
+    SimpleFileOptions::default().compression_method(CompressionMethod::Stored)
+}
+
+impl Batch<'_> {
+    /// Save all collected labels as single PDF document, nothing is saved when there are none.
+    fn save_book(&self, labels: &[Label], per_page: usize, page_size: PageSize) -> Option<String> {
+        if labels.is_empty() {
+            warn!("No swatches generated, label book is not saved");
+            return None;
+        }
+        let path = resolve_conflict(self.output_file, self.on_conflict, self.quiet)?;
+        ensure_parent_dir(&path, self.create_dirs);
+        let book = timing::measure(Stage::Encoding, || label_book(labels, per_page, page_size));
+        if let Err(e) = fs::write(&path, book) {
+            error!("Can't write {:?} - {}", path, e);
+            std::process::exit(exitcode::CANTCREAT);
+        }
+        info!("Label book saved as {}", path);
+        Some(path)
+    }
+
+    /// Save sprite sheet as SVG document, nothing is saved when it is empty.
+    fn save_sprite(&self, sheet: SpriteSheet) -> Option<String> {
+        if sheet.is_empty() {
+            warn!("No swatches generated, sprite sheet is not saved");
+            return None;
+        }
+        let path = resolve_conflict(self.output_file, self.on_conflict, self.quiet)?;
+        ensure_parent_dir(&path, self.create_dirs);
+        if let Err(e) = fs::write(&path, sheet.finish()) {
+            error!("Can't write {:?} - {}", path, e);
+            std::process::exit(exitcode::CANTCREAT);
+        }
+        info!("Sprite sheet saved as {}", path);
+        Some(path)
+    }
+
+    fn open_archive(&self) -> Option<(String, Box<ZipWriter<fs::File>>)> {
+        let path = resolve_conflict(self.output_file, self.on_conflict, self.quiet)?;
+        ensure_parent_dir(&path, self.create_dirs);
+        let archive = Box::new(ZipWriter::new(create_file(&path)));
+        Some((path, archive))
+    }
+
+    /// Name of image in archive - output filename template with extension of image format
+    /// instead of `.zip`.
+    fn archive_entry(&self, index: usize, id: &str) -> String {
+        let path = std::path::Path::new(self.output_file);
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let extension = self.settings.file_format.extension();
+        output_path(&format!("{}.{}", stem, extension), Some(index), id)
+    }
+
+    /// Add manifest of the batch to archive and finish it.
+    fn finish_archive(
+        &self,
+        path: &str,
+        mut archive: Box<ZipWriter<fs::File>>,
+        entries: &[ManifestEntry],
+    ) {
+        let written = archive
+            .start_file("manifest.json", SimpleFileOptions::default())
+            .map_err(|e| e.to_string())
+            .and_then(|_| {
+                serde_json::to_writer_pretty(&mut archive, entries).map_err(|e| e.to_string())
+            })
+            .and_then(|_| archive.flush().map_err(|e| e.to_string()))
+            .and_then(|_| archive.finish().map(|_| ()).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            error!("Can't write {:?} - {}", path, e);
+            std::process::exit(exitcode::CANTCREAT);
+        }
+        info!("Archive saved as {}", path);
+    }
+}
+
+/// Substances with the same key are duplicates. Whitespace is never part of InChI, but it may
+/// sneak into pasted or wrapped lines.
+fn duplicate_key(substance: &str, ignore_whitespace: bool) -> String {
+    match ignore_whitespace {
+        true => substance.chars().filter(|c| !c.is_whitespace()).collect(),
+        false => substance.to_string(),
+    }
+}
+
+/// Fill placeholders of output filename template. In batch (with index) template without any of
+/// them gets index appended, so images don't overwrite each other. Placeholders of payload file
+/// are left for `fill_file_placeholders`.
+fn output_path(template: &str, index: Option<usize>, id: &str) -> String {
+    let Some(index) = index else {
+        return template.replace("{id}", id);
+    };
+    if !template.contains("{index}")
+        && !template.contains("{id}")
+        && !uses_file_placeholders(template)
+    {
+        let path = std::path::Path::new(template);
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let extension = match path.extension() {
+            Some(extension) => format!(".{}", extension.to_string_lossy()),
+            None => "".to_string(),
+        };
+        return path
+            .with_file_name(format!("{}-{}{}", stem, index, extension))
+            .to_string_lossy()
+            .to_string();
+    }
+    template
+        .replace("{index}", &index.to_string())
+        .replace("{id}", id)
+}
+
+fn uses_file_placeholders(template: &str) -> bool {
+    template.contains("{name}") || template.contains("{path}")
+}
+
+fn fill_file_placeholders(path: String, file: Option<&InputFile>) -> String {
+    match file {
+        Some(file) => path
+            .replace("{name}", &file.name)
+            .replace("{path}", &file.relative),
+        None => path,
+    }
+}
+
+/// Payload file matched by input glob.
+struct InputFile {
+    path: String,
+    /// Name of the file without extension.
+    name: String,
+    /// Path relative to directory the pattern starts from (its part before the first wildcard),
+    /// without extension.
+    relative: String,
+}
+
+/// Read payload files matching glob pattern, in order of their paths.
+fn read_input_files(pattern: &str) -> impl Iterator<Item = (String, Option<InputFile>)> {
+    let paths = match glob::glob(pattern) {
+        Ok(paths) => paths,
+        Err(e) => {
+            error!("Invalid input glob {:?} - {}", pattern, e);
+            std::process::exit(exitcode::USAGE);
+        }
+    };
+    let base: std::path::PathBuf = std::path::Path::new(pattern)
+        .components()
+        .take_while(|component| {
+            let component = component.as_os_str().to_string_lossy();
+            !component.contains(['*', '?', '['])
+        })
+        .collect();
+    let mut files = vec![];
+    for path in paths {
+        match path {
+            Ok(path) if path.is_file() => files.push(path),
+            Ok(_) => {}
+            Err(e) => {
+                error!("Can't read {:?} - {}", e.path(), e.error());
+                std::process::exit(exitcode::NOINPUT);
+            }
+        }
+    }
+    if files.is_empty() {
+        error!("No files match {:?}", pattern);
+        std::process::exit(exitcode::NOINPUT);
     }
-    fn flush(&mut self) {
-        match self.path {
-            Some(ref path) => {
-                let file = fs::File::create(path).unwrap();
-                serde_json::to_writer_pretty(file, &self.doc_root).unwrap();
-                info!("Image saved as {}", path);
+    files.into_iter().map(move |path| {
+        let substance = match fs::read_to_string(&path) {
+            Ok(substance) => substance,
+            Err(e) => {
+                error!("Error reading file {:?} - {}", path, e);
+                std::process::exit(exitcode::IOERR);
+            }
+        };
+        let relative = path.strip_prefix(&base).unwrap_or(&path).with_extension("");
+        let file = InputFile {
+            path: path.to_string_lossy().to_string(),
+            name: path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+            relative: relative.to_string_lossy().to_string(),
+        };
+        (substance, Some(file))
+    })
+}
+
+/// Write SHA-256 checksums of saved files, either next to each of them, or all into single
+/// `SHA256SUMS` file in directory of the first one. Files are listed relative to that directory,
+/// so `sha256sum --check` can be run there.
+fn write_checksums(paths: &[String], checksums: Checksums, on_conflict: OnConflict, quiet: bool) {
+    let Some(first) = paths.first() else {
+        return;
+    };
+    let checksum_line = |path: &str, name: &str| match fs::read(path) {
+        Ok(content) => format!("{:x}  {}\n", Sha256::digest(content), name),
+        Err(e) => {
+            error!("Can't read {:?} - {}", path, e);
+            std::process::exit(exitcode::IOERR);
+        }
+    };
+    match checksums {
+        Checksums::Sidecar => {
+            for path in paths {
+                let name = std::path::Path::new(path).file_name().unwrap_or_default();
+                let sidecar = format!("{}.sha256", path);
+                let Some(sidecar) = resolve_conflict(&sidecar, on_conflict, quiet) else {
+                    continue;
+                };
+                if let Err(e) = fs::write(&sidecar, checksum_line(path, &name.to_string_lossy())) {
+                    error!("Can't write {:?} - {}", sidecar, e);
+                    std::process::exit(exitcode::CANTCREAT);
+                }
+                debug!("Checksum saved as {}", sidecar);
             }
-            None => {
-                println!("{}", serde_json::to_string_pretty(&self.doc_root).unwrap());
+        }
+        Checksums::Sums => {
+            let directory = std::path::Path::new(first)
+                .parent()
+                .unwrap_or(std::path::Path::new(""));
+            let lines: String = paths
+                .iter()
+                .map(|path| {
+                    let name = std::path::Path::new(path)
+                        .strip_prefix(directory)
+                        .map(|name| name.to_string_lossy().to_string())
+                        .unwrap_or(path.clone());
+                    checksum_line(path, &name)
+                })
+                .collect();
+            let sums = directory.join("SHA256SUMS").to_string_lossy().to_string();
+            let Some(sums) = resolve_conflict(&sums, on_conflict, quiet) else {
+                return;
+            };
+            if let Err(e) = fs::write(&sums, lines) {
+                error!("Can't write {:?} - {}", sums, e);
+                std::process::exit(exitcode::CANTCREAT);
             }
+            info!("Checksums saved as {}", sums);
         }
     }
 }
 
-struct YamlWriter {
-    path: Option<String>,
-    doc_root: serde_yaml::Mapping,
+fn write_manifest(
+    entries: &[ManifestEntry],
+    path: &str,
+    on_conflict: OnConflict,
+    quiet: bool,
+    create_dirs: bool,
+) {
+    if path == "-" {
+        println!("{}", serde_json::to_string_pretty(entries).unwrap());
+        return;
+    }
+    let Some(path) = resolve_conflict(path, on_conflict, quiet) else {
+        return;
+    };
+    ensure_parent_dir(&path, create_dirs);
+    let file = create_file(&path);
+    if let Err(e) = serde_json::to_writer_pretty(file, entries) {
+        error!("Can't write manifest {:?} - {}", path, e);
+        std::process::exit(exitcode::IOERR);
+    }
+    info!("Manifest saved as {}", path);
+}
+
+fn open_file(path: &str) -> fs::File {
+    match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) => {
+            error!("Can't read {:?} - {}", path, e);
+            std::process::exit(exitcode::NOINPUT);
+        }
+    }
 }
 
-impl YamlWriter {
-    fn new(path: Option<String>) -> Self {
-        let doc_root = serde_yaml::Mapping::new();
-        YamlWriter { path, doc_root }
+/// Download list of substances.
+#[cfg(feature = "remote")]
+fn fetch_substances(url: &str) -> String {
+    info!("Fetching substances from {}", url);
+    match moleco::remote::fetch_text(url) {
+        Ok(text) => text,
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(exitcode::UNAVAILABLE);
+        }
     }
 }
 
-impl Writer for YamlWriter {
-    fn write(&mut self, substance: String) {
-        let palette = calculate_scheme(substance.to_string());
-        let mut sub_yaml = serde_yaml::Mapping::new();
-        sub_yaml.insert(
-            serde_yaml::Value::String("primary".to_string()),
-            serde_yaml::Value::Number(serde_yaml::Number::from(palette.primary.hue)),
-        );
-        sub_yaml.insert(
-            serde_yaml::Value::String("first_accent".to_string()),
-            serde_yaml::Value::Number(serde_yaml::Number::from(palette.first_accent.hue)),
-        );
-        sub_yaml.insert(
-            serde_yaml::Value::String("second_accent".to_string()),
-            serde_yaml::Value::Number(serde_yaml::Number::from(palette.second_accent.hue)),
-        );
-        sub_yaml.insert(
-            serde_yaml::Value::String("complementary".to_string()),
-            serde_yaml::Value::Number(serde_yaml::Number::from(palette.complementary.hue)),
+#[cfg(not(feature = "remote"))]
+fn fetch_substances(url: &str) -> String {
+    error!(
+        "Can't fetch {} - moleco was built without `remote` feature.",
+        url
+    );
+    std::process::exit(exitcode::USAGE);
+}
+
+/// Standard InChI of substance given by name or identifier.
+fn resolve_substance(resolver: &Resolver, query: &Query, error_format: ErrorFormat) -> String {
+    if let Query::Cas(cas) = query {
+        if let Err(e) = check_cas(cas.trim()) {
+            report(error_format, ErrorRecord::new("invalid-cas", e, Some(cas)));
+            std::process::exit(exitcode::DATAERR);
+        }
+    }
+    match resolver.resolve(query) {
+        Ok(inchi) => {
+            info!("Resolved to {}", inchi);
+            inchi
+        }
+        Err(e) => {
+            let input = match query {
+                Query::Name(name) | Query::Cas(name) => name.clone(),
+                Query::Cid(cid) => cid.to_string(),
+            };
+            report(
+                error_format,
+                ErrorRecord::new("unresolved-substance", e, Some(&input)),
+            );
+            std::process::exit(exitcode::UNAVAILABLE);
+        }
+    }
+}
+
+/// Read single substance from file, or from standard input for `-`. Surrounding whitespace
+/// (like trailing newline) is dropped.
+fn read_payload(path: &str) -> String {
+    let content = match path {
+        "-" => std::io::read_to_string(std::io::stdin()),
+        _ => fs::read_to_string(path),
+    };
+    match content {
+        Ok(content) if !content.trim().is_empty() => content.trim().to_string(),
+        Ok(_) => {
+            error!("No substance in \"{}\"", path);
+            std::process::exit(exitcode::NOINPUT);
+        }
+        Err(e) => {
+            error!("Error reading file \"{}\" - {}", path, e);
+            std::process::exit(exitcode::NOINPUT);
+        }
+    }
+}
+
+/// Layers of MInChI changed by canonicalization, as lines of diff - `-` for the input and `+`
+/// for canonical MInChI.
+fn layers_diff(input: &str, canonical: &str) -> Vec<String> {
+    let (Ok(before), Ok(after)) = (minchi_layers(input), minchi_layers(canonical)) else {
+        return vec![];
+    };
+    let layers = [
+        (
+            "components",
+            Some(before.molecules.join("&")),
+            Some(after.molecules.join("&")),
+        ),
+        (
+            "indexing",
+            before.indexing.map(str::to_string),
+            after.indexing.map(str::to_string),
+        ),
+        (
+            "concentration",
+            before.concentration.map(str::to_string),
+            after.concentration.map(str::to_string),
+        ),
+    ];
+    let mut lines = vec![];
+    for (name, before, after) in layers.into_iter().filter(|(_, a, b)| a != b) {
+        lines.extend(before.map(|layer| format!("- {} {}", name, layer)));
+        lines.extend(after.map(|layer| format!("+ {} {}", name, layer)));
+    }
+    lines
+}
+
+/// Normalized input, with components of MInChI sorted when requested. MInChI that can't be
+/// canonicalized is left as it is, its problems are reported when it is used.
+fn normalize(input: &str, url_decode: bool, canonicalize: bool) -> String {
+    let input = normalize_input(input, url_decode);
+    match canonicalize {
+        true => canonicalize_minchi(&input).unwrap_or(input),
+        false => input,
+    }
+}
+
+/// Read substances from file, one per line, skipping empty ones.
+fn read_substances(path: &str, url_decode: bool, canonicalize: bool) -> Vec<String> {
+    if !file_exists(path) {
+        error!("File \"{}\" does not exist", path);
+        std::process::exit(exitcode::NOINPUT);
+    }
+    if is_file_empty(path) {
+        error!("File \"{}\" is empty", path);
+        std::process::exit(exitcode::NOINPUT);
+    }
+    match fs::read_to_string(path) {
+        Ok(content) => content
+            .lines()
+            .map(|line| normalize(line, url_decode, canonicalize))
+            .filter(|line| !line.is_empty())
+            .collect(),
+        Err(e) => {
+            error!("Error reading file \"{}\" - {}", path, e);
+            std::process::exit(exitcode::IOERR);
+        }
+    }
+}
+
+/// Formats of `calculate` output, as registered in default registry.
+fn format_parser() -> clap::builder::PossibleValuesParser {
+    let formats = Registry::default().formats();
+    clap::builder::PossibleValuesParser::new(
+        formats
+            .into_iter()
+            .map(|(name, description)| clap::builder::PossibleValue::new(name).help(description)),
+    )
+}
+
+/// Save image written by `encode` to output file, or print it to standard output with `-`.
+/// Returns path of saved file - None when nothing was saved.
+fn save_image(
+    output_file: &str,
+    on_conflict: OnConflict,
+    quiet: bool,
+    create_dirs: bool,
+    encode: impl FnOnce(&mut dyn Write) -> Result<(), String>,
+) -> Option<String> {
+    if output_file == "-" {
+        if let Err(e) = encode(&mut std::io::stdout().lock()) {
+            error!("{}", e);
+            std::process::exit(exitcode::IOERR);
+        }
+        return None;
+    }
+    let output_file = output_target(output_file, on_conflict, quiet, create_dirs)?;
+    if let Err(e) = write_image(&output_file, encode) {
+        error!("{}", e);
+        std::process::exit(exitcode::CANTCREAT);
+    }
+    Some(output_file)
+}
+
+/// Write image to the file, which is already resolved (see `resolve_conflict`).
+fn write_image(
+    output_file: &str,
+    encode: impl FnOnce(&mut dyn Write) -> Result<(), String>,
+) -> Result<(), String> {
+    fs::File::create(output_file)
+        .map_err(|e| format!("Can't create {:?} - {}", output_file, e))
+        .and_then(|file| encode(&mut BufWriter::new(file)))?;
+    info!("Image saved as {}", output_file);
+    Ok(())
+}
+
+/// Format of images saved to output file - the given one, or the one of its extension. Images
+/// in `.zip` archive and printed to standard output (`-`) are PNG, unless given otherwise.
+fn file_format(output_file: &str, image_format: Option<FileFormat>) -> FileFormat {
+    if let Some(image_format) = image_format {
+        return image_format;
+    }
+    if output_file == "-" || output_file.ends_with(".zip") {
+        return FileFormat::default();
+    }
+    FileFormat::from_path(output_file).unwrap_or_else(|| {
+        error!(
+            "Can't tell image format by extension of {:?}, set it with --image-format.",
+            output_file
         );
-        self.doc_root.insert(
-            serde_yaml::Value::String(substance.to_string()),
-            serde_yaml::Value::Mapping(sub_yaml),
+        std::process::exit(exitcode::USAGE);
+    })
+}
+
+/// Path image should be saved to, with its directory ready. None when existing file is kept.
+fn output_target(
+    output_file: &str,
+    on_conflict: OnConflict,
+    quiet: bool,
+    create_dirs: bool,
+) -> Option<String> {
+    let output_file = resolve_conflict(output_file, on_conflict, quiet)?;
+    ensure_parent_dir(&output_file, create_dirs);
+    Some(output_file)
+}
+
+/// Show saved image in default viewer of the system.
+fn open_image(path: &str) {
+    if let Err(e) = open::that_detached(path) {
+        error!("Can't open {} - {}", path, e);
+    }
+}
+
+/// Overwriting of existing file that wasn't confirmed.
+enum Unconfirmed {
+    /// User answered no.
+    Declined,
+    /// Nobody can answer - in quiet mode, or without terminal (cron, CI).
+    Unanswerable,
+}
+
+/// Path that output should be written to, according to conflict policy. None when existing
+/// file is kept and nothing should be written. Overwriting that isn't confirmed ends the run -
+/// declined one successfully, as nothing is left to do.
+fn resolve_conflict(path: &str, on_conflict: OnConflict, quiet: bool) -> Option<String> {
+    match conflict_target(path, on_conflict, quiet) {
+        Ok(path) => path,
+        Err(Unconfirmed::Declined) => std::process::exit(exitcode::OK),
+        Err(Unconfirmed::Unanswerable) => {
+            error!(
+                "File \"{}\" already exists, not overwriting it without confirmation (see --on-conflict).",
+                path
+            );
+            std::process::exit(exitcode::CANTCREAT);
+        }
+    }
+}
+
+/// Path that image of batch entry should be saved to, see `resolve_conflict`. Overwriting that
+/// isn't confirmed keeps the existing file, so the rest of the batch still runs - error is the
+/// reason why the entry is skipped.
+fn entry_target(
+    path: &str,
+    on_conflict: OnConflict,
+    quiet: bool,
+) -> Result<Option<String>, String> {
+    conflict_target(path, on_conflict, quiet).map_err(|unconfirmed| {
+        let reason = match unconfirmed {
+            Unconfirmed::Declined => "overwriting it was declined",
+            Unconfirmed::Unanswerable => "not overwritten without confirmation (see --on-conflict)",
+        };
+        let message = format!("File \"{}\" already exists, {}", path, reason);
+        warn!("{}", message);
+        message
+    })
+}
+
+fn conflict_target(
+    path: &str,
+    on_conflict: OnConflict,
+    quiet: bool,
+) -> Result<Option<String>, Unconfirmed> {
+    if !file_exists(path) {
+        return Ok(Some(path.to_string()));
+    }
+    let path = match on_conflict {
+        OnConflict::Ask => {
+            confirm_overwrite(path, quiet)?;
+            Some(path.to_string())
+        }
+        OnConflict::Overwrite => Some(path.to_string()),
+        OnConflict::Skip => {
+            info!("File \"{}\" already exists, skipped", path);
+            None
+        }
+        OnConflict::Rename => {
+            let path = std::path::Path::new(path);
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let extension = match path.extension() {
+                Some(extension) => format!(".{}", extension.to_string_lossy()),
+                None => "".to_string(),
+            };
+            // Existing file is the first one. Candidate is created right away, so concurrent
+            // runs can't pick the same one.
+            (2..)
+                .map(|n| path.with_file_name(format!("{}-{}{}", stem, n, extension)))
+                .map(|candidate| candidate.to_string_lossy().to_string())
+                .find(|candidate| {
+                    let created = fs::OpenOptions::new()
+                        .write(true)
+                        .create_new(true)
+                        .open(candidate);
+                    // Other errors are reported when the file is written
+                    !matches!(created, Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists)
+                })
+        }
+    };
+    Ok(path)
+}
+
+/// Ask whether existing file may be overwritten. In quiet mode, or without terminal (cron, CI),
+/// nobody can answer - then file is never overwritten.
+fn confirm_overwrite(path: &str, quiet: bool) -> Result<(), Unconfirmed> {
+    if quiet || !std::io::stdin().is_terminal() {
+        return Err(Unconfirmed::Unanswerable);
+    }
+    match Confirm::new()
+        .with_prompt(format!("File \"{}\" already exists, overwrite?", path))
+        .interact()
+        .unwrap()
+    {
+        true => Ok(()),
+        false => Err(Unconfirmed::Declined),
+    }
+}
+
+/// Directory of output file must exist before writing, create it if asked to.
+fn ensure_parent_dir(path: &str, create_dirs: bool) {
+    let directory = match std::path::Path::new(path).parent() {
+        Some(directory) if !directory.as_os_str().is_empty() && !directory.exists() => directory,
+        _ => return,
+    };
+    if !create_dirs {
+        error!(
+            "Directory {:?} does not exist, pass --create-dirs to create it.",
+            directory
         );
+        std::process::exit(exitcode::CANTCREAT);
+    }
+    if let Err(e) = fs::create_dir_all(directory) {
+        error!("Can't create directory {:?} - {}", directory, e);
+        std::process::exit(exitcode::CANTCREAT);
     }
-    fn flush(&mut self) {
-        match self.path {
-            Some(ref path) => {
-                let file = fs::File::create(path).unwrap();
-                serde_yaml::to_writer(file, &self.doc_root).unwrap();
-                info!("Image saved as {}", path);
-            }
-            None => {
-                println!("{}", serde_yaml::to_string(&self.doc_root).unwrap());
-            }
-        }
-    }
-}
-struct CsvStdoutWriter {
-    output: csv::Writer<std::io::Stdout>,
-}
-
-impl CsvStdoutWriter {
-    fn new() -> Self {
-        let mut output = csv::Writer::from_writer(std::io::stdout());
-        output
-            .write_record(&[
-                "Substance",
-                "Primary hue",
-                "First accent hue",
-                "Second accent hue",
-                "Complementary hue",
-            ])
-            .unwrap();
-        CsvStdoutWriter { output }
-    }
-}
-
-impl Writer for CsvStdoutWriter {
-    fn write(&mut self, substance: String) {
-        let palette = calculate_scheme(substance.to_string());
-        self.output
-            .write_record(&[
-                &substance,
-                &palette.primary.hue.to_string(),
-                &palette.first_accent.hue.to_string(),
-                &palette.second_accent.hue.to_string(),
-                &palette.complementary.hue.to_string(),
-            ])
-            .unwrap();
-    }
-    fn flush(&mut self) {
-        self.output.flush().unwrap();
-    }
-}
-
-struct CsvFileWriter {
-    output: csv::Writer<fs::File>,
-}
-
-impl CsvFileWriter {
-    fn new(path: String) -> Self {
-        let file = fs::File::create(path).unwrap();
-        let mut output = csv::Writer::from_writer(file);
-        output
-            .write_record(&[
-                "Substance",
-                "Primary hue",
-                "First accent hue",
-                "Second accent hue",
-                "Complementary hue",
-            ])
-            .unwrap();
-        CsvFileWriter { output }
-    }
-}
-
-impl Writer for CsvFileWriter {
-    fn write(&mut self, substance: String) {
-        let palette = calculate_scheme(substance.to_string());
-        self.output
-            .write_record(&[
-                &substance,
-                &palette.primary.hue.to_string(),
-                &palette.first_accent.hue.to_string(),
-                &palette.second_accent.hue.to_string(),
-                &palette.complementary.hue.to_string(),
-            ])
-            .unwrap();
-    }
-    fn flush(&mut self) {
-        self.output.flush().unwrap();
+    debug!("Created directory {:?}", directory);
+}
+
+fn create_file(path: &str) -> fs::File {
+    match fs::File::create(path) {
+        Ok(file) => file,
+        Err(e) => {
+            error!("Can't create {:?} - {}", path, e);
+            std::process::exit(exitcode::CANTCREAT);
+        }
     }
 }
 
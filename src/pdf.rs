@@ -0,0 +1,266 @@
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use image::RgbaImage;
+use std::io::Write;
+
+/// Paper size of PDF pages.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PageSize {
+    #[default]
+    A4,
+    Letter,
+}
+
+impl PageSize {
+    /// Width and height in points.
+    pub fn dimensions(&self) -> (f32, f32) {
+        match self {
+            PageSize::A4 => (595.28, 841.89),
+            PageSize::Letter => (612.0, 792.0),
+        }
+    }
+}
+
+/// Picture with lines of caption below it.
+pub struct Label {
+    pub image: RgbaImage,
+    pub caption: Vec<String>,
+}
+
+/// Half an inch, in points.
+const MARGIN: f32 = 36.0;
+/// Space around pictures in their cells.
+const PADDING: f32 = 6.0;
+const FONT_SIZE: f32 = 8.0;
+const LINE_HEIGHT: f32 = 10.0;
+/// Captions use built-in Courier font, all its glyphs are 0.6 em wide.
+const CHAR_WIDTH: f32 = FONT_SIZE * 0.6;
+
+/// Object numbers of catalog, page tree and font, others follow them.
+const CATALOG: usize = 1;
+const PAGES: usize = 2;
+const FONT: usize = 3;
+
+/// Lay labels out in a grid, `per_page` on each page (in cells as square as possible), with page
+/// numbers in the footer - ready to print label book. Too long captions are shortened.
+pub fn label_book(labels: &[Label], per_page: usize, page_size: PageSize) -> Vec<u8> {
+    let per_page = per_page.max(1);
+    let (page_width, page_height) = page_size.dimensions();
+    let columns = ((per_page as f32 * page_width / page_height).sqrt().ceil() as usize).max(1);
+    let rows = per_page.div_ceil(columns);
+    let cell_width = (page_width - MARGIN * 2.0) / columns as f32;
+    let cell_height = (page_height - MARGIN * 2.0 - LINE_HEIGHT * 2.0) / rows as f32;
+
+    let pages = labels.chunks(per_page).collect::<Vec<_>>();
+    let page_count = pages.len().max(1);
+    // Page and its content for every page, then image and its alpha for every label
+    let page_id = |page: usize| FONT + 1 + page * 2;
+    let image_id = |label: usize| FONT + 1 + page_count * 2 + label * 2;
+
+    let mut pdf = PdfWriter::new(FONT + (page_count + labels.len()) * 2);
+    pdf.object(
+        CATALOG,
+        format!("<< /Type /Catalog /Pages {} 0 R >>", PAGES).as_bytes(),
+    );
+    let kids: Vec<String> = (0..page_count)
+        .map(|page| format!("{} 0 R", page_id(page)))
+        .collect();
+    pdf.object(
+        PAGES,
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} /MediaBox [0 0 {} {}] >>",
+            kids.join(" "),
+            page_count,
+            page_width,
+            page_height
+        )
+        .as_bytes(),
+    );
+    pdf.object(
+        FONT,
+        b"<< /Type /Font /Subtype /Type1 /BaseFont /Courier /Encoding /WinAnsiEncoding >>",
+    );
+
+    for page in 0..page_count {
+        let labels = pages.get(page).copied().unwrap_or_default();
+        let mut content = String::new();
+        let mut images = String::new();
+        for (position, label) in labels.iter().enumerate() {
+            let index = page * per_page + position;
+            let left = MARGIN + (position % columns) as f32 * cell_width + PADDING;
+            let top = page_height - MARGIN - (position / columns) as f32 * cell_height - PADDING;
+            let width = cell_width - PADDING * 2.0;
+            let caption_height = label.caption.len() as f32 * LINE_HEIGHT;
+            let height = cell_height - PADDING * 2.0 - caption_height;
+
+            let (image_width, image_height) = label.image.dimensions();
+            let scale = (width / image_width as f32).min(height / image_height as f32);
+            let (drawn_width, drawn_height) =
+                (image_width as f32 * scale, image_height as f32 * scale);
+            content.push_str(&format!(
+                "q {:.2} 0 0 {:.2} {:.2} {:.2} cm /Im{} Do Q\n",
+                drawn_width,
+                drawn_height,
+                left + (width - drawn_width) / 2.0,
+                top - drawn_height,
+                index
+            ));
+            images.push_str(&format!("/Im{} {} 0 R ", index, image_id(index)));
+
+            let max_chars = (width / CHAR_WIDTH) as usize;
+            for (line, text) in label.caption.iter().enumerate() {
+                let text = shorten(text, max_chars);
+                let baseline = top - drawn_height - (line + 1) as f32 * LINE_HEIGHT;
+                let text_width = text.chars().count() as f32 * CHAR_WIDTH;
+                content.push_str(&text_command(
+                    &text,
+                    left + (width - text_width) / 2.0,
+                    baseline,
+                ));
+            }
+        }
+        let number = format!("Page {} of {}", page + 1, page_count);
+        let number_width = number.len() as f32 * CHAR_WIDTH;
+        content.push_str(&text_command(
+            &number,
+            (page_width - number_width) / 2.0,
+            MARGIN,
+        ));
+
+        pdf.object(
+            page_id(page),
+            format!(
+                "<< /Type /Page /Parent {} 0 R /Contents {} 0 R /Resources << /Font << /F1 {} 0 R >> /XObject << {}>> >> >>",
+                PAGES,
+                page_id(page) + 1,
+                FONT,
+                images
+            )
+            .as_bytes(),
+        );
+        pdf.stream(page_id(page) + 1, "", content.as_bytes());
+    }
+
+    for (index, label) in labels.iter().enumerate() {
+        let (width, height) = label.image.dimensions();
+        let pixels = label.image.pixels();
+        let colors: Vec<u8> = pixels
+            .clone()
+            .flat_map(|p| [p.0[0], p.0[1], p.0[2]])
+            .collect();
+        let alpha: Vec<u8> = pixels.map(|p| p.0[3]).collect();
+        let image_dictionary = |color_space: &str| {
+            format!(
+                "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /{} /BitsPerComponent 8 /Filter /FlateDecode",
+                width, height, color_space
+            )
+        };
+        pdf.stream(
+            image_id(index),
+            &format!(
+                "{} /SMask {} 0 R",
+                image_dictionary("DeviceRGB"),
+                image_id(index) + 1
+            ),
+            &deflate(&colors),
+        );
+        pdf.stream(
+            image_id(index) + 1,
+            &image_dictionary("DeviceGray"),
+            &deflate(&alpha),
+        );
+    }
+    pdf.finish()
+}
+
+/// Text shown at given position (of its baseline), in caption font.
+fn text_command(text: &str, x: f32, y: f32) -> String {
+    format!(
+        "BT /F1 {} Tf {:.2} {:.2} Td ({}) Tj ET\n",
+        FONT_SIZE,
+        x,
+        y,
+        escape(text)
+    )
+}
+
+/// Long texts are cut, with ellipsis at the end.
+fn shorten(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let kept: String = text.chars().take(max_chars.saturating_sub(3)).collect();
+    format!("{}...", kept)
+}
+
+/// PDF strings are enclosed in parentheses. Characters out of ASCII are replaced, as built-in
+/// fonts don't cover them anyway.
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '\\' | '(' | ')' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            ' '..='~' => escaped.push(c),
+            _ => escaped.push('?'),
+        }
+    }
+    escaped
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    // Writing to vector never fails
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Objects are written as they come, their positions are collected for cross-reference table.
+struct PdfWriter {
+    buffer: Vec<u8>,
+    offsets: Vec<usize>,
+}
+
+impl PdfWriter {
+    fn new(object_count: usize) -> Self {
+        Self {
+            // Binary comment tells tools that file is not plain text
+            buffer: b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec(),
+            offsets: vec![0; object_count + 1],
+        }
+    }
+
+    fn object(&mut self, id: usize, body: &[u8]) {
+        self.offsets[id] = self.buffer.len();
+        self.buffer
+            .extend_from_slice(format!("{} 0 obj\n", id).as_bytes());
+        self.buffer.extend_from_slice(body);
+        self.buffer.extend_from_slice(b"\nendobj\n");
+    }
+
+    fn stream(&mut self, id: usize, dictionary: &str, data: &[u8]) {
+        let mut body =
+            format!("<< {} /Length {} >>\nstream\n", dictionary, data.len()).into_bytes();
+        body.extend_from_slice(data);
+        body.extend_from_slice(b"\nendstream");
+        self.object(id, &body);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let xref = self.buffer.len();
+        let mut table = format!("xref\n0 {}\n0000000000 65535 f \n", self.offsets.len());
+        for offset in &self.offsets[1..] {
+            table.push_str(&format!("{:010} 00000 n \n", offset));
+        }
+        table.push_str(&format!(
+            "trailer\n<< /Size {} /Root {} 0 R >>\nstartxref\n{}\n%%EOF\n",
+            self.offsets.len(),
+            CATALOG,
+            xref
+        ));
+        self.buffer.extend_from_slice(table.as_bytes());
+        self.buffer
+    }
+}
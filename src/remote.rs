@@ -0,0 +1,32 @@
+use std::time::Duration;
+
+/// How long remote server may keep us waiting.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// HTTP client identifying itself as moleco, as public services ask for.
+pub fn client() -> Result<reqwest::blocking::Client, String> {
+    reqwest::blocking::Client::builder()
+        .user_agent(concat!("moleco/", env!("CARGO_PKG_VERSION")))
+        .timeout(TIMEOUT)
+        .build()
+        .map_err(|e| format!("Can't create HTTP client - {}", e))
+}
+
+/// Download text document, like list of substances exported from registry. Responses other than
+/// success are errors.
+pub fn fetch_text(url: &str) -> Result<String, String> {
+    let response = client()?
+        .get(url)
+        .send()
+        .map_err(|e| format!("Can't fetch {} - {}", url, e))?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!(
+            "Can't fetch {} - server responded with {}",
+            url, status
+        ));
+    }
+    response
+        .text()
+        .map_err(|e| format!("Can't read response of {} - {}", url, e))
+}
@@ -0,0 +1,239 @@
+use crate::cache::{default_dir, write_atomically, RESOLVER_DIR};
+use data_encoding::HEXLOWER;
+use log::{debug, info, warn};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+
+/// PubChem PUG REST service.
+pub const DEFAULT_ENDPOINT: &str = "https://pubchem.ncbi.nlm.nih.gov/rest/pug";
+
+/// How substance is identified when asking resolver for its InChI.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Query {
+    /// Common or systematic name, like `acetone`.
+    Name(String),
+    /// PubChem compound identifier, like 180.
+    Cid(u64),
+    /// CAS registry number, like `67-64-1`.
+    Cas(String),
+}
+
+impl Query {
+    /// Guess kind of identifier - numbers are CIDs, well formed CAS registry numbers are CAS
+    /// numbers, and anything else is name.
+    pub fn detect(identifier: &str) -> Query {
+        let identifier = identifier.trim();
+        if let Ok(cid) = identifier.parse::<u64>() {
+            return Query::Cid(cid);
+        }
+        match check_cas(identifier) {
+            Ok(()) => Query::Cas(identifier.to_string()),
+            Err(_) => Query::Name(identifier.to_string()),
+        }
+    }
+
+    /// Namespace of PUG REST compound lookup.
+    fn namespace(&self) -> &'static str {
+        match self {
+            // PubChem knows CAS numbers as synonyms of compounds
+            Query::Name(_) | Query::Cas(_) => "name",
+            Query::Cid(_) => "cid",
+        }
+    }
+
+    fn identifier(&self) -> String {
+        match self {
+            Query::Name(name) | Query::Cas(name) => name.trim().to_string(),
+            Query::Cid(cid) => cid.to_string(),
+        }
+    }
+}
+
+/// Check that CAS registry number is well formed - 2 to 7 digits, 2 digits and check digit,
+/// separated by hyphens, with check digit matching the rest.
+pub fn check_cas(cas: &str) -> Result<(), String> {
+    let lengths: Vec<usize> = cas.split('-').map(str::len).collect();
+    let digits_only = cas
+        .bytes()
+        .all(|byte| byte.is_ascii_digit() || byte == b'-');
+    let well_formed = match lengths.as_slice() {
+        [first, 2, 1] => (2..=7).contains(first),
+        _ => false,
+    };
+    if !(digits_only && well_formed) {
+        return Err(format!(
+            "{:?} is not CAS registry number, like 67-64-1",
+            cas
+        ));
+    }
+    let digits: Vec<u32> = cas.chars().filter_map(|c| c.to_digit(10)).collect();
+    let (check, rest) = digits.split_last().unwrap();
+    let sum: u32 = rest
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(position, digit)| (position as u32 + 1) * digit)
+        .sum();
+    match sum % 10 == *check {
+        true => Ok(()),
+        false => Err(format!(
+            "Check digit of CAS registry number {} should be {}",
+            cas,
+            sum % 10
+        )),
+    }
+}
+
+/// Looks up standard InChI of substances in PubChem (or service with the same API). Answers
+/// are cached in local directory, so each substance is fetched only once.
+#[derive(Debug, Clone)]
+pub struct Resolver {
+    endpoint: String,
+    cache: Option<PathBuf>,
+}
+
+impl Default for Resolver {
+    /// PubChem, cached in default cache directory of the user (when there is one).
+    fn default() -> Self {
+        Self {
+            endpoint: DEFAULT_ENDPOINT.to_string(),
+            cache: default_dir().map(|directory| directory.join(RESOLVER_DIR)),
+        }
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use another service with PUG REST API, like mirror or mock of PubChem.
+    pub fn with_endpoint(self, endpoint: &str) -> Self {
+        Self {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            ..self
+        }
+    }
+
+    /// Cache answers in given directory, or not at all with None.
+    pub fn with_cache(self, cache: Option<PathBuf>) -> Self {
+        Self { cache, ..self }
+    }
+
+    /// URL returning InChI of the substance as plain text.
+    pub fn url(&self, query: &Query) -> String {
+        format!(
+            "{}/compound/{}/{}/property/InChI/TXT",
+            self.endpoint,
+            query.namespace(),
+            percent_encode(&query.identifier())
+        )
+    }
+
+    /// File with cached answer - named by hash, as names may contain anything. Endpoint is
+    /// hashed too, so answers of different services don't mix.
+    pub fn cache_path(&self, query: &Query) -> Option<PathBuf> {
+        let key = Sha256::digest(format!("{}\n{}", self.endpoint, query.identifier()).as_bytes());
+        self.cache.as_ref().map(|directory| {
+            directory.join(format!(
+                "{}-{}.inchi",
+                query.namespace(),
+                &HEXLOWER.encode(&key)[..32]
+            ))
+        })
+    }
+
+    /// Standard InChI of the substance. When resolver knows more compounds for the query (like
+    /// for ambiguous name), the first one is used.
+    pub fn resolve(&self, query: &Query) -> Result<String, String> {
+        if query.identifier().is_empty() {
+            return Err("Nothing to resolve, identifier is empty".to_string());
+        }
+        if let Query::Cas(cas) = query {
+            check_cas(cas.trim())?;
+        }
+        let cache_path = self.cache_path(query);
+        if let Some(inchi) = cache_path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+        {
+            debug!("Using cached InChI of {:?}", query);
+            return Ok(inchi.trim().to_string());
+        }
+        let answer = self.fetch(query)?;
+        let mut found = answer
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty());
+        let inchi = match found.next() {
+            Some(inchi) if inchi.starts_with("InChI=") => inchi.to_string(),
+            _ => return Err(format!("Resolver gave no InChI for {}", describe(query))),
+        };
+        if found.next().is_some() {
+            info!(
+                "More compounds match {}, using the first one",
+                describe(query)
+            );
+        }
+        if let Some(path) = cache_path {
+            if let Err(e) = write_atomically(&path, inchi.as_bytes()) {
+                warn!("Can't cache resolved InChI - {}", e);
+            }
+        }
+        Ok(inchi)
+    }
+
+    #[cfg(feature = "remote")]
+    fn fetch(&self, query: &Query) -> Result<String, String> {
+        let url = self.url(query);
+        info!("Resolving {} with {}", describe(query), url);
+        let response = crate::remote::client()?
+            .get(&url)
+            .send()
+            .map_err(|e| format!("Can't reach resolver - {}", e))?;
+        let status = response.status();
+        if status == reqwest::StatusCode::NOT_FOUND {
+            return Err(format!(
+                "Resolver knows no compound for {}",
+                describe(query)
+            ));
+        }
+        if !status.is_success() {
+            return Err(format!("Resolver responded with {}", status));
+        }
+        response
+            .text()
+            .map_err(|e| format!("Can't read answer of resolver - {}", e))
+    }
+
+    /// Without HTTP client, only cached answers are available.
+    #[cfg(not(feature = "remote"))]
+    fn fetch(&self, query: &Query) -> Result<String, String> {
+        Err(format!(
+            "Can't resolve {} - it isn't cached, and moleco was built without `remote` feature",
+            describe(query)
+        ))
+    }
+}
+
+fn describe(query: &Query) -> String {
+    match query {
+        Query::Name(name) => format!("name {:?}", name.trim()),
+        Query::Cid(cid) => format!("CID {}", cid),
+        Query::Cas(cas) => format!("CAS {}", cas.trim()),
+    }
+}
+
+/// Encode everything but unreserved characters, so identifier stays single segment of URL path.
+fn percent_encode(input: &str) -> String {
+    input
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
@@ -0,0 +1,496 @@
+use crate::layouts::{
+    Align, Blend, Circle, Line, Orientation, Pattern, Picture, Rectangle, RegionKind, Scene,
+    Sector, Shape, Square, Text,
+};
+use crate::text::{bundled_font, TextRenderer};
+use palette::Srgba;
+use std::collections::HashSet;
+
+/// Font used for text, bundled font is tried first so text looks like in PNG pictures.
+const FONT_FAMILY: &str = "DejaVu Sans Mono, monospace";
+
+/// Encode picture as SVG. Transparent rectangle with `data-` attributes is put over every swatch
+/// and bar segment (see `Picture::regions`), so web pages can add tooltips or links to
+/// components without deriving the layout again:
+///
+/// - `data-component` - index of the component, as in MInChI (missing for unknown substances),
+/// - `data-fraction` - its share in the mixture (only for mixtures),
+/// - `data-hue` - hue of its primary color.
+pub fn picture_to_svg(picture: &Picture) -> Result<String, String> {
+    let (svg, overlay) = picture_builder(picture, "")?;
+    Ok(svg.finish(&overlay))
+}
+
+/// Picture as SVG to inline in HTML, labelled with `alt` for screen readers (like `alt` of
+/// images). Masks and patterns get `id` (like short identifier of the substance) as prefix, so
+/// they don't clash with ones of other pictures in the same page.
+pub fn picture_to_inline_svg(picture: &Picture, id: &str, alt: &str) -> Result<String, String> {
+    let (svg, overlay) = picture_builder(picture, &format!("{}-", id))?;
+    Ok(svg.finish(&overlay).replacen(
+        "<svg ",
+        &format!("<svg role=\"img\" aria-label=\"{}\" ", escape(alt)),
+        1,
+    ))
+}
+
+/// Builder with the picture drawn, and overlay of its regions.
+fn picture_builder(picture: &Picture, prefix: &str) -> Result<(SvgBuilder, String), String> {
+    let scene = picture.to_scene()?;
+    let schemes = picture.schemes();
+    let mut overlay = String::new();
+    for region in picture.regions()? {
+        let class = match region.kind {
+            RegionKind::Swatch => "moleco-swatch",
+            RegionKind::Segment => "moleco-segment",
+        };
+        overlay.push_str(&format!(
+            "<rect class=\"{}\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"transparent\"",
+            class, region.x, region.y, region.width, region.height
+        ));
+        if let Some(component) = region.component {
+            let hue = schemes[component - 1].primary.hue;
+            overlay.push_str(&format!(
+                " data-component=\"{}\" data-hue=\"{}\"",
+                component, hue
+            ));
+        }
+        if let Some(fraction) = region.fraction {
+            overlay.push_str(&format!(" data-fraction=\"{}\"", fraction));
+        }
+        overlay.push_str("/>");
+    }
+    let mut svg = SvgBuilder::new(&scene, prefix);
+    svg.draw(&scene);
+    Ok((svg, overlay))
+}
+
+/// Pictures as `<symbol>` elements of single SVG document, so web pages can draw any of them
+/// with `<use href="sprite.svg#id"/>` (sized by `width` and `height`, as symbols scale to fit).
+/// Symbols carry the same overlay of regions as `picture_to_svg`.
+#[derive(Default)]
+pub struct SpriteSheet {
+    symbols: String,
+    ids: HashSet<String>,
+}
+
+impl SpriteSheet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add picture as symbol with given id. Masks and patterns within it get the id as prefix,
+    /// so they don't clash with ones of other symbols. Picture with id already in the sheet is
+    /// skipped.
+    pub fn add(&mut self, id: &str, picture: &Picture) -> Result<(), String> {
+        if self.ids.contains(id) {
+            return Ok(());
+        }
+        let (svg, overlay) = picture_builder(picture, &format!("{}-", id))?;
+        self.symbols.push_str(&format!(
+            "<symbol id=\"{}\" viewBox=\"0 0 {} {}\">{}</symbol>",
+            escape(id),
+            svg.width,
+            svg.height,
+            svg.contents(&overlay)
+        ));
+        self.ids.insert(id.to_string());
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// SVG document with all symbols.
+    pub fn finish(self) -> String {
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\">{}</svg>\n",
+            self.symbols
+        )
+    }
+}
+
+/// Encode scene as SVG. Shapes with `Blend::Clear` (like cutouts) are made with masks, so they
+/// make holes in shapes drawn before, the same way as in PNG pictures.
+pub fn scene_to_svg(scene: &Scene) -> String {
+    let mut svg = SvgBuilder::new(scene, "");
+    svg.draw(scene);
+    svg.finish("")
+}
+
+struct SvgBuilder {
+    width: u32,
+    height: u32,
+    defs: String,
+    body: String,
+    /// Prefix of ids of masks and patterns, unique within the document.
+    prefix: String,
+    next_id: usize,
+    text_renderer: TextRenderer,
+}
+
+impl SvgBuilder {
+    fn new(scene: &Scene, prefix: &str) -> Self {
+        Self {
+            width: scene.width,
+            height: scene.height,
+            defs: String::new(),
+            body: String::new(),
+            prefix: prefix.to_string(),
+            next_id: 0,
+            text_renderer: TextRenderer::new(),
+        }
+    }
+
+    fn id(&mut self, kind: &str) -> String {
+        self.next_id += 1;
+        format!("{}{}{}", self.prefix, kind, self.next_id)
+    }
+
+    fn draw(&mut self, scene: &Scene) {
+        if scene.background.alpha > 0 {
+            let background = Shape::Rectangle(Rectangle {
+                x: 0,
+                y: 0,
+                width: scene.width,
+                height: scene.height,
+                color: scene.background,
+                pattern: Pattern::Solid,
+                blend: Blend::Over,
+            });
+            let element = self.element(&background, scene.background);
+            self.body.push_str(&element);
+        }
+
+        // Consecutive shapes clearing the picture are collected, and cut out of everything drawn
+        // before at once. Background is painted over them again, unless it is transparent.
+        let mut cutouts = String::new();
+        let mut refills = String::new();
+        for shape in scene
+            .ordered_layers()
+            .into_iter()
+            .flat_map(|layer| &layer.shapes)
+        {
+            if blend(shape) == Blend::Clear {
+                // Opaque background covers anything, no need to cut it out
+                if scene.background.alpha < 255 {
+                    cutouts.push_str(&self.element(shape, Srgba::new(0, 0, 0, 255)));
+                }
+                if scene.background.alpha > 0 {
+                    refills.push_str(&self.element(shape, scene.background));
+                }
+                continue;
+            }
+            self.clear(&cutouts, &refills);
+            cutouts.clear();
+            refills.clear();
+            let element = self.element(shape, color(shape));
+            self.body.push_str(&element);
+        }
+        self.clear(&cutouts, &refills);
+
+        if scene.corner_radius > 0 {
+            let id = self.id("corners");
+            self.defs.push_str(&format!(
+                "<clipPath id=\"{}\"><rect width=\"{}\" height=\"{}\" rx=\"{r}\" ry=\"{r}\"/></clipPath>",
+                id,
+                scene.width,
+                scene.height,
+                r = scene.corner_radius.min(scene.width.min(scene.height) / 2)
+            ));
+            self.body = format!("<g clip-path=\"url(#{})\">{}</g>", id, self.body);
+        }
+    }
+
+    /// Hide parts of everything drawn so far with mask, and paint them with background.
+    fn clear(&mut self, cutouts: &str, refills: &str) {
+        if !cutouts.is_empty() {
+            self.cut_out(cutouts);
+        }
+        self.body.push_str(refills);
+    }
+
+    fn cut_out(&mut self, shapes: &str) {
+        let id = self.id("cutout");
+        self.defs.push_str(&format!(
+            "<mask id=\"{}\" maskUnits=\"userSpaceOnUse\" x=\"0\" y=\"0\" width=\"{w}\" height=\"{h}\"><rect width=\"{w}\" height=\"{h}\" fill=\"white\"/>{}</mask>",
+            id,
+            shapes,
+            w = self.width,
+            h = self.height
+        ));
+        self.body = format!("<g mask=\"url(#{})\">{}</g>", id, self.body);
+    }
+
+    /// SVG element of the shape, painted with given color. Sizes follow rasterization - shapes
+    /// cover whole pixels they are drawn on, so coordinates of pixel centers are shifted by half.
+    fn element(&mut self, shape: &Shape, color: Srgba<u8>) -> String {
+        match shape {
+            Shape::Square(square) => square_element(square, color),
+            Shape::Rectangle(rectangle) => {
+                let fill = match rectangle.pattern {
+                    Pattern::Solid => fill(color),
+                    pattern => {
+                        let id = self.id("pattern");
+                        self.defs
+                            .push_str(&pattern_element(&id, rectangle, pattern, color));
+                        format!("fill=\"url(#{})\"", id)
+                    }
+                };
+                format!(
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" {}/>",
+                    rectangle.x, rectangle.y, rectangle.width, rectangle.height, fill
+                )
+            }
+            Shape::Line(line) => line_element(line, color),
+            Shape::Circle(circle) => {
+                let clip = match circle.sector {
+                    Some(sector) => {
+                        let id = self.id("sector");
+                        self.defs.push_str(&format!(
+                            "<clipPath id=\"{}\"><polygon points=\"{}\"/></clipPath>",
+                            id,
+                            sector_points(circle, sector)
+                        ));
+                        format!(" clip-path=\"url(#{})\"", id)
+                    }
+                    None => String::new(),
+                };
+                circle_element(circle, color, &clip)
+            }
+            Shape::Text(text) => self.text_element(text, color),
+        }
+    }
+
+    fn text_element(&mut self, text: &Text, color: Srgba<u8>) -> String {
+        let (content, size) = match text.max_width {
+            Some(max_width) => {
+                self.text_renderer
+                    .fit(&text.content, text.size, max_width, text.shrink)
+            }
+            None => (text.content.clone(), text.size),
+        };
+        // Text is anchored in the top of the line, SVG anchors it on the baseline
+        let ascent = bundled_font()
+            .horizontal_line_metrics(size as f32)
+            .map_or(size as f32, |metrics| metrics.ascent);
+        let anchor = match text.align {
+            Align::Left => "start",
+            Align::Center => "middle",
+            Align::Right => "end",
+        };
+        format!(
+            "<text x=\"{}\" y=\"{}\" font-family=\"{}\" font-size=\"{}\" text-anchor=\"{}\" {}>{}</text>",
+            text.x,
+            text.y as f32 + ascent,
+            FONT_FAMILY,
+            size,
+            anchor,
+            fill(color),
+            escape(&content)
+        )
+    }
+
+    fn finish(self, overlay: &str) -> String {
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">{}</svg>\n",
+            self.contents(overlay),
+            w = self.width,
+            h = self.height
+        )
+    }
+
+    /// Definitions, shapes and overlay, without enclosing element.
+    fn contents(&self, overlay: &str) -> String {
+        let mut svg = String::new();
+        if !self.defs.is_empty() {
+            svg.push_str(&format!("<defs>{}</defs>", self.defs));
+        }
+        svg.push_str(&self.body);
+        if !overlay.is_empty() {
+            svg.push_str(&format!("<g class=\"moleco-regions\">{}</g>", overlay));
+        }
+        svg
+    }
+}
+
+fn color(shape: &Shape) -> Srgba<u8> {
+    match shape {
+        Shape::Square(square) => square.color,
+        Shape::Rectangle(rectangle) => rectangle.color,
+        Shape::Line(line) => line.color,
+        Shape::Circle(circle) => circle.color,
+        Shape::Text(text) => text.color,
+    }
+}
+
+fn blend(shape: &Shape) -> Blend {
+    match shape {
+        Shape::Square(square) => square.blend,
+        Shape::Rectangle(rectangle) => rectangle.blend,
+        Shape::Line(line) => line.blend,
+        Shape::Circle(circle) => circle.blend,
+        Shape::Text(_) => Blend::Over,
+    }
+}
+
+fn hex(color: Srgba<u8>) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.red, color.green, color.blue)
+}
+
+fn fill(color: Srgba<u8>) -> String {
+    match color.alpha {
+        255 => format!("fill=\"{}\"", hex(color)),
+        alpha => format!(
+            "fill=\"{}\" fill-opacity=\"{:.3}\"",
+            hex(color),
+            alpha as f32 / 255f32
+        ),
+    }
+}
+
+/// Escape text for XML (and HTML) content and attribute values.
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn points(points: &[(f32, f32)]) -> String {
+    points
+        .iter()
+        .map(|(x, y)| format!("{},{}", x, y))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn square_element(square: &Square, color: Srgba<u8>) -> String {
+    let half_size = ((square.size.max(1) - 1) / 2) as f32;
+    let (x, y) = (square.x as f32, square.y as f32);
+    match square.orientation {
+        Orientation::Horizontal => format!(
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" {}/>",
+            x - half_size,
+            y - half_size,
+            square.size,
+            square.size,
+            fill(color)
+        ),
+        // Diamond goes through the middle of its outermost pixels
+        Orientation::Vertical => format!(
+            "<polygon points=\"{}\" {}/>",
+            points(&[
+                (x + 0.5, y - half_size),
+                (x + half_size + 1f32, y + 0.5),
+                (x + 0.5, y + half_size + 1f32),
+                (x - half_size, y + 0.5),
+            ]),
+            fill(color)
+        ),
+    }
+}
+
+/// Line is drawn with square brush, so it is outline of the brush moved from start to end.
+fn line_element(line: &Line, color: Srgba<u8>) -> String {
+    let ((x1, y1), (x2, y2)) = match line.x1 > line.x2 {
+        true => ((line.x2, line.y2), (line.x1, line.y1)),
+        false => ((line.x1, line.y1), (line.x2, line.y2)),
+    };
+    let (x1, y1, x2, y2) = (
+        x1 as f32 + 0.5,
+        y1 as f32 + 0.5,
+        x2 as f32 + 0.5,
+        y2 as f32 + 0.5,
+    );
+    let reach = ((line.border_size.max(1) - 1) / 2) as f32 + 0.5;
+    let outline = match y2 >= y1 {
+        true => [
+            (x1 - reach, y1 - reach),
+            (x1 + reach, y1 - reach),
+            (x2 + reach, y2 - reach),
+            (x2 + reach, y2 + reach),
+            (x2 - reach, y2 + reach),
+            (x1 - reach, y1 + reach),
+        ],
+        false => [
+            (x1 - reach, y1 - reach),
+            (x2 - reach, y2 - reach),
+            (x2 + reach, y2 - reach),
+            (x2 + reach, y2 + reach),
+            (x1 + reach, y1 + reach),
+            (x1 - reach, y1 + reach),
+        ],
+    };
+    format!("<polygon points=\"{}\" {}/>", points(&outline), fill(color))
+}
+
+fn circle_element(circle: &Circle, color: Srgba<u8>, clip: &str) -> String {
+    let (x, y) = (circle.x as f32 + 0.5, circle.y as f32 + 0.5);
+    match circle.ring {
+        Some(width) => {
+            let half_width = ((width.max(1) - 1) / 2) as f32;
+            let inner = (circle.radius as f32 - half_width - 0.5).max(0f32);
+            let outer = circle.radius as f32 + half_width + 0.5;
+            let stroke = fill(color).replace("fill", "stroke");
+            format!(
+                "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"none\" {} stroke-width=\"{}\"{}/>",
+                x,
+                y,
+                (inner + outer) / 2f32,
+                stroke,
+                outer - inner,
+                clip
+            )
+        }
+        None => format!(
+            "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" {}{}/>",
+            x,
+            y,
+            circle.radius as f32 + 0.5,
+            fill(color),
+            clip
+        ),
+    }
+}
+
+/// Triangle from the center of the circle, covering its quarter split diagonally.
+fn sector_points(circle: &Circle, sector: Sector) -> String {
+    let (x, y) = (circle.x as f32 + 0.5, circle.y as f32 + 0.5);
+    let reach = (circle.radius + circle.ring.unwrap_or(0) + 1) as f32;
+    let corners = match sector {
+        Sector::Top => [(x - reach, y - reach), (x + reach, y - reach)],
+        Sector::Bottom => [(x - reach, y + reach), (x + reach, y + reach)],
+        Sector::Left => [(x - reach, y - reach), (x - reach, y + reach)],
+        Sector::Right => [(x + reach, y - reach), (x + reach, y + reach)],
+    };
+    points(&[(x, y), corners[0], corners[1]])
+}
+
+/// Tile of the pattern, repeated from the top left corner of the rectangle like in PNG pictures.
+fn pattern_element(id: &str, rectangle: &Rectangle, pattern: Pattern, color: Srgba<u8>) -> String {
+    let period = (rectangle.height / 4).max(2);
+    let half = period / 2;
+    let tile = match pattern {
+        Pattern::Hatched => format!(
+            "<polygon points=\"0,0 {h},0 0,{h}\" {f}/><polygon points=\"{p},0 {p},{h} {h},{p} 0,{p}\" {f}/>",
+            h = half,
+            p = period,
+            f = fill(color)
+        ),
+        _ => format!(
+            "<rect width=\"{}\" height=\"{}\" {}/>",
+            half,
+            half,
+            fill(color)
+        ),
+    };
+    format!(
+        "<pattern id=\"{}\" patternUnits=\"userSpaceOnUse\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\">{}</pattern>",
+        id, rectangle.x, rectangle.y, period, period, tile
+    )
+}
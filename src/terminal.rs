@@ -0,0 +1,255 @@
+use image::imageops::FilterType;
+use image::RgbaImage;
+use std::env;
+use std::io::IsTerminal;
+
+/// Graphics protocol supported by the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Graphics {
+    Kitty,
+    Iterm,
+    Sixel,
+    /// No graphics at all (or output is not a terminal) - only colored characters can be used.
+    None,
+}
+
+/// Terminals known to support Sixel graphics, by `TERM` value.
+const SIXEL_TERMS: [&str; 5] = ["mlterm", "foot", "yaft", "contour", "xterm-sixel"];
+
+/// Guess graphics protocol of the terminal from environment, the same way it is done by `viuer`
+/// for Kitty and iTerm. Sixel is guessed from `TERM`, as asking terminal itself requires reading
+/// its reply from standard input.
+pub fn detect_graphics() -> Graphics {
+    if !std::io::stdout().is_terminal() {
+        return Graphics::None;
+    }
+    let term = env::var("TERM").unwrap_or_default();
+    if term == "dumb" {
+        return Graphics::None;
+    }
+    if viuer::get_kitty_support() != viuer::KittySupport::None {
+        return Graphics::Kitty;
+    }
+    if viuer::is_iterm_supported() {
+        return Graphics::Iterm;
+    }
+    if SIXEL_TERMS.iter().any(|name| term.starts_with(name)) || term.contains("sixel") {
+        return Graphics::Sixel;
+    }
+    Graphics::None
+}
+
+/// Whether terminal can show 24-bit colors, otherwise 256 colors palette is used.
+pub fn truecolor_available() -> bool {
+    env::var("COLORTERM")
+        .map(|value| value.contains("truecolor") || value.contains("24bit"))
+        .unwrap_or(false)
+}
+
+/// Color part of escape sequence. Without truecolor the closest color of 6x6x6 cube of 256 colors
+/// palette is used.
+fn color_code(red: u8, green: u8, blue: u8, truecolor: bool) -> String {
+    if truecolor {
+        format!("2;{};{};{}", red, green, blue)
+    } else {
+        let level = |value: u8| (value as u32 * 5 + 127) / 255;
+        format!(
+            "5;{}",
+            16 + level(red) * 36 + level(green) * 6 + level(blue)
+        )
+    }
+}
+
+fn background(red: u8, green: u8, blue: u8, truecolor: bool) -> String {
+    format!("\x1b[48;{}m", color_code(red, green, blue, truecolor))
+}
+
+fn foreground(red: u8, green: u8, blue: u8, truecolor: bool) -> String {
+    format!("\x1b[38;{}m", color_code(red, green, blue, truecolor))
+}
+
+/// Resize picture to given width, keeping its proportions. Height is rounded up to multiple of
+/// `cell_height`, so picture can be split into whole characters.
+fn sample(buffer: &RgbaImage, width: u32, cell_height: u32) -> RgbaImage {
+    let width = width.clamp(1, buffer.width().max(1));
+    let height = (buffer.height() as u64 * width as u64 / buffer.width().max(1) as u64).max(1);
+    let height = (height as u32).div_ceil(cell_height) * cell_height;
+    image::imageops::resize(buffer, width, height, FilterType::Triangle)
+}
+
+/// Approximation of the picture with colored blocks, for terminals without graphics. Each
+/// block is two characters wide, so picture keeps its proportions. Transparent parts show
+/// terminal background. `columns` is maximal width of the result.
+pub fn block_art(buffer: &RgbaImage, columns: u32, truecolor: bool) -> String {
+    let sampled = sample(buffer, columns / 2, 1);
+
+    let mut art = String::new();
+    for row in sampled.rows() {
+        for pixel in row {
+            let [red, green, blue, alpha] = pixel.0;
+            if alpha < 128 {
+                art.push_str("\x1b[0m  ");
+            } else {
+                art.push_str(&background(red, green, blue, truecolor));
+                art.push_str("  ");
+            }
+        }
+        art.push_str("\x1b[0m\n");
+    }
+    art
+}
+
+/// Picture drawn with half blocks (`▀`) - each character shows two pixels, one above another, so
+/// resolution is twice as good as of `block_art`. `columns` is maximal width of the result.
+pub fn half_blocks(buffer: &RgbaImage, columns: u32, truecolor: bool) -> String {
+    let sampled = sample(buffer, columns, 2);
+    let opaque = |x: u32, y: u32| {
+        let pixel = sampled.get_pixel(x, y).0;
+        match pixel[3] < 128 {
+            true => None,
+            false => Some((pixel[0], pixel[1], pixel[2])),
+        }
+    };
+
+    let mut art = String::new();
+    for y in (0..sampled.height()).step_by(2) {
+        for x in 0..sampled.width() {
+            art.push_str("\x1b[0m");
+            match (opaque(x, y), opaque(x, y + 1)) {
+                (None, None) => art.push(' '),
+                (Some((r, g, b)), None) => {
+                    art.push_str(&foreground(r, g, b, truecolor));
+                    art.push('▀');
+                }
+                (None, Some((r, g, b))) => {
+                    art.push_str(&foreground(r, g, b, truecolor));
+                    art.push('▄');
+                }
+                (Some((r, g, b)), Some((r2, g2, b2))) => {
+                    art.push_str(&foreground(r, g, b, truecolor));
+                    art.push_str(&background(r2, g2, b2, truecolor));
+                    art.push('▀');
+                }
+            }
+        }
+        art.push_str("\x1b[0m\n");
+    }
+    art
+}
+
+/// Bits of braille dots, by position (column, row) in 2x4 character cell.
+const BRAILLE_DOTS: [[u32; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+
+/// Picture drawn with braille characters - each one shows 2x4 pixels, so the finest details (like
+/// borders) are kept, but all dots of single character share average color. Dots are drawn for
+/// opaque pixels. `columns` is maximal width of the result.
+pub fn braille(buffer: &RgbaImage, columns: u32, truecolor: bool) -> String {
+    let sampled = sample(buffer, columns * 2, 4);
+
+    let mut art = String::new();
+    for top in (0..sampled.height()).step_by(4) {
+        for left in (0..sampled.width()).step_by(2) {
+            let mut dots = 0;
+            let mut sum = [0u32; 3];
+            for (dx, column) in BRAILLE_DOTS.iter().enumerate() {
+                for (dy, bit) in column.iter().enumerate() {
+                    let (x, y) = (left + dx as u32, top + dy as u32);
+                    if x >= sampled.width() {
+                        continue;
+                    }
+                    let pixel = sampled.get_pixel(x, y).0;
+                    if pixel[3] >= 128 {
+                        dots |= bit;
+                        for channel in 0..3 {
+                            sum[channel] += pixel[channel] as u32;
+                        }
+                    }
+                }
+            }
+            if dots == 0 {
+                art.push_str("\x1b[0m ");
+                continue;
+            }
+            let count = dots.count_ones();
+            let [r, g, b] = sum.map(|channel| (channel / count) as u8);
+            art.push_str(&foreground(r, g, b, truecolor));
+            art.push(char::from_u32(0x2800 + dots).unwrap());
+        }
+        art.push_str("\x1b[0m\n");
+    }
+    art
+}
+
+/// Encode picture as Sixel graphics. Colors are reduced to 6x6x6 cube and transparent pixels
+/// are left unpainted.
+pub fn sixel(buffer: &RgbaImage) -> String {
+    let level = |value: u8| (value as usize * 5 + 127) / 255;
+    let color_index = |x: u32, y: u32| -> Option<usize> {
+        let [red, green, blue, alpha] = buffer.get_pixel(x, y).0;
+        match alpha < 128 {
+            true => None,
+            false => Some(level(red) * 36 + level(green) * 6 + level(blue)),
+        }
+    };
+
+    // P2 = 1 - pixels that are not painted keep background
+    let mut result = format!("\x1bP0;1;0q\"1;1;{};{}", buffer.width(), buffer.height());
+    for index in 0..216 {
+        let percent = |level: usize| level * 100 / 5;
+        result.push_str(&format!(
+            "#{};2;{};{};{}",
+            index,
+            percent(index / 36),
+            percent(index / 6 % 6),
+            percent(index % 6)
+        ));
+    }
+
+    for band in (0..buffer.height()).step_by(6) {
+        let rows = band..(band + 6).min(buffer.height());
+        let mut colors: Vec<usize> = rows
+            .clone()
+            .flat_map(|y| (0..buffer.width()).filter_map(move |x| color_index(x, y)))
+            .collect();
+        colors.sort_unstable();
+        colors.dedup();
+
+        for (position, color) in colors.iter().enumerate() {
+            if position > 0 {
+                // Carriage return, next color is painted over the same band
+                result.push('$');
+            }
+            result.push_str(&format!("#{}", color));
+            let mut run: Option<(char, usize)> = None;
+            for x in 0..buffer.width() {
+                let bits = rows
+                    .clone()
+                    .filter(|y| color_index(x, *y) == Some(*color))
+                    .fold(0u8, |bits, y| bits | 1 << (y - band));
+                let character = (63 + bits) as char;
+                run = match run {
+                    Some((previous, count)) if previous == character => Some((previous, count + 1)),
+                    Some((previous, count)) => {
+                        push_run(&mut result, previous, count);
+                        Some((character, 1))
+                    }
+                    None => Some((character, 1)),
+                };
+            }
+            if let Some((character, count)) = run {
+                push_run(&mut result, character, count);
+            }
+        }
+        result.push('-');
+    }
+    result.push_str("\x1b\\");
+    result
+}
+
+/// Repeated sixels are compressed as `!<count><sixel>`.
+fn push_run(result: &mut String, character: char, count: usize) {
+    match count {
+        1..=3 => result.extend(std::iter::repeat_n(character, count)),
+        _ => result.push_str(&format!("!{}{}", count, character)),
+    }
+}
@@ -0,0 +1,132 @@
+use crate::layouts::Picture;
+use image::RgbaImage;
+use palette::color_difference::Ciede2000;
+use palette::{FromColor, Lab, Srgb};
+use std::env;
+use std::fs;
+use std::path::Path;
+
+/// Golden files are written (or rewritten) instead of compared when this variable is set.
+pub const UPDATE_VARIABLE: &str = "MOLECO_UPDATE_SNAPSHOTS";
+
+/// How much snapshots may differ and still match.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tolerance {
+    /// Pixels with larger color difference (CIEDE2000, composited over white) differ.
+    pub max_delta_e: f32,
+    /// Share (0 to 1) of pixels that may differ, like antialiased edges.
+    pub max_differing: f32,
+}
+
+impl Default for Tolerance {
+    /// Differences hardly visible to human eye, on at most 0.1% of pixels.
+    fn default() -> Self {
+        Self {
+            max_delta_e: 1.0,
+            max_differing: 0.001,
+        }
+    }
+}
+
+/// Render picture to bytes that stay the same as long as its pixels do - uncompressed PAM
+/// image, which (unlike PNG) doesn't depend on version of encoder.
+pub fn snapshot(picture: &Picture) -> Result<Vec<u8>, String> {
+    Ok(to_pam(&picture.generate()?))
+}
+
+fn to_pam(buffer: &RgbaImage) -> Vec<u8> {
+    let mut bytes = format!(
+        "P7\nWIDTH {}\nHEIGHT {}\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n",
+        buffer.width(),
+        buffer.height()
+    )
+    .into_bytes();
+    bytes.extend_from_slice(buffer.as_raw());
+    bytes
+}
+
+fn from_pam(bytes: &[u8]) -> Result<RgbaImage, String> {
+    let header_end = bytes
+        .windows(7)
+        .position(|window| window == b"ENDHDR\n")
+        .ok_or("Snapshot is not PAM image")?;
+    let header = String::from_utf8_lossy(&bytes[..header_end]);
+    let field = |name: &str| {
+        header
+            .lines()
+            .find_map(|line| line.strip_prefix(name))
+            .and_then(|value| value.trim().parse::<u32>().ok())
+            .ok_or(format!("Snapshot has no {}", name.trim()))
+    };
+    if !header.starts_with("P7\n") || field("DEPTH ")? != 4 || field("MAXVAL ")? != 255 {
+        return Err("Snapshot is not RGBA PAM image".to_string());
+    }
+    let (width, height) = (field("WIDTH ")?, field("HEIGHT ")?);
+    RgbaImage::from_raw(width, height, bytes[header_end + 7..].to_vec())
+        .ok_or("Snapshot is truncated".to_string())
+}
+
+/// Color of pixel over white background, so fully transparent pixels don't differ by their
+/// (invisible) colors.
+fn over_white(pixel: [u8; 4]) -> Lab {
+    let alpha = pixel[3] as f32 / 255.0;
+    let channel = |value: u8| (value as f32 / 255.0) * alpha + (1.0 - alpha);
+    let srgb = Srgb::new(channel(pixel[0]), channel(pixel[1]), channel(pixel[2]));
+    Lab::from_color(srgb.into_linear())
+}
+
+/// Compare snapshot with golden one, error describes how much they differ.
+pub fn compare(actual: &[u8], golden: &[u8], tolerance: Tolerance) -> Result<(), String> {
+    if actual == golden {
+        return Ok(());
+    }
+    let (actual, golden) = (from_pam(actual)?, from_pam(golden)?);
+    if actual.dimensions() != golden.dimensions() {
+        return Err(format!(
+            "Size differs - {:?} instead of {:?}",
+            actual.dimensions(),
+            golden.dimensions()
+        ));
+    }
+    let mut differing = 0;
+    let mut max_delta_e: f32 = 0.0;
+    for (first, second) in actual.pixels().zip(golden.pixels()) {
+        let delta_e = over_white(first.0).difference(over_white(second.0));
+        max_delta_e = max_delta_e.max(delta_e);
+        if delta_e > tolerance.max_delta_e {
+            differing += 1;
+        }
+    }
+    let share = differing as f32 / (actual.width() * actual.height()).max(1) as f32;
+    match share > tolerance.max_differing {
+        true => Err(format!(
+            "{} pixel(s) differ ({:.2}%), largest difference is {:.2}",
+            differing,
+            share * 100.0,
+            max_delta_e
+        )),
+        false => Ok(()),
+    }
+}
+
+/// Compare picture with golden file, which is written when it doesn't exist yet (or when
+/// `MOLECO_UPDATE_SNAPSHOTS` is set). Panics on difference, like other assertions.
+pub fn assert_snapshot(picture: &Picture, golden: impl AsRef<Path>, tolerance: Tolerance) {
+    let golden = golden.as_ref();
+    let actual = snapshot(picture).unwrap_or_else(|e| panic!("Can't render picture - {}", e));
+    if env::var_os(UPDATE_VARIABLE).is_some() || !golden.exists() {
+        if let Some(directory) = golden.parent() {
+            fs::create_dir_all(directory)
+                .unwrap_or_else(|e| panic!("Can't create {:?} - {}", directory, e));
+        }
+        fs::write(golden, actual).unwrap_or_else(|e| panic!("Can't write {:?} - {}", golden, e));
+        return;
+    }
+    let expected = fs::read(golden).unwrap_or_else(|e| panic!("Can't read {:?} - {}", golden, e));
+    if let Err(e) = compare(&actual, &expected, tolerance) {
+        panic!(
+            "Picture doesn't match snapshot {:?} - {} (set {} to update it)",
+            golden, e, UPDATE_VARIABLE
+        );
+    }
+}
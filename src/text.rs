@@ -0,0 +1,176 @@
+use fontdue::{Font, FontSettings, Metrics};
+use image::{ImageBuffer, Rgba};
+use palette::Srgba;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// DejaVu Sans Mono, license is in `assets/fonts/LICENSE-DejaVu.txt`. Monospaced font keeps
+/// numbers aligned and width of text easy to predict.
+static FONT_DATA: &[u8] = include_bytes!("../assets/fonts/DejaVuSansMono.ttf");
+
+/// Font bundled with moleco, parsed once.
+pub fn bundled_font() -> &'static Font {
+    static FONT: OnceLock<Font> = OnceLock::new();
+    FONT.get_or_init(|| {
+        Font::from_bytes(FONT_DATA, FontSettings::default()).expect("Bundled font is valid")
+    })
+}
+
+const ELLIPSIS: &str = "…";
+
+/// Rasterized glyph - coverage (0-255) of each pixel of its bounding box.
+struct Glyph {
+    metrics: Metrics,
+    coverage: Vec<u8>,
+}
+
+/// Draws text with bundled font. Glyphs are rasterized once per size and cached, as the same
+/// characters (mostly digits) are drawn over and over.
+pub struct TextRenderer {
+    font: &'static Font,
+    glyphs: HashMap<(char, u32), Glyph>,
+}
+
+impl Default for TextRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TextRenderer {
+    pub fn new() -> Self {
+        Self {
+            font: bundled_font(),
+            glyphs: HashMap::new(),
+        }
+    }
+
+    /// Number of glyphs rasterized so far.
+    pub fn cached_glyphs(&self) -> usize {
+        self.glyphs.len()
+    }
+
+    fn glyph(&mut self, character: char, size: u32) -> &Glyph {
+        let font = self.font;
+        self.glyphs.entry((character, size)).or_insert_with(|| {
+            let (metrics, coverage) = font.rasterize(character, size as f32);
+            Glyph { metrics, coverage }
+        })
+    }
+
+    /// Distance from the top of the line to the baseline and height of the line, for font of
+    /// given size (in pixels).
+    fn line_metrics(&self, size: u32) -> (f32, u32) {
+        match self.font.horizontal_line_metrics(size as f32) {
+            Some(metrics) => (
+                metrics.ascent,
+                (metrics.ascent - metrics.descent).ceil() as u32,
+            ),
+            None => (size as f32, size),
+        }
+    }
+
+    /// Width and height (in pixels) of single line of text.
+    pub fn measure(&mut self, text: &str, size: u32) -> (u32, u32) {
+        let width: f32 = text
+            .chars()
+            .map(|c| self.glyph(c, size).metrics.advance_width)
+            .sum();
+        (width.ceil() as u32, self.line_metrics(size).1)
+    }
+
+    /// Shorten text to given width by replacing its middle with ellipsis - beginning and end of
+    /// identifiers (like InChI layers or InChIKey checksum) are usually the most telling.
+    pub fn truncate_middle(&mut self, text: &str, size: u32, max_width: u32) -> String {
+        if self.measure(text, size).0 <= max_width {
+            return text.to_string();
+        }
+        let chars: Vec<char> = text.chars().collect();
+        let shortened = |kept: usize| -> String {
+            let head = kept.div_ceil(2);
+            let tail = kept / 2;
+            let mut result: String = chars[..head].iter().collect();
+            result.push_str(ELLIPSIS);
+            result.extend(&chars[chars.len() - tail..]);
+            result
+        };
+        // Text shrinks with every removed character, so the first fitting one is the longest
+        for kept in (0..chars.len()).rev() {
+            let candidate = shortened(kept);
+            if self.measure(&candidate, size).0 <= max_width {
+                return candidate;
+            }
+        }
+        String::new()
+    }
+
+    /// Fit text in given width. When `shrink` is set, font size is reduced first (down to half
+    /// of the original size), then text is truncated in the middle if needed.
+    /// Returns fitted text and its font size.
+    pub fn fit(&mut self, text: &str, size: u32, max_width: u32, shrink: bool) -> (String, u32) {
+        let mut size = size;
+        if shrink {
+            let min_size = (size / 2).max(1);
+            while size > min_size && self.measure(text, size).0 > max_width {
+                size -= 1;
+            }
+        }
+        (self.truncate_middle(text, size, max_width), size)
+    }
+
+    /// Draw single line of text with top left corner in given point. Text is blended with
+    /// the picture, so antialiased edges look well on any background. Pixels outside of
+    /// the picture are skipped.
+    pub fn draw(
+        &mut self,
+        buffer: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+        text: &str,
+        x: i32,
+        y: i32,
+        size: u32,
+        color: Srgba<u8>,
+    ) {
+        let (ascent, _) = self.line_metrics(size);
+        let baseline = y as f32 + ascent;
+        let mut pen = x as f32;
+        for c in text.chars() {
+            let glyph = self.glyph(c, size);
+            let metrics = glyph.metrics;
+            let left = (pen + metrics.xmin as f32).round() as i32;
+            let top = (baseline - metrics.ymin as f32 - metrics.height as f32).round() as i32;
+            for (index, coverage) in glyph.coverage.iter().enumerate() {
+                if *coverage == 0 {
+                    continue;
+                }
+                let px = left + (index % metrics.width) as i32;
+                let py = top + (index / metrics.width) as i32;
+                if px < 0 || py < 0 || px >= buffer.width() as i32 || py >= buffer.height() as i32 {
+                    continue;
+                }
+                let alpha = color.alpha as u32 * *coverage as u32 / 255;
+                let pixel = buffer.get_pixel_mut(px as u32, py as u32);
+                *pixel = blend(*pixel, color, alpha as u8);
+            }
+            pen += metrics.advance_width;
+        }
+    }
+}
+
+/// Standard "over" compositing of color with given alpha over the pixel.
+pub(crate) fn blend(pixel: Rgba<u8>, color: Srgba<u8>, alpha: u8) -> Rgba<u8> {
+    let alpha = alpha as f32 / 255f32;
+    let below = pixel.0[3] as f32 / 255f32;
+    let result = alpha + below * (1f32 - alpha);
+    if result == 0f32 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    let channel = |top: u8, bottom: u8| {
+        ((top as f32 * alpha + bottom as f32 * below * (1f32 - alpha)) / result).round() as u8
+    };
+    Rgba([
+        channel(color.red, pixel.0[0]),
+        channel(color.green, pixel.0[1]),
+        channel(color.blue, pixel.0[2]),
+        (result * 255f32).round() as u8,
+    ])
+}
@@ -0,0 +1,180 @@
+use crate::layouts::{Cutouts, MarkPosition, MarkShape, OpenEnd, Pattern, Style};
+use palette::Srgba;
+use serde::Deserialize;
+use std::path::Path;
+
+/// Look of pictures, loaded from TOML or JSON file, so it can be shared without recompiling.
+/// All fields are optional, missing ones keep values of the style theme is applied to.
+///
+/// ```toml
+/// border_color = "#1a1a1a"
+/// background = "#ffffff"
+/// cutout_scale = 0.5
+/// cutouts = "center"
+/// cutout_fill = "#ffffff"
+/// orientation_mark = "top"
+/// mark_shape = "dot"
+/// corner_radius = 16
+/// frame_inset = 4
+/// spacing = 4
+/// min_border_contrast = 3
+///
+/// [bar]
+/// unknown_color = "#cccccc80"
+/// unknown_pattern = "hatched"
+/// open_end = "fade"
+/// divider_width = 0
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    /// Color in `#rrggbb` or `#rrggbbaa` notation.
+    pub border_color: Option<String>,
+    /// Color in `#rrggbb` or `#rrggbbaa` notation.
+    pub background: Option<String>,
+    pub cutout_scale: Option<f32>,
+    pub cutouts: Option<Cutouts>,
+    /// Color in `#rrggbb` or `#rrggbbaa` notation.
+    pub cutout_fill: Option<String>,
+    pub orientation_mark: Option<MarkPosition>,
+    pub mark_shape: Option<MarkShape>,
+    /// In pixels.
+    pub corner_radius: Option<u32>,
+    /// Gap around the frame in pixels, frame is drawn only when it is set.
+    pub frame_inset: Option<u32>,
+    pub spacing: Option<u32>,
+    /// WCAG contrast ratio, from 1 to 21.
+    pub min_border_contrast: Option<f64>,
+    pub bar: BarTheme,
+}
+
+/// Style of the mixture bar.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BarTheme {
+    /// Color in `#rrggbb` or `#rrggbbaa` notation.
+    pub unknown_color: Option<String>,
+    pub unknown_pattern: Option<Pattern>,
+    pub unknown_min_width: Option<f32>,
+    pub open_end: Option<OpenEnd>,
+    pub zero_width_markers: Option<bool>,
+    /// Color in `#rrggbb` or `#rrggbbaa` notation.
+    pub divider_color: Option<String>,
+    pub divider_width: Option<u32>,
+}
+
+impl Theme {
+    pub fn from_toml(content: &str) -> Result<Self, String> {
+        toml::from_str(content).map_err(|e| format!("Invalid theme - {}", e))
+    }
+
+    pub fn from_json(content: &str) -> Result<Self, String> {
+        serde_json::from_str(content).map_err(|e| format!("Invalid theme - {}", e))
+    }
+
+    /// Load theme from file, format is chosen by extension (`.toml` or `.json`).
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Can't read theme {:?} - {}", path, e))?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => Self::from_toml(&content),
+            Some("json") => Self::from_json(&content),
+            _ => Err(format!(
+                "Unsupported theme format {:?}, use TOML or JSON",
+                path
+            )),
+        }
+    }
+
+    /// Override given style with values set in theme.
+    pub fn apply(&self, style: Style) -> Result<Style, String> {
+        let mut style = style;
+        if let Some(color) = &self.border_color {
+            style.border_color = parse_color(color)?;
+        }
+        if let Some(color) = &self.background {
+            style.background = parse_color(color)?;
+        }
+        if let Some(scale) = self.cutout_scale {
+            if !(0f32..=2f32).contains(&scale) {
+                return Err(format!(
+                    "Cutout scale must be between 0 and 2, got {}",
+                    scale
+                ));
+            }
+            style.cutout_scale = scale;
+        }
+        if let Some(cutouts) = self.cutouts {
+            style.cutouts = cutouts;
+        }
+        if let Some(color) = &self.cutout_fill {
+            style.cutout_fill = Some(parse_color(color)?);
+        }
+        if let Some(position) = self.orientation_mark {
+            style.orientation_mark = position;
+        }
+        if let Some(shape) = self.mark_shape {
+            style.mark_shape = shape;
+        }
+        if let Some(radius) = self.corner_radius {
+            style.corner_radius = radius;
+        }
+        if let Some(inset) = self.frame_inset {
+            style.frame_inset = Some(inset);
+        }
+        if let Some(spacing) = self.spacing {
+            style.spacing = spacing;
+        }
+        if let Some(contrast) = self.min_border_contrast {
+            if !(1f64..=21f64).contains(&contrast) {
+                return Err(format!(
+                    "Minimal border contrast must be between 1 and 21, got {}",
+                    contrast
+                ));
+            }
+            style.min_border_contrast = Some(contrast);
+        }
+        if let Some(color) = &self.bar.unknown_color {
+            style.unknown_color = parse_color(color)?;
+        }
+        if let Some(pattern) = self.bar.unknown_pattern {
+            style.unknown_pattern = pattern;
+        }
+        if let Some(width) = self.bar.unknown_min_width {
+            style.unknown_min_width = width;
+        }
+        if let Some(open_end) = self.bar.open_end {
+            style.open_end = open_end;
+        }
+        if let Some(markers) = self.bar.zero_width_markers {
+            style.zero_width_markers = markers;
+        }
+        if let Some(color) = &self.bar.divider_color {
+            style.divider_color = Some(parse_color(color)?);
+        }
+        if let Some(width) = self.bar.divider_width {
+            style.divider_width = Some(width);
+        }
+        Ok(style)
+    }
+}
+
+/// Parse color in `#rrggbb` or `#rrggbbaa` notation (hash is optional).
+pub fn parse_color(color: &str) -> Result<Srgba<u8>, String> {
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    let invalid = || format!("Invalid color {:?}, expected #rrggbb or #rrggbbaa", color);
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    let channel = |index: usize| u8::from_str_radix(&hex[index..index + 2], 16);
+    let alpha = match hex.len() {
+        8 => channel(6).map_err(|_| invalid())?,
+        _ => 255,
+    };
+    Ok(Srgba::new(
+        channel(0).map_err(|_| invalid())?,
+        channel(2).map_err(|_| invalid())?,
+        channel(4).map_err(|_| invalid())?,
+        alpha,
+    ))
+}
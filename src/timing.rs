@@ -0,0 +1,107 @@
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+
+/// Stage of generation of an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Hashing of substances into their color schemes.
+    Hashing,
+    /// Splitting mixture indexing and concentrations into tokens.
+    Tokenization,
+    /// Parsing of mixture out of tokens.
+    Parsing,
+    /// Widths of mixture bar segments and ordering of swatches.
+    WidthCalculation,
+    /// Describing picture as shapes.
+    ShapeGeneration,
+    /// Drawing shapes into pixels.
+    Rasterization,
+    /// Encoding of pixels into PNG (or PDF).
+    Encoding,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 7] = [
+        Stage::Hashing,
+        Stage::Tokenization,
+        Stage::Parsing,
+        Stage::WidthCalculation,
+        Stage::ShapeGeneration,
+        Stage::Rasterization,
+        Stage::Encoding,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::Hashing => "hashing",
+            Stage::Tokenization => "tokenization",
+            Stage::Parsing => "parsing",
+            Stage::WidthCalculation => "width calculation",
+            Stage::ShapeGeneration => "shape generation",
+            Stage::Rasterization => "rasterization",
+            Stage::Encoding => "encoding",
+        }
+    }
+}
+
+/// Time spent in each stage.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timings {
+    spent: [Duration; Stage::ALL.len()],
+}
+
+impl Timings {
+    pub fn get(&self, stage: Stage) -> Duration {
+        self.spent[stage as usize]
+    }
+
+    pub fn add(&mut self, other: &Timings) {
+        for (spent, other) in self.spent.iter_mut().zip(other.spent) {
+            *spent += other;
+        }
+    }
+
+    /// Time spent since `earlier` timings of the same thread were peeked.
+    pub fn since(&self, earlier: &Timings) -> Timings {
+        let mut spent = self.spent;
+        for (spent, earlier) in spent.iter_mut().zip(earlier.spent) {
+            *spent = spent.saturating_sub(earlier);
+        }
+        Timings { spent }
+    }
+
+    /// Time spent in all stages.
+    pub fn total(&self) -> Duration {
+        self.spent.iter().sum()
+    }
+}
+
+thread_local! {
+    static SPENT: RefCell<Timings> = RefCell::new(Timings::default());
+}
+
+/// Run `work`, adding time it took to given stage. Time of stages measured within it is counted
+/// only once, in the inner stage.
+pub fn measure<T>(stage: Stage, work: impl FnOnce() -> T) -> T {
+    let outer = take();
+    let start = Instant::now();
+    let result = work();
+    let elapsed = start.elapsed();
+    SPENT.with(|spent| {
+        let mut spent = spent.borrow_mut();
+        let inner: Duration = spent.spent.iter().sum();
+        spent.spent[stage as usize] += elapsed.saturating_sub(inner);
+        spent.add(&outer);
+    });
+    result
+}
+
+/// Time spent in stages (by current thread) since the last call of `take`, without resetting it.
+pub fn peek() -> Timings {
+    SPENT.with(|spent| *spent.borrow())
+}
+
+/// Time spent in stages (by current thread) since the last call.
+pub fn take() -> Timings {
+    SPENT.with(|spent| spent.replace(Timings::default()))
+}
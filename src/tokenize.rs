@@ -1,5 +1,114 @@
+use crate::timing::{measure, Stage};
+use std::fmt;
+use std::io::{BufReader, Read};
 use std::iter::Peekable;
-use std::str::Chars;
+use std::slice::Iter;
+
+/// How many characters around the problematic part are shown in annotated error.
+const ANNOTATION_CONTEXT: usize = 30;
+
+/// Error of indexing/concentration notation parsing.
+///
+/// Besides the message it keeps the input that was parsed and (if possible) position of the
+/// offending part, so it can be pointed out to the user. Positions are character offsets,
+/// `span` is `(start, end)` with exclusive end.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct ParseError {
+    pub message: String,
+    pub input: String,
+    pub span: Option<(usize, usize)>,
+}
+
+impl ParseError {
+    pub fn new(message: String, input: &str, span: Option<(usize, usize)>) -> Self {
+        Self {
+            message,
+            input: input.to_string(),
+            span,
+        }
+    }
+
+    /// Offending part of the input, if position is known.
+    pub fn snippet(&self) -> Option<String> {
+        self.span
+            .map(|(start, end)| self.input.chars().skip(start).take(end - start).collect())
+    }
+
+    /// Move the error into wider context, e.g. from the concentration layer to the whole MInChI.
+    /// Input is looked up from the end of the context, as layers are always at the end of
+    /// payload. If input can't be found, error is returned untouched.
+    pub fn within(self, context: &str) -> Self {
+        match context.rfind(&self.input) {
+            Some(byte_offset) => {
+                let offset = context[..byte_offset].chars().count();
+                Self {
+                    message: self.message,
+                    input: context.to_string(),
+                    span: self.span.map(|(start, end)| (start + offset, end + offset)),
+                }
+            }
+            None => self,
+        }
+    }
+
+    /// Error of the value starting at `offset` of the notation, so its position is in the whole
+    /// notation. Input is left to the caller.
+    fn at(self, offset: usize) -> Self {
+        Self {
+            span: self.span.map(|(start, end)| (start + offset, end + offset)),
+            ..self
+        }
+    }
+
+    /// Render input with caret line pointing at the offending part. Very long inputs (which is
+    /// the case for most of MInChIs) are cut to the surroundings of the problem.
+    ///
+    /// ```text
+    /// ...&C6H14/c1-4-5-6(2)3/n{6&{1&{3&2&4&5}}/g{1mr0&...
+    ///                                        ^
+    /// ```
+    pub fn annotate(&self) -> Option<String> {
+        let (start, end) = self.span?;
+        let chars: Vec<char> = self.input.chars().collect();
+        // Errors of notation read from other sources have no input
+        if end > chars.len() {
+            return None;
+        }
+        let window_start = start.saturating_sub(ANNOTATION_CONTEXT);
+        let window_end = (end + ANNOTATION_CONTEXT).min(chars.len());
+
+        let mut line = String::new();
+        let mut padding = start - window_start;
+        if window_start > 0 {
+            line.push_str("...");
+            padding += 3;
+        }
+        line.extend(&chars[window_start..window_end]);
+        if window_end < chars.len() {
+            line.push_str("...");
+        }
+        let carets = "^".repeat((end - start).max(1));
+        Some(format!("{}\n{}{}", line, " ".repeat(padding), carets))
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.span {
+            Some((start, _)) => write!(f, "{} (at position {})", self.message, start),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl From<ParseError> for String {
+    fn from(error: ParseError) -> Self {
+        match error.annotate() {
+            Some(annotation) => format!("{}\n{}", error, annotation),
+            None => error.to_string(),
+        }
+    }
+}
 
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub struct Group {
@@ -22,126 +131,200 @@ macro_rules! parse_result {
     ($result:expr, $payload:expr) => {
         match $result {
             Ok(v) => Ok(v),
-            Err(_) => Err(format!(
-                "Invalid content notation, invalid value - {:?}",
-                $payload
+            Err(_) => Err(ParseError::new(
+                format!("Invalid content notation, invalid value - {:?}", $payload),
+                $payload,
+                Some((0, $payload.chars().count())),
             )),
         }
     };
 }
 
-pub fn tokenize_string(input: &str, start: char) -> Result<Group, String> {
-    if input.is_empty() {
-        return Err("Empty group passed to tokenization".to_string());
-    }
-    // Prefix removal
-    let mut iter = input.chars();
-    if iter.next().unwrap() != start {
-        return Err(format!(
-            "Wrong first character, expected {} in input {}",
-            start, input
-        ));
-    }
-    let mut new_input: String = iter.collect();
+/// Characters of the notation with their positions (prefix is 0). Parentheses are checked as
+/// they are read - on the first problem (also of the source) error is kept and reading stops.
+struct Checked<I> {
+    chars: I,
+    position: usize,
+    /// Positions of parentheses that are not closed yet.
+    opened: Vec<usize>,
+    error: Option<ParseError>,
+}
 
-    // Check if first and last character is '{' and '}'
-    let len = new_input.len();
-    let ends_with_paren = match len {
-        l if l > 0 => match new_input.get(len - 1..) {
-            Some("}") => true,
-            _ => false,
-        },
-        _ => false,
-    };
-    let mut iter = new_input.chars().peekable();
-    let starts_with_paren = match iter.peek() {
-        Some(&'{') => true,
-        _ => false,
-    };
+impl<I: Iterator<Item = Result<char, String>>> Iterator for Checked<I> {
+    type Item = (usize, char);
 
-    // Check if parentheses are matching
-    // Also check if the first group is covering the entire payload (and thus is obsolete)
-    let mut first_group_is_covering_entire_payload = true;
-    let mut level_indicator = 0;
-    while let Some(&c) = iter.peek() {
-        let mut decreased = false;
-        match c {
-            '{' => {
-                level_indicator += 1;
+    fn next(&mut self) -> Option<(usize, char)> {
+        if self.error.is_some() {
+            return None;
+        }
+        let span = Some((self.position, self.position + 1));
+        let c = match self.chars.next()? {
+            Ok(c) => c,
+            Err(message) => {
+                self.error = Some(ParseError::new(message, "", span));
+                return None;
             }
-            '}' => {
-                level_indicator -= 1;
-                decreased = true;
+        };
+        match c {
+            '{' => self.opened.push(self.position),
+            '}' if self.opened.pop().is_none() => {
+                self.error = Some(ParseError::new(
+                    "Unmatching parentheses, closing one without opening".to_string(),
+                    "",
+                    span,
+                ));
+                return None;
             }
             _ => {}
         }
-        if level_indicator < 0 {
-            return Err(format!("Unmatching parentheses in input {}", input));
+        self.position += 1;
+        Some((self.position - 1, c))
+    }
+}
+
+/// Tokenize notation in single pass over its characters, without copying it. Errors have
+/// positions, but no input - it is up to the caller to fill it in. Offsets of values (of tokens
+/// and groups) are returned too, in order they are written in.
+fn tokenize<I: Iterator<Item = Result<char, String>>>(
+    mut chars: I,
+    start: char,
+) -> Result<(Group, Vec<usize>), ParseError> {
+    // Prefix removal
+    match chars.next() {
+        None => {
+            return Err(ParseError::new(
+                "Empty group passed to tokenization".to_string(),
+                "",
+                None,
+            ))
         }
-        iter.next();
-        // Check if first group closed before the end of the payload
-        if decreased && level_indicator == 0 && iter.peek().is_some() {
-            first_group_is_covering_entire_payload = false;
+        Some(Ok(c)) if c == start => {}
+        Some(Err(message)) => return Err(ParseError::new(message, "", Some((0, 1)))),
+        Some(Ok(_)) => {
+            return Err(ParseError::new(
+                format!("Wrong first character, expected {}", start),
+                "",
+                Some((0, 1)),
+            ))
         }
     }
-    if level_indicator != 0 {
-        return Err(format!("Unmatching parentheses in input {}", input));
+    let mut checked = Checked {
+        chars,
+        position: 1,
+        opened: vec![],
+        error: None,
+    };
+    let mut iter = checked.by_ref().peekable();
+    let mut offsets = vec![];
+    let mut group = parse_group(&mut iter, 1, &mut offsets);
+    // Top level group ends at unmatched parenthesis, rest is read to check it
+    for _ in iter {}
+    if let Some(error) = checked.error {
+        return Err(error);
+    }
+    if let Some(&unclosed) = checked.opened.last() {
+        return Err(ParseError::new(
+            "Unmatching parentheses, opening one is never closed".to_string(),
+            "",
+            Some((unclosed, unclosed + 1)),
+        ));
     }
 
-    // Remove the first and last character if they are '{' and '}' and the group is covering the entire payload
-    if starts_with_paren && ends_with_paren && first_group_is_covering_entire_payload {
-        new_input = new_input.get(1..len - 1).unwrap().to_string();
+    // Group covering the entire payload is obsolete, like in "n{1&2}"
+    if let [Component::Group(inner)] = group.components.as_mut_slice() {
+        if group.value.is_none() && inner.value.is_none() {
+            let mut inner = std::mem::take(&mut inner.components);
+            // The same as empty payload, placed inside of "{}"
+            if inner.is_empty() {
+                inner.push(Component::Token(Token {
+                    value: "".to_string(),
+                }));
+                offsets.push(2);
+            }
+            group.components = inner;
+        }
     }
+    Ok((group, offsets))
+}
+
+pub fn tokenize_string(input: &str, start: char) -> Result<Group, ParseError> {
+    tokenize_located(input, start).map(|(group, _)| group)
+}
+
+/// Tokenize notation, keeping offsets of its values (see `tokenize`).
+fn tokenize_located(input: &str, start: char) -> Result<(Group, Vec<usize>), ParseError> {
+    tokenize(input.chars().map(Ok), start).map_err(|error| ParseError {
+        input: input.to_string(),
+        ..error
+    })
+}
 
-    let mut iter = new_input.chars().peekable();
-    Ok(parse_group(&mut iter))
+/// Tokenize notation read from any source, like very long MInChI layer in a file - it is never
+/// held in memory as a whole. Notation is ASCII only. Errors have positions, but no input.
+pub fn tokenize_reader<R: Read>(reader: R, start: char) -> Result<Group, ParseError> {
+    let chars = BufReader::new(reader).bytes().map(|byte| match byte {
+        Ok(byte) if byte.is_ascii() => Ok(byte as char),
+        Ok(_) => Err("Invalid character, notation is ASCII only".to_string()),
+        Err(e) => Err(format!("Can't read notation - {}", e)),
+    });
+    tokenize(chars, start).map(|(group, _)| group)
 }
 
-fn parse_group(iter: &mut Peekable<Chars>) -> Group {
+/// Parse group starting at position `start`, adding offsets of its values to `offsets`.
+fn parse_group<I: Iterator<Item = (usize, char)>>(
+    iter: &mut Peekable<I>,
+    start: usize,
+    offsets: &mut Vec<usize>,
+) -> Group {
     let mut components = Vec::new();
     let mut current_token = String::new();
+    let mut token_start = start;
 
     match iter.peek() {
         // None - case for the empty string
         // Some(&) - case for & in the beginning of the group or payload (e.g. "{&&&)}")
-        Some('&') | None => {
+        Some((_, '&')) | None => {
             components.push(Component::Token(Token {
                 value: "".to_string(),
             }));
+            offsets.push(start);
         }
         _ => {}
     }
 
-    while let Some(&c) = iter.peek() {
+    while let Some(&(position, c)) = iter.peek() {
         match c {
             '&' => {
                 if !current_token.is_empty() {
                     components.push(Component::Token(Token {
                         value: current_token,
                     }));
+                    offsets.push(token_start);
                     current_token = String::new();
                 }
                 iter.next();
 
                 match iter.peek() {
                     // Case for & at the end of the group or payload (e.g. "n{1&{2&}}")
-                    Some(&'}') | None => {
+                    Some((_, '}')) | None => {
                         components.push(Component::Token(Token {
                             value: "".to_string(),
                         }));
+                        offsets.push(position + 1);
                     }
                     // Case for groupped &&
-                    Some(&'&') => {
+                    Some((_, '&')) => {
                         components.push(Component::Token(Token {
                             value: "".to_string(),
                         }));
+                        offsets.push(position + 1);
                     }
                     _ => {}
                 }
             }
             '{' => {
                 iter.next();
-                components.push(Component::Group(parse_group(iter)));
+                components.push(Component::Group(parse_group(iter, position + 1, offsets)));
             }
             '}' => {
                 iter.next();
@@ -149,15 +332,19 @@ fn parse_group(iter: &mut Peekable<Chars>) -> Group {
                     components.push(Component::Token(Token {
                         value: current_token,
                     }));
+                    offsets.push(token_start);
                     current_token = String::new();
                 }
                 // Parsing the value of the group if it exists, like "{group}value&other"
-                while let Some(&c) = iter.peek() {
+                while let Some(&(position, c)) = iter.peek() {
                     match c {
                         '}' | '&' => {
                             break;
                         }
                         _ => {
+                            if current_token.is_empty() {
+                                token_start = position;
+                            }
                             current_token.push(c);
                             iter.next();
                         }
@@ -165,11 +352,17 @@ fn parse_group(iter: &mut Peekable<Chars>) -> Group {
                 }
                 let value = match current_token.is_empty() {
                     true => None,
-                    false => Some(current_token),
+                    false => {
+                        offsets.push(token_start);
+                        Some(current_token)
+                    }
                 };
                 return Group { components, value };
             }
             _ => {
+                if current_token.is_empty() {
+                    token_start = position;
+                }
                 current_token.push(c);
                 iter.next();
             }
@@ -181,6 +374,7 @@ fn parse_group(iter: &mut Peekable<Chars>) -> Group {
         components.push(Component::Token(Token {
             value: current_token,
         }));
+        offsets.push(token_start);
     }
 
     Group {
@@ -189,7 +383,7 @@ fn parse_group(iter: &mut Peekable<Chars>) -> Group {
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
 pub enum Concentration {
     // To get idea what those infixes do, check cauculate_capacity function
     /// Percentage points, 51pp0 equals 51 percent, 5pp1 equals 50 percent
@@ -210,6 +404,22 @@ pub enum Concentration {
     MB,
 }
 
+impl fmt::Display for Concentration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let infix = match self {
+            Concentration::PP => "pp",
+            Concentration::WV => "wv",
+            Concentration::WF => "wf",
+            Concentration::RF => "rf",
+            Concentration::MF => "mf",
+            Concentration::VP => "vp",
+            Concentration::MR => "mr",
+            Concentration::MB => "mb",
+        };
+        write!(f, "{}", infix)
+    }
+}
+
 #[derive(Debug, Eq, PartialEq)]
 pub enum Capacity {
     Absolute(usize),
@@ -217,35 +427,118 @@ pub enum Capacity {
     Unestimated,
 }
 
+/// Error of calculations between magnitudes of contents.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum MagnitudeError {
+    /// Magnitude is above the level at which calculation makes sense.
+    TooBig(isize),
+    /// Result doesn't fit into integer, which happens for extreme differences of magnitudes.
+    Overflow,
+}
+
+impl fmt::Display for MagnitudeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MagnitudeError::TooBig(magnitude) => {
+                write!(f, "Magnitude {} is too big for calculation", magnitude)
+            }
+            MagnitudeError::Overflow => {
+                write!(
+                    f,
+                    "Magnitudes of contents are too far apart to compare them"
+                )
+            }
+        }
+    }
+}
+
+impl From<MagnitudeError> for String {
+    fn from(error: MagnitudeError) -> Self {
+        error.to_string()
+    }
+}
+
+fn power_of_ten(exponent: isize) -> Result<usize, MagnitudeError> {
+    u32::try_from(exponent)
+        .ok()
+        .and_then(|exponent| 10usize.checked_pow(exponent))
+        .ok_or(MagnitudeError::Overflow)
+}
+
+/// How the value of content relates to the real amount, as noted by prefix of the value.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Default)]
+pub enum Qualifier {
+    #[default]
+    Exact,
+    /// ~
+    Approx,
+    /// <
+    AtMost,
+    /// <=
+    AtMostInclusive,
+    /// >
+    AtLeast,
+    /// >=
+    AtLeastInclusive,
+}
+
+impl fmt::Display for Qualifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Qualifier::Exact => write!(f, ""),
+            Qualifier::Approx => write!(f, "~"),
+            Qualifier::AtMost => write!(f, "<"),
+            Qualifier::AtMostInclusive => write!(f, "<="),
+            Qualifier::AtLeast => write!(f, ">"),
+            Qualifier::AtLeastInclusive => write!(f, ">="),
+        }
+    }
+}
+
 #[derive(Debug, Eq, PartialEq)]
 pub struct Content {
+    /// Value used for calculations - for ranges it is the upper bound.
     pub value: usize,
     pub concentration: Concentration,
     pub magnitude: isize,
+    pub qualifier: Qualifier,
+    /// Lower and upper bound of the range (like 10:15pp0), at the same magnitude as value.
+    pub range: Option<(usize, usize)>,
 }
 
 impl Content {
-    pub fn from_str(payload: &str) -> Result<Self, String> {
-        let (value, concentration, magnitude) = split_payload(payload)?;
-        Ok(Self {
-            value,
-            concentration,
-            magnitude,
-        })
+    pub fn from_str(payload: &str) -> Result<Self, ParseError> {
+        split_payload(payload)
+    }
+
+    /// Lower and upper bound of the content, for single values both are the same.
+    pub fn bounds(&self) -> (usize, usize) {
+        self.range.unwrap_or((self.value, self.value))
     }
 
-    pub fn value_at_magnitude(&self, magnitude: &isize) -> usize {
+    /// Middle of the range (or simply the value), at magnitude of the content.
+    pub fn midpoint(&self) -> f64 {
+        let (min, max) = self.bounds();
+        (min as f64 + max as f64) / 2f64
+    }
+
+    pub fn value_at_magnitude(&self, magnitude: &isize) -> Result<usize, MagnitudeError> {
         if magnitude == &self.magnitude {
-            return self.value;
+            Ok(self.value)
         } else if magnitude > &self.magnitude {
-            // TODO: test this
             // It's not like it is impossible to calculate size at higher magnitude,
             // but it makes no sense in this context, so this is defensive check against it.
             // (The flow will always choose lowest available magnitude to avoid float caltulations,
             // so this should never be triggered)
-            unreachable!("Calculating size at higher magnitude is blocked");
+            Err(MagnitudeError::TooBig(*magnitude))
         } else {
-            return self.value * 10usize.pow((self.magnitude - magnitude) as u32);
+            let exponent = self
+                .magnitude
+                .checked_sub(*magnitude)
+                .ok_or(MagnitudeError::Overflow)?;
+            self.value
+                .checked_mul(power_of_ten(exponent)?)
+                .ok_or(MagnitudeError::Overflow)
         }
     }
 
@@ -259,22 +552,29 @@ impl Content {
     /// sums up to (maximum capacity) 100.
     ///
     /// For 5pp1, 2pp1 and 3pp1 values will be 5, 2 and 3, so they will sum up to 10 (max capacity at magnitude 1).
-    pub fn calculate_capacity(concentration: &Concentration, magnitude: &isize) -> Capacity {
+    pub fn calculate_capacity(
+        concentration: &Concentration,
+        magnitude: &isize,
+    ) -> Result<Capacity, MagnitudeError> {
         match concentration {
             Concentration::PP | Concentration::MF => {
                 if magnitude > &1isize {
-                    unreachable!("Magnitude too big");
+                    return Err(MagnitudeError::TooBig(*magnitude));
                 }
-                Capacity::Absolute(10usize.pow(-(magnitude - 2) as u32))
+                let exponent = 2isize
+                    .checked_sub(*magnitude)
+                    .ok_or(MagnitudeError::Overflow)?;
+                Ok(Capacity::Absolute(power_of_ten(exponent)?))
             }
             Concentration::WV | Concentration::WF | Concentration::RF => {
                 if magnitude > &-1isize {
-                    unreachable!("Magnitude too big");
+                    return Err(MagnitudeError::TooBig(*magnitude));
                 }
-                Capacity::Absolute(10usize.pow(-magnitude as u32))
+                let exponent = magnitude.checked_neg().ok_or(MagnitudeError::Overflow)?;
+                Ok(Capacity::Absolute(power_of_ten(exponent)?))
             }
-            Concentration::VP => Capacity::Relative,
-            Concentration::MR | Concentration::MB => Capacity::Unestimated,
+            Concentration::VP => Ok(Capacity::Relative),
+            Concentration::MR | Concentration::MB => Ok(Capacity::Unestimated),
         }
     }
 
@@ -289,9 +589,68 @@ impl Content {
             Concentration::VP => None,
         }
     }
+
+    /// Amount in units of the concentration type - 37wf-2 is 0.37 (mass fraction),
+    /// 17mr-1 is 1.7 (mol/L) and 25wv-3 is 0.025 (g/mL).
+    pub fn quantity(&self) -> f64 {
+        self.value as f64 * 10f64.powf(self.magnitude as f64)
+    }
+
+    /// Convert the content into quantity of other concentration type, see `convert_quantity`.
+    pub fn convert(
+        &self,
+        to: &Concentration,
+        properties: &Properties,
+    ) -> Result<f64, ConversionError> {
+        convert_quantity(self.quantity(), &self.concentration, to, properties)
+    }
+
+    /// Proportion (0.0-1.0) of the content in given capacity, where capacity is expressed at given
+    /// magnitude. Returns None if capacity is zero or magnitudes can't be compared.
+    ///
+    /// This is the single place where contents become fractions, so rendering and any other
+    /// consumer end up with exactly the same proportions.
+    pub fn as_fraction(&self, capacity: usize, magnitude: &isize) -> Option<f64> {
+        if capacity == 0 {
+            return None;
+        }
+        let value = self.value_at_magnitude(magnitude).ok()?;
+        Some((value as f64 / capacity as f64).min(1f64))
+    }
+
+    /// Proportion of the content in the whole, for concentrations with absolute capacity,
+    /// so 37wf-2 is 0.37 and 5pp1 is 0.5. Returns None for relative and unestimated concentrations.
+    pub fn absolute_fraction(&self) -> Option<f64> {
+        let magnitude = match Self::maximum_viable_magnitude(&self.concentration) {
+            Some(max) => self.magnitude.min(max),
+            None => self.magnitude,
+        };
+        match Self::calculate_capacity(&self.concentration, &magnitude).ok()? {
+            Capacity::Absolute(capacity) => self.as_fraction(capacity, &magnitude),
+            Capacity::Relative | Capacity::Unestimated => None,
+        }
+    }
+}
+
+impl fmt::Display for Content {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.range {
+            Some((min, max)) => write!(
+                f,
+                "{}{}:{}{}{}",
+                self.qualifier, min, max, self.concentration, self.magnitude
+            ),
+            None => write!(
+                f,
+                "{}{}{}{}",
+                self.qualifier, self.value, self.concentration, self.magnitude
+            ),
+        }
+    }
 }
 
-fn split_payload(payload: &str) -> Result<(usize, Concentration, isize), String> {
+fn split_payload(payload: &str) -> Result<Content, ParseError> {
+    let whole = Some((0, payload.chars().count()));
     let (concentration, split) = match payload {
         s if s.contains("pp") => (Concentration::PP, payload.split("pp")),
         s if s.contains("wf") => (Concentration::WF, payload.split("wf")),
@@ -302,9 +661,13 @@ fn split_payload(payload: &str) -> Result<(usize, Concentration, isize), String>
         s if s.contains("mr") => (Concentration::MR, payload.split("mr")),
         s if s.contains("mb") => (Concentration::MB, payload.split("mb")),
         _ => {
-            return Err(format!(
-                "Invalid content notation, unrecognized content infix idetifier - {:?}",
-                payload
+            return Err(ParseError::new(
+                format!(
+                    "Invalid content notation, unrecognized content infix idetifier - {:?}",
+                    payload
+                ),
+                payload,
+                whole,
             ))
         }
     };
@@ -312,39 +675,92 @@ fn split_payload(payload: &str) -> Result<(usize, Concentration, isize), String>
     let chunks: Vec<&str> = split.collect();
 
     if chunks.len() != 2 {
-        return Err(format!(
-            "Invalid content notation, too many parts - {:?}",
-            payload
+        return Err(ParseError::new(
+            format!("Invalid content notation, too many parts - {:?}", payload),
+            payload,
+            whole,
         ));
     }
 
-    let value = match chunks[0] {
-        s if s.starts_with("<=") || s.starts_with(">=") => {
-            parse_result!(s[2..].parse::<usize>(), payload)?
-        }
-        s if s.starts_with('~') || s.starts_with('<') || s.starts_with('>') => {
-            parse_result!(s[1..].parse::<usize>(), payload)?
-        }
+    // Value is kept as integer with number of decimal places, which are later moved into the
+    // magnitude - 2.5wv-2 becomes 25wv-3.
+    let mut range = None;
+    let (qualifier, value) = match chunks[0] {
+        s if s.starts_with("<=") => (Qualifier::AtMostInclusive, &s[2..]),
+        s if s.starts_with(">=") => (Qualifier::AtLeastInclusive, &s[2..]),
+        s if s.starts_with('<') => (Qualifier::AtMost, &s[1..]),
+        s if s.starts_with('>') => (Qualifier::AtLeast, &s[1..]),
+        s if s.starts_with('~') => (Qualifier::Approx, &s[1..]),
+        s => (Qualifier::Exact, s),
+    };
+    let (value, decimal_places) = match value {
         s if s.contains(":") => {
             let parts: Vec<&str> = s.split(":").collect();
             if parts.len() != 2 {
-                return Err(format!(
-                    "Invalid content notation, too many parts - {:?}",
-                    payload
+                return Err(ParseError::new(
+                    format!("Invalid content notation, too many parts - {:?}", payload),
+                    payload,
+                    whole,
                 ));
             }
 
-            // To be honest first parsing isnt really needed, but lets validate it anyway
-            // TODO: describe why bigger part it taken only
-            parse_result!(parts[0].parse::<usize>(), payload)?;
-            parse_result!(parts[1].parse::<usize>(), payload)?
+            // Second part is used as the value for calculations, whole range is kept aside
+            let (first, first_places) = parse_decimal(parts[0], payload)?;
+            let (value, places) = parse_decimal(parts[1], payload)?;
+            // Both parts must be at the same scale, as they share magnitude
+            let common_places = first_places.max(places);
+            let scale = |value: usize, places: u32| {
+                power_of_ten((common_places - places) as isize)
+                    .ok()
+                    .and_then(|scale| value.checked_mul(scale))
+                    .ok_or(())
+            };
+            let first = parse_result!(scale(first, first_places), payload)?;
+            let value = parse_result!(scale(value, places), payload)?;
+            range = Some((first.min(value), first.max(value)));
+            (value, common_places)
         }
-        s => parse_result!(s.parse::<usize>(), payload)?,
+        s => parse_decimal(s, payload)?,
     };
 
     let magnitude = parse_result!(chunks[1].parse::<isize>(), payload)?;
+    let magnitude = parse_result!(
+        magnitude.checked_sub(decimal_places as isize).ok_or(()),
+        payload
+    )?;
 
-    Ok((value, concentration, magnitude))
+    Ok(Content {
+        value,
+        concentration,
+        magnitude,
+        qualifier,
+        range,
+    })
+}
+
+/// Parse (possibly decimal) value into integer and number of its decimal places, so 2.5 becomes
+/// (25, 1). That way all calculations downstream can stay on integers.
+fn parse_decimal(value: &str, payload: &str) -> Result<(usize, u32), ParseError> {
+    match value.split_once('.') {
+        Some((integer, fraction)) => {
+            if integer.is_empty()
+                || fraction.is_empty()
+                || !fraction.chars().all(|c| c.is_ascii_digit())
+            {
+                return Err(ParseError::new(
+                    format!("Invalid content notation, invalid value - {:?}", payload),
+                    payload,
+                    Some((0, payload.chars().count())),
+                ));
+            }
+            let digits = format!("{}{}", integer, fraction);
+            Ok((
+                parse_result!(digits.parse::<usize>(), payload)?,
+                fraction.len() as u32,
+            ))
+        }
+        None => Ok((parse_result!(value.parse::<usize>(), payload)?, 0)),
+    }
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -363,65 +779,192 @@ pub struct Substance {
 pub enum Ingredient {
     Mixture(Mixture),
     Substance(Substance),
+    /// Marker of ingredient that could not be parsed, only produced in lenient mode.
+    Invalid(ParseError),
 }
 
-pub fn generate_mixture_tree(indexing: &str, concentration: &str) -> Result<Mixture, String> {
+pub fn generate_mixture_tree(indexing: &str, concentration: &str) -> Result<Mixture, ParseError> {
+    let (mixture, mut errors) = generate_mixture_tree_lenient(indexing, concentration);
+    match errors.is_empty() {
+        true => Ok(mixture),
+        false => Err(errors.remove(0)),
+    }
+}
+
+/// Lenient version of `generate_mixture_tree` - instead of failing on first problem it goes
+/// through the whole notation and collects all errors. Returned mixture is partial: ingredients
+/// that could not be parsed are replaced with `Ingredient::Invalid` markers, and if structure of
+/// any of the layers is broken, mixture is empty.
+pub fn generate_mixture_tree_lenient(
+    indexing: &str,
+    concentration: &str,
+) -> (Mixture, Vec<ParseError>) {
+    let mut errors = vec![];
     if indexing.is_empty() {
-        return Err("Empty indexing part, you must pass at least \"/n/\"".to_string());
+        errors.push(ParseError::new(
+            "Empty indexing part, you must pass at least \"/n/\"".to_string(),
+            indexing,
+            None,
+        ));
     }
     if concentration.is_empty() {
-        return Err("Empty concentration part, you must pass at least \"/g/\"".to_string());
-    }
-    let i_tree = tokenize_string(indexing, 'n')?;
-    let c_tree = tokenize_string(concentration, 'g')?;
-    combine_groups(&i_tree, &c_tree)
-}
-
-fn combine_groups(indexing_group: &Group, concentration_group: &Group) -> Result<Mixture, String> {
-    Ok(Mixture {
-        ingredients: combine_components(
-            &indexing_group.components,
-            &concentration_group.components,
-        )?,
-        content: match &concentration_group.value {
-            Some(v) => Some(Content::from_str(&v)?),
-            None => None,
-        },
-    })
+        errors.push(ParseError::new(
+            "Empty concentration part, you must pass at least \"/g/\"".to_string(),
+            concentration,
+            None,
+        ));
+    }
+    if !errors.is_empty() {
+        return (empty_mixture(), errors);
+    }
+
+    let (i_tree, c_tree) = measure(Stage::Tokenization, || {
+        (
+            tokenize_string(indexing, 'n'),
+            tokenize_located(concentration, 'g'),
+        )
+    });
+    let (i_tree, (c_tree, offsets)) = match (i_tree, c_tree) {
+        (Ok(i_tree), Ok(c_tree)) => (i_tree, c_tree),
+        (i_tree, c_tree) => {
+            errors.extend(i_tree.err());
+            errors.extend(c_tree.err());
+            return (empty_mixture(), errors);
+        }
+    };
+
+    let mixture = combine_groups(&i_tree, &c_tree, &mut offsets.iter(), &mut errors);
+    // Content errors are positioned in the concentration layer they come from
+    let errors = errors
+        .into_iter()
+        .map(|e| ParseError {
+            input: concentration.to_string(),
+            ..e
+        })
+        .collect();
+    (mixture, errors)
+}
+
+fn empty_mixture() -> Mixture {
+    Mixture {
+        ingredients: vec![],
+        content: None,
+    }
+}
+
+/// Combine groups of both layers, `offsets` of concentration values are taken in order they
+/// are written in.
+fn combine_groups(
+    indexing_group: &Group,
+    concentration_group: &Group,
+    offsets: &mut Iter<usize>,
+    errors: &mut Vec<ParseError>,
+) -> Mixture {
+    let ingredients = combine_components(
+        &indexing_group.components,
+        &concentration_group.components,
+        offsets,
+        errors,
+    );
+    let content = match &concentration_group.value {
+        Some(v) => {
+            let offset = offsets.next().copied().unwrap_or_default();
+            match Content::from_str(v) {
+                Ok(content) => Some(content),
+                Err(e) => {
+                    errors.push(e.at(offset));
+                    None
+                }
+            }
+        }
+        None => None,
+    };
+    Mixture {
+        ingredients,
+        content,
+    }
+}
+
+/// Number of values (of tokens and groups) in the component.
+fn count_values(component: &Component) -> usize {
+    match component {
+        Component::Token(_) => 1,
+        Component::Group(group) => {
+            group.components.iter().map(count_values).sum::<usize>() + group.value.iter().count()
+        }
+    }
 }
 
 fn combine_components(
     indexing_components: &Vec<Component>,
     concentration_components: &Vec<Component>,
-) -> Result<Vec<Ingredient>, String> {
+    offsets: &mut Iter<usize>,
+    errors: &mut Vec<ParseError>,
+) -> Vec<Ingredient> {
     let mut combined_components = Vec::new();
     if indexing_components.len() != concentration_components.len() {
-        return Err(format!(
-            "Mismatched components, found {} and {} items (\"{}\" and \"{}\")",
-            indexing_components.len(),
-            concentration_components.len(),
-            stringify_group(&Group {
-                components: indexing_components.clone(),
-                value: None
-            }),
-            stringify_group(&Group {
-                components: concentration_components.clone(),
-                value: None
-            }),
+        errors.push(ParseError::new(
+            format!(
+                "Mismatched components, found {} and {} items (\"{}\" and \"{}\")",
+                indexing_components.len(),
+                concentration_components.len(),
+                stringify_group(&Group {
+                    components: indexing_components.clone(),
+                    value: None
+                }),
+                stringify_group(&Group {
+                    components: concentration_components.clone(),
+                    value: None
+                }),
+            ),
+            "",
+            None,
         ));
     }
-    for i in 0..indexing_components.len() {
+    // In case of mismatch, pairs that can be matched are still combined (to find further
+    // errors), the rest is marked as invalid.
+    let common = indexing_components
+        .len()
+        .min(concentration_components.len());
+    for i in 0..common {
         match (&indexing_components[i], &concentration_components[i]) {
             (Component::Token(t1), Component::Token(t2)) => {
-                combined_components.push(Ingredient::Substance(create_substance(t1, t2)?));
+                let offset = offsets.next().copied().unwrap_or_default();
+                combined_components.push(match create_substance(t1, t2, offset) {
+                    Ok(substance) => Ingredient::Substance(substance),
+                    Err(e) => {
+                        errors.push(e.clone());
+                        Ingredient::Invalid(e)
+                    }
+                });
             }
             (Component::Group(g1), Component::Group(g2)) => {
-                combined_components.push(Ingredient::Mixture(combine_groups(g1, g2)?));
+                combined_components
+                    .push(Ingredient::Mixture(combine_groups(g1, g2, offsets, errors)));
+            }
+            (_, concentration) => {
+                // Values of concentration without counterpart are skipped
+                offsets.take(count_values(concentration)).for_each(drop);
+                let error = ParseError::new("Mismatched components, found mixture and substance on corresponding positions in indexing and concentration notation".to_string(), "", None);
+                errors.push(error.clone());
+                combined_components.push(Ingredient::Invalid(error));
             }
-            _ => return Err(format!("Mismatched components, found mixture and substance on corresponding positions in indexing and concentration notation"))
         }
     }
-    Ok(combined_components)
+    for concentration in &concentration_components[common..] {
+        offsets.take(count_values(concentration)).for_each(drop);
+    }
+    let longer = indexing_components
+        .len()
+        .max(concentration_components.len());
+    for _ in common..longer {
+        combined_components.push(Ingredient::Invalid(ParseError::new(
+            "Component without counterpart in indexing or concentration notation".to_string(),
+            "",
+            None,
+        )));
+    }
+    combined_components
 }
 
 fn stringify_group(group: &Group) -> String {
@@ -446,7 +989,11 @@ fn stringify_group(group: &Group) -> String {
     result
 }
 
-fn create_substance(indexing: &Token, concentration: &Token) -> Result<Substance, String> {
+fn create_substance(
+    indexing: &Token,
+    concentration: &Token,
+    offset: usize,
+) -> Result<Substance, ParseError> {
     Ok(Substance {
         index: match &indexing.value {
             c if *c == "".to_string() => None,
@@ -454,7 +1001,336 @@ fn create_substance(indexing: &Token, concentration: &Token) -> Result<Substance
         },
         content: match concentration.value.clone() {
             c if c == "".to_string() => None,
-            _ => Some(Content::from_str(&concentration.value)?),
+            _ => Some(Content::from_str(&concentration.value).map_err(|e| e.at(offset))?),
         },
     })
 }
+
+/// How much (in percent of capacity) sum of contents may exceed capacity, before it is reported.
+/// Some excess is normal, as values in notation are often rounded.
+const SUM_TOLERANCE_PERCENT: usize = 10;
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum WarningKind {
+    /// Content with value of zero.
+    ZeroValue,
+    /// Single content above 100% of its concentration type.
+    ExceedsCapacity,
+    /// Contents of one group sum up far above 100%.
+    SumExceedsCapacity,
+    /// Index pointing to no component of the mixture, drawn as unknown substance.
+    UnknownIndex,
+    /// Range of content, drawn as its single value. Note only (see `Picture::notes`).
+    CollapsedRange,
+    /// Proportions of the mixture can't be estimated, so unknown substance is drawn. Note only
+    /// (see `Picture::notes`).
+    UnestimatedCapacity,
+}
+
+/// Non-fatal problem found in notation. Picture can still be rendered, but it may be misleading.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Check if contents in the mixture make sense - no zero values, no values above 100% and groups
+/// that don't sum up far above 100%.
+pub fn check_contents(mixture: &Mixture) -> Vec<Warning> {
+    let mut warnings = vec![];
+    if let Some(content) = &mixture.content {
+        check_content(content, &mut warnings);
+    }
+    check_ingredients(&mixture.ingredients, &mut warnings);
+    warnings
+}
+
+fn check_ingredients(ingredients: &[Ingredient], warnings: &mut Vec<Warning>) {
+    let mut contents: Vec<&Content> = vec![];
+    for ingredient in ingredients {
+        match ingredient {
+            Ingredient::Mixture(mixture) => {
+                contents.extend(mixture.content.as_ref());
+                check_ingredients(&mixture.ingredients, warnings);
+            }
+            Ingredient::Substance(substance) => contents.extend(substance.content.as_ref()),
+            Ingredient::Invalid(_) => {}
+        }
+    }
+    for content in &contents {
+        check_content(content, warnings);
+    }
+
+    // Sum can be checked only for single concentration type with absolute capacity, other cases
+    // are either unestimated or reported as errors during rendering.
+    let concentration = match contents.first() {
+        Some(content) => &content.concentration,
+        None => return,
+    };
+    if contents.iter().any(|c| &c.concentration != concentration) {
+        return;
+    }
+    let magnitude = match Content::maximum_viable_magnitude(concentration) {
+        Some(max_level) => contents
+            .iter()
+            .map(|c| c.magnitude)
+            .chain([max_level])
+            .min()
+            .unwrap(),
+        None => return,
+    };
+    // Overflows are ignored here, they are errors reported during rendering
+    if let Ok(Capacity::Absolute(capacity)) = Content::calculate_capacity(concentration, &magnitude)
+    {
+        let sum = contents.iter().try_fold(0usize, |sum, c| {
+            c.value_at_magnitude(&magnitude)
+                .ok()
+                .and_then(|value| sum.checked_add(value))
+        });
+        let (sum, limit) = match (sum, capacity.checked_mul(100 + SUM_TOLERANCE_PERCENT)) {
+            (Some(sum), Some(limit)) => (sum as u128, limit as u128),
+            _ => return,
+        };
+        if sum * 100 > limit {
+            warnings.push(Warning {
+                kind: WarningKind::SumExceedsCapacity,
+                message: format!(
+                    "Contents sum up to {}% of {} capacity",
+                    sum * 100 / capacity as u128,
+                    concentration
+                ),
+            });
+        }
+    }
+}
+
+fn check_content(content: &Content, warnings: &mut Vec<Warning>) {
+    if content.value == 0 {
+        warnings.push(Warning {
+            kind: WarningKind::ZeroValue,
+            message: format!("Zero amount of component ({})", content),
+        });
+        return;
+    }
+    let magnitude = match Content::maximum_viable_magnitude(&content.concentration) {
+        Some(max_level) => content.magnitude.min(max_level),
+        None => return,
+    };
+    if let Ok(Capacity::Absolute(capacity)) =
+        Content::calculate_capacity(&content.concentration, &magnitude)
+    {
+        if content
+            .value_at_magnitude(&magnitude)
+            .map_or(true, |value| value > capacity)
+        {
+            warnings.push(Warning {
+                kind: WarningKind::ExceedsCapacity,
+                message: format!("Amount of component exceeds 100% ({})", content),
+            });
+        }
+    }
+}
+
+/// Check that indices in the mixture point to its components (numbered from 1), usually they
+/// don't in malformed MInChI.
+pub fn check_indices(mixture: &Mixture, components: usize) -> Vec<Warning> {
+    let mut warnings = vec![];
+    check_ingredient_indices(&mixture.ingredients, components, &mut warnings);
+    warnings
+}
+
+fn check_ingredient_indices(
+    ingredients: &[Ingredient],
+    components: usize,
+    warnings: &mut Vec<Warning>,
+) {
+    for ingredient in ingredients {
+        match ingredient {
+            Ingredient::Mixture(mixture) => {
+                check_ingredient_indices(&mixture.ingredients, components, warnings)
+            }
+            Ingredient::Substance(Substance {
+                index: Some(index), ..
+            }) if !is_known_index(index, components) => warnings.push(Warning {
+                kind: WarningKind::UnknownIndex,
+                message: format!(
+                    "Index {} points to no component (there are {}), drawn as unknown substance",
+                    index, components
+                ),
+            }),
+            Ingredient::Substance(_) | Ingredient::Invalid(_) => {}
+        }
+    }
+}
+
+/// Find contents given as ranges - only single value of each of them can be drawn.
+pub fn check_ranges(mixture: &Mixture) -> Vec<Warning> {
+    let mut warnings = vec![];
+    check_ingredient_ranges(&mixture.ingredients, &mut warnings);
+    warnings
+}
+
+fn check_ingredient_ranges(ingredients: &[Ingredient], warnings: &mut Vec<Warning>) {
+    for ingredient in ingredients {
+        let content = match ingredient {
+            Ingredient::Mixture(mixture) => {
+                check_ingredient_ranges(&mixture.ingredients, warnings);
+                &mixture.content
+            }
+            Ingredient::Substance(substance) => &substance.content,
+            Ingredient::Invalid(_) => &None,
+        };
+        if let Some(content @ Content { range: Some(_), .. }) = content {
+            warnings.push(Warning {
+                kind: WarningKind::CollapsedRange,
+                message: format!(
+                    "Range ({}) is drawn as single value {}{}{}",
+                    content, content.value, content.concentration, content.magnitude
+                ),
+            });
+        }
+    }
+}
+
+/// Whether index points to one of components, numbered from 1.
+pub fn is_known_index(index: &str, components: usize) -> bool {
+    index
+        .parse::<usize>()
+        .is_ok_and(|index| (1..=components).contains(&index))
+}
+
+/// Physical properties needed to convert between concentration types.
+/// Densities are in g/mL, molar masses in g/mol.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Properties {
+    /// Density of the substance itself.
+    pub density: Option<f64>,
+    /// Molar mass of the substance.
+    pub molar_mass: Option<f64>,
+    /// Density of the whole solution.
+    pub solution_density: Option<f64>,
+    /// Average molar mass of the whole solution.
+    pub solution_molar_mass: Option<f64>,
+}
+
+impl Properties {
+    /// Collect properties of substance and solution from provider.
+    pub fn from_provider(provider: &dyn PropertyProvider, substance: &str, solution: &str) -> Self {
+        Self {
+            density: provider.density(substance),
+            molar_mass: provider.molar_mass(substance),
+            solution_density: provider.density(solution),
+            solution_molar_mass: provider.molar_mass(solution),
+        }
+    }
+}
+
+/// Source of physical data for substances (and solutions), identified by whatever the
+/// implementation understands - InChI, index in mixture or name.
+/// Moleco itself has no such database, so all data is unknown by default.
+pub trait PropertyProvider {
+    fn density(&self, _identifier: &str) -> Option<f64> {
+        None
+    }
+
+    fn molar_mass(&self, _identifier: &str) -> Option<f64> {
+        None
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ConversionError {
+    /// Concentration type can't be converted at all (percentage points and ratios have no
+    /// physical meaning on their own).
+    Unsupported(Concentration),
+    /// Conversion needs property that was not provided.
+    MissingProperty(&'static str),
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConversionError::Unsupported(concentration) => write!(
+                f,
+                "Concentration {:?} can't be converted to other types",
+                concentration
+            ),
+            ConversionError::MissingProperty(property) => {
+                write!(f, "Conversion requires {}, which is unknown", property)
+            }
+        }
+    }
+}
+
+impl From<ConversionError> for String {
+    fn from(error: ConversionError) -> Self {
+        error.to_string()
+    }
+}
+
+fn require(value: Option<f64>, property: &'static str) -> Result<f64, ConversionError> {
+    value.ok_or(ConversionError::MissingProperty(property))
+}
+
+/// Convert quantity (see `Content::quantity`) between concentration types.
+///
+/// Every conversion goes through mass fraction, so only properties needed for both steps are
+/// required - wf to wv needs density of solution, wf to rf needs density of the substance as well,
+/// mf and mr need molar mass. Molality (mb) assumes the substance is the only solute.
+pub fn convert_quantity(
+    quantity: f64,
+    from: &Concentration,
+    to: &Concentration,
+    properties: &Properties,
+) -> Result<f64, ConversionError> {
+    if from == to {
+        return Ok(quantity);
+    }
+    let mass_fraction = match from {
+        Concentration::WF => quantity,
+        Concentration::WV => quantity / require(properties.solution_density, "solution density")?,
+        Concentration::RF => {
+            quantity * require(properties.density, "density")?
+                / require(properties.solution_density, "solution density")?
+        }
+        Concentration::MF => {
+            quantity * require(properties.molar_mass, "molar mass")?
+                / require(properties.solution_molar_mass, "solution molar mass")?
+        }
+        Concentration::MR => {
+            quantity * require(properties.molar_mass, "molar mass")?
+                / (1000f64 * require(properties.solution_density, "solution density")?)
+        }
+        Concentration::MB => {
+            let solute_per_gram =
+                quantity * require(properties.molar_mass, "molar mass")? / 1000f64;
+            solute_per_gram / (1f64 + solute_per_gram)
+        }
+        Concentration::PP | Concentration::VP => return Err(ConversionError::Unsupported(*from)),
+    };
+    match to {
+        Concentration::WF => Ok(mass_fraction),
+        Concentration::WV => {
+            Ok(mass_fraction * require(properties.solution_density, "solution density")?)
+        }
+        Concentration::RF => Ok(mass_fraction
+            * require(properties.solution_density, "solution density")?
+            / require(properties.density, "density")?),
+        Concentration::MF => Ok(mass_fraction
+            * require(properties.solution_molar_mass, "solution molar mass")?
+            / require(properties.molar_mass, "molar mass")?),
+        Concentration::MR => Ok(mass_fraction
+            * 1000f64
+            * require(properties.solution_density, "solution density")?
+            / require(properties.molar_mass, "molar mass")?),
+        Concentration::MB => Ok(mass_fraction / (1f64 - mass_fraction) * 1000f64
+            / require(properties.molar_mass, "molar mass")?),
+        Concentration::PP | Concentration::VP => Err(ConversionError::Unsupported(*to)),
+    }
+}
@@ -0,0 +1,916 @@
+use crate::formula::molecular_weight;
+use crate::{
+    calculate_scheme_with, composition, contrast_ratio, short_id, srgb_hex, Component, Role,
+    Scheme, SchemeOptions,
+};
+use log::{info, warn};
+use palette::Srgb;
+use prettytable::{row, Cell, Table};
+use std::fs;
+use std::io::Write;
+
+/// Names of built-in formats.
+pub const TABLE: &str = "table";
+pub const JSON: &str = "json";
+pub const YAML: &str = "yaml";
+pub const CSV: &str = "csv";
+pub const TAILWIND: &str = "tailwind";
+pub const MATERIAL: &str = "material";
+pub const DESIGN_TOKENS: &str = "design-tokens";
+
+/// Contrast ratios of scheme colors, in order of `Role::ALL`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contrast {
+    /// Contrast with text (foreground) and with background.
+    pub ratios: [(f64, f64); 4],
+    /// Roles too dark or too light for text of foreground color.
+    pub unsuitable_for_text: Vec<Role>,
+}
+
+/// What is asked of writer of any format.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WriterOptions {
+    /// File to write into, standard output when None.
+    pub output_file: Option<String>,
+    /// Molecular weight is written (when it could be calculated).
+    pub with_mw: bool,
+    /// Short identifier of substance is written.
+    pub with_id: bool,
+    /// Contrast ratios are written.
+    pub with_contrast: bool,
+}
+
+/// Output of `calculate` in single format. Molecular weight is passed only when requested, and
+/// only if it could be calculated. The same goes for short identifier of the palette. Contrast
+/// is passed only when requested.
+pub trait Writer {
+    fn write(
+        &mut self,
+        substance: String,
+        palette: Scheme,
+        molecular_weight: Option<f64>,
+        contrast: Option<Contrast>,
+    );
+    /// Components of MInChI, written after the components themselves.
+    fn write_composition(&mut self, mixture: String, components: Vec<Component>);
+    /// Called once, when everything is written.
+    fn flush(&mut self) -> Result<(), String>;
+}
+
+/// Creates writer for given options.
+pub type WriterFactory = Box<dyn Fn(&WriterOptions) -> Result<Box<dyn Writer>, String>>;
+
+struct Format {
+    name: &'static str,
+    description: &'static str,
+    factory: WriterFactory,
+}
+
+/// Output formats of `calculate`, keyed by name. Default registry has all built-in formats, and
+/// crates embedding moleco can add their own (like XML dialect of their LIMS) with `with_writer`.
+pub struct Registry {
+    formats: Vec<Format>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+            .with_writer(
+                TABLE,
+                "Table for terminal, can't be saved to file.",
+                |options| {
+                    if options.output_file.is_some() {
+                        return Err("Output file is not supported for table format.".to_string());
+                    }
+                    Ok(Box::new(TableWriter::new(
+                        options.with_mw,
+                        options.with_id,
+                        options.with_contrast,
+                    )))
+                },
+            )
+            .with_writer(JSON, "Substances keyed by their InChI.", |options| {
+                Ok(Box::new(JsonWriter::new(options.output_file.clone())))
+            })
+            .with_writer(YAML, "Substances keyed by their InChI.", |options| {
+                Ok(Box::new(YamlWriter::new(options.output_file.clone())))
+            })
+            .with_writer(CSV, "Row for each substance.", |options| {
+                Ok(Box::new(CsvWriter::new(
+                    options.output_file.as_deref(),
+                    options.with_mw,
+                    options.with_id,
+                    options.with_contrast,
+                )?))
+            })
+            .with_writer(
+                TAILWIND,
+                "Tailwind config with variants (100 to 900) of scheme colors.",
+                |options| {
+                    let path = options.output_file.clone();
+                    Ok(Box::new(TokensWriter::new(path, Tokens::Tailwind)))
+                },
+            )
+            .with_writer(
+                MATERIAL,
+                "Material palette JSON with variants (100 to 900) of scheme colors.",
+                |options| {
+                    let path = options.output_file.clone();
+                    Ok(Box::new(TokensWriter::new(path, Tokens::Material)))
+                },
+            )
+            .with_writer(
+                DESIGN_TOKENS,
+                "Design tokens of W3C community group, for Figma plugins and token pipelines.",
+                |options| {
+                    let path = options.output_file.clone();
+                    Ok(Box::new(TokensWriter::new(path, Tokens::Design)))
+                },
+            )
+    }
+}
+
+impl Registry {
+    /// Registry without any formats.
+    pub fn new() -> Self {
+        Self { formats: vec![] }
+    }
+
+    /// Add format, replacing format of the same name.
+    pub fn with_writer(
+        mut self,
+        name: &'static str,
+        description: &'static str,
+        factory: impl Fn(&WriterOptions) -> Result<Box<dyn Writer>, String> + 'static,
+    ) -> Self {
+        let format = Format {
+            name,
+            description,
+            factory: Box::new(factory),
+        };
+        match self.formats.iter().position(|format| format.name == name) {
+            Some(position) => self.formats[position] = format,
+            None => self.formats.push(format),
+        }
+        self
+    }
+
+    /// Names of formats with their descriptions, in order they were added.
+    pub fn formats(&self) -> Vec<(&'static str, &'static str)> {
+        self.formats
+            .iter()
+            .map(|format| (format.name, format.description))
+            .collect()
+    }
+
+    /// Writer of the format.
+    pub fn writer(&self, name: &str, options: &WriterOptions) -> Result<Box<dyn Writer>, String> {
+        match self.formats.iter().find(|format| format.name == name) {
+            Some(format) => (format.factory)(options),
+            None => Err(format!("Unknown format {:?}", name)),
+        }
+    }
+}
+
+/// Colors that scheme colors are checked against, when contrast is written. Defaults are black
+/// text on white page and WCAG level AA.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct A11y {
+    pub foreground: Srgb<u8>,
+    pub background: Srgb<u8>,
+    /// Minimal contrast ratio with foreground, colors below it are unsuitable for text.
+    pub min_contrast: f64,
+}
+
+impl Default for A11y {
+    fn default() -> Self {
+        Self {
+            foreground: Srgb::new(0, 0, 0),
+            background: Srgb::new(255, 255, 255),
+            min_contrast: 4.5,
+        }
+    }
+}
+
+impl A11y {
+    pub fn check(&self, palette: &Scheme) -> Contrast {
+        // Two decimal places are enough to compare with WCAG thresholds
+        let round = |ratio: f64| (ratio * 100f64).round() / 100f64;
+        let ratios = palette.colors().map(|color| {
+            (
+                round(contrast_ratio(color.srgb, self.foreground)),
+                round(contrast_ratio(color.srgb, self.background)),
+            )
+        });
+        let unsuitable_for_text = Role::ALL
+            .into_iter()
+            .zip(&ratios)
+            .filter(|(_, (text, _))| *text < self.min_contrast)
+            .map(|(role, _)| role)
+            .collect();
+        Contrast {
+            ratios,
+            unsuitable_for_text,
+        }
+    }
+}
+
+/// How substances are calculated, beyond what is written (see `WriterOptions`).
+#[derive(Clone, Copy, Default)]
+pub struct CalculationOptions<'a> {
+    /// Substances that can't be calculated (or weighed) are skipped and counted, instead of
+    /// failing.
+    pub skip_errors: bool,
+    /// Used when contrast is written.
+    pub a11y: A11y,
+    pub scheme: SchemeOptions<'a>,
+}
+
+/// Output of `calculate` - schemes of substances written by writer of the format from registry,
+/// so crates with formats of their own drive it the same way as moleco does.
+pub struct Calculation<'a> {
+    writer: Box<dyn Writer>,
+    with_mw: bool,
+    with_id: bool,
+    with_contrast: bool,
+    options: CalculationOptions<'a>,
+    skipped: usize,
+}
+
+impl<'a> Calculation<'a> {
+    pub fn new(
+        registry: &Registry,
+        format: &str,
+        writer_options: &WriterOptions,
+        options: CalculationOptions<'a>,
+    ) -> Result<Self, String> {
+        Ok(Self {
+            writer: registry.writer(format, writer_options)?,
+            with_mw: writer_options.with_mw,
+            with_id: writer_options.with_id,
+            with_contrast: writer_options.with_contrast,
+            options,
+            skipped: 0,
+        })
+    }
+
+    /// Substances left out (or without molecular weight) because of `skip_errors`.
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// Calculate and write the substance. Components of MInChI are written as any other
+    /// substance, followed by composition of the mixture.
+    pub fn write(&mut self, substance: String) -> Result<(), String> {
+        if substance.starts_with("MInChI=") {
+            return self.write_mixture(substance);
+        }
+        if !substance.starts_with("InChI=1S/") && !self.options.skip_errors {
+            return Err(format!(
+                "Only InChI version 1S is supported for now, you may pass flag to skip it. Error received: {}",
+                substance
+            ));
+        }
+
+        if substance.starts_with("InChI=") {
+            let weight = if self.with_mw {
+                match molecular_weight(&substance) {
+                    Ok(weight) => Some(weight),
+                    Err(message) if self.options.skip_errors => {
+                        warn!("{}", message);
+                        self.skipped += 1;
+                        None
+                    }
+                    Err(message) => return Err(message),
+                }
+            } else {
+                None
+            };
+            let palette = calculate_scheme_with(substance.clone(), self.options.scheme);
+            let palette = match self.with_id {
+                true => palette,
+                false => palette.with_short_id(None),
+            };
+            let contrast = self
+                .with_contrast
+                .then(|| self.options.a11y.check(&palette));
+            self.writer.write(substance, palette, weight, contrast);
+        } else {
+            if !self.options.skip_errors {
+                return Err(format!(
+                    "No InChI provided, only payload starting with 'InChI=' or 'MInChI=' is supported for calculation. Error source: {}",
+                    substance
+                ));
+            }
+            if !substance.trim().is_empty() {
+                self.skipped += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_mixture(&mut self, substance: String) -> Result<(), String> {
+        let components = match composition(&substance) {
+            Ok(components) => components,
+            Err(message) if self.options.skip_errors => {
+                warn!("{}", message);
+                self.skipped += 1;
+                return Ok(());
+            }
+            Err(message) => return Err(message),
+        };
+        for component in &components {
+            self.write(component.inchi.clone())?;
+        }
+        self.writer.write_composition(substance, components);
+        Ok(())
+    }
+
+    /// Called once, when all substances are written.
+    pub fn flush(&mut self) -> Result<(), String> {
+        self.writer.flush()
+    }
+}
+
+struct TableWriter {
+    table: Table,
+    // Printed below the table of substances, when there are any mixtures
+    compositions: Table,
+    with_mw: bool,
+    with_id: bool,
+}
+impl TableWriter {
+    fn new(with_mw: bool, with_id: bool, with_contrast: bool) -> Self {
+        let mut table = Table::new();
+        let mut header = row![
+            "Substance",
+            "Primary hue",
+            "First accent hue",
+            "Second accent hue",
+            "Complementary hue"
+        ];
+        if with_mw {
+            header.add_cell(Cell::new("Molecular weight"));
+        }
+        if with_id {
+            header.add_cell(Cell::new("Short ID"));
+        }
+        if with_contrast {
+            for title in CONTRAST_HEADER {
+                header.add_cell(Cell::new(title));
+            }
+        }
+        table.add_row(header);
+        let mut compositions = Table::new();
+        compositions.add_row(row![
+            "Mixture",
+            "Index",
+            "InChI",
+            "Concentration",
+            "Fraction"
+        ]);
+        TableWriter {
+            table,
+            compositions,
+            with_mw,
+            with_id,
+        }
+    }
+}
+
+impl Writer for TableWriter {
+    fn write(
+        &mut self,
+        substance: String,
+        palette: Scheme,
+        molecular_weight: Option<f64>,
+        contrast: Option<Contrast>,
+    ) {
+        let mut row = row![substance];
+        for color in palette.colors() {
+            row.add_cell(Cell::new(&color.hue.to_string()));
+        }
+        if self.with_mw {
+            row.add_cell(Cell::new(&format_weight(molecular_weight)));
+        }
+        if self.with_id {
+            row.add_cell(Cell::new(&palette.short_id.unwrap_or_default()));
+        }
+        if let Some(contrast) = contrast {
+            for value in contrast_record(&contrast) {
+                row.add_cell(Cell::new(&value));
+            }
+        }
+        self.table.add_row(row);
+    }
+    fn write_composition(&mut self, mixture: String, components: Vec<Component>) {
+        for component in components {
+            self.compositions.add_row(row![
+                mixture,
+                component.index,
+                component.inchi,
+                component.contents.join(" "),
+                format!("{:.4}", component.fraction)
+            ]);
+        }
+    }
+    fn flush(&mut self) -> Result<(), String> {
+        self.table.printstd();
+        if self.compositions.len() > 1 {
+            self.compositions.printstd();
+        }
+        Ok(())
+    }
+}
+
+/// Version of JSON output, bumped on every incompatible change of its structure. Version 2 added
+/// `mixtures` next to the substances.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// JSON Schema of JSON output, matching `SCHEMA_VERSION`.
+pub fn json_schema() -> serde_json::Value {
+    let hue = serde_json::json!({"type": "integer", "minimum": 0, "maximum": 359});
+    let ratio = serde_json::json!({"type": "number", "minimum": 1, "maximum": 21});
+    let ratios = serde_json::json!({
+        "type": "object",
+        "properties": {"text": ratio, "background": ratio},
+        "required": ["text", "background"],
+        "additionalProperties": false
+    });
+    serde_json::json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "moleco calculate output",
+        "description": "Color schemes of substances, keyed by substance.",
+        "type": "object",
+        "properties": {
+            "schema_version": {"const": SCHEMA_VERSION},
+            "mixtures": {
+                "type": "object",
+                "description": "Components of MInChIs, keyed by MInChI.",
+                "additionalProperties": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "index": {"type": "integer", "minimum": 1},
+                            "inchi": {"type": "string"},
+                            "contents": {"type": "array", "items": {"type": "string"}},
+                            "fraction": {"type": "number", "minimum": 0, "maximum": 1}
+                        },
+                        "required": ["index", "inchi", "contents", "fraction"],
+                        "additionalProperties": false
+                    }
+                }
+            }
+        },
+        "required": ["schema_version"],
+        "additionalProperties": {
+            "type": "object",
+            "properties": {
+                "primary": hue,
+                "first_accent": hue,
+                "second_accent": hue,
+                "complementary": hue,
+                "molecular_weight": {
+                    "type": "number",
+                    "description": "In g/mol, present only when requested and calculated."
+                },
+                "short_id": {
+                    "type": "string",
+                    "pattern": "^[a-z2-7]{16}$",
+                    "description": "Short identifier of the substance, present only when requested."
+                },
+                "contrast": {
+                    "type": "object",
+                    "description": "WCAG contrast ratios of colors with text and background colors, present only with --a11y.",
+                    "properties": {
+                        "primary": ratios,
+                        "first_accent": ratios,
+                        "second_accent": ratios,
+                        "complementary": ratios,
+                        "unsuitable_for_text": {
+                            "type": "array",
+                            "items": {"enum": Role::ALL.map(|role| role.name())}
+                        }
+                    },
+                    "required": ["primary", "first_accent", "second_accent", "complementary", "unsuitable_for_text"],
+                    "additionalProperties": false
+                }
+            },
+            "required": ["primary", "first_accent", "second_accent", "complementary"],
+            "additionalProperties": false
+        }
+    })
+}
+
+struct JsonWriter {
+    path: Option<String>,
+    doc_root: serde_json::Map<String, serde_json::Value>,
+}
+
+impl JsonWriter {
+    fn new(path: Option<String>) -> Self {
+        let mut doc_root = serde_json::Map::new();
+        doc_root.insert("schema_version".to_string(), SCHEMA_VERSION.into());
+        JsonWriter { path, doc_root }
+    }
+}
+
+impl Writer for JsonWriter {
+    fn write(
+        &mut self,
+        substance: String,
+        palette: Scheme,
+        molecular_weight: Option<f64>,
+        contrast: Option<Contrast>,
+    ) {
+        let mut sub_json = serde_json::Map::new();
+        for (role, color) in palette.iter() {
+            sub_json.insert(role.to_string(), color.hue.into());
+        }
+        if let Some(weight) = molecular_weight {
+            sub_json.insert("molecular_weight".to_string(), round_weight(weight).into());
+        }
+        if let Some(short_id) = palette.short_id {
+            sub_json.insert("short_id".to_string(), short_id.into());
+        }
+        if let Some(contrast) = contrast {
+            sub_json.insert("contrast".to_string(), contrast_json(&contrast));
+        }
+        self.doc_root
+            .insert(substance.to_string(), serde_json::Value::Object(sub_json));
+    }
+    fn write_composition(&mut self, mixture: String, components: Vec<Component>) {
+        let mixtures = self
+            .doc_root
+            .entry("mixtures")
+            .or_insert_with(|| serde_json::Value::Object(serde_json::Map::new()));
+        if let serde_json::Value::Object(mixtures) = mixtures {
+            mixtures.insert(mixture, composition_json(&components));
+        }
+    }
+    fn flush(&mut self) -> Result<(), String> {
+        match self.path {
+            Some(ref path) => {
+                let file = create_file(path)?;
+                serde_json::to_writer_pretty(file, &self.doc_root)
+                    .map_err(|e| format!("Can't write {:?} - {}", path, e))?;
+                info!("Image saved as {}", path);
+            }
+            None => {
+                println!("{}", serde_json::to_string_pretty(&self.doc_root).unwrap());
+            }
+        }
+        Ok(())
+    }
+}
+
+struct YamlWriter {
+    path: Option<String>,
+    doc_root: serde_yaml::Mapping,
+}
+
+impl YamlWriter {
+    fn new(path: Option<String>) -> Self {
+        let doc_root = serde_yaml::Mapping::new();
+        YamlWriter { path, doc_root }
+    }
+}
+
+impl Writer for YamlWriter {
+    fn write(
+        &mut self,
+        substance: String,
+        palette: Scheme,
+        molecular_weight: Option<f64>,
+        contrast: Option<Contrast>,
+    ) {
+        let mut sub_yaml = serde_yaml::Mapping::new();
+        for (role, color) in palette.iter() {
+            sub_yaml.insert(
+                serde_yaml::Value::String(role.to_string()),
+                serde_yaml::Value::Number(serde_yaml::Number::from(color.hue)),
+            );
+        }
+        if let Some(weight) = molecular_weight {
+            sub_yaml.insert(
+                serde_yaml::Value::String("molecular_weight".to_string()),
+                serde_yaml::Value::Number(serde_yaml::Number::from(round_weight(weight))),
+            );
+        }
+        if let Some(short_id) = palette.short_id {
+            sub_yaml.insert(
+                serde_yaml::Value::String("short_id".to_string()),
+                serde_yaml::Value::String(short_id),
+            );
+        }
+        if let Some(contrast) = contrast {
+            sub_yaml.insert(
+                serde_yaml::Value::String("contrast".to_string()),
+                serde_yaml::to_value(contrast_json(&contrast)).unwrap(),
+            );
+        }
+        self.doc_root.insert(
+            serde_yaml::Value::String(substance.to_string()),
+            serde_yaml::Value::Mapping(sub_yaml),
+        );
+    }
+    fn write_composition(&mut self, mixture: String, components: Vec<Component>) {
+        let mixtures = self
+            .doc_root
+            .entry(serde_yaml::Value::String("mixtures".to_string()))
+            .or_insert_with(|| serde_yaml::Value::Mapping(serde_yaml::Mapping::new()));
+        if let serde_yaml::Value::Mapping(mixtures) = mixtures {
+            mixtures.insert(
+                serde_yaml::Value::String(mixture),
+                serde_yaml::to_value(composition_json(&components)).unwrap(),
+            );
+        }
+    }
+    fn flush(&mut self) -> Result<(), String> {
+        match self.path {
+            Some(ref path) => {
+                let file = create_file(path)?;
+                serde_yaml::to_writer(file, &self.doc_root)
+                    .map_err(|e| format!("Can't write {:?} - {}", path, e))?;
+                info!("Image saved as {}", path);
+            }
+            None => {
+                println!("{}", serde_yaml::to_string(&self.doc_root).unwrap());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Flavour of design tokens.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Tokens {
+    Tailwind,
+    Material,
+    /// Format of W3C design tokens community group, with scheme colors only.
+    Design,
+}
+
+/// Number of variants of each color in design tokens, levels 100 to 900.
+const TOKEN_STEPS: usize = 9;
+
+/// Design tokens for frontend toolchains, made of scheme colors (and their variants). Molecular
+/// weight, contrast ratios and composition of mixtures are left out.
+struct TokensWriter {
+    path: Option<String>,
+    tokens: Tokens,
+    colors: serde_json::Map<String, serde_json::Value>,
+}
+
+impl TokensWriter {
+    fn new(path: Option<String>, tokens: Tokens) -> Self {
+        TokensWriter {
+            path,
+            tokens,
+            colors: serde_json::Map::new(),
+        }
+    }
+
+    fn document(&self) -> String {
+        let colors = serde_json::Value::Object(self.colors.clone());
+        match self.tokens {
+            Tokens::Material => serde_json::to_string_pretty(&colors).unwrap(),
+            Tokens::Design => {
+                serde_json::to_string_pretty(&serde_json::json!({"moleco": colors})).unwrap()
+            }
+            Tokens::Tailwind => format!(
+                "module.exports = {};",
+                serde_json::to_string_pretty(
+                    &serde_json::json!({"theme": {"extend": {"colors": colors}}})
+                )
+                .unwrap()
+            ),
+        }
+    }
+}
+
+impl Writer for TokensWriter {
+    fn write(
+        &mut self,
+        substance: String,
+        palette: Scheme,
+        _molecular_weight: Option<f64>,
+        _contrast: Option<Contrast>,
+    ) {
+        if self.tokens == Tokens::Design {
+            let mut group = serde_json::Map::new();
+            group.insert("$type".to_string(), "color".into());
+            group.insert("$description".to_string(), substance.clone().into());
+            for (role, color) in palette.iter() {
+                group.insert(
+                    role.to_string(),
+                    serde_json::json!({"$value": color.to_hex()}),
+                );
+            }
+            self.colors.insert(short_id(&substance), group.into());
+            return;
+        }
+        let variants = palette.variants(TOKEN_STEPS);
+        let mut roles = serde_json::Map::new();
+        for role in Role::ALL {
+            let levels: serde_json::Map<_, _> = variants
+                .iter()
+                .map(|variant| {
+                    (
+                        variant.level.to_string(),
+                        srgb_hex(variant.get(role)).into(),
+                    )
+                })
+                .collect();
+            // Tailwind class names, like `bg-moleco-73vquxf2pgyl4etw-first-accent-100`
+            let name = match self.tokens {
+                Tokens::Tailwind => role.name().replace('_', "-"),
+                _ => role.to_string(),
+            };
+            roles.insert(name, levels.into());
+        }
+        let name = match self.tokens {
+            Tokens::Tailwind => format!("moleco-{}", short_id(&substance)),
+            _ => short_id(&substance),
+        };
+        self.colors.insert(name, roles.into());
+    }
+    fn write_composition(&mut self, _mixture: String, _components: Vec<Component>) {}
+    fn flush(&mut self) -> Result<(), String> {
+        match self.path {
+            Some(ref path) => {
+                let mut file = create_file(path)?;
+                writeln!(file, "{}", self.document())
+                    .map_err(|e| format!("Can't write {:?} - {}", path, e))?;
+                info!("Tokens saved as {}", path);
+            }
+            None => println!("{}", self.document()),
+        }
+        Ok(())
+    }
+}
+
+/// Rows of substances, written as they come. Standard output is used without path.
+struct CsvWriter {
+    output: csv::Writer<Box<dyn Write>>,
+    with_mw: bool,
+    with_id: bool,
+}
+
+impl CsvWriter {
+    fn new(
+        path: Option<&str>,
+        with_mw: bool,
+        with_id: bool,
+        with_contrast: bool,
+    ) -> Result<Self, String> {
+        let output: Box<dyn Write> = match path {
+            Some(path) => Box::new(create_file(path)?),
+            None => Box::new(std::io::stdout()),
+        };
+        let mut output = csv::Writer::from_writer(output);
+        output
+            .write_record(csv_header(with_mw, with_id, with_contrast))
+            .map_err(|e| e.to_string())?;
+        Ok(CsvWriter {
+            output,
+            with_mw,
+            with_id,
+        })
+    }
+}
+
+impl Writer for CsvWriter {
+    fn write(
+        &mut self,
+        substance: String,
+        palette: Scheme,
+        molecular_weight: Option<f64>,
+        contrast: Option<Contrast>,
+    ) {
+        let mut record = vec![substance];
+        record.extend(palette.colors().map(|color| color.hue.to_string()));
+        if self.with_mw {
+            record.push(format_weight(molecular_weight));
+        }
+        if self.with_id {
+            record.push(palette.short_id.unwrap_or_default());
+        }
+        if let Some(contrast) = contrast {
+            record.extend(contrast_record(&contrast));
+        }
+        self.output.write_record(&record).unwrap();
+    }
+    fn write_composition(&mut self, mixture: String, _components: Vec<Component>) {
+        warn_composition_left_out(&mixture);
+    }
+    fn flush(&mut self) -> Result<(), String> {
+        self.output.flush().map_err(|e| e.to_string())
+    }
+}
+
+/// Rows of CSV share the same columns, so there is no place for composition of mixtures.
+fn warn_composition_left_out(mixture: &str) {
+    warn!(
+        "Composition of {} is left out of CSV output, use JSON or YAML for it",
+        mixture
+    );
+}
+
+fn csv_header(with_mw: bool, with_id: bool, with_contrast: bool) -> Vec<&'static str> {
+    let mut header = vec![
+        "Substance",
+        "Primary hue",
+        "First accent hue",
+        "Second accent hue",
+        "Complementary hue",
+    ];
+    if with_mw {
+        header.push("Molecular weight");
+    }
+    if with_id {
+        header.push("Short ID");
+    }
+    if with_contrast {
+        header.extend(CONTRAST_HEADER);
+    }
+    header
+}
+
+/// Columns of `--a11y` in table and CSV output, matching `contrast_record`.
+const CONTRAST_HEADER: [&str; 9] = [
+    "Primary text contrast",
+    "Primary background contrast",
+    "First accent text contrast",
+    "First accent background contrast",
+    "Second accent text contrast",
+    "Second accent background contrast",
+    "Complementary text contrast",
+    "Complementary background contrast",
+    "Unsuitable for text",
+];
+
+fn contrast_record(contrast: &Contrast) -> Vec<String> {
+    let mut record: Vec<String> = contrast
+        .ratios
+        .iter()
+        .flat_map(|(text, background)| [format!("{:.2}", text), format!("{:.2}", background)])
+        .collect();
+    record.push(roles_names(&contrast.unsuitable_for_text).join(" "));
+    record
+}
+
+fn composition_json(components: &[Component]) -> serde_json::Value {
+    components
+        .iter()
+        .map(|component| {
+            serde_json::json!({
+                "index": component.index,
+                "inchi": component.inchi,
+                "contents": component.contents,
+                "fraction": round_fraction(component.fraction),
+            })
+        })
+        .collect()
+}
+
+fn contrast_json(contrast: &Contrast) -> serde_json::Value {
+    let mut json = serde_json::Map::new();
+    for (role, (text, background)) in Role::ALL.iter().zip(&contrast.ratios) {
+        json.insert(
+            role.to_string(),
+            serde_json::json!({"text": text, "background": background}),
+        );
+    }
+    json.insert(
+        "unsuitable_for_text".to_string(),
+        roles_names(&contrast.unsuitable_for_text).into(),
+    );
+    serde_json::Value::Object(json)
+}
+
+/// Names of scheme colors, as in JSON output.
+fn roles_names(roles: &[Role]) -> Vec<&'static str> {
+    roles.iter().map(Role::name).collect()
+}
+
+/// Atomic masses are not more precise anyway.
+fn round_weight(weight: f64) -> f64 {
+    (weight * 1000f64).round() / 1000f64
+}
+
+fn round_fraction(fraction: f32) -> f64 {
+    (fraction as f64 * 10000f64).round() / 10000f64
+}
+
+fn format_weight(molecular_weight: Option<f64>) -> String {
+    match molecular_weight {
+        Some(weight) => format!("{:.3}", weight),
+        None => "".to_string(),
+    }
+}
+
+fn create_file(path: &str) -> Result<fs::File, String> {
+    fs::File::create(path).map_err(|e| format!("Can't create {:?} - {}", path, e))
+}
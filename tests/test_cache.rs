@@ -0,0 +1,47 @@
+#![cfg(feature = "std")]
+use moleco::cache::{clear, write_atomically};
+use std::fs;
+use std::path::PathBuf;
+
+fn temporary_dir(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("moleco-{}-{}", name, std::process::id()))
+}
+
+#[test]
+fn test_clear() {
+    let directory = temporary_dir("clear");
+    write_atomically(&directory.join("first.inchi"), b"InChI=1S/H2O/h1H2").unwrap();
+    write_atomically(&directory.join("nested/second.png"), b"").unwrap();
+    assert_eq!(
+        fs::read_to_string(directory.join("first.inchi")).unwrap(),
+        "InChI=1S/H2O/h1H2"
+    );
+    assert_eq!(clear(&directory), Ok(2));
+    assert!(!directory.exists());
+    assert_eq!(clear(&directory), Ok(0));
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn test_image_cache() {
+    use moleco::cache::ImageCache;
+    use moleco::generate_moleco;
+
+    let directory = temporary_dir("images");
+    let cache = ImageCache::new(&directory);
+    let key = ImageCache::key("InChI=1S/H2O/h1H2", "base size 50");
+    assert_eq!(key.len(), 64);
+    assert_ne!(key, ImageCache::key("InChI=1S/H2O/h1H2", "base size 60"));
+    assert!(cache.get(&key).is_none());
+
+    let picture = generate_moleco("InChI=1S/H2O/h1H2".to_string(), 50, 3, true).unwrap();
+    let buffer = picture.generate().unwrap();
+    cache.put(&key, &buffer).unwrap();
+    let cached = cache.get(&key);
+    // Broken entry is a miss
+    fs::write(cache.path(&key), b"not an image").unwrap();
+    let broken = cache.get(&key);
+    fs::remove_dir_all(&directory).unwrap();
+    assert_eq!(cached, Some(buffer));
+    assert!(broken.is_none());
+}
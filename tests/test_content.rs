@@ -1,31 +1,39 @@
-use moleco::tokenize::{Capacity, Concentration, Content};
+#![cfg(feature = "std")]
+use moleco::tokenize::{
+    check_contents, check_indices, convert_quantity, generate_mixture_tree, Capacity,
+    Concentration, Content, ConversionError, MagnitudeError, Properties, PropertyProvider,
+    WarningKind,
+};
 
 macro_rules! assert_absolute_capacity {
     ($result: expr, $value: expr) => {
-        if let Capacity::Absolute(value) = $result {
+        let result = $result.unwrap();
+        if let Capacity::Absolute(value) = result {
             assert_eq!(value, $value);
         } else {
-            panic!("Expected absolute capacity, got {:?}", $result);
+            panic!("Expected absolute capacity, got {:?}", result);
         }
     };
 }
 
 macro_rules! assert_relative_capacity {
     ($result: expr) => {
-        if let Capacity::Relative = $result {
+        let result = $result.unwrap();
+        if let Capacity::Relative = result {
             // Ok
         } else {
-            panic!("Expected absolute capacity, got {:?}", $result);
+            panic!("Expected absolute capacity, got {:?}", result);
         }
     };
 }
 
 macro_rules! assert_unestimated_capacity {
     ($result: expr) => {
-        if let Capacity::Unestimated = $result {
+        let result = $result.unwrap();
+        if let Capacity::Unestimated = result {
             // Ok
         } else {
-            panic!("Expected absolute capacity, got {:?}", $result);
+            panic!("Expected absolute capacity, got {:?}", result);
         }
     };
 }
@@ -38,7 +46,7 @@ fn test_content_pp_1() {
     assert_eq!(content.value, 6);
     assert_eq!(content.concentration, Concentration::PP);
     assert_eq!(content.magnitude, 1);
-    assert_eq!(content.value_at_magnitude(&0), 60);
+    assert_eq!(content.value_at_magnitude(&0).unwrap(), 60);
 }
 
 #[test]
@@ -68,9 +76,11 @@ fn test_content_capacity_pp_2() {
 }
 
 #[test]
-#[should_panic]
 fn test_content_capacity_pp_3() {
-    Content::calculate_capacity(&Concentration::PP, &2isize);
+    assert_eq!(
+        Content::calculate_capacity(&Concentration::PP, &2isize),
+        Err(MagnitudeError::TooBig(2))
+    );
 }
 
 #[test]
@@ -89,7 +99,7 @@ fn test_content_wv_1() {
     assert_eq!(content.value, 25);
     assert_eq!(content.concentration, Concentration::WV);
     assert_eq!(content.magnitude, -2);
-    assert_eq!(content.value_at_magnitude(&-3), 250);
+    assert_eq!(content.value_at_magnitude(&-3).unwrap(), 250);
 }
 
 #[test]
@@ -118,9 +128,11 @@ fn test_content_capacity_wv_2() {
 }
 
 #[test]
-#[should_panic]
 fn test_content_capacity_wv_3() {
-    Content::calculate_capacity(&Concentration::WV, &0isize);
+    assert_eq!(
+        Content::calculate_capacity(&Concentration::WV, &0isize),
+        Err(MagnitudeError::TooBig(0))
+    );
 }
 
 #[test]
@@ -139,7 +151,7 @@ fn test_content_wf_1() {
     assert_eq!(content.value, 37);
     assert_eq!(content.concentration, Concentration::WF);
     assert_eq!(content.magnitude, -3);
-    assert_eq!(content.value_at_magnitude(&-4), 370);
+    assert_eq!(content.value_at_magnitude(&-4).unwrap(), 370);
 }
 
 #[test]
@@ -168,9 +180,11 @@ fn test_content_capacity_wf_2() {
 }
 
 #[test]
-#[should_panic]
 fn test_content_capacity_wf_3() {
-    Content::calculate_capacity(&Concentration::WF, &0isize);
+    assert_eq!(
+        Content::calculate_capacity(&Concentration::WF, &0isize),
+        Err(MagnitudeError::TooBig(0))
+    );
 }
 
 #[test]
@@ -189,7 +203,7 @@ fn test_content_rf_1() {
     assert_eq!(content.value, 42);
     assert_eq!(content.concentration, Concentration::RF);
     assert_eq!(content.magnitude, -2);
-    assert_eq!(content.value_at_magnitude(&-3), 420);
+    assert_eq!(content.value_at_magnitude(&-3).unwrap(), 420);
 }
 
 #[test]
@@ -218,9 +232,11 @@ fn test_content_capacity_rf_2() {
 }
 
 #[test]
-#[should_panic]
 fn test_content_capacity_rf_3() {
-    Content::calculate_capacity(&Concentration::RF, &0isize);
+    assert_eq!(
+        Content::calculate_capacity(&Concentration::RF, &0isize),
+        Err(MagnitudeError::TooBig(0))
+    );
 }
 
 #[test]
@@ -239,7 +255,7 @@ fn test_content_mf_1() {
     assert_eq!(content.value, 3);
     assert_eq!(content.concentration, Concentration::MF);
     assert_eq!(content.magnitude, 1);
-    assert_eq!(content.value_at_magnitude(&0), 30);
+    assert_eq!(content.value_at_magnitude(&0).unwrap(), 30);
 }
 
 #[test]
@@ -269,9 +285,11 @@ fn test_content_capacity_mf_2() {
 }
 
 #[test]
-#[should_panic]
 fn test_content_capacity_mf_3() {
-    Content::calculate_capacity(&Concentration::MF, &2isize);
+    assert_eq!(
+        Content::calculate_capacity(&Concentration::MF, &2isize),
+        Err(MagnitudeError::TooBig(2))
+    );
 }
 
 //--- VP
@@ -282,7 +300,7 @@ fn test_content_vp_1() {
     assert_eq!(content.value, 5);
     assert_eq!(content.concentration, Concentration::VP);
     assert_eq!(content.magnitude, 3);
-    assert_eq!(content.value_at_magnitude(&0), 5000);
+    assert_eq!(content.value_at_magnitude(&0).unwrap(), 5000);
 }
 
 #[test]
@@ -303,7 +321,7 @@ fn test_content_mr_1() {
     assert_eq!(content.value, 3);
     assert_eq!(content.concentration, Concentration::MR);
     assert_eq!(content.magnitude, 0);
-    assert_eq!(content.value_at_magnitude(&0), 3);
+    assert_eq!(content.value_at_magnitude(&0).unwrap(), 3);
 }
 
 #[test]
@@ -328,7 +346,7 @@ fn test_content_mb_1() {
     assert_eq!(content.value, 3);
     assert_eq!(content.concentration, Concentration::MB);
     assert_eq!(content.magnitude, 0);
-    assert_eq!(content.value_at_magnitude(&0), 3);
+    assert_eq!(content.value_at_magnitude(&0).unwrap(), 3);
 }
 
 #[test]
@@ -344,3 +362,239 @@ fn test_content_capacity_mb_2() {
     assert_unestimated_capacity!(Content::calculate_capacity(&Concentration::MB, &-2isize));
     assert_unestimated_capacity!(Content::calculate_capacity(&Concentration::MB, &-3isize));
 }
+
+//--- Semantic checks
+
+fn warning_kinds(indexing: &str, concentration: &str) -> Vec<WarningKind> {
+    let mixture = generate_mixture_tree(indexing, concentration).unwrap();
+    check_contents(&mixture)
+        .into_iter()
+        .map(|warning| warning.kind)
+        .collect()
+}
+
+#[test]
+fn test_content_display() {
+    for payload in [
+        "37wf-2", "6pp1", "1mr0", "4vp-1", "~5pp0", "<5pp0", ">5pp0", "2:5pp0",
+    ] {
+        assert_eq!(Content::from_str(payload).unwrap().to_string(), payload);
+    }
+}
+
+#[test]
+fn test_check_contents_valid() {
+    assert!(warning_kinds("n{1&2}", "g{37wf-2&}").is_empty());
+    assert!(warning_kinds("n{1&2}", "g{37pp0&63pp0}").is_empty());
+    // Rounding is tolerated
+    assert!(warning_kinds("n{1&2&3}", "g{34pp0&34pp0&34pp0}").is_empty());
+    // Ratios and molar contents have no capacity to exceed
+    assert!(warning_kinds("n{1&2}", "g{370vp0&630vp0}").is_empty());
+    assert!(warning_kinds("n{1&2}", "g{37mr0&63mr0}").is_empty());
+}
+
+#[test]
+fn test_check_indices() {
+    let unknown = |indexing: &str, concentration: &str| {
+        let mixture = generate_mixture_tree(indexing, concentration).unwrap();
+        check_indices(&mixture, 2)
+            .into_iter()
+            .map(|warning| {
+                assert_eq!(warning.kind, WarningKind::UnknownIndex);
+                warning.message
+            })
+            .collect::<Vec<_>>()
+    };
+    assert!(unknown("n{1&2}", "g{&}").is_empty());
+    assert!(unknown("n{{1&2}&1}", "g{{&}&}").is_empty());
+    assert_eq!(
+        unknown("n{{1&3}&0}", "g{{&}&}"),
+        vec![
+            "Index 3 points to no component (there are 2), drawn as unknown substance",
+            "Index 0 points to no component (there are 2), drawn as unknown substance",
+        ]
+    );
+}
+
+#[test]
+fn test_check_contents_zero() {
+    assert_eq!(
+        warning_kinds("n{1&2}", "g{0pp0&}"),
+        vec![WarningKind::ZeroValue]
+    );
+}
+
+#[test]
+fn test_check_contents_exceeding() {
+    assert_eq!(
+        warning_kinds("n{1&2}", "g{15pp1&}"),
+        vec![
+            WarningKind::ExceedsCapacity,
+            WarningKind::SumExceedsCapacity
+        ]
+    );
+    assert_eq!(
+        warning_kinds("n{1&2}", "g{1500wf-3&}"),
+        vec![
+            WarningKind::ExceedsCapacity,
+            WarningKind::SumExceedsCapacity
+        ]
+    );
+}
+
+#[test]
+fn test_check_contents_nested_sum() {
+    assert_eq!(
+        warning_kinds("n{1&{2&3}}", "g{37pp0&{80pp0&40pp0}63pp0}"),
+        vec![WarningKind::SumExceedsCapacity]
+    );
+}
+
+#[test]
+fn test_magnitude_overflow() {
+    let content = Content::from_str("5pp1").unwrap();
+    assert_eq!(
+        content.value_at_magnitude(&-40),
+        Err(MagnitudeError::Overflow)
+    );
+    assert_eq!(
+        content.value_at_magnitude(&isize::MIN),
+        Err(MagnitudeError::Overflow)
+    );
+    assert_eq!(
+        content.value_at_magnitude(&2),
+        Err(MagnitudeError::TooBig(2))
+    );
+    assert_eq!(
+        Content::calculate_capacity(&Concentration::PP, &-40isize),
+        Err(MagnitudeError::Overflow)
+    );
+    assert_eq!(
+        Content::calculate_capacity(&Concentration::WF, &isize::MIN),
+        Err(MagnitudeError::Overflow)
+    );
+}
+
+#[test]
+fn test_extreme_magnitudes_in_notation() {
+    assert!(Content::from_str(&format!("1.5pp{}", isize::MIN)).is_err());
+    assert!(Content::from_str("1:1.00000000000000000001pp0").is_err());
+}
+
+#[test]
+fn test_content_as_fraction() {
+    let content = Content::from_str("37wf-2").unwrap();
+    assert_eq!(content.as_fraction(100, &-2), Some(0.37));
+    assert_eq!(content.as_fraction(1000, &-3), Some(0.37));
+    assert_eq!(content.as_fraction(0, &-2), None);
+    assert_eq!(content.as_fraction(100, &isize::MIN), None);
+    // Proportion never goes over the whole
+    assert_eq!(content.as_fraction(10, &-2), Some(1.0));
+}
+
+#[test]
+fn test_content_absolute_fraction() {
+    assert_eq!(
+        Content::from_str("37wf-2").unwrap().absolute_fraction(),
+        Some(0.37)
+    );
+    assert_eq!(
+        Content::from_str("5pp1").unwrap().absolute_fraction(),
+        Some(0.5)
+    );
+    assert_eq!(
+        Content::from_str("25pp0").unwrap().absolute_fraction(),
+        Some(0.25)
+    );
+    assert_eq!(Content::from_str("3vp0").unwrap().absolute_fraction(), None);
+    assert_eq!(Content::from_str("3mr0").unwrap().absolute_fraction(), None);
+}
+
+fn assert_close(left: f64, right: f64) {
+    assert!((left - right).abs() < 1e-9, "{} != {}", left, right);
+}
+
+#[test]
+fn test_content_quantity() {
+    assert_close(Content::from_str("37wf-2").unwrap().quantity(), 0.37);
+    assert_close(Content::from_str("17mr-1").unwrap().quantity(), 1.7);
+    assert_close(Content::from_str("25wv-3").unwrap().quantity(), 0.025);
+}
+
+#[test]
+fn test_concentration_conversion() {
+    // 37% formaldehyde solution
+    let properties = Properties {
+        density: Some(0.815),
+        molar_mass: Some(30.026),
+        solution_density: Some(1.09),
+        solution_molar_mass: Some(22.4),
+    };
+    let content = Content::from_str("37wf-2").unwrap();
+    assert_close(
+        content.convert(&Concentration::WV, &properties).unwrap(),
+        0.37 * 1.09,
+    );
+    assert_close(
+        content.convert(&Concentration::RF, &properties).unwrap(),
+        0.37 * 1.09 / 0.815,
+    );
+    let molarity = content.convert(&Concentration::MR, &properties).unwrap();
+    assert_close(molarity, 0.37 * 1.09 * 1000.0 / 30.026);
+
+    // Round trips
+    for concentration in [
+        Concentration::WV,
+        Concentration::RF,
+        Concentration::MF,
+        Concentration::MR,
+        Concentration::MB,
+    ] {
+        let converted = content.convert(&concentration, &properties).unwrap();
+        let back =
+            convert_quantity(converted, &concentration, &Concentration::WF, &properties).unwrap();
+        assert_close(back, 0.37);
+    }
+}
+
+#[test]
+fn test_concentration_conversion_errors() {
+    let content = Content::from_str("37wf-2").unwrap();
+    assert_eq!(
+        content.convert(&Concentration::WV, &Properties::default()),
+        Err(ConversionError::MissingProperty("solution density"))
+    );
+    assert_eq!(
+        content.convert(&Concentration::PP, &Properties::default()),
+        Err(ConversionError::Unsupported(Concentration::PP))
+    );
+    assert_eq!(
+        Content::from_str("3vp0")
+            .unwrap()
+            .convert(&Concentration::WF, &Properties::default()),
+        Err(ConversionError::Unsupported(Concentration::VP))
+    );
+    assert_close(
+        content
+            .convert(&Concentration::WF, &Properties::default())
+            .unwrap(),
+        0.37,
+    );
+}
+
+#[test]
+fn test_properties_from_provider() {
+    struct Water;
+    impl PropertyProvider for Water {
+        fn density(&self, identifier: &str) -> Option<f64> {
+            match identifier {
+                "water" => Some(1.0),
+                _ => None,
+            }
+        }
+    }
+    let properties = Properties::from_provider(&Water, "ethanol", "water");
+    assert_eq!(properties.density, None);
+    assert_eq!(properties.molar_mass, None);
+    assert_eq!(properties.solution_density, Some(1.0));
+}
@@ -0,0 +1,103 @@
+use moleco::core::{
+    calculate_hues, hash_modulo, hashed_part, hues_from_hash, shade_from_hash, substance_hash,
+    Analogous, Classic, HueStrategy, Hues, SchemeParams, Shade, Tetradic, Triadic,
+};
+
+#[test]
+fn test_calculate_hues() {
+    let water = Hues {
+        primary: 215,
+        first_accent: 270,
+        second_accent: 60,
+        complementary: 25,
+    };
+    assert_eq!(calculate_hues("InChI=1S/H2O/h1H2"), water);
+    // Molecule of MInChI lacks prefix, but it is the same substance
+    assert_eq!(calculate_hues("1S/H2O/h1H2"), water);
+    assert_eq!(hashed_part("InChI=1S/H2O/h1H2"), "1S/H2O/h1H2");
+}
+
+#[test]
+fn test_hue_strategies() {
+    let strategies: [&dyn HueStrategy; 4] = [&Classic, &Triadic, &Tetradic, &Analogous];
+    // Clockwise distance from primary hue
+    let turn = |primary: u32, hue: u32| (hue + 360 - primary) % 360;
+    for index in 0..200 {
+        let hash = substance_hash(&format!("InChI=1S/C{}H4/c{}", index % 7 + 1, index));
+        for strategy in strategies {
+            let hues = strategy.hues(&hash);
+            assert_eq!(hues, strategy.hues(&hash));
+            assert!(hues.primary < 360);
+            let first_accent = turn(hues.primary, hues.first_accent);
+            let second_accent = turn(hues.primary, hues.second_accent);
+            let complementary = turn(hues.primary, hues.complementary);
+            assert!(first_accent < complementary && complementary <= second_accent);
+        }
+        assert_eq!(Classic.hues(&hash), hues_from_hash(&hash));
+    }
+}
+
+#[test]
+fn test_scheme_params() {
+    let defaults = SchemeParams::default();
+    assert_eq!(SchemeParams::new(165, 30, 5), Ok(defaults));
+    assert!(SchemeParams::new(165, 0, 5).is_err());
+    assert!(SchemeParams::new(10, 30, 5).is_err());
+    assert!(SchemeParams::new(300, 51, 5).is_err());
+    assert!(SchemeParams::new(u32::MAX, u32::MAX, u32::MAX).is_err());
+
+    // Tightest spacing still leaves room for accents
+    let tight = SchemeParams::new(300, 50, 5).unwrap();
+    let narrow = SchemeParams::new(11, 1, 5).unwrap();
+    let turn = |primary: u32, hue: u32| (hue + 360 - primary) % 360;
+    for index in 0..200 {
+        let hash = substance_hash(&format!("InChI=1S/C{}H4/c{}", index % 7 + 1, index));
+        assert_eq!(defaults.hues(&hash), Classic.hues(&hash));
+        for params in [tight, narrow] {
+            let hues = params.hues(&hash);
+            let complementary = turn(hues.primary, hues.complementary);
+            assert!(complementary >= params.complementary_offset());
+            assert!(turn(hues.primary, hues.first_accent) + 10 < complementary);
+            assert!(complementary <= turn(hues.primary, hues.second_accent));
+            assert!(turn(hues.primary, hues.second_accent) < 350);
+        }
+    }
+}
+
+#[test]
+fn test_shade_from_hash() {
+    let mut shades = vec![];
+    for index in 0..200 {
+        let hash = substance_hash(&format!("InChI=1S/C{}H4/c{}", index % 7 + 1, index));
+        let shade = shade_from_hash(&hash);
+        assert_eq!(shade, shade_from_hash(&hash));
+        assert!((55..=85).contains(&shade.saturation));
+        assert!((75..=95).contains(&shade.value));
+        shades.push(shade);
+    }
+    // Substances differ in shade, not only in hues
+    assert!(shades.iter().any(|shade| *shade != shades[0]));
+    assert!(shades.iter().any(|shade| *shade != Shade::default()));
+}
+
+#[test]
+fn test_hash_modulo() {
+    assert_eq!(hash_modulo(&[], 360), 0);
+    assert_eq!(hash_modulo(&[1, 104], 360), 0);
+    assert_eq!(hash_modulo(&[0xff; 64], 1), 0);
+    assert_eq!(hash_modulo(&[0x12, 0x34], 1000), 0x1234 % 1000);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_hash_modulo_matches_big_numbers() {
+    use moleco::core::substance_hash;
+    use moleco::modulo;
+    use num_bigint::BigUint;
+
+    let hash = substance_hash("InChI=1S/CH4O/c1-2/h2H,1H3");
+    let number = BigUint::from_bytes_be(&hash);
+    for divisor in [7, 30, 175, 360, u32::MAX] {
+        assert_eq!(hash_modulo(&hash, divisor), modulo(&number, divisor));
+    }
+}
@@ -0,0 +1,275 @@
+#![cfg(feature = "render")]
+use base64::Engine;
+use moleco::export::{
+    alt_text, data_uri, encode_image, encode_png, encode_png_in_bands, html_img, save_png,
+    Description, FileFormat, PayloadTag, Preset,
+};
+use moleco::generate_moleco;
+use moleco::layouts::{Align, Layer, Shape, Style, Text};
+use palette::Srgba;
+
+/// Hex dump of EXIF, stored in compressed text chunk.
+fn exif_hex(bytes: &[u8]) -> String {
+    let reader = png::Decoder::new(bytes).read_info().unwrap();
+    let chunk = &reader.info().compressed_latin1_text[0];
+    assert_eq!(chunk.keyword, "Raw profile type exif");
+    let mut chunk = chunk.clone();
+    chunk.decompress_text().unwrap();
+    chunk.get_text().unwrap().replace('\n', "")
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode(bytes: &[u8]) -> (Option<png::PixelDimensions>, Vec<u8>) {
+    let mut reader = png::Decoder::new(bytes).read_info().unwrap();
+    let dimensions = reader.info().pixel_dims;
+    let mut data = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut data).unwrap();
+    (dimensions, data)
+}
+
+#[test]
+fn test_png_resolution() {
+    let buffer = generate_moleco("InChI=1S/H2O/h1H2".to_string(), 50, 3, true)
+        .unwrap()
+        .generate()
+        .unwrap();
+
+    let mut bytes = vec![];
+    encode_png(&buffer, &mut bytes, Some("water".into()), None).unwrap();
+    let (dimensions, data) = decode(&bytes);
+    assert!(dimensions.is_none());
+    assert_eq!(data, buffer.as_raw().clone());
+
+    let mut bytes = vec![];
+    encode_png(&buffer, &mut bytes, Some("water".into()), Some(300)).unwrap();
+    let (dimensions, data) = decode(&bytes);
+    let dimensions = dimensions.unwrap();
+    assert_eq!(dimensions.unit, png::Unit::Meter);
+    // 300 DPI is 11811 pixels per meter
+    assert_eq!((dimensions.xppu, dimensions.yppu), (11811, 11811));
+    assert_eq!(data, buffer.as_raw().clone());
+}
+
+#[test]
+fn test_png_metadata() {
+    let buffer = generate_moleco("InChI=1S/H2O/h1H2".to_string(), 50, 3, true)
+        .unwrap()
+        .generate()
+        .unwrap();
+    // EXIF is stored in compressed text chunk
+    let text_chunks = |description| {
+        let mut bytes = vec![];
+        encode_png(&buffer, &mut bytes, description, Some(300)).unwrap();
+        let reader = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+        reader.info().compressed_latin1_text.len()
+    };
+    assert_eq!(text_chunks(Some("water".into())), 1);
+    assert_eq!(text_chunks(None), 0);
+}
+
+#[test]
+fn test_png_in_bands() {
+    // Markers are lines crossing bands
+    let substance = "InChI=1S/C3H7NO2/c1-2(4)3(5)6/h2H,4H2,1H3,(H,5,6)/p-1/t2-/m0/s1";
+    let style = Style {
+        charge_marker: true,
+        stereo_marker: true,
+        corner_radius: 23,
+        ..Style::default()
+    };
+    let picture = generate_moleco(substance.to_string(), 101, 3, true)
+        .unwrap()
+        .with_style(style);
+    let mut scene = picture.to_scene().unwrap();
+    scene
+        .layers
+        .push(Layer::new("text", 1000).with_shapes(vec![Shape::Text(Text {
+            x: scene.width / 2,
+            y: scene.height / 3,
+            size: 30,
+            content: "Ala".to_string(),
+            align: Align::Center,
+            color: Srgba::new(0, 0, 0, 255),
+            max_width: None,
+            shrink: false,
+        })]));
+    let buffer = scene.render();
+
+    for band_height in [1, 7, 64, buffer.height(), buffer.height() + 1] {
+        let mut bytes = vec![];
+        encode_png_in_bands(
+            &scene,
+            &mut bytes,
+            Some(substance.into()),
+            Some(300),
+            band_height,
+        )
+        .unwrap();
+        let (dimensions, data) = decode(&bytes);
+        assert_eq!(dimensions.unwrap().xppu, 11811);
+        assert!(data == *buffer.as_raw(), "band height {}", band_height);
+    }
+    let band_heights: Vec<u32> = scene.bands(64).map(|band| band.height()).collect();
+    let last = buffer.height() - 64 * (band_heights.len() as u32 - 1);
+    assert_eq!(band_heights.last(), Some(&last));
+}
+
+#[test]
+fn test_png_resolution_rounding() {
+    let buffer = image::RgbaImage::new(3, 3);
+    // PNG stores pixels per meter, so value is rounded
+    for (dpi, pixels_per_meter) in [(72, 2835), (96, 3780), (203, 7992), (600, 23622)] {
+        let mut bytes = vec![];
+        encode_png(&buffer, &mut bytes, None, Some(dpi)).unwrap();
+        let dimensions = decode(&bytes).0.unwrap();
+        assert_eq!(dimensions.xppu, pixels_per_meter);
+        assert_eq!(dimensions.yppu, pixels_per_meter);
+    }
+}
+
+#[test]
+fn test_png_description() {
+    let substance = "InChI=1S/H2O/h1H2";
+    let buffer = generate_moleco(substance.to_string(), 50, 3, true)
+        .unwrap()
+        .generate()
+        .unwrap();
+    let path = std::env::temp_dir().join("moleco_test_png_description.png");
+    save_png(&buffer, &path, Some(substance.into()), Some(300)).unwrap();
+    let bytes = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let exif = exif_hex(&bytes);
+    assert!(exif.contains(&hex(substance.as_bytes())));
+    let software = format!("moleco {}", env!("CARGO_PKG_VERSION"));
+    assert!(exif.contains(&hex(software.as_bytes())));
+}
+
+#[test]
+fn test_payload_tags() {
+    let buffer = generate_moleco("InChI=1S/H2O/h1H2".to_string(), 50, 3, true)
+        .unwrap()
+        .generate()
+        .unwrap();
+    let exif = |text, tag| {
+        let mut bytes = vec![];
+        encode_png(&buffer, &mut bytes, Some(Description { text, tag }), None).unwrap();
+        exif_hex(&bytes)
+    };
+    let utf16 =
+        |text: &str| -> Vec<u8> { text.encode_utf16().flat_map(u16::to_le_bytes).collect() };
+
+    // User comment starts with character code
+    assert!(exif("water", PayloadTag::UserComment).contains(&hex(b"ASCII\0\0\0water")));
+    assert!(exif("wäter", PayloadTag::UserComment).contains(&hex(&[
+        b"UNICODE\0".to_vec(),
+        utf16("wäter")
+    ]
+    .concat())));
+    assert!(
+        exif("wäter", PayloadTag::XpComment).contains(&hex(&[utf16("wäter"), vec![0, 0]].concat()))
+    );
+    assert!(!exif("water", PayloadTag::XpComment).contains(&hex(b"water")));
+}
+
+#[test]
+fn test_file_formats() {
+    assert_eq!(FileFormat::from_path("moleco.png"), Some(FileFormat::Png));
+    assert_eq!(
+        FileFormat::from_path("out/{id}.WEBP"),
+        Some(FileFormat::Webp)
+    );
+    assert_eq!(FileFormat::from_path("/tmp/tmp.x1b2c3"), None);
+    assert_eq!(FileFormat::from_path("-"), None);
+
+    let buffer = generate_moleco("InChI=1S/H2O/h1H2".to_string(), 50, 3, true)
+        .unwrap()
+        .generate()
+        .unwrap();
+    for format in [FileFormat::Png, FileFormat::Webp] {
+        let mut bytes = vec![];
+        encode_image(&buffer, &mut bytes, format, Some("water".into()), Some(300)).unwrap();
+        let decoded = image::load_from_memory(&bytes).unwrap();
+        assert_eq!(
+            image::guess_format(&bytes).unwrap().extensions_str()[0],
+            format.extension()
+        );
+        // Both are lossless
+        assert_eq!(decoded.to_rgba8(), buffer);
+    }
+}
+
+#[test]
+fn test_presets_fit_labels() {
+    let payload = "MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&2}/g{37wf-2&}".to_string();
+    // Height of the label, in inches
+    for (preset, height) in [(Preset::Avery5160, 1f32), (Preset::Thermal2In, 2f32)] {
+        let buffer = generate_moleco(
+            payload.clone(),
+            preset.base_size(),
+            preset.border_size(),
+            true,
+        )
+        .unwrap()
+        .generate()
+        .unwrap();
+        assert!(buffer.height() as f32 / preset.dpi() as f32 <= height);
+    }
+
+    let icon = Preset::Icon;
+    let buffer = generate_moleco(
+        "InChI=1S/H2O/h1H2".to_string(),
+        icon.base_size(),
+        icon.border_size(),
+        true,
+    )
+    .unwrap()
+    .generate()
+    .unwrap();
+    assert_eq!(buffer.dimensions(), (511, 511));
+}
+
+#[test]
+fn test_data_uri() {
+    let buffer = generate_moleco("InChI=1S/H2O/h1H2".to_string(), 50, 3, true)
+        .unwrap()
+        .generate()
+        .unwrap();
+    let uri = data_uri(&buffer, Some("water".into()), Some(300)).unwrap();
+    let encoded = uri.strip_prefix("data:image/png;base64,").unwrap();
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .unwrap();
+
+    let mut expected = vec![];
+    encode_png(&buffer, &mut expected, Some("water".into()), Some(300)).unwrap();
+    assert_eq!(bytes, expected);
+}
+
+#[test]
+fn test_html_img() {
+    let water = "InChI=1S/H2O/h1H2";
+    let buffer = generate_moleco(water.to_string(), 50, 3, true)
+        .unwrap()
+        .generate()
+        .unwrap();
+    assert_eq!(
+        alt_text(water),
+        "Color code 73vquxf2pgyl4etw of InChI=1S/H2O/h1H2"
+    );
+    assert_eq!(
+        html_img(&buffer, water.into(), None).unwrap(),
+        format!(
+            "<img src=\"{}\" width=\"{w}\" height=\"{w}\" alt=\"{}\"/>",
+            data_uri(&buffer, Some(water.into()), None).unwrap(),
+            alt_text(water),
+            w = buffer.width()
+        )
+    );
+    // Substances can't break out of the attribute
+    let img = html_img(&buffer, "a\"><script>".into(), None).unwrap();
+    assert!(img.ends_with(" of a&quot;&gt;&lt;script&gt;\"/>"));
+}
@@ -0,0 +1,100 @@
+#![cfg(feature = "std")]
+use moleco::formula::{
+    atomic_mass, formula_layer, molecular_weight, parse_formula, parse_inchi_formula,
+};
+
+#[test]
+fn test_formula_layer() {
+    assert_eq!(
+        formula_layer("InChI=1S/C8H10N4O2/c1-10-4-9-6-5(10)7(13)12(3)8(14)11(6)2/h4H,1-3H3"),
+        Ok("C8H10N4O2")
+    );
+    assert_eq!(formula_layer("CH2O/c1-2/h1H2"), Ok("CH2O"));
+    assert_eq!(formula_layer("H2O/h1H2"), Ok("H2O"));
+    assert_eq!(formula_layer("InChI=1S/p+1"), Ok(""));
+    assert!(formula_layer("InChI=1S").is_err());
+}
+
+#[test]
+fn test_parse_formula() {
+    let formula =
+        parse_inchi_formula("InChI=1S/C8H10N4O2/c1-10-4-9-6-5(10)7(13)12(3)8(14)11(6)2").unwrap();
+    assert_eq!(formula.parts.len(), 1);
+    assert_eq!(formula.count("C"), Ok(8));
+    assert_eq!(formula.count("H"), Ok(10));
+    assert_eq!(formula.count("N"), Ok(4));
+    assert_eq!(formula.count("O"), Ok(2));
+    assert_eq!(formula.count("Cl"), Ok(0));
+}
+
+#[test]
+fn test_parse_formula_with_parts() {
+    let formula = parse_formula("Ca.H3O4P.2H2O").unwrap();
+    assert_eq!(formula.parts.len(), 3);
+    assert_eq!(formula.parts[2].multiplier, 2);
+    let counts = formula.element_counts().unwrap();
+    assert_eq!(counts["Ca"], 1);
+    assert_eq!(counts["H"], 7);
+    assert_eq!(counts["O"], 6);
+    assert_eq!(counts["P"], 1);
+
+    let formula = parse_formula("C12H26O4S.Na").unwrap();
+    assert_eq!(formula.count("Na"), Ok(1));
+    assert_eq!(formula.count("S"), Ok(1));
+    assert!(parse_formula("").unwrap().parts.is_empty());
+}
+
+#[test]
+fn test_parse_invalid_formula() {
+    for formula in [
+        "C2Xx",
+        "c2H6",
+        "C2H6..O",
+        "0H2O",
+        "C2-H6",
+        "C99999999999999999999999",
+    ] {
+        assert!(parse_formula(formula).is_err(), "{}", formula);
+    }
+}
+
+#[test]
+fn test_formula_too_big() {
+    let formula = parse_inchi_formula("InChI=1S/2000000000000C10000000000").unwrap();
+    assert!(formula.element_counts().is_err());
+    assert!(formula.molecular_weight().is_err());
+    assert!(parse_inchi_formula("InChI=1S/C18446744073709551615C2").is_err());
+    assert!(molecular_weight("InChI=1S/2000000000000C10000000000").is_err());
+}
+
+fn assert_close(left: f64, right: f64) {
+    assert!((left - right).abs() < 1e-3, "{} != {}", left, right);
+}
+
+#[test]
+fn test_molecular_weight() {
+    assert_close(
+        molecular_weight("InChI=1S/C8H10N4O2/c1-10-4-9-6-5(10)7(13)12(3)8(14)11(6)2/h4H,1-3H3")
+            .unwrap(),
+        194.194,
+    );
+    assert_close(molecular_weight("InChI=1S/H2O/h1H2").unwrap(), 18.015);
+    assert_close(
+        molecular_weight("InChI=1S/ClH.Na/h1H;/q;+1/p-1").unwrap(),
+        58.44,
+    );
+    assert_close(molecular_weight("InChI=1S/p+1").unwrap(), 1.008);
+    assert!(molecular_weight("InChI=1S/C2Xx").is_err());
+    assert!(molecular_weight("InChI=1S/H2O/px").is_err());
+    // Labelled substances and mixtures are rejected, instead of weighing them wrong
+    assert!(molecular_weight("InChI=1S/H2O/h1H2/i/hD2").is_err());
+    assert!(molecular_weight("InChI=1S/CH4/h1H4/i1+1").is_err());
+    assert!(molecular_weight("MInChI=0.00.1S/H2O/h1H2/n{1}/g{}").is_err());
+}
+
+#[test]
+fn test_atomic_mass() {
+    assert_eq!(atomic_mass("H"), Some(1.008));
+    assert_eq!(atomic_mass("Og"), Some(294.0));
+    assert_eq!(atomic_mass("Xx"), None);
+}
@@ -1,6 +1,33 @@
-use moleco::{calculate_scheme, modulo};
+#![cfg(feature = "std")]
+use moleco::{
+    calculate_scheme, calculate_scheme_with, canonicalize_minchi, cluster_hues, contrast_ratio,
+    hues_to_srgb, minchi_layers, modulo, normalize_input, short_id, validate, Harmony, SchemeMode,
+    SchemeOptions, SchemeVersion,
+};
+use moleco::{Role, Scheme};
 use num_bigint::BigUint;
 use num_traits::One;
+use palette::{FromColor, Hsv, Srgb};
+
+// Used only by tests of rendering, the rest run without it
+#[cfg(feature = "render")]
+use moleco::layouts::{
+    apportion, z, Align, BarSegment, Blend, CellGeometry, Charge, Geometry, Layer, Layout, Line,
+    MarkPosition, MarkShape, OpenEnd, Pattern, Picture, Rectangle, Scene, Shape, Stereo, Style,
+    Text, Tile,
+};
+#[cfg(feature = "render")]
+use moleco::tokenize::WarningKind;
+#[cfg(feature = "render")]
+use moleco::{composition, detect_markers, generate_moleco, generate_moleco_with};
+#[cfg(feature = "render")]
+use palette::Srgba;
+#[cfg(feature = "render")]
+use std::collections::HashSet;
+#[cfg(feature = "render")]
+use std::sync::Arc;
+#[cfg(feature = "render")]
+use std::thread;
 
 /// Main test to check if the calculate_scheme function works stable.
 /// If values changed, it means the hashing algorithm changed.
@@ -19,7 +46,139 @@ fn test_calculate_scheme() {
     assert_eq!(scheme2.complementary.hue, scheme.complementary.hue);
 }
 
+#[test]
+fn test_color_hex() {
+    let scheme = Scheme::new(0, 120, 240, 180);
+    assert_eq!(scheme.primary.to_hex(), "#e64545");
+    assert_eq!(scheme.first_accent.to_hex(), "#45e645");
+    assert_eq!(scheme.second_accent.to_hex(), "#4545e6");
+
+    #[cfg(feature = "render")]
+    {
+        let payload = "MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&2}/g{37wf-2&}".to_string();
+        let picture = generate_moleco(payload, 50, 3, true).unwrap();
+        // Molecules of MInChI have no prefix
+        let water = calculate_scheme("H2O/h1H2".to_string());
+        assert_eq!(picture.schemes().len(), 2);
+        assert_eq!(
+            picture.schemes()[1].primary.to_hex(),
+            water.primary.to_hex()
+        );
+    }
+}
+
+#[test]
+fn test_color_formatting() {
+    let scheme = Scheme::new(0, 120, 240, 180);
+    assert_eq!(scheme.primary.rgb_tuple(), (0xe6, 0x45, 0x45));
+    assert_eq!(scheme.primary.to_string(), "#e64545");
+    assert_eq!(format!("{:>9}", scheme.second_accent), "  #4545e6");
+    assert_eq!(scheme.to_string(), "#e64545 #45e645 #4545e6 #45e6e6");
+    let hues: Vec<u32> = scheme.colors().iter().map(|color| color.hue).collect();
+    assert_eq!(hues, [0, 120, 240, 180]);
+
+    // Colors are converted from their HSV
+    for color in scheme.colors() {
+        let hsv = color.hsv();
+        assert_eq!(hsv.hue.into_positive_degrees(), color.hue as f32);
+        let srgb: Srgb<u8> = Srgb::from_color(hsv).into_format();
+        assert_eq!(srgb, color.srgb);
+    }
+}
+
+#[test]
+fn test_roles() {
+    let scheme = Scheme::new(0, 120, 240, 180);
+    assert_eq!(scheme.get(Role::FirstAccent).hue, 120);
+    assert_eq!(scheme[Role::Complementary].hue, 180);
+    let roles: Vec<(Role, u32)> = scheme
+        .iter()
+        .map(|(role, color)| (role, color.hue))
+        .collect();
+    assert_eq!(
+        roles,
+        [
+            (Role::Primary, 0),
+            (Role::FirstAccent, 120),
+            (Role::SecondAccent, 240),
+            (Role::Complementary, 180)
+        ]
+    );
+    for (role, color) in Role::ALL.into_iter().zip(scheme.colors()) {
+        assert_eq!(scheme[role].hue, color.hue);
+    }
+    let names: Vec<String> = Role::ALL.iter().map(Role::to_string).collect();
+    assert_eq!(
+        names,
+        ["primary", "first_accent", "second_accent", "complementary"]
+    );
+}
+
+#[test]
+fn test_hues_to_srgb() {
+    let scheme = Scheme::new(0, 119, 241, 359);
+    let colors = hues_to_srgb(&[0, 119, 241, 359]);
+    assert_eq!(
+        colors,
+        [
+            scheme.primary.srgb,
+            scheme.first_accent.srgb,
+            scheme.second_accent.srgb,
+            scheme.complementary.srgb
+        ]
+    );
+    // Hues are converted the same way beyond full circle
+    let convert =
+        |hue: u32| -> Srgb<u8> { Srgb::from_color(Hsv::new(hue as f32, 0.7, 0.9)).into_format() };
+    for hue in [0, 45, 180, 300, 359, 360, 725] {
+        assert_eq!(hues_to_srgb(&[hue])[0], convert(hue));
+    }
+    assert_eq!(hues_to_srgb(&[360]), hues_to_srgb(&[0]));
+}
+
+#[test]
+fn test_short_id() {
+    let caffeine = "InChI=1S/C8H10N4O2/c1-10-4-9-6-5(10)7(13)12(3)8(14)11(6)2/h4H,1-3H3";
+    let id = short_id(caffeine);
+    assert_eq!(id.len(), 16);
+    assert!(id
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || ('2'..='7').contains(&c)));
+    // Same hash as hues come from
+    assert_eq!(
+        calculate_scheme(caffeine.to_string()).short_id,
+        Some(id.clone())
+    );
+    assert_eq!(short_id(&caffeine[6..]), id);
+    assert_ne!(short_id("InChI=1S/H2O/h1H2"), id);
+    assert_eq!(
+        calculate_scheme_with(
+            caffeine.to_string(),
+            SchemeOptions::default().with_mode(SchemeMode::Composition)
+        )
+        .short_id,
+        Some(id)
+    );
+    assert_eq!(Scheme::new(0, 120, 240, 180).short_id, None);
+}
+
 /// Function copied from biguint docs.
+#[test]
+fn test_cluster_hues() {
+    let hues = [100, 355, 5, 200, 108, 116, 300];
+    // Neighbours are close enough, even if whole group is not. Group going over 0 starts at 355.
+    assert_eq!(
+        cluster_hues(&hues, 10),
+        vec![vec![0, 4, 5], vec![3], vec![6], vec![1, 2]]
+    );
+    assert_eq!(cluster_hues(&hues, 8).len(), 5);
+    assert_eq!(cluster_hues(&hues, 0).len(), 7);
+    // Whole wheel is covered
+    assert_eq!(cluster_hues(&[0, 90, 180, 270], 90), vec![vec![0, 1, 2, 3]]);
+    assert_eq!(cluster_hues(&[42, 42], 0), vec![vec![0, 1]]);
+    assert!(cluster_hues(&[], 10).is_empty());
+}
+
 fn fib(n: usize) -> BigUint {
     let mut f0 = BigUint::ZERO;
     let mut f1 = BigUint::one();
@@ -35,3 +194,1251 @@ fn fib(n: usize) -> BigUint {
 fn test_modulo() {
     assert_eq!(modulo(&fib(1000), 511), 119);
 }
+
+#[test]
+fn test_validate() {
+    assert!(validate(
+        "InChI=1S/C8H10N4O2/c1-10-4-9-6-5(10)7(13)12(3)8(14)11(6)2/h4H,1-3H3",
+        true
+    )
+    .errors
+    .is_empty());
+    assert_eq!(validate("InChI=1/CH4/h1H4", true).errors.len(), 1);
+    assert!(validate("InChI=1/CH4/h1H4", false).errors.is_empty());
+    assert_eq!(
+        validate("InChIKey=VNWKTOKETHGBQD-UHFFFAOYSA-N", true)
+            .errors
+            .len(),
+        1
+    );
+
+    let errors = validate(
+        "MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&2}/g{37xx-2&4pp}",
+        true,
+    )
+    .errors;
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].span, Some((48, 54)));
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn test_picture_warnings() {
+    let picture = generate_moleco(
+        "MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&2}/g{37wf-2&}".to_string(),
+        50,
+        1,
+        true,
+    )
+    .unwrap();
+    assert!(picture.warnings().is_empty());
+
+    // Unknown index is drawn as unknown substance
+    let payload = "MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&0}/g{37wf-2&}";
+    let picture = generate_moleco(payload.to_string(), 50, 1, true).unwrap();
+    let kinds: Vec<_> = picture.warnings().iter().map(|w| w.kind.clone()).collect();
+    assert_eq!(kinds, vec![WarningKind::UnknownIndex]);
+    picture.generate().unwrap();
+    assert_eq!(validate(payload, true).warnings, picture.warnings());
+
+    // Simplifications made to draw the mixture are only notes of the picture
+    let kinds = |payload: &str| -> Vec<WarningKind> {
+        let picture = generate_moleco(payload.to_string(), 50, 1, true).unwrap();
+        assert!(validate(payload, true).warnings.is_empty());
+        assert!(picture.warnings().is_empty());
+        picture.notes().into_iter().map(|w| w.kind).collect()
+    };
+    assert_eq!(
+        kinds("MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&2}/g{30:40wf-2&}"),
+        vec![WarningKind::CollapsedRange]
+    );
+    assert_eq!(
+        kinds("MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&2}/g{37vp0&}"),
+        vec![WarningKind::UnestimatedCapacity]
+    );
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn test_composition() {
+    let components =
+        composition("MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&2}/g{37wf-2&}").unwrap();
+    assert_eq!(components.len(), 2);
+    assert_eq!(components[0].index, 1);
+    assert_eq!(components[0].inchi, "InChI=1S/CH2O/c1-2/h1H2");
+    assert_eq!(components[0].contents, vec!["37wf-2"]);
+    assert!((components[0].fraction - 0.37).abs() < 1e-6);
+    assert_eq!(components[1].inchi, "InChI=1S/H2O/h1H2");
+    assert!(components[1].contents.is_empty());
+    assert!((components[1].fraction - 0.63).abs() < 1e-6);
+
+    // Contents are canonical, not as written
+    let components = composition("MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&2}/g{33.3vp0&}");
+    assert_eq!(components.unwrap()[0].contents, vec!["333vp-1"]);
+
+    // Nothing is known without concentration
+    let components = composition("MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&2}/g").unwrap();
+    assert!(components
+        .iter()
+        .all(|component| component.contents.is_empty() && component.fraction == 0f32));
+
+    assert!(composition("MInChI=0.00.1S/CH2O/c1-2/h1H2/n{1}/g{x}").is_err());
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn test_minchi_with_missing_sections() {
+    for payload in [
+        "MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&2}/g/",
+        "MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&2}/g",
+        "MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&2}",
+        "MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{{1&2}&1}/g",
+        "MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2",
+    ] {
+        let picture = generate_moleco(payload.to_string(), 50, 1, true).unwrap();
+        picture.generate().unwrap();
+        assert!(validate(payload, true).errors.is_empty());
+    }
+    assert!(generate_moleco("MInChI=0.00.1S/".to_string(), 50, 1, true).is_err());
+}
+
+#[test]
+fn test_validate_warnings() {
+    let validation = validate(
+        "MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&2}/g{37wf-2&63wf-2}",
+        true,
+    );
+    assert!(validation.errors.is_empty());
+    assert!(validation.warnings.is_empty());
+
+    let validation = validate(
+        "MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&2}/g{37wf-2&93wf-2}",
+        true,
+    );
+    assert!(validation.errors.is_empty());
+    assert_eq!(validation.warnings.len(), 1);
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn test_unknown_style() {
+    let payload = "MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&2}/g{37vp0&}".to_string();
+    let magenta = Srgba::new(255, 0, 255, 255);
+    let count_magenta = |pattern: Pattern| {
+        let style = Style {
+            unknown_color: magenta,
+            unknown_pattern: pattern,
+            ..Default::default()
+        };
+        let buffer = generate_moleco(payload.clone(), 50, 1, true)
+            .unwrap()
+            .with_style(style)
+            .generate()
+            .unwrap();
+        buffer
+            .pixels()
+            .filter(|p| p.0 == [255, 0, 255, 255])
+            .count()
+    };
+    let solid = count_magenta(Pattern::Solid);
+    let hatched = count_magenta(Pattern::Hatched);
+    assert!(solid > 0);
+    assert!(hatched > 0 && hatched < solid);
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn test_open_end_styles() {
+    let payload = "MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&2}/g{37vp0&}".to_string();
+    let render = |open_end: OpenEnd| {
+        let style = Style {
+            open_end,
+            ..Default::default()
+        };
+        generate_moleco(payload.clone(), 50, 3, true)
+            .unwrap()
+            .with_style(style)
+            .generate()
+            .unwrap()
+    };
+    let zigzag = render(OpenEnd::ZigZag);
+    for open_end in [OpenEnd::Fade, OpenEnd::Dotted, OpenEnd::Arrow] {
+        let other = render(open_end);
+        assert_eq!(zigzag.dimensions(), other.dimensions());
+        assert_ne!(zigzag, other, "{:?} looks the same as zig-zag", open_end);
+    }
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn test_extreme_magnitudes() {
+    let payload = "MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&2}/g{5pp1&5pp-40}".to_string();
+    let picture = generate_moleco(payload.clone(), 50, 1, true).unwrap();
+    assert!(picture.generate().is_err());
+    assert!(validate(&payload, true).errors.is_empty());
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn test_composition_scheme_mode() {
+    let chloroform = "InChI=1S/CHCl3/c2-1(3)4/h1H".to_string();
+    let hash = calculate_scheme(chloroform.clone());
+    let same = calculate_scheme_with(
+        chloroform.clone(),
+        SchemeOptions::default().with_mode(SchemeMode::Hash),
+    );
+    assert_eq!(hash.first_accent.hue, same.first_accent.hue);
+    assert_eq!(hash.second_accent.hue, same.second_accent.hue);
+
+    let composition = calculate_scheme_with(
+        chloroform.clone(),
+        SchemeOptions::default().with_mode(SchemeMode::Composition),
+    );
+    assert_eq!(composition.primary.hue, hash.primary.hue);
+    assert_eq!(composition.complementary.hue, hash.complementary.hue);
+    // Accents are closer to green than before
+    let distance = |hue: u32| {
+        let d = (hue + 360 - 120) % 360;
+        d.min(360 - d)
+    };
+    assert!(distance(composition.first_accent.hue) < distance(hash.first_accent.hue));
+    assert!(distance(composition.second_accent.hue) < distance(hash.second_accent.hue));
+    // And it is still deterministic
+    let again = calculate_scheme_with(
+        chloroform,
+        SchemeOptions::default().with_mode(SchemeMode::Composition),
+    );
+    assert_eq!(again.first_accent.hue, composition.first_accent.hue);
+
+    // Nothing to nudge by
+    let water = "InChI=1S/H2O/h1H2".to_string();
+    let hash = calculate_scheme(water.clone());
+    let composition = calculate_scheme_with(
+        water,
+        SchemeOptions::default().with_mode(SchemeMode::Composition),
+    );
+    assert_eq!(hash.first_accent.hue, composition.first_accent.hue);
+    assert_eq!(hash.second_accent.hue, composition.second_accent.hue);
+
+    // Counts too big to add up are treated like unparsable formula
+    let huge = "InChI=1S/2000000000000C10000000000".to_string();
+    let hash = calculate_scheme(huge.clone());
+    let composition = calculate_scheme_with(
+        huge,
+        SchemeOptions::default().with_mode(SchemeMode::Composition),
+    );
+    assert_eq!(hash.first_accent.hue, composition.first_accent.hue);
+
+    let picture = generate_moleco_with(
+        "InChI=1S/CHCl3/c2-1(3)4/h1H".to_string(),
+        50,
+        1,
+        true,
+        SchemeOptions::default().with_mode(SchemeMode::Composition),
+    );
+    assert!(picture.is_ok());
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn test_stereo_marker() {
+    let alanine = "InChI=1S/C3H7NO2/c1-2(4)3(5)6/h2H,4H2,1H3,(H,5,6)/t2-/m0/s1";
+    assert_eq!(detect_markers(alanine).stereo, Some(Stereo::Normal));
+    assert_eq!(
+        detect_markers("InChI=1S/C3H7NO2/c1-2(4)3(5)6/h2H,4H2,1H3,(H,5,6)/t2-/m1/s1").stereo,
+        Some(Stereo::Inverted)
+    );
+    assert_eq!(detect_markers("InChI=1S/H2O/h1H2").stereo, None);
+    assert_eq!(detect_markers("C2H4O2/c1-2(3)4/h1H3,(H,3,4)").stereo, None);
+
+    let render = |stereo_marker: bool| {
+        let style = Style {
+            stereo_marker,
+            ..Default::default()
+        };
+        generate_moleco(alanine.to_string(), 50, 1, true)
+            .unwrap()
+            .with_style(style)
+            .generate()
+            .unwrap()
+    };
+    let plain = render(false);
+    let marked = render(true);
+    assert_eq!(plain.dimensions(), marked.dimensions());
+    assert_ne!(plain, marked);
+    // Marker is drawn outside of the swatch, in transparent corner
+    assert_eq!(plain.get_pixel(5, 5).0[3], 0);
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn test_charge_marker() {
+    assert_eq!(
+        detect_markers("InChI=1S/ClH.Na/h1H;/q;+1/p-1").charge,
+        Some(Charge::Neutralized)
+    );
+    assert_eq!(
+        detect_markers("InChI=1S/H3N/h1H3/p+1").charge,
+        Some(Charge::Positive)
+    );
+    assert_eq!(
+        detect_markers("InChI=1S/C2H4O2/c1-2(3)4/h1H3,(H,3,4)/p-1").charge,
+        Some(Charge::Negative)
+    );
+    assert_eq!(
+        detect_markers("FH2O3P.2Na/c1-5(2,3)4;;/h(H2,2,3,4);;/q;2*+1/p-2").charge,
+        Some(Charge::Neutralized)
+    );
+    assert_eq!(detect_markers("Ca/q+2").charge, Some(Charge::Positive));
+    assert_eq!(detect_markers("InChI=1S/H2O/h1H2").charge, None);
+
+    let payload = "InChI=1S/H3N/h1H3/p+1".to_string();
+    let render = |charge_marker: bool| {
+        let style = Style {
+            charge_marker,
+            ..Default::default()
+        };
+        generate_moleco(payload.clone(), 50, 1, true)
+            .unwrap()
+            .with_style(style)
+            .generate()
+            .unwrap()
+    };
+    assert_ne!(render(false), render(true));
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn test_isotope_marker() {
+    let payload = "InChI=1S/CH4O/c1-2/h2H,1H3/i1D3";
+    assert!(detect_markers(payload).isotopes);
+    assert!(!detect_markers("InChI=1S/CH4O/c1-2/h2H,1H3").isotopes);
+
+    let render = |isotope_marker: bool| {
+        let style = Style {
+            isotope_marker,
+            ..Default::default()
+        };
+        generate_moleco(payload.to_string(), 50, 1, true)
+            .unwrap()
+            .with_style(style)
+            .generate()
+            .unwrap()
+    };
+    assert_ne!(render(false), render(true));
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn test_circle_geometry() {
+    let payload = "MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&2}/g{37wf-2&}".to_string();
+    let render = |geometry: Geometry| {
+        let style = Style {
+            geometry,
+            ..Default::default()
+        };
+        generate_moleco(payload.clone(), 50, 3, true)
+            .unwrap()
+            .with_style(style)
+            .generate()
+            .unwrap()
+    };
+    let kite = render(Geometry::Kite);
+    let circle = render(Geometry::Circle);
+    assert_eq!(kite.dimensions(), circle.dimensions());
+    assert_ne!(kite, circle);
+    // Corners of the cell are empty, but circle covers more of its edges than kite
+    assert_eq!(circle.get_pixel(0, 0).0[3], 0);
+    let (width, height) = (kite.width(), kite.height());
+    let opaque = |image: &image::RgbaImage| {
+        image.pixels().filter(|p| p.0[3] > 0).count() * 100 / (width * height) as usize
+    };
+    assert!(opaque(&circle) > opaque(&kite));
+}
+
+#[cfg(feature = "render")]
+/// Layout filling whole cell with primary color.
+struct SolidLayout;
+
+#[cfg(feature = "render")]
+impl Layout for SolidLayout {
+    fn shapes(&self, scheme: &Scheme, geometry: &CellGeometry) -> Vec<Layer> {
+        let size = geometry.cell_size();
+        vec![
+            Layer::new("colors", z::COLORS).with_shapes(vec![Shape::Rectangle(Rectangle {
+                x: geometry.offset,
+                y: 0,
+                width: size,
+                height: size,
+                color: scheme.primary.srgb.into(),
+                pattern: Pattern::Solid,
+                blend: Blend::Over,
+            })]),
+        ]
+    }
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn test_orientation_mark() {
+    let mark = |orientation_mark: MarkPosition, mark_shape: MarkShape| {
+        let style = Style {
+            orientation_mark,
+            mark_shape,
+            ..Default::default()
+        };
+        let scene = generate_moleco("InChI=1S/H2O/h1H2".to_string(), 50, 3, true)
+            .unwrap()
+            .with_style(style)
+            .to_scene()
+            .unwrap();
+        let details = scene.layers.iter().find(|layer| layer.name == "details");
+        match details.unwrap().shapes.first() {
+            Some(Shape::Line(line)) => (line.x1, line.y1, line.x2, line.y2, line.border_size),
+            shape => panic!("Expected line, got {:?}", shape),
+        }
+    };
+    // Center of the swatch
+    let center = 52;
+
+    let (x1, y1, x2, y2, _) = mark(MarkPosition::Bottom, MarkShape::Line);
+    assert_eq!(y1, y2);
+    assert!(y1 > center);
+    assert!(x1 < center && x2 > center);
+    let (_, top_y, _, _, _) = mark(MarkPosition::Top, MarkShape::Line);
+    assert_eq!(top_y, center * 2 - y1);
+    let (left_x, y1, left_x2, y2, _) = mark(MarkPosition::Left, MarkShape::Line);
+    assert_eq!(left_x, left_x2);
+    assert!(left_x < center);
+    assert!(y1 < center && y2 > center);
+    let (right_x, _, _, _, _) = mark(MarkPosition::Right, MarkShape::Line);
+    assert_eq!(right_x, center * 2 - left_x);
+
+    let (x1, y1, x2, y2, size) = mark(MarkPosition::Left, MarkShape::Dot);
+    assert_eq!((x1, y1), (x2, y2));
+    assert_eq!(x1, left_x);
+    assert!(y1.abs_diff(center) <= 1);
+    assert_eq!(size, 3);
+
+    // Without the mark, the first detail is border of a cutout
+    let (x1, _, x2, _, _) = mark(MarkPosition::None, MarkShape::Line);
+    assert_eq!(x1 + x2, center * 2);
+    assert!(x1 < center);
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn test_frame_and_corners() {
+    let payload = "MInChI=0.00.1S/CH4O/c1-2/h2H,1H3&H2O/h1H2/n{1&2}/g{25wf-2&}".to_string();
+    let plain = generate_moleco(payload.clone(), 50, 3, true).unwrap();
+    let style = Style {
+        background: Srgba::new(255, 255, 255, 255),
+        corner_radius: 10,
+        frame_inset: Some(4),
+        ..Default::default()
+    };
+    let framed = generate_moleco(payload, 50, 3, true)
+        .unwrap()
+        .with_style(style.clone());
+    let image = framed.generate().unwrap();
+    // Frame (1 pixel) with gaps of 4 pixels on both sides
+    assert_eq!(image.dimensions(), (209 + 18, 130 + 18));
+    assert_eq!(image.get_pixel(0, 0).0, [0, 0, 0, 0]);
+    assert_eq!(image.get_pixel(10, 0).0, [255, 255, 255, 255]);
+    assert_eq!(image.get_pixel(0, 74).0, [255, 255, 255, 255]);
+    let border: [u8; 4] = style.border_color.into();
+    assert_eq!(image.get_pixel(4, 74).0, border);
+    assert_eq!(image.get_pixel(209 + 18 - 5, 74).0, border);
+    assert_eq!(image.get_pixel(8, 74).0, [255, 255, 255, 255]);
+
+    // Everything else is moved by the frame
+    for (region, framed) in plain
+        .regions()
+        .unwrap()
+        .iter()
+        .zip(framed.regions().unwrap())
+    {
+        assert_eq!((region.x + 9, region.y + 9), (framed.x, framed.y));
+    }
+    let segments = framed.bar_segments().unwrap();
+    assert_eq!(segments[0].x, plain.bar_segments().unwrap()[0].x + 9);
+
+    // Scene keeps rounded corners
+    let json = serde_json::to_value(framed.to_scene().unwrap()).unwrap();
+    assert_eq!(json["corner_radius"], 10);
+    let restored: Scene = serde_json::from_value(json).unwrap();
+    assert_eq!(restored.render(), image);
+    let mut invalid = restored.clone();
+    invalid.corner_radius = 100;
+    assert!(invalid.validate().is_err());
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn test_parallel_rasterization() {
+    let payload =
+        "MInChI=0.00.1S/C2H6O/c1-2-3/h3H,2H2,1H3&CH4O/c1-2/h2H,1H3&H2O/h1H2/n{1&2&3}/g{40wf-2&20wf-2&}"
+            .to_string();
+    let styles = [
+        Style::default(),
+        Style {
+            corner_radius: 15,
+            frame_inset: Some(3),
+            spacing: 7,
+            stereo_marker: true,
+            ..Default::default()
+        },
+    ];
+    for style in styles {
+        let render = |threads: usize| {
+            generate_moleco(payload.clone(), 61, 3, true)
+                .unwrap()
+                .with_style(style.clone())
+                .with_threads(threads)
+                .generate()
+                .unwrap()
+        };
+        let whole = render(1);
+        // Tiles are composited into the same picture, whatever number of threads renders them
+        for threads in [2, 3, 4, 16] {
+            assert!(render(threads) == whole, "{} threads", threads);
+        }
+    }
+
+    // Shapes crossing edges of tiles, text included
+    let mut scene = generate_moleco(payload, 40, 3, true)
+        .unwrap()
+        .to_scene()
+        .unwrap();
+    scene
+        .layers
+        .push(Layer::new("text", 1000).with_shapes(vec![Shape::Text(Text {
+            x: scene.width / 2,
+            y: 20,
+            size: 40,
+            content: "EtOH".to_string(),
+            align: Align::Center,
+            color: Srgba::new(0, 0, 0, 255),
+            max_width: None,
+            shrink: false,
+        })]));
+    let tiles: Vec<Tile> = (0..scene.width)
+        .step_by(37)
+        .flat_map(|x| {
+            (0..scene.height).step_by(29).map(move |y| Tile {
+                x,
+                y,
+                width: 37,
+                height: 29,
+            })
+        })
+        .collect();
+    assert!(scene.render_tiles(&tiles, 3) == scene.render());
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn test_custom_layout() {
+    let payload = "InChI=1S/H2O/h1H2".to_string();
+    let kite = generate_moleco(payload.clone(), 50, 3, true)
+        .unwrap()
+        .generate()
+        .unwrap();
+    let solid = generate_moleco(payload, 50, 3, true)
+        .unwrap()
+        .with_layout(Box::new(SolidLayout))
+        .generate()
+        .unwrap();
+    assert_eq!(kite.dimensions(), solid.dimensions());
+    assert_ne!(kite, solid);
+    // Whole cell is covered, corners included
+    assert_eq!(solid.get_pixel(0, 0).0[3], 255);
+    assert_eq!(
+        solid.get_pixel(solid.width() - 1, solid.height() - 1).0[3],
+        255
+    );
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn test_picture_shared_between_threads() {
+    let payload = "MInChI=0.00.1S/C2H6O/c1-2-3/h3H,2H2,1H3&H2O/h1H2/n{1&2}/g{40wf-2&}".to_string();
+    let picture = Arc::new(
+        generate_moleco(payload, 50, 3, true)
+            .unwrap()
+            .with_layout(Box::new(SolidLayout)),
+    );
+    let expected = picture.generate().unwrap();
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let picture = Arc::clone(&picture);
+            thread::spawn(move || picture.generate().unwrap())
+        })
+        .collect();
+    for handle in handles {
+        assert!(handle.join().unwrap() == expected);
+    }
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn test_scene_export() {
+    let payload = "MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&2}/g{37wf-2&}".to_string();
+    let picture = generate_moleco(payload, 50, 3, true).unwrap();
+    let scene = picture.to_scene().unwrap();
+    let image = picture.generate().unwrap();
+    assert_eq!((scene.width, scene.height), image.dimensions());
+    assert_eq!(scene.render(), image);
+
+    let json = serde_json::to_value(&scene).unwrap();
+    assert_eq!(json["layers"][0]["name"], "bar");
+    assert_eq!(json["layers"][0]["z"], z::BAR);
+    assert_eq!(json["layers"][0]["shapes"][0]["type"], "rectangle");
+    assert_eq!(json["layers"][0]["shapes"][0]["pattern"], "solid");
+    assert_eq!(json["background"]["alpha"], 0);
+    // Serialized scene has everything to render the same image
+    let restored: Scene = serde_json::from_value(json.clone()).unwrap();
+    assert_eq!(restored.render(), image);
+
+    // Older scenes have layers as plain lists of shapes, drawn in order
+    let mut old = json;
+    for layer in old["layers"].as_array_mut().unwrap() {
+        *layer = layer["shapes"].take();
+    }
+    let restored: Scene = serde_json::from_value(old).unwrap();
+    assert!(restored.layers.iter().all(|layer| layer.z == 0));
+    assert_eq!(restored.render(), image);
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn test_layer_order() {
+    let payload = "MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&2}/g{37wf-2&}".to_string();
+    let picture = generate_moleco(payload, 50, 3, true).unwrap();
+    let image = picture.generate().unwrap();
+    let mut scene = picture.to_scene().unwrap();
+    let names: Vec<&str> = scene
+        .layers
+        .iter()
+        .map(|layer| layer.name.as_str())
+        .collect();
+    assert_eq!(&names[..4], ["bar", "bar-lines", "colors", "lines"]);
+
+    // Highlight over the colors of all swatches, but below their lines
+    let highlight =
+        Layer::new("highlight", z::COLORS + 1).with_shapes(vec![Shape::Rectangle(Rectangle {
+            x: 0,
+            y: 0,
+            width: scene.width,
+            height: scene.height,
+            color: Srgba::new(255, 255, 255, 255),
+            pattern: Pattern::Solid,
+            blend: Blend::Over,
+        })]);
+    scene.layers.insert(0, highlight);
+    let ordered: Vec<&str> = scene
+        .ordered_layers()
+        .iter()
+        .map(|layer| layer.name.as_str())
+        .collect();
+    let position = |name: &str| ordered.iter().position(|other| *other == name).unwrap();
+    assert!(position("highlight") > ordered.iter().rposition(|name| *name == "colors").unwrap());
+    assert!(position("highlight") < position("lines"));
+
+    let highlighted = scene.render();
+    // Lines are still visible, only colors are covered
+    let lines = scene
+        .layers
+        .iter()
+        .find(|layer| layer.name == "lines")
+        .unwrap();
+    // Top left border, its end is the top of the kite
+    let Shape::Line(line) = &lines.shapes[2] else {
+        panic!("Expected line, got {:?}", lines.shapes[2]);
+    };
+    assert_eq!(
+        highlighted.get_pixel(line.x2, line.y2).0,
+        <[u8; 4]>::from(line.color)
+    );
+    // Primary color below the top
+    let (x, y) = (line.x2, line.y2 + 10);
+    assert_ne!(image.get_pixel(x, y).0, [255, 255, 255, 255]);
+    assert_eq!(highlighted.get_pixel(x, y).0, [255, 255, 255, 255]);
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn test_scene_import() {
+    let payload = "MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&2}/g{37wf-2&}".to_string();
+    let picture = generate_moleco(payload, 50, 3, true).unwrap();
+    let image = picture.generate().unwrap();
+    let scene = picture.to_scene().unwrap();
+    assert!(scene.validate().is_ok());
+
+    let restored = Picture::from_scene(scene.clone()).unwrap();
+    assert_eq!(restored.generate().unwrap(), image);
+
+    let scaled = Picture::from_scene(scene.scaled(0.5)).unwrap();
+    let scaled = scaled.generate().unwrap();
+    assert_eq!(scaled.width(), (image.width() as f32 / 2f32).round() as u32);
+    assert_eq!(
+        scaled.height(),
+        (image.height() as f32 / 2f32).round() as u32
+    );
+
+    // Hostile sizes are rejected before anything is allocated
+    for (width, height) in [(u32::MAX, 1), (u32::MAX, u32::MAX), (20000, 20000)] {
+        let huge = Scene {
+            width,
+            height,
+            ..scene.clone()
+        };
+        assert!(huge.validate().is_err(), "{}x{}", width, height);
+    }
+
+    let mut scene = scene;
+    scene.layers.push(
+        Layer::new("outside", 0).with_shapes(vec![Shape::Rectangle(Rectangle {
+            x: scene.width + 1,
+            y: 0,
+            width: 1,
+            height: 1,
+            color: Srgba::new(0, 0, 0, 255),
+            pattern: Pattern::Solid,
+            blend: Blend::Over,
+        })]),
+    );
+    assert!(Picture::from_scene(scene).is_err());
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn test_bar_segments() {
+    let payload = "MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&2}/g{37wf-2&}".to_string();
+    let picture = generate_moleco(payload, 50, 3, true).unwrap();
+    let segments = picture.bar_segments().unwrap();
+    assert_eq!(segments.len(), 2);
+    assert_eq!(segments[0].component, Some(1));
+    assert_eq!(segments[1].component, Some(2));
+    assert!((segments[0].fraction - 0.37).abs() < 1e-6);
+    assert!((segments[1].fraction - 0.63).abs() < 1e-6);
+
+    // Segments match rectangles of the bar in the picture
+    let scene = picture.to_scene().unwrap();
+    let rectangles: Vec<(u32, u32)> = scene.layers[0]
+        .shapes
+        .iter()
+        .filter_map(|shape| match shape {
+            Shape::Rectangle(rectangle) => Some((rectangle.x, rectangle.width)),
+            _ => None,
+        })
+        .collect();
+    let expected: Vec<(u32, u32)> = segments.iter().map(|s| (s.x, s.width)).collect();
+    assert_eq!(rectangles, expected);
+
+    // Space for unknown rest of the mixture has no share
+    let payload = "MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&2}/g{37vp0&}".to_string();
+    let segments = generate_moleco(payload, 50, 3, true)
+        .unwrap()
+        .bar_segments()
+        .unwrap();
+    let last = segments.last().unwrap();
+    assert_eq!(last.component, None);
+    assert_eq!(last.fraction, 0f32);
+
+    let water = generate_moleco("InChI=1S/H2O/h1H2".to_string(), 50, 3, true).unwrap();
+    assert!(water.bar_segments().unwrap().is_empty());
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn test_bar_gradient() {
+    let payload = "MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&2}/g{37wf-2&}".to_string();
+    let picture = generate_moleco(payload, 50, 3, true).unwrap();
+    let segments = picture.bar_segments().unwrap();
+    let length = (segments[1].x + segments[1].width - segments[0].x) as f32;
+    let border = (segments[1].x - segments[0].x) as f32 / length * 100.0;
+    assert_eq!(
+        picture.bar_gradient().unwrap().unwrap(),
+        format!(
+            "linear-gradient(to right, {} 0.00% {:.2}%, {} {:.2}% 100.00%)",
+            picture.schemes()[0].primary,
+            border,
+            picture.schemes()[1].primary,
+            border
+        )
+    );
+
+    // Unknown rest of the mixture is drawn in unknown color, translucent one keeps its alpha
+    let payload = "MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&2}/g{37vp0&}".to_string();
+    let style = Style {
+        unknown_color: Srgba::new(0x12, 0x34, 0x56, 0x80),
+        ..Default::default()
+    };
+    let picture = generate_moleco(payload, 50, 3, true)
+        .unwrap()
+        .with_style(style);
+    let gradient = picture.bar_gradient().unwrap().unwrap();
+    assert!(gradient.contains(", #12345680 "));
+    assert!(gradient.ends_with(" 100.00%)"));
+
+    let water = generate_moleco("InChI=1S/H2O/h1H2".to_string(), 50, 3, true).unwrap();
+    assert_eq!(water.bar_gradient().unwrap(), None);
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn test_zero_width_markers() {
+    // Sodium has no room left in the mixture
+    let payload =
+        "MInChI=0.00.1S/CH4O/c1-2/h2H,1H3&H2O/h1H2&Na/n{1&2&3}/g{60wf-2&40wf-2&}".to_string();
+    let picture = generate_moleco(payload.clone(), 50, 3, true).unwrap();
+    let segments = picture.bar_segments().unwrap();
+    assert!(segments.iter().all(|s| s.component != Some(3) && !s.marker));
+
+    let style = Style {
+        zero_width_markers: true,
+        ..Default::default()
+    };
+    let marked = generate_moleco(payload, 50, 3, true)
+        .unwrap()
+        .with_style(style);
+    let marked_segments = marked.bar_segments().unwrap();
+    assert_eq!(marked_segments.len(), segments.len() + 1);
+    // Marker is thin (three borders), goes after known components but before unknown rest
+    let marker = &marked_segments[2];
+    assert_eq!((marker.component, marker.marker), (Some(3), true));
+    assert_eq!(marker.fraction, 0f32);
+    assert_eq!(marker.width, 3);
+    assert_eq!(marked_segments[3].component, None);
+    // Bar keeps its width
+    let end = |segments: &[BarSegment]| segments.last().map(|s| s.x + s.width);
+    assert_eq!(end(&segments), end(&marked_segments));
+
+    let scene = marked.to_scene().unwrap();
+    assert!(scene.layers[0].shapes.iter().any(|shape| matches!(
+        shape,
+        Shape::Rectangle(Rectangle {
+            pattern: Pattern::Hatched,
+            width: 3,
+            ..
+        })
+    )));
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn test_apportion() {
+    assert_eq!(apportion(&[1f32, 1f32, 1f32], 10), vec![4, 3, 3]);
+    assert_eq!(apportion(&[3f32, 1f32], 10), vec![8, 2]);
+    // Largest remainders win, not the first parts
+    assert_eq!(apportion(&[1f32, 2f32, 2f32], 7), vec![1, 3, 3]);
+    assert_eq!(apportion(&[0f32, 0f32], 5), vec![3, 2]);
+    assert_eq!(apportion(&[-1f32, 1f32], 5), vec![0, 5]);
+    assert!(apportion(&[], 5).is_empty());
+
+    // Random weights, from simple linear congruential generator
+    let mut seed: u64 = 42;
+    let mut random = |max: u64| {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (seed >> 33) % max
+    };
+    for _ in 0..1000 {
+        let count = random(12) as usize + 1;
+        let weights: Vec<f32> = (0..count)
+            .map(|_| random(10000) as f32 / 100f32 + 0.01)
+            .collect();
+        let total = random(2000) as u32;
+        let parts = apportion(&weights, total);
+        assert_eq!(parts.iter().sum::<u32>(), total);
+
+        let sum: f64 = weights.iter().map(|w| *w as f64).sum();
+        for (part, weight) in parts.iter().zip(&weights) {
+            let quota = *weight as f64 / sum * total as f64;
+            assert!(
+                (*part as f64 - quota).abs() < 1f64,
+                "{:?} for {:?}",
+                parts,
+                weights
+            );
+        }
+        // Bigger weight never gets less pixels
+        for (a, b) in (0..count).flat_map(|a| (0..count).map(move |b| (a, b))) {
+            if weights[a] > weights[b] {
+                assert!(parts[a] >= parts[b], "{:?} for {:?}", parts, weights);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn test_swatch_order() {
+    let inchi = generate_moleco("InChI=1S/H2O/h1H2".to_string(), 50, 3, true).unwrap();
+    assert_eq!(inchi.swatch_order().unwrap(), vec![0]);
+
+    // Swatches of mixture follow its bar
+    let payload = "MInChI=0.00.1S/CH4O/c1-2/h2H,1H3&H2O/h1H2&Na/n{1&2&3}/g{10wf-2&30wf-2&60wf-2}";
+    let mixture = generate_moleco(payload.to_string(), 50, 3, true).unwrap();
+    let order = mixture.swatch_order().unwrap();
+    let bar: Vec<usize> = mixture
+        .bar_segments()
+        .unwrap()
+        .iter()
+        .filter_map(|s| s.component.map(|c| c - 1))
+        .collect();
+    assert_eq!(order, bar);
+    let mut sorted = order.clone();
+    sorted.sort();
+    assert_eq!(sorted, vec![0, 1, 2]);
+}
+
+#[test]
+fn test_contrast_ratio() {
+    let black = Srgb::new(0, 0, 0);
+    let white = Srgb::new(255, 255, 255);
+    assert_eq!(contrast_ratio(black, white), 21f64);
+    assert_eq!(contrast_ratio(white, black), 21f64);
+    assert_eq!(contrast_ratio(white, white), 1f64);
+    // The lightest gray passing level AA on white
+    let gray = |value| contrast_ratio(Srgb::new(value, value, value), white);
+    assert!(gray(0x76) >= 4.5 && gray(0x77) < 4.5);
+
+    // Swatch colors are bright, black text is readable on most of them
+    let scheme = Scheme::new(60, 120, 240, 300);
+    assert!(contrast_ratio(scheme.primary.srgb, black) > 10f64);
+    assert!(contrast_ratio(scheme.second_accent.srgb, black) < 4.5);
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn test_border_contrast() {
+    let dark_scheme = || {
+        let mut scheme = Scheme::new(260, 20, 200, 80);
+        for color in [
+            &mut scheme.primary,
+            &mut scheme.first_accent,
+            &mut scheme.second_accent,
+            &mut scheme.complementary,
+        ] {
+            color.srgb = Srgb::new(30, 20, 60);
+        }
+        scheme
+    };
+    let bright_scheme = || Scheme::new(60, 120, 240, 300);
+    let border_colors = |style: Style| {
+        let picture =
+            Picture::new(50, 1, vec![dark_scheme(), bright_scheme()], None).with_style(style);
+        (
+            picture.border_color(&dark_scheme()),
+            picture.border_color(&bright_scheme()),
+        )
+    };
+
+    // Fixed border color by default
+    let default = Style::default().border_color;
+    assert_eq!(border_colors(Style::default()), (default, default));
+
+    // Dark swatch gets light border, bright one keeps the default
+    let (dark_border, bright_border) = border_colors(Style {
+        min_border_contrast: Some(3f64),
+        ..Default::default()
+    });
+    assert!(dark_border.red > 200 && dark_border.green > 200 && dark_border.blue > 200);
+    assert_eq!(bright_border, default);
+
+    // Mid gray is fine on dark swatch only
+    let gray = Srgba::new(128, 128, 128, 255);
+    let (dark_border, bright_border) = border_colors(Style {
+        border_color: gray,
+        min_border_contrast: Some(3f64),
+        ..Default::default()
+    });
+    assert_eq!(dark_border, gray);
+    assert!(bright_border.red < 50);
+
+    // Swatch lines are drawn with per swatch color
+    let picture = Picture::new(50, 1, vec![dark_scheme()], None).with_style(Style {
+        min_border_contrast: Some(3f64),
+        ..Default::default()
+    });
+    let scene = picture.to_scene().unwrap();
+    assert!(scene.layers.iter().flat_map(|layer| &layer.shapes).any(
+        |shape| matches!(shape, Shape::Line(line) if line.color == picture.border_color(&dark_scheme()))
+    ));
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn test_blend_modes() {
+    let rectangle = |x: u32, color: Srgba<u8>, blend: Blend| {
+        Shape::Rectangle(Rectangle {
+            x,
+            y: 0,
+            width: 4,
+            height: 4,
+            color,
+            pattern: Pattern::Solid,
+            blend,
+        })
+    };
+    let render = |background: Srgba<u8>| {
+        Scene {
+            width: 8,
+            height: 4,
+            background,
+            layers: vec![
+                Layer::new("colors", 0).with_shapes(vec![rectangle(
+                    0,
+                    Srgba::new(255, 0, 0, 255),
+                    Blend::Over,
+                )]),
+                // Watermark over the left half and the background
+                Layer::new("watermark", 0).with_shapes(vec![rectangle(
+                    2,
+                    Srgba::new(0, 0, 255, 128),
+                    Blend::Over,
+                )]),
+                Layer::new("cutouts", 0).with_shapes(vec![rectangle(
+                    0,
+                    Srgba::new(0, 0, 0, 0),
+                    Blend::Clear,
+                )]),
+            ],
+            corner_radius: 0,
+        }
+        .render()
+    };
+
+    let image = render(Srgba::new(0, 0, 0, 0));
+    // Cleared, even though shape color is transparent
+    assert_eq!(image.get_pixel(1, 1).0, [0, 0, 0, 0]);
+    // Watermark over transparent background keeps its own color
+    assert_eq!(image.get_pixel(5, 1).0, [0, 0, 255, 128]);
+
+    let image = render(Srgba::new(255, 255, 255, 255));
+    // Cleared back to the background
+    assert_eq!(image.get_pixel(1, 1).0, [255, 255, 255, 255]);
+    // Half transparent blue over white
+    assert_eq!(image.get_pixel(5, 1).0, [127, 127, 255, 255]);
+
+    // Translucent line is painted evenly, although brush passes pixels many times
+    let scene = Scene {
+        width: 10,
+        height: 10,
+        background: Srgba::new(255, 255, 255, 255),
+        layers: vec![Layer::new("lines", 0).with_shapes(vec![Shape::Line(Line {
+            x1: 1,
+            y1: 1,
+            x2: 8,
+            y2: 8,
+            border_size: 3,
+            color: Srgba::new(0, 0, 0, 128),
+            blend: Blend::Over,
+        })])],
+        corner_radius: 0,
+    };
+    let image = scene.render();
+    let shades: HashSet<u8> = image
+        .pixels()
+        .filter(|pixel| pixel.0[0] < 255)
+        .map(|pixel| pixel.0[0])
+        .collect();
+    assert_eq!(shades.len(), 1);
+}
+
+#[test]
+fn test_normalize_input() {
+    let water = "InChI=1S/H2O/h1H2";
+    assert_eq!(
+        normalize_input("\u{feff}InChI=1S/H2O/h1H2\r\n", false),
+        water
+    );
+    assert_eq!(normalize_input("  InChI=1S/H2O/h1H2\t", false), water);
+    assert_eq!(
+        normalize_input("InChI%3D1S%2FH2O%2Fh1H2", false),
+        "InChI%3D1S%2FH2O%2Fh1H2"
+    );
+    assert_eq!(
+        normalize_input("InChI%3D1S%2FH2O%2Fh1H2%0D%0A", true),
+        water
+    );
+    // Plus sign is a charge, not encoded space
+    assert_eq!(
+        normalize_input("InChI=1S/H3N/h1H3/p%2B1/q+1", true),
+        "InChI=1S/H3N/h1H3/p+1/q+1"
+    );
+    // Invalid sequences are kept
+    assert_eq!(normalize_input("100%&%zz%4", true), "100%&%zz%4");
+    assert_eq!(normalize_input("/p%+1/q%-1", true), "/p%+1/q%-1");
+    assert_eq!(
+        short_id(&normalize_input("\u{feff}InChI=1S/H2O/h1H2", true)),
+        short_id(water)
+    );
+}
+
+#[test]
+fn test_canonicalize_minchi() {
+    let canonical = "MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&2}/g{37wf-2&}";
+    assert_eq!(canonicalize_minchi(canonical).unwrap(), canonical);
+    assert_eq!(
+        canonicalize_minchi("MInChI=0.00.1S/H2O/h1H2&CH2O/c1-2/h1H2/n{1&2}/g{&37wf-2}").unwrap(),
+        canonical
+    );
+    assert_eq!(
+        canonicalize_minchi("MInChI=0.00.1S/H2O/h1H2&CH2O/c1-2/h1H2/n{2&1}/g{37wf-2&}").unwrap(),
+        canonical
+    );
+    // Nested mixtures are sorted by their lowest index
+    assert_eq!(
+        canonicalize_minchi(
+            "MInChI=0.00.1S/H2O/h1H2&CH4O/c1-2/h2H,1H3&CH2O/c1-2/h1H2/n{{2&1}&3}/g{{10pp0&}50vf-2&}"
+        )
+        .unwrap(),
+        "MInChI=0.00.1S/CH2O/c1-2/h1H2&CH4O/c1-2/h2H,1H3&H2O/h1H2/n{1&{2&3}}/g{&{10pp0&}50vf-2}"
+    );
+    // Empty layers are dropped, indexing is kept when there is concentration
+    assert_eq!(
+        canonicalize_minchi("MInChI=0.00.1S/H2O/h1H2&CH2O/c1-2/h1H2/n/g/").unwrap(),
+        "MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2"
+    );
+    assert_eq!(
+        canonicalize_minchi("MInChI=0.00.1S/H2O/h1H2&CH2O/c1-2/h1H2/g{10pp0&}").unwrap(),
+        "MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&2}/g{&10pp0}"
+    );
+    // Unknown indices stay, and go last
+    assert_eq!(
+        canonicalize_minchi("MInChI=0.00.1S/H2O/h1H2&CH2O/c1-2/h1H2/n{0&1&2}").unwrap(),
+        "MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&2&0}"
+    );
+    assert_eq!(
+        canonicalize_minchi("InChI=1S/H2O/h1H2").unwrap(),
+        "InChI=1S/H2O/h1H2"
+    );
+    assert!(canonicalize_minchi("MInChI=0.00.1S/H2O/h1H2&CH2O/c1-2/h1H2/n{1&2}/g{&}}").is_err());
+    assert!(canonicalize_minchi("MInChI=0.00.1S/H2O/h1H2&CH2O/c1-2/h1H2/n{1&2}/g{&&}").is_err());
+}
+
+#[test]
+fn test_minchi_layers() {
+    let layers = minchi_layers("MInChI=0.00.1S/H2O/h1H2&CH2O/c1-2/h1H2/n{1&2}/g/").unwrap();
+    assert_eq!(layers.molecules, vec!["H2O/h1H2", "CH2O/c1-2/h1H2"]);
+    assert_eq!(layers.indexing, Some("n{1&2}"));
+    assert_eq!(layers.concentration, None);
+    assert!(minchi_layers("MInChI=0.00.1S").is_err());
+}
+
+#[test]
+fn test_harmony() {
+    let water = "InChI=1S/H2O/h1H2".to_string();
+    let classic = calculate_scheme_with(
+        water.clone(),
+        SchemeOptions::default().with_strategy(Harmony::Classic.strategy()),
+    );
+    assert_eq!(
+        classic.to_string(),
+        calculate_scheme(water.clone()).to_string()
+    );
+
+    let triadic = calculate_scheme_with(
+        water.clone(),
+        SchemeOptions::default().with_strategy(Harmony::Triadic.strategy()),
+    );
+    // Primary hue is the same, only its relationship with others differs
+    assert_eq!(triadic.primary.hue, classic.primary.hue);
+    let turn = (triadic.first_accent.hue + 360 - triadic.primary.hue) % 360;
+    assert!((110..=130).contains(&turn));
+
+    // Composition nudges accents of other strategies too
+    let chloroform = "InChI=1S/CHCl3/c2-1(3)4/h1H".to_string();
+    let analogous = SchemeOptions::default().with_strategy(Harmony::Analogous.strategy());
+    let hash = calculate_scheme_with(chloroform.clone(), analogous);
+    let composition = calculate_scheme_with(
+        chloroform.clone(),
+        analogous.with_mode(SchemeMode::Composition),
+    );
+    assert_eq!(composition.primary.hue, hash.primary.hue);
+    assert_ne!(composition.to_string(), hash.to_string());
+
+    #[cfg(feature = "render")]
+    {
+        let picture = generate_moleco_with(
+            chloroform,
+            50,
+            1,
+            true,
+            SchemeOptions::default()
+                .with_mode(SchemeMode::Composition)
+                .with_strategy(Harmony::Tetradic.strategy()),
+        );
+        assert!(picture.is_ok());
+    }
+}
+
+#[test]
+fn test_scheme_version() {
+    let water = "InChI=1S/H2O/h1H2".to_string();
+    let first = calculate_scheme_with(
+        water.clone(),
+        SchemeOptions::default().with_version(SchemeVersion::V1),
+    );
+    assert_eq!(
+        first.to_string(),
+        calculate_scheme(water.clone()).to_string()
+    );
+
+    let version_2 = SchemeOptions::default().with_version(SchemeVersion::V2);
+    let second = calculate_scheme_with(water.clone(), version_2);
+    assert_ne!(second.to_string(), first.to_string());
+    for ((_, old), (_, new)) in first.iter().zip(second.iter()) {
+        assert_eq!(old.hue, new.hue);
+        assert_eq!(new.shade, second.primary.shade);
+        let srgb: Srgb<u8> = Srgb::from_color(new.hsv()).into_format();
+        assert_eq!(srgb, new.srgb);
+    }
+    assert_eq!(second.short_id, first.short_id);
+
+    // Nudged accents keep the shade
+    let chloroform = "InChI=1S/CHCl3/c2-1(3)4/h1H".to_string();
+    let hash = calculate_scheme_with(chloroform.clone(), version_2);
+    let composition = calculate_scheme_with(
+        chloroform.clone(),
+        version_2.with_mode(SchemeMode::Composition),
+    );
+    assert_ne!(composition.first_accent.hue, hash.first_accent.hue);
+    assert_eq!(composition.first_accent.shade, hash.first_accent.shade);
+
+    #[cfg(feature = "render")]
+    {
+        let picture = generate_moleco_with(chloroform, 50, 1, true, version_2);
+        assert!(picture.is_ok());
+    }
+}
+
+#[test]
+fn test_scheme_variants() {
+    let scheme = Scheme::new(60, 120, 240, 300);
+    assert!(scheme.variants(0).is_empty());
+    let single = scheme.variants(1);
+    assert_eq!(single[0].level, 500);
+    assert_eq!(single[0].get(Role::FirstAccent), scheme.first_accent.srgb);
+
+    let ladder = scheme.variants(9);
+    let levels: Vec<u32> = ladder.iter().map(|variant| variant.level).collect();
+    assert_eq!(levels, [100, 200, 300, 400, 500, 600, 700, 800, 900]);
+    assert_eq!(ladder[4].colors, scheme.colors().map(|color| color.srgb));
+    let white = Srgb::new(255, 255, 255);
+    for role in Role::ALL {
+        // Each step is darker than the previous one
+        for pair in ladder.windows(2) {
+            assert!(
+                contrast_ratio(pair[0].get(role), white) < contrast_ratio(pair[1].get(role), white)
+            );
+        }
+        // Lightest variant works as background of dark text
+        assert!(contrast_ratio(ladder[0].get(role), white) < 1.2);
+    }
+    assert_eq!(scheme.variants(3)[1].colors, ladder[4].colors);
+}
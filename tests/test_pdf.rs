@@ -0,0 +1,65 @@
+#![cfg(feature = "render")]
+use image::{Rgba, RgbaImage};
+use moleco::pdf::{label_book, Label, PageSize};
+
+fn labels(count: usize) -> Vec<Label> {
+    (0..count)
+        .map(|index| Label {
+            image: RgbaImage::from_pixel(20, 10, Rgba([255, 0, 0, 128])),
+            caption: vec![format!("{}. label", index + 1), "(caption)".to_string()],
+        })
+        .collect()
+}
+
+/// Binary data (compressed pictures) is masked, so offsets stay the same.
+fn text(pdf: &[u8]) -> String {
+    pdf.iter()
+        .map(|&byte| match byte.is_ascii() {
+            true => byte as char,
+            false => '?',
+        })
+        .collect()
+}
+
+#[test]
+fn test_label_book() {
+    let pdf = label_book(&labels(5), 2, PageSize::A4);
+    let content = text(&pdf);
+    assert!(content.starts_with("%PDF-1.4\n"));
+    assert!(content.ends_with("%%EOF\n"));
+    assert!(content.contains("/Count 3 /MediaBox [0 0 595.28 841.89]"));
+    assert!(content.contains("(Page 1 of 3)"));
+    assert!(content.contains("(Page 3 of 3)"));
+    assert!(content.contains("(5. label)"));
+    // Parentheses are escaped
+    assert!(content.contains("(\\(caption\\))"));
+    // Every picture has its alpha channel
+    assert_eq!(content.matches("/SMask").count(), 5);
+
+    // Cross-reference table points to objects
+    let start: usize = content
+        .rsplit("startxref\n")
+        .next()
+        .unwrap()
+        .lines()
+        .next()
+        .unwrap()
+        .parse()
+        .unwrap();
+    // Catalog, page tree, font, 3 pages with contents and 5 pictures with alpha
+    assert!(content[start..].starts_with("xref\n0 20\n"));
+    for (index, line) in content[start..].lines().skip(3).take(19).enumerate() {
+        let offset: usize = line[..10].parse().unwrap();
+        assert!(content[offset..].starts_with(&format!("{} 0 obj", index + 1)));
+    }
+}
+
+#[test]
+fn test_long_captions() {
+    let mut labels = labels(1);
+    labels[0].caption = vec!["InChI=1S/".to_string() + &"C".repeat(200)];
+    let content = text(&label_book(&labels, 12, PageSize::Letter));
+    assert!(content.contains("/MediaBox [0 0 612 792]"));
+    assert!(content.contains("CCC...) Tj"));
+    assert!(!content.contains(&"C".repeat(200)));
+}
@@ -0,0 +1,95 @@
+#![cfg(feature = "std")]
+use moleco::resolve::{check_cas, Query, Resolver};
+use std::fs;
+
+#[test]
+fn test_url() {
+    let resolver = Resolver::new().with_endpoint("http://localhost:8000/rest/pug/");
+    assert_eq!(
+        resolver.url(&Query::Cid(180)),
+        "http://localhost:8000/rest/pug/compound/cid/180/property/InChI/TXT"
+    );
+    assert_eq!(
+        resolver.url(&Query::Name(" acetic acid/ethanol ".to_string())),
+        "http://localhost:8000/rest/pug/compound/name/acetic%20acid%2Fethanol/property/InChI/TXT"
+    );
+}
+
+#[test]
+fn test_cache_path() {
+    let resolver = Resolver::new().with_cache(Some("cache".into()));
+    let acetone = resolver
+        .cache_path(&Query::Name("acetone".to_string()))
+        .unwrap();
+    assert!(acetone.starts_with("cache"));
+    assert!(acetone.to_string_lossy().ends_with(".inchi"));
+    assert_eq!(
+        resolver.cache_path(&Query::Name(" acetone\n".to_string())),
+        Some(acetone.clone())
+    );
+    assert_ne!(resolver.cache_path(&Query::Cid(180)), Some(acetone.clone()));
+    let mirror = resolver.clone().with_endpoint("http://localhost:8000");
+    assert_ne!(
+        mirror.cache_path(&Query::Name("acetone".to_string())),
+        Some(acetone)
+    );
+    assert_eq!(resolver.with_cache(None).cache_path(&Query::Cid(180)), None);
+}
+
+#[test]
+fn test_resolve_cached() {
+    let cache = std::env::temp_dir().join(format!("moleco-resolve-{}", std::process::id()));
+    let resolver = Resolver::new()
+        .with_endpoint("http://127.0.0.1:9")
+        .with_cache(Some(cache.clone()));
+    let query = Query::Cid(180);
+    let path = resolver.cache_path(&query).unwrap();
+    fs::create_dir_all(&cache).unwrap();
+    fs::write(&path, "InChI=1S/C3H6O/c1-3(2)4/h1-2H3\n").unwrap();
+    let result = resolver.resolve(&query);
+    fs::remove_dir_all(&cache).unwrap();
+    assert_eq!(result, Ok("InChI=1S/C3H6O/c1-3(2)4/h1-2H3".to_string()));
+
+    assert!(resolver.resolve(&Query::Name(" ".to_string())).is_err());
+    // Nothing listens on the endpoint
+    assert!(resolver.resolve(&Query::Cid(181)).is_err());
+}
+
+#[test]
+fn test_check_cas() {
+    assert_eq!(check_cas("67-64-1"), Ok(()));
+    assert_eq!(check_cas("7732-18-5"), Ok(()));
+    assert_eq!(check_cas("9003-07-0"), Ok(()));
+    assert_eq!(
+        check_cas("67-64-2"),
+        Err("Check digit of CAS registry number 67-64-2 should be 1".to_string())
+    );
+    for invalid in [
+        "",
+        "6-64-1",
+        "12345678-00-0",
+        "67-6-1",
+        "67-64-10",
+        "6a-64-1",
+        "67641",
+    ] {
+        assert!(check_cas(invalid).is_err(), "{}", invalid);
+    }
+    // Rejected before resolver is asked
+    let resolver = Resolver::new().with_cache(None);
+    let error = resolver.resolve(&Query::Cas("67-64-2".to_string()));
+    assert!(error.unwrap_err().contains("Check digit"));
+}
+
+#[test]
+fn test_detect() {
+    assert_eq!(Query::detect(" 180 "), Query::Cid(180));
+    assert_eq!(Query::detect("67-64-1"), Query::Cas("67-64-1".to_string()));
+    assert_eq!(Query::detect("acetone"), Query::Name("acetone".to_string()));
+    // Invalid check digit, so it's rather name than mistyped number
+    assert_eq!(Query::detect("67-64-2"), Query::Name("67-64-2".to_string()));
+    assert_eq!(
+        Query::detect("2,4-dinitrophenol"),
+        Query::Name("2,4-dinitrophenol".to_string())
+    );
+}
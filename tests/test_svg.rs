@@ -0,0 +1,145 @@
+#![cfg(feature = "render")]
+use moleco::generate_moleco;
+use moleco::layouts::{Align, Layer, RegionKind, Scene, Shape, Style, Text};
+use moleco::svg::{picture_to_inline_svg, picture_to_svg, scene_to_svg, SpriteSheet};
+use palette::Srgba;
+
+#[test]
+fn test_regions() {
+    let payload = "MInChI=0.00.1S/CH4O/c1-2/h2H,1H3&H2O/h1H2/n{1&2}/g{25wf-2&75wf-2}".to_string();
+    let picture = generate_moleco(payload, 50, 3, true).unwrap();
+    let regions = picture.regions().unwrap();
+    let order = picture.swatch_order().unwrap();
+    let segments = picture.bar_segments().unwrap();
+    assert_eq!(regions.len(), order.len() + segments.len());
+
+    let (swatches, bar) = regions.split_at(order.len());
+    for (swatch, index) in swatches.iter().zip(&order) {
+        assert_eq!(swatch.kind, RegionKind::Swatch);
+        assert_eq!(swatch.component, Some(index + 1));
+        assert_eq!((swatch.y, swatch.width, swatch.height), (0, 105, 105));
+    }
+    // Neighbouring swatches share the border
+    assert_eq!(swatches[1].x, 104);
+    assert_eq!(swatches[0].fraction, Some(0.25));
+    for (region, segment) in bar.iter().zip(&segments) {
+        assert_eq!(region.kind, RegionKind::Segment);
+        assert_eq!((region.x, region.width), (segment.x, segment.width));
+        assert!(region.y >= 105);
+    }
+
+    let picture = generate_moleco("InChI=1S/H2O/h1H2".to_string(), 50, 3, true).unwrap();
+    let regions = picture.regions().unwrap();
+    assert_eq!(regions.len(), 1);
+    assert_eq!((regions[0].component, regions[0].fraction), (Some(1), None));
+}
+
+#[test]
+fn test_picture_to_svg() {
+    let payload = "MInChI=0.00.1S/CH4O/c1-2/h2H,1H3&H2O/h1H2/n{1&2}/g{25wf-2&}".to_string();
+    let picture = generate_moleco(payload, 50, 3, true).unwrap();
+    let svg = picture_to_svg(&picture).unwrap();
+    assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"209\""));
+    assert!(svg.ends_with("</svg>\n"));
+    assert_eq!(svg.matches("class=\"moleco-swatch\"").count(), 2);
+    // Second component fills the rest of the mixture
+    assert_eq!(svg.matches("class=\"moleco-segment\"").count(), 2);
+    let hue = picture.schemes()[0].primary.hue;
+    assert!(svg.contains(&format!(
+        "data-component=\"1\" data-hue=\"{}\" data-fraction=\"0.25\"",
+        hue
+    )));
+    // Background is transparent, so kite cutouts are masked out
+    assert!(svg.contains("<mask id=\"cutout"));
+
+    let style = Style {
+        background: Srgba::new(255, 255, 255, 255),
+        ..Default::default()
+    };
+    let svg = picture_to_svg(&picture.with_style(style)).unwrap();
+    assert!(!svg.contains("<mask"));
+    assert!(svg.contains("<rect x=\"0\" y=\"0\" width=\"209\" height=\"130\" fill=\"#ffffff\"/>"));
+}
+
+#[test]
+fn test_scene_to_svg() {
+    let scene = Scene {
+        width: 100,
+        height: 30,
+        background: Srgba::new(0, 0, 0, 0),
+        layers: vec![Layer::new("text", 0).with_shapes(vec![Shape::Text(Text {
+            x: 50,
+            y: 0,
+            size: 20,
+            content: "<H2O & NaCl>".to_string(),
+            align: Align::Center,
+            color: Srgba::new(0, 0, 0, 128),
+            max_width: None,
+            shrink: false,
+        })])],
+        corner_radius: 0,
+    };
+    let svg = scene_to_svg(&scene);
+    assert!(svg.contains("text-anchor=\"middle\""));
+    assert!(svg.contains("fill-opacity=\"0.502\""));
+    assert!(svg.contains(">&lt;H2O &amp; NaCl&gt;</text>"));
+    // Text is blended, so translucent one doesn't cut anything out
+    assert!(!svg.contains("<mask"));
+    assert!(!svg.contains("moleco-regions"));
+}
+
+#[test]
+fn test_sprite_sheet() {
+    // Cutouts need masks, their ids must not clash between symbols
+    let water = generate_moleco("InChI=1S/H2O/h1H2".to_string(), 50, 3, true).unwrap();
+    let methane = generate_moleco("InChI=1S/CH4/h1H4".to_string(), 50, 3, true).unwrap();
+    let mut sheet = SpriteSheet::new();
+    assert!(sheet.is_empty());
+    sheet.add("water", &water).unwrap();
+    sheet.add("methane", &methane).unwrap();
+    sheet.add("water", &methane).unwrap();
+    assert_eq!(sheet.len(), 2);
+
+    let svg = sheet.finish();
+    assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\"><symbol id=\"water\""));
+    assert_eq!(svg.matches("<symbol ").count(), 2);
+    assert!(svg.contains("<symbol id=\"methane\" viewBox=\"0 0 "));
+    assert!(svg.contains("mask id=\"water-cutout1\""));
+    assert!(svg.contains("mask id=\"methane-cutout1\""));
+    assert!(svg.contains("<g class=\"moleco-regions\">"));
+    // Symbol holds the same drawing as standalone picture
+    let standalone = picture_to_svg(&water).unwrap();
+    let body = |svg: &str| {
+        svg.split("</defs>")
+            .nth(1)
+            .unwrap()
+            .split("<g class")
+            .next()
+            .unwrap()
+            .to_string()
+    };
+    assert_eq!(
+        body(&standalone).replace("cutout1", "water-cutout1"),
+        body(svg.split("<symbol id=\"methane\"").next().unwrap())
+    );
+}
+
+#[test]
+fn test_inline_svg() {
+    let picture = generate_moleco("InChI=1S/H2O/h1H2".to_string(), 50, 3, true).unwrap();
+    let svg = picture_to_svg(&picture).unwrap();
+    let inline = picture_to_inline_svg(&picture, "water", "Water & <ice>").unwrap();
+    assert_eq!(
+        inline,
+        svg.replacen(
+            "<svg ",
+            "<svg role=\"img\" aria-label=\"Water &amp; &lt;ice&gt;\" ",
+            1
+        )
+        .replace("cutout1", "water-cutout1")
+    );
+    // Masks of pictures inlined in the same page don't clash
+    let methane = generate_moleco("InChI=1S/CH4/h1H4".to_string(), 50, 3, true).unwrap();
+    let other = picture_to_inline_svg(&methane, "methane", "Methane").unwrap();
+    assert!(other.contains("mask id=\"methane-cutout1\""));
+}
@@ -0,0 +1,92 @@
+#![cfg(feature = "render")]
+use image::{Rgba, RgbaImage};
+use moleco::terminal::{block_art, braille, half_blocks, sixel};
+
+fn two_colors() -> RgbaImage {
+    // Left half red, right half transparent
+    RgbaImage::from_fn(40, 20, |x, _| match x < 20 {
+        true => Rgba([255, 0, 0, 255]),
+        false => Rgba([0, 0, 0, 0]),
+    })
+}
+
+#[test]
+fn test_block_art() {
+    let art = block_art(&two_colors(), 20, true);
+    let lines: Vec<&str> = art.lines().collect();
+    // 10 blocks wide, proportions are kept
+    assert_eq!(lines.len(), 5);
+    for line in &lines {
+        assert!(line.starts_with("\x1b[48;2;255;0;0m  "));
+        assert!(line.ends_with("\x1b[0m  \x1b[0m"));
+        assert_eq!(line.matches("  ").count(), 10);
+    }
+
+    // Closest color of 256 colors palette
+    let art = block_art(&two_colors(), 20, false);
+    assert!(art.starts_with("\x1b[48;5;196m  "));
+
+    // Picture is never enlarged
+    let art = block_art(&two_colors(), 1000, true);
+    assert_eq!(art.lines().count(), 20);
+}
+
+#[test]
+fn test_sixel() {
+    let encoded = sixel(&two_colors());
+    assert!(encoded.starts_with("\x1bP0;1;0q\"1;1;40;20"));
+    assert!(encoded.ends_with("-\x1b\\"));
+    // 4 bands of 6 rows
+    assert_eq!(encoded.matches('-').count(), 4);
+    // Red is 180th color of the cube, painted in runs of 20 pixels, rest is left unpainted
+    assert!(encoded.contains("#180!20~!20?"));
+    assert!(!encoded.contains('$'));
+}
+
+fn strip_escapes(art: &str) -> String {
+    let mut result = String::new();
+    let mut escape = false;
+    for c in art.chars() {
+        match (escape, c) {
+            (false, '\x1b') => escape = true,
+            (false, c) => result.push(c),
+            (true, 'm') => escape = false,
+            (true, _) => {}
+        }
+    }
+    result
+}
+
+#[test]
+fn test_half_blocks() {
+    // Top half red, bottom half green, rightmost column transparent
+    let buffer = RgbaImage::from_fn(4, 4, |x, y| match (x, y) {
+        (3, _) => Rgba([0, 0, 0, 0]),
+        (_, 0 | 1) => Rgba([255, 0, 0, 255]),
+        _ => Rgba([0, 255, 0, 255]),
+    });
+    let art = half_blocks(&buffer, 4, true);
+    assert_eq!(strip_escapes(&art), "▀▀▀ \n▀▀▀ \n");
+    assert!(art.starts_with("\x1b[0m\x1b[38;2;255;0;0m\x1b[48;2;255;0;0m▀"));
+    assert!(art.contains("\x1b[38;2;0;255;0m\x1b[48;2;0;255;0m▀"));
+}
+
+#[test]
+fn test_braille() {
+    // Only left column of each 2x4 cell is opaque
+    let buffer = RgbaImage::from_fn(4, 4, |x, _| match x % 2 {
+        0 => Rgba([0, 0, 255, 255]),
+        _ => Rgba([0, 0, 0, 0]),
+    });
+    let art = braille(&buffer, 2, true);
+    assert_eq!(strip_escapes(&art), "⡇⡇\n");
+    assert!(art.starts_with("\x1b[38;2;0;0;255m⡇"));
+
+    // Too wide picture is scaled down, one character is 2 pixels wide
+    let art = braille(
+        &RgbaImage::from_pixel(100, 8, Rgba([0, 0, 0, 255])),
+        10,
+        true,
+    );
+    assert_eq!(strip_escapes(&art).lines().next().unwrap(), "⣿".repeat(10));
+}
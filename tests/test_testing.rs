@@ -0,0 +1,53 @@
+#![cfg(feature = "testing")]
+use moleco::generate_moleco;
+use moleco::layouts::Style;
+use moleco::testing::{assert_snapshot, compare, snapshot, Tolerance};
+use palette::Srgba;
+
+#[test]
+fn test_snapshot() {
+    let picture = generate_moleco("InChI=1S/H2O/h1H2".to_string(), 50, 3, true).unwrap();
+    let bytes = snapshot(&picture).unwrap();
+    assert!(bytes.starts_with(b"P7\nWIDTH 105\nHEIGHT 105\nDEPTH 4\n"));
+    assert_eq!(snapshot(&picture).unwrap(), bytes);
+    assert_eq!(compare(&bytes, &bytes, Tolerance::default()), Ok(()));
+
+    // Only transparent background turns white, which is invisible over white
+    let style = Style {
+        background: Srgba::new(255, 255, 255, 255),
+        ..Default::default()
+    };
+    let white = picture.with_style(style);
+    let white_bytes = snapshot(&white).unwrap();
+    assert_ne!(white_bytes, bytes);
+    assert_eq!(compare(&white_bytes, &bytes, Tolerance::default()), Ok(()));
+
+    let other = generate_moleco("InChI=1S/CH4/h1H4".to_string(), 50, 3, true).unwrap();
+    let error = compare(&snapshot(&other).unwrap(), &bytes, Tolerance::default());
+    assert!(error.unwrap_err().contains("pixel(s) differ"));
+    let larger = generate_moleco("InChI=1S/H2O/h1H2".to_string(), 60, 3, true).unwrap();
+    let error = compare(&snapshot(&larger).unwrap(), &bytes, Tolerance::default());
+    assert_eq!(
+        error,
+        Err("Size differs - (125, 125) instead of (105, 105)".to_string())
+    );
+    assert!(compare(b"not an image", &bytes, Tolerance::default()).is_err());
+}
+
+#[test]
+fn test_assert_snapshot() {
+    let golden = std::env::temp_dir().join(format!("moleco-golden-{}.pam", std::process::id()));
+    let _ = std::fs::remove_file(&golden);
+    let picture = generate_moleco("InChI=1S/H2O/h1H2".to_string(), 50, 3, true).unwrap();
+    // Missing golden is written
+    assert_snapshot(&picture, &golden, Tolerance::default());
+    assert!(golden.exists());
+    assert_snapshot(&picture, &golden, Tolerance::default());
+
+    let other = generate_moleco("InChI=1S/CH4/h1H4".to_string(), 50, 3, true).unwrap();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        assert_snapshot(&other, &golden, Tolerance::default());
+    }));
+    std::fs::remove_file(&golden).unwrap();
+    assert!(result.is_err());
+}
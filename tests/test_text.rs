@@ -0,0 +1,160 @@
+#![cfg(feature = "render")]
+use image::RgbaImage;
+use moleco::layouts::{Align, Layer, Scene, Shape, Text};
+use moleco::text::TextRenderer;
+use palette::Srgba;
+
+fn opaque_columns(image: &RgbaImage) -> Vec<u32> {
+    (0..image.width())
+        .filter(|x| (0..image.height()).any(|y| image.get_pixel(*x, y).0[3] > 0))
+        .collect()
+}
+
+#[test]
+fn test_measure() {
+    let mut renderer = TextRenderer::new();
+    let (width, height) = renderer.measure("0", 20);
+    assert!(width > 0 && height >= 20);
+    // Bundled font is monospaced
+    assert_eq!(
+        renderer.measure("1234567890", 20).0,
+        renderer.measure("0000000000", 20).0
+    );
+    assert_eq!(renderer.measure("i", 20).0, renderer.measure("W", 20).0);
+    assert_eq!(renderer.measure("", 20).0, 0);
+    // Bigger font, bigger text
+    assert!(renderer.measure("0", 40).0 > width);
+}
+
+#[test]
+fn test_glyph_cache() {
+    let mut renderer = TextRenderer::new();
+    let mut buffer = RgbaImage::new(100, 30);
+    let color = Srgba::new(0, 0, 0, 255);
+    renderer.draw(&mut buffer, "1111", 0, 0, 20, color);
+    assert_eq!(renderer.cached_glyphs(), 1);
+    renderer.draw(&mut buffer, "10%", 0, 0, 20, color);
+    assert_eq!(renderer.cached_glyphs(), 3);
+    renderer.draw(&mut buffer, "10%", 0, 0, 10, color);
+    assert_eq!(renderer.cached_glyphs(), 6);
+}
+
+#[test]
+fn test_draw() {
+    let mut renderer = TextRenderer::new();
+    let mut buffer = RgbaImage::new(100, 30);
+    renderer.draw(&mut buffer, "42%", 5, 0, 20, Srgba::new(200, 0, 0, 255));
+    let columns = opaque_columns(&buffer);
+    assert!(columns[0] >= 5);
+    assert!(*columns.last().unwrap() <= 5 + renderer.measure("42%", 20).0);
+    // Only given color is used, edges are antialiased
+    assert!(buffer.pixels().all(|p| p.0[3] == 0 || p.0[0] == 200));
+    assert!(buffer.pixels().any(|p| p.0[3] == 255));
+    assert!(buffer.pixels().any(|p| p.0[3] > 0 && p.0[3] < 255));
+
+    // Text outside of the picture is clipped
+    let mut buffer = RgbaImage::new(10, 10);
+    renderer.draw(&mut buffer, "42%", -15, -5, 20, Srgba::new(0, 0, 0, 255));
+}
+
+#[test]
+fn test_text_shape() {
+    let render = |align: Align| {
+        let scene = Scene {
+            width: 100,
+            height: 30,
+            background: Srgba::new(255, 255, 255, 255),
+            layers: vec![Layer::new("text", 0).with_shapes(vec![Shape::Text(Text {
+                x: 50,
+                y: 0,
+                size: 20,
+                content: "ABC".to_string(),
+                align,
+                color: Srgba::new(0, 0, 0, 255),
+                max_width: None,
+                shrink: false,
+            })])],
+            corner_radius: 0,
+        };
+        let image = scene.render();
+        let dark: Vec<u32> = (0..image.width())
+            .filter(|x| (0..image.height()).any(|y| image.get_pixel(*x, y).0[0] < 128))
+            .collect();
+        (dark[0], *dark.last().unwrap())
+    };
+    let (left_start, _) = render(Align::Left);
+    let (center_start, center_end) = render(Align::Center);
+    let (_, right_end) = render(Align::Right);
+    assert!(left_start >= 50);
+    assert!(center_start < 50 && center_end > 50);
+    assert!(right_end <= 50);
+}
+
+#[test]
+fn test_truncate_middle() {
+    let mut renderer = TextRenderer::new();
+    let inchi = "InChI=1S/C8H10N4O2/c1-10-4-9-6-5(10)7(13)12(3)8(14)11(6)2/h4H,1-3H3";
+    let (full_width, _) = renderer.measure(inchi, 10);
+    assert_eq!(renderer.truncate_middle(inchi, 10, full_width), inchi);
+
+    let truncated = renderer.truncate_middle(inchi, 10, full_width / 2);
+    assert!(renderer.measure(&truncated, 10).0 <= full_width / 2);
+    let (head, tail) = truncated.split_once('…').unwrap();
+    assert!(inchi.starts_with(head) && inchi.ends_with(tail));
+    // Both ends are kept, beginning may be one character longer
+    assert!(head.len() - tail.len() <= 1);
+    assert!(head.starts_with("InChI=1S/") && tail.ends_with("1-3H3"));
+
+    // In the narrowest space only ellipsis is left, or nothing at all
+    let (ellipsis_width, _) = renderer.measure("…", 10);
+    assert_eq!(renderer.truncate_middle(inchi, 10, ellipsis_width), "…");
+    assert_eq!(renderer.truncate_middle(inchi, 10, ellipsis_width - 1), "");
+}
+
+#[test]
+fn test_fit() {
+    let mut renderer = TextRenderer::new();
+    let text = "MInChI=0.00.1S/H2O";
+    let (width, _) = renderer.measure(text, 20);
+
+    assert_eq!(renderer.fit(text, 20, width, true), (text.to_string(), 20));
+    // Shrinking is enough
+    let (fitted, size) = renderer.fit(text, 20, width * 3 / 4, true);
+    assert_eq!(fitted, text);
+    assert!((10..20).contains(&size));
+    assert!(renderer.measure(text, size).0 <= width * 3 / 4);
+    // Font is not shrunk below half of the size, so text is truncated as well
+    let (fitted, size) = renderer.fit(text, 20, width / 4, true);
+    assert_eq!(size, 10);
+    assert!(fitted.contains('…'));
+    // Without shrinking only truncation is done
+    let (fitted, size) = renderer.fit(text, 20, width * 3 / 4, false);
+    assert_eq!(size, 20);
+    assert!(fitted.contains('…'));
+}
+
+#[test]
+fn test_text_shape_max_width() {
+    let render = |max_width: Option<u32>| {
+        let scene = Scene {
+            width: 200,
+            height: 30,
+            background: Srgba::new(0, 0, 0, 0),
+            layers: vec![Layer::new("text", 0).with_shapes(vec![Shape::Text(Text {
+                x: 0,
+                y: 0,
+                size: 20,
+                content: "InChI=1S/C8H10N4O2/c1-10-4-9-6-5(10)7(13)12(3)8(14)11(6)2".to_string(),
+                align: Align::Left,
+                color: Srgba::new(0, 0, 0, 255),
+                max_width,
+                shrink: false,
+            })])],
+            corner_radius: 0,
+        };
+        opaque_columns(&scene.render())
+    };
+    // Without limit text is clipped at the edge of the picture
+    assert_eq!(*render(None).last().unwrap(), 199);
+    assert!(*render(Some(100)).last().unwrap() < 100);
+}
@@ -0,0 +1,154 @@
+#![cfg(feature = "render")]
+use moleco::generate_moleco;
+use moleco::layouts::{OpenEnd, Pattern, Shape, Style};
+use moleco::theme::{parse_color, Theme};
+use palette::Srgba;
+
+#[test]
+fn test_parse_color() {
+    assert_eq!(parse_color("#ff8000"), Ok(Srgba::new(255, 128, 0, 255)));
+    assert_eq!(parse_color("ff800040"), Ok(Srgba::new(255, 128, 0, 64)));
+    assert!(parse_color("#ff80").is_err());
+    assert!(parse_color("#gg8000").is_err());
+    assert!(parse_color("#ff800é").is_err());
+}
+
+#[test]
+fn test_toml_and_json_themes() {
+    let toml = Theme::from_toml(
+        r##"
+        border_color = "#000000"
+        spacing = 4
+        min_border_contrast = 3
+
+        [bar]
+        unknown_pattern = "hatched"
+        open_end = "fade"
+        zero_width_markers = true
+        "##,
+    )
+    .unwrap();
+    let json = Theme::from_json(
+        r##"{
+            "border_color": "#000000",
+            "spacing": 4,
+            "min_border_contrast": 3,
+            "bar": {"unknown_pattern": "hatched", "open_end": "fade", "zero_width_markers": true}
+        }"##,
+    )
+    .unwrap();
+    assert_eq!(toml, json);
+
+    let style = toml.apply(Style::default()).unwrap();
+    assert_eq!(style.border_color, Srgba::new(0, 0, 0, 255));
+    assert_eq!(style.spacing, 4);
+    assert_eq!(style.min_border_contrast, Some(3f64));
+    assert_eq!(style.unknown_pattern, Pattern::Hatched);
+    assert_eq!(style.open_end, OpenEnd::Fade);
+    assert!(style.zero_width_markers);
+    // Not mentioned in theme, so left untouched
+    assert_eq!(style.background, Style::default().background);
+    assert_eq!(style.cutout_scale, 1f32);
+}
+
+#[test]
+fn test_invalid_themes() {
+    assert!(Theme::from_toml("unknown_field = 1").is_err());
+    assert!(Theme::from_toml("[bar]\nopen_end = \"spiral\"").is_err());
+    assert!(Theme::from_toml("cutouts = \"corners\"").is_err());
+    assert!(Theme::from_toml("orientation_mark = \"center\"").is_err());
+    let theme = Theme::from_toml("background = \"white\"").unwrap();
+    assert!(theme.apply(Style::default()).is_err());
+    let theme = Theme::from_toml("cutout_scale = 3.0").unwrap();
+    assert!(theme.apply(Style::default()).is_err());
+    let theme = Theme::from_toml("min_border_contrast = 0.5").unwrap();
+    assert!(theme.apply(Style::default()).is_err());
+}
+
+#[test]
+fn test_themed_picture() {
+    let payload = "MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&2}/g{37wf-2&}".to_string();
+    let render = |theme: &str| {
+        let style = Theme::from_toml(theme)
+            .unwrap()
+            .apply(Style::default())
+            .unwrap();
+        generate_moleco(payload.clone(), 50, 3, true)
+            .unwrap()
+            .with_style(style)
+            .generate()
+            .unwrap()
+    };
+    let plain = render("");
+    assert_eq!(plain.get_pixel(0, 0).0, [0, 0, 0, 0]);
+
+    let background = render("background = \"#ffffff\"");
+    assert_eq!(background.dimensions(), plain.dimensions());
+    assert_eq!(background.get_pixel(0, 0).0, [255, 255, 255, 255]);
+    assert!(background.pixels().all(|p| p.0[3] == 255));
+
+    let spaced = render("spacing = 10");
+    assert_eq!(spaced.width(), plain.width() + 10);
+    assert_eq!(spaced.height(), plain.height());
+
+    let no_cutouts = render("cutout_scale = 0");
+    let big_cutouts = render("cutout_scale = 2");
+    assert_eq!(no_cutouts.dimensions(), plain.dimensions());
+    let transparent = |image: &image::RgbaImage| image.pixels().filter(|p| p.0[3] == 0).count();
+    assert!(transparent(&no_cutouts) < transparent(&plain));
+    assert!(transparent(&plain) < transparent(&big_cutouts));
+
+    let center_cutout = render("cutouts = \"center\"");
+    assert!(transparent(&center_cutout) < transparent(&plain));
+    assert!(transparent(&no_cutouts) < transparent(&center_cutout));
+    assert_eq!(render("cutouts = \"none\""), no_cutouts);
+
+    // Filled cutouts don't show the background
+    let filled = render("cutout_fill = \"#ff0000\"");
+    assert!(transparent(&filled) < transparent(&no_cutouts));
+    let red = |image: &image::RgbaImage| image.pixels().filter(|p| p.0 == [255, 0, 0, 255]).count();
+    assert_eq!(red(&no_cutouts), 0);
+    assert!(red(&filled) > 0);
+}
+
+#[test]
+fn test_bar_dividers() {
+    let payload = "MInChI=0.00.1S/CH4O/c1-2/h2H,1H3&H2O/h1H2&Na/n{1&2&3}/g{60wf-2&30wf-2&10wf-2}";
+    // Vertical lines of the bar, as (x, width, color)
+    let dividers = |theme: &str| {
+        let style = Theme::from_toml(theme)
+            .unwrap()
+            .apply(Style::default())
+            .unwrap();
+        let scene = generate_moleco(payload.to_string(), 50, 3, true)
+            .unwrap()
+            .with_style(style)
+            .to_scene()
+            .unwrap();
+        scene.layers[1]
+            .shapes
+            .iter()
+            .filter_map(|shape| match shape {
+                Shape::Line(line) if line.x1 == line.x2 && line.x1 > 0 => {
+                    Some((line.x1, line.border_size, line.color))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+    let border = Style::default().border_color;
+    let plain = dividers("");
+    // Two dividers and closing border
+    assert_eq!(plain.len(), 3);
+    assert!(plain
+        .iter()
+        .all(|(_, width, color)| *width == 1 && *color == border));
+
+    let styled = dividers("[bar]\ndivider_color = \"#ffffff\"\ndivider_width = 5");
+    assert_eq!(styled.len(), 3);
+    assert_eq!(styled[0], (plain[0].0, 5, Srgba::new(255, 255, 255, 255)));
+    // End of the bar is still closed with border
+    assert_eq!(styled[2], plain[2]);
+
+    assert_eq!(dividers("[bar]\ndivider_width = 0"), vec![plain[2]]);
+}
@@ -0,0 +1,76 @@
+#![cfg(feature = "std")]
+use moleco::timing::{measure, take, Stage, Timings};
+use std::thread::sleep;
+use std::time::Duration;
+
+#[test]
+fn test_measure() {
+    take();
+    let result = measure(Stage::ShapeGeneration, || {
+        sleep(Duration::from_millis(10));
+        measure(Stage::Encoding, || sleep(Duration::from_millis(100)));
+        42
+    });
+    assert_eq!(result, 42);
+    let timings = take();
+    // Inner stage isn't counted in the outer one
+    assert!(timings.get(Stage::Encoding) >= Duration::from_millis(100));
+    assert!(timings.get(Stage::ShapeGeneration) >= Duration::from_millis(10));
+    assert!(timings.get(Stage::ShapeGeneration) < Duration::from_millis(100));
+    assert_eq!(timings.get(Stage::Hashing), Duration::ZERO);
+    assert_eq!(take(), Timings::default());
+
+    let mut total = Timings::default();
+    total.add(&timings);
+    total.add(&timings);
+    assert_eq!(total.get(Stage::Encoding), timings.get(Stage::Encoding) * 2);
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn test_generation_stages() {
+    use moleco::generate_moleco;
+
+    take();
+    let picture = generate_moleco("InChI=1S/H2O/h1H2".to_string(), 50, 3, true).unwrap();
+    picture.generate().unwrap();
+    let timings = take();
+    assert!(timings.get(Stage::Hashing) > Duration::ZERO);
+    assert!(timings.get(Stage::ShapeGeneration) > Duration::ZERO);
+    assert!(timings.get(Stage::Rasterization) > Duration::ZERO);
+    // InChI is not a mixture
+    assert_eq!(timings.get(Stage::Tokenization), Duration::ZERO);
+    assert_eq!(timings.get(Stage::WidthCalculation), Duration::ZERO);
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn test_mixture_stages() {
+    use moleco::generate_moleco;
+    use moleco::timing::peek;
+
+    take();
+    measure(Stage::Encoding, || sleep(Duration::from_millis(1)));
+    let before = peek();
+    let picture = generate_moleco(
+        "MInChI=0.00.1S/H2O/h1H2&CH4O/c1-2/h2H,1H3/n{1&2}/g{1:1vp0&1:1vp0}".to_string(),
+        50,
+        3,
+        false,
+    )
+    .unwrap();
+    picture.generate().unwrap();
+    let spent = peek().since(&before);
+    assert_eq!(spent.get(Stage::Encoding), Duration::ZERO);
+    for stage in [
+        Stage::Hashing,
+        Stage::Tokenization,
+        Stage::Parsing,
+        Stage::WidthCalculation,
+        Stage::ShapeGeneration,
+        Stage::Rasterization,
+    ] {
+        assert!(spent.get(stage) > Duration::ZERO, "{}", stage.name());
+    }
+    assert_eq!(take().total(), before.total() + spent.total());
+}
@@ -1,6 +1,7 @@
+#![cfg(feature = "std")]
 use moleco::tokenize::{
-    generate_mixture_tree, tokenize_string, Component, Concentration, Content, Group, Ingredient,
-    Mixture, Substance, Token,
+    generate_mixture_tree, generate_mixture_tree_lenient, tokenize_reader, tokenize_string,
+    Component, Concentration, Content, Group, Ingredient, Mixture, Qualifier, Substance, Token,
 };
 
 // source: http://molmatinf.com/minchidemo/
@@ -314,6 +315,8 @@ fn test_content_parsing() {
             value: 66,
             concentration: Concentration::WF,
             magnitude: -3,
+            qualifier: Qualifier::Exact,
+            range: None,
         }
     );
 }
@@ -327,6 +330,8 @@ fn test_content_parsing_range() {
             value: 7,
             concentration: Concentration::WF,
             magnitude: -3,
+            qualifier: Qualifier::Exact,
+            range: Some((5, 7)),
         }
     );
 }
@@ -340,10 +345,90 @@ fn test_content_parsing_range_2() {
             value: 5,
             concentration: Concentration::PP,
             magnitude: 0,
+            qualifier: Qualifier::Exact,
+            range: Some((2, 5)),
         }
     );
 }
 
+#[test]
+fn test_content_parsing_decimal() {
+    let content = Content::from_str("2.5wv-2").unwrap();
+    assert_eq!(
+        content,
+        Content {
+            value: 25,
+            concentration: Concentration::WV,
+            magnitude: -3,
+            qualifier: Qualifier::Exact,
+            range: None,
+        }
+    );
+    let content = Content::from_str("~0.05pp0").unwrap();
+    assert_eq!(content.value, 5);
+    assert_eq!(content.magnitude, -2);
+    assert_eq!(content.qualifier, Qualifier::Approx);
+}
+
+#[test]
+fn test_content_parsing_qualifiers() {
+    for (payload, qualifier) in [
+        ("5pp0", Qualifier::Exact),
+        ("~5pp0", Qualifier::Approx),
+        ("<5pp0", Qualifier::AtMost),
+        ("<=5pp0", Qualifier::AtMostInclusive),
+        (">5pp0", Qualifier::AtLeast),
+        (">=5pp0", Qualifier::AtLeastInclusive),
+    ] {
+        let content = Content::from_str(payload).unwrap();
+        assert_eq!(content.qualifier, qualifier, "{}", payload);
+        assert_eq!(content.value, 5);
+        assert_eq!(content.bounds(), (5, 5));
+        assert_eq!(content.to_string(), payload);
+    }
+}
+
+#[test]
+fn test_content_range_accessors() {
+    let content = Content::from_str("10:15pp0").unwrap();
+    assert_eq!(content.value, 15);
+    assert_eq!(content.bounds(), (10, 15));
+    assert_eq!(content.midpoint(), 12.5);
+
+    let content = Content::from_str("15:10pp0").unwrap();
+    assert_eq!(content.value, 10);
+    assert_eq!(content.bounds(), (10, 15));
+
+    let content = Content::from_str("7pp0").unwrap();
+    assert_eq!(content.midpoint(), 7.0);
+}
+
+#[test]
+fn test_content_parsing_decimal_range() {
+    let content = Content::from_str("1.25:2.5pp0").unwrap();
+    assert_eq!(
+        content,
+        Content {
+            value: 250,
+            concentration: Concentration::PP,
+            magnitude: -2,
+            qualifier: Qualifier::Exact,
+            range: Some((125, 250)),
+        }
+    );
+}
+
+#[test]
+fn test_content_parsing_invalid_decimal() {
+    for payload in ["2.wv-2", ".5wv-2", "2.5.1wv-2", "2.-5wv-2"] {
+        assert!(
+            Content::from_str(payload).is_err(),
+            "{} should fail",
+            payload
+        );
+    }
+}
+
 #[test]
 fn test_mixture_tree() {
     let (indexing, concentration) = get_ic(&DISHWASHING_LIQUID);
@@ -374,3 +459,130 @@ fn test_mixture_tree() {
         }
     );
 }
+
+#[test]
+fn test_unmatched_closing_parenthesis_span() {
+    let error = tokenize_string("n{1&2}}&3", 'n').unwrap_err();
+    assert_eq!(error.span, Some((6, 7)));
+    assert_eq!(error.snippet().unwrap(), "}");
+}
+
+#[test]
+fn test_unclosed_parenthesis_span() {
+    let error = tokenize_string("n{1&{2&3}", 'n').unwrap_err();
+    assert_eq!(error.span, Some((1, 2)));
+    assert_eq!(error.annotate().unwrap(), "n{1&{2&3}\n ^");
+}
+
+#[test]
+fn test_content_error_span_in_whole_payload() {
+    let payload = "MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&2}/g{37xx-2&}";
+    let (indexing, concentration) = get_ic(payload);
+    let error = generate_mixture_tree(indexing, concentration)
+        .unwrap_err()
+        .within(payload);
+    assert_eq!(error.snippet().unwrap(), "37xx-2");
+    assert_eq!(error.span, Some((48, 54)));
+}
+
+#[test]
+fn test_span_of_repeated_content() {
+    let (_, errors) =
+        generate_mixture_tree_lenient("n{1&2&{3&4}}", "g{37xx-2&37xx-2&{1pp0&}37xx-2}");
+    let spans: Vec<_> = errors.iter().map(|error| error.span).collect();
+    assert_eq!(spans, vec![Some((2, 8)), Some((9, 15)), Some((23, 29))]);
+    assert!(errors
+        .iter()
+        .all(|error| error.snippet().unwrap() == "37xx-2"));
+
+    // Values of mismatched components are skipped
+    let (_, errors) = generate_mixture_tree_lenient("n{1&2&3}", "g{{37xx-2&1pp0}&2&37xx-2}");
+    assert_eq!(errors.last().unwrap().span, Some((18, 24)));
+}
+
+#[test]
+fn test_annotation_of_long_input() {
+    let (indexing, concentration) = get_ic(LITHIUM_DIISOPROPYLAMIDE_SOLUTION);
+    // Outermost group is left unclosed
+    let indexing = indexing.replace("5}}}", "5}}");
+    let error = generate_mixture_tree(&indexing, concentration)
+        .unwrap_err()
+        .within(&LITHIUM_DIISOPROPYLAMIDE_SOLUTION.replace("5}}}", "5}}"));
+    let annotation = error.annotate().unwrap();
+    let lines: Vec<&str> = annotation.lines().collect();
+    assert!(lines[0].starts_with("..."));
+    assert!(lines[0].ends_with("..."));
+    assert_eq!(lines[1].find('^').unwrap(), lines[0].find("{6&").unwrap());
+}
+
+#[test]
+fn test_lenient_tree_collects_all_errors() {
+    let (mixture, errors) = generate_mixture_tree_lenient("n{1&{2&3}&4}", "g{37xx-2&{1pp&2pp0}&}");
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].snippet().unwrap(), "37xx-2");
+    assert_eq!(errors[1].snippet().unwrap(), "1pp");
+    assert!(matches!(mixture.ingredients[0], Ingredient::Invalid(_)));
+    match &mixture.ingredients[1] {
+        Ingredient::Mixture(inner) => {
+            assert!(matches!(inner.ingredients[0], Ingredient::Invalid(_)));
+            assert!(matches!(inner.ingredients[1], Ingredient::Substance(_)));
+        }
+        other => panic!("Expected mixture, got {:?}", other),
+    }
+    assert!(matches!(mixture.ingredients[2], Ingredient::Substance(_)));
+
+    // Strict mode reports the first of them
+    let error = generate_mixture_tree("n{1&{2&3}&4}", "g{37xx-2&{1pp&2pp0}&}").unwrap_err();
+    assert_eq!(error, errors[0]);
+}
+
+#[test]
+fn test_lenient_tree_with_mismatched_components() {
+    let (mixture, errors) = generate_mixture_tree_lenient("n{1&2&3}", "g{1pp&2pp0}");
+    assert_eq!(errors.len(), 2);
+    assert_eq!(mixture.ingredients.len(), 3);
+    assert!(matches!(mixture.ingredients[2], Ingredient::Invalid(_)));
+}
+
+#[test]
+fn test_lenient_tree_with_broken_structure() {
+    let (mixture, errors) = generate_mixture_tree_lenient("n{1&2}}", "g{{1pp0&2pp0}");
+    assert_eq!(errors.len(), 2);
+    assert!(mixture.ingredients.is_empty());
+}
+
+#[test]
+fn test_tokenize_reader() {
+    for notation in [
+        "n{6&{1&{3&2&4&5}}}",
+        "g{1mr0&{1vp0&{5:7pp1&1:2pp1}7vp0}}",
+        "n{}",
+        "n",
+    ] {
+        let start = notation.chars().next().unwrap();
+        assert_eq!(
+            tokenize_reader(notation.as_bytes(), start),
+            tokenize_string(notation, start)
+        );
+    }
+
+    // Hundreds of components are read as they come
+    let notation = format!(
+        "n{{{}}}",
+        (1..=500)
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .join("&")
+    );
+    let group = tokenize_reader(notation.as_bytes(), 'n').unwrap();
+    assert_eq!(group.components.len(), 500);
+    assert_eq!(group.components[499], enum_token!("500"));
+
+    // Errors point at the problem, but have no input to annotate
+    let error = tokenize_reader("n{1&2}}".as_bytes(), 'n').unwrap_err();
+    assert_eq!(error.span, Some((6, 7)));
+    assert_eq!(error.annotate(), None);
+    let error = tokenize_reader("n{1&2é}".as_bytes(), 'n').unwrap_err();
+    assert_eq!(error.message, "Invalid character, notation is ASCII only");
+    assert_eq!(error.span, Some((5, 6)));
+}
@@ -0,0 +1,143 @@
+#![cfg(feature = "render")]
+use moleco::writers::{
+    Calculation, CalculationOptions, Contrast, Registry, Writer, WriterOptions, CSV, JSON, TABLE,
+};
+use moleco::{calculate_scheme, Component, Scheme};
+use std::cell::RefCell;
+use std::fs;
+use std::rc::Rc;
+
+/// Collects substances with their primary hues, like site-specific format would.
+struct Collector {
+    written: Rc<RefCell<Vec<String>>>,
+}
+
+impl Writer for Collector {
+    fn write(
+        &mut self,
+        substance: String,
+        palette: Scheme,
+        _molecular_weight: Option<f64>,
+        _contrast: Option<Contrast>,
+    ) {
+        let record = format!("{} {}", substance, palette.primary.hue);
+        self.written.borrow_mut().push(record);
+    }
+    fn write_composition(&mut self, mixture: String, components: Vec<Component>) {
+        let record = format!("{} {}", mixture, components.len());
+        self.written.borrow_mut().push(record);
+    }
+    fn flush(&mut self) -> Result<(), String> {
+        self.written.borrow_mut().push("flushed".to_string());
+        Ok(())
+    }
+}
+
+#[test]
+fn test_custom_writer() {
+    let written = Rc::new(RefCell::new(vec![]));
+    let shared = written.clone();
+    let registry = Registry::default().with_writer("collector", "For tests.", move |_| {
+        Ok(Box::new(Collector {
+            written: shared.clone(),
+        }))
+    });
+    let names: Vec<&str> = registry.formats().iter().map(|(name, _)| *name).collect();
+    assert_eq!(names.first(), Some(&TABLE));
+    assert_eq!(names.last(), Some(&"collector"));
+
+    let mut writer = registry
+        .writer("collector", &WriterOptions::default())
+        .unwrap();
+    let water = "InChI=1S/H2O/h1H2".to_string();
+    writer.write(water.clone(), calculate_scheme(water), None, None);
+    writer.flush().unwrap();
+    assert_eq!(
+        *written.borrow(),
+        vec!["InChI=1S/H2O/h1H2 215".to_string(), "flushed".to_string()]
+    );
+
+    assert!(registry.writer("xml", &WriterOptions::default()).is_err());
+    assert!(Registry::new().formats().is_empty());
+}
+
+#[test]
+fn test_calculation() {
+    let written = Rc::new(RefCell::new(vec![]));
+    let shared = written.clone();
+    let registry = Registry::new().with_writer("collector", "For tests.", move |_| {
+        Ok(Box::new(Collector {
+            written: shared.clone(),
+        }))
+    });
+    let options = CalculationOptions {
+        skip_errors: true,
+        ..Default::default()
+    };
+    let mut calculation =
+        Calculation::new(&registry, "collector", &WriterOptions::default(), options).unwrap();
+    let mixture = "MInChI=0.00.1S/CH2O/c1-2/h1H2&H2O/h1H2/n{1&2}/g{37wf-2&}";
+    calculation.write(mixture.to_string()).unwrap();
+    calculation.write("water".to_string()).unwrap();
+    calculation.flush().unwrap();
+    assert_eq!(calculation.skipped(), 1);
+    assert_eq!(
+        *written.borrow(),
+        vec![
+            "InChI=1S/CH2O/c1-2/h1H2 153".to_string(),
+            "InChI=1S/H2O/h1H2 215".to_string(),
+            format!("{} 2", mixture),
+            "flushed".to_string()
+        ]
+    );
+
+    let mut calculation = Calculation::new(
+        &registry,
+        "collector",
+        &WriterOptions::default(),
+        CalculationOptions::default(),
+    )
+    .unwrap();
+    assert!(calculation.write("water".to_string()).is_err());
+    assert!(Calculation::new(&registry, TABLE, &WriterOptions::default(), options).is_err());
+}
+
+#[test]
+fn test_replaced_writer() {
+    let registry =
+        Registry::default().with_writer(JSON, "Nothing at all.", |_| Err("Not today".to_string()));
+    assert_eq!(
+        registry.formats().len(),
+        Registry::default().formats().len()
+    );
+    assert_eq!(
+        registry.writer(JSON, &WriterOptions::default()).err(),
+        Some("Not today".to_string())
+    );
+}
+
+#[test]
+fn test_builtin_writer_to_file() {
+    let path = std::env::temp_dir().join(format!("moleco-writers-{}.csv", std::process::id()));
+    let options = WriterOptions {
+        output_file: Some(path.to_string_lossy().to_string()),
+        with_id: true,
+        ..Default::default()
+    };
+    let mut writer = Registry::default().writer(CSV, &options).unwrap();
+    let water = "InChI=1S/H2O/h1H2".to_string();
+    writer.write(water.clone(), calculate_scheme(water), None, None);
+    writer.flush().unwrap();
+    let written = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(
+        written.lines().collect::<Vec<_>>(),
+        vec![
+            "Substance,Primary hue,First accent hue,Second accent hue,Complementary hue,Short ID",
+            "InChI=1S/H2O/h1H2,215,270,60,25,73vquxf2pgyl4etw"
+        ]
+    );
+
+    // Table is only printed
+    assert!(Registry::default().writer(TABLE, &options).is_err());
+}